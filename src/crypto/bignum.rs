@@ -0,0 +1,281 @@
+use std::cmp::Ordering;
+
+/*
+Arbitrary precision unsigned integers, just enough for RSA and elliptic curve arithmetic.
+
+Numbers are little endian vectors of 32 bit limbs with no high zero limbs, so zero is the empty
+vector. Division is Knuth's algorithm D (TAOCP 4.3.1), and every modular operation reduces with
+it.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn zero() -> Self {
+        Self { limbs: Vec::new() }
+    }
+
+    pub fn from_u32(value: u32) -> Self {
+        Self::from_limbs(vec![value])
+    }
+
+    fn from_limbs(mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Self { limbs }
+    }
+
+    pub fn from_bytes_be(bytes: &[u8]) -> Self {
+        let limbs = bytes
+            .rchunks(4)
+            .map(|chunk| chunk.iter().fold(0u32, |limb, b| (limb << 8) | *b as u32))
+            .collect();
+        Self::from_limbs(limbs)
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        let bytes: Vec<u8> = digits
+            .rchunks(2)
+            .rev()
+            .map(|pair| pair.iter().fold(0, |byte, d| (byte << 4) | d))
+            .collect();
+        Some(Self::from_bytes_be(&bytes))
+    }
+
+    // big endian, left padded with zeroes to len bytes when it's shorter
+    pub fn to_bytes_be(&self, len: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self
+            .limbs
+            .iter()
+            .rev()
+            .flat_map(|limb| limb.to_be_bytes())
+            .skip_while(|b| *b == 0)
+            .collect();
+        if bytes.len() < len {
+            let mut padded = vec![0; len - bytes.len()];
+            padded.append(&mut bytes);
+            return padded;
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.limbs.first().is_some_and(|limb| limb & 1 == 1)
+    }
+
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(top) => self.limbs.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    pub fn bit(&self, index: usize) -> bool {
+        self.limbs
+            .get(index / 32)
+            .is_some_and(|limb| (limb >> (index % 32)) & 1 == 1)
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64
+                + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        Self::from_limbs(limbs)
+    }
+
+    // self - other, which has to be no larger than self
+    pub fn sub(&self, other: &Self) -> Self {
+        debug_assert!(*self >= *other);
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for i in 0..self.limbs.len() {
+            let mut diff = self.limbs[i] as i64 - *other.limbs.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = 0;
+            if diff < 0 {
+                diff += 1 << 32;
+                borrow = 1;
+            }
+            limbs.push(diff as u32);
+        }
+        Self::from_limbs(limbs)
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        if self.is_zero() || other.is_zero() {
+            return Self::zero();
+        }
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.limbs.iter().enumerate() {
+                let product = *a as u64 * *b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        Self::from_limbs(limbs)
+    }
+
+    fn shl_bits(&self, shift: u32) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        let mut carry = 0u32;
+        for limb in &self.limbs {
+            limbs.push((limb << shift) | carry);
+            carry = if shift == 0 { 0 } else { limb >> (32 - shift) };
+        }
+        limbs.push(carry);
+        limbs
+    }
+
+    // (self / divisor, self % divisor), divisor can't be zero
+    pub fn divrem(&self, divisor: &Self) -> (Self, Self) {
+        assert!(!divisor.is_zero(), "division by zero");
+        if *self < *divisor {
+            return (Self::zero(), self.clone());
+        }
+        if divisor.limbs.len() == 1 {
+            let d = divisor.limbs[0] as u64;
+            let mut quotient = vec![0u32; self.limbs.len()];
+            let mut rest = 0u64;
+            for i in (0..self.limbs.len()).rev() {
+                let current = (rest << 32) | self.limbs[i] as u64;
+                quotient[i] = (current / d) as u32;
+                rest = current % d;
+            }
+            return (Self::from_limbs(quotient), Self::from_u32(rest as u32));
+        }
+
+        // normalize so the divisor's top limb has its high bit set
+        let shift = divisor.limbs.last().unwrap().leading_zeros();
+        let v = divisor.shl_bits(shift);
+        let v = &v[..divisor.limbs.len()];
+        let mut u = self.shl_bits(shift);
+        let n = v.len();
+        let m = u.len() - n - 1;
+        let mut quotient = vec![0u32; m + 1];
+        let base = 1u64 << 32;
+
+        for j in (0..=m).rev() {
+            let top = ((u[j + n] as u64) << 32) | u[j + n - 1] as u64;
+            let mut qhat = top / v[n - 1] as u64;
+            let mut rhat = top % v[n - 1] as u64;
+            while qhat >= base || qhat * v[n - 2] as u64 > ((rhat << 32) | u[j + n - 2] as u64) {
+                qhat -= 1;
+                rhat += v[n - 1] as u64;
+                if rhat >= base {
+                    break;
+                }
+            }
+
+            // u[j..=j + n] -= qhat * v
+            let mut borrow = 0i64;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let product = qhat * v[i] as u64 + carry;
+                carry = product >> 32;
+                let diff = u[i + j] as i64 - (product & 0xffff_ffff) as i64 - borrow;
+                u[i + j] = diff as u32;
+                borrow = if diff < 0 { 1 } else { 0 };
+            }
+            let diff = u[j + n] as i64 - carry as i64 - borrow;
+            u[j + n] = diff as u32;
+
+            if diff < 0 {
+                // qhat was one too large, add the divisor back
+                qhat -= 1;
+                let mut carry = 0u64;
+                for i in 0..n {
+                    let sum = u[i + j] as u64 + v[i] as u64 + carry;
+                    u[i + j] = sum as u32;
+                    carry = sum >> 32;
+                }
+                u[j + n] = u[j + n].wrapping_add(carry as u32);
+            }
+            quotient[j] = qhat as u32;
+        }
+
+        // unnormalize the remainder
+        let mut remainder = vec![0u32; n];
+        for i in 0..n {
+            remainder[i] = if shift == 0 {
+                u[i]
+            } else {
+                (u[i] >> shift) | (u[i + 1] << (32 - shift))
+            };
+        }
+        (Self::from_limbs(quotient), Self::from_limbs(remainder))
+    }
+
+    pub fn rem(&self, modulus: &Self) -> Self {
+        self.divrem(modulus).1
+    }
+
+    pub fn mul_mod(&self, other: &Self, modulus: &Self) -> Self {
+        self.mul(other).rem(modulus)
+    }
+
+    pub fn add_mod(&self, other: &Self, modulus: &Self) -> Self {
+        self.add(other).rem(modulus)
+    }
+
+    // (self - other) mod modulus, both already reduced
+    pub fn sub_mod(&self, other: &Self, modulus: &Self) -> Self {
+        if *self >= *other {
+            self.sub(other)
+        } else {
+            self.add(modulus).sub(other)
+        }
+    }
+
+    // left to right square and multiply
+    pub fn pow_mod(&self, exponent: &Self, modulus: &Self) -> Self {
+        let base = self.rem(modulus);
+        let mut result = Self::from_u32(1).rem(modulus);
+        for i in (0..exponent.bits()).rev() {
+            result = result.mul_mod(&result, modulus);
+            if exponent.bit(i) {
+                result = result.mul_mod(&base, modulus);
+            }
+        }
+        result
+    }
+
+    // inverse modulo a prime, by Fermat's little theorem
+    pub fn inv_mod_prime(&self, prime: &Self) -> Self {
+        self.pow_mod(&prime.sub(&Self::from_u32(2)), prime)
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
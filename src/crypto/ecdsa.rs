@@ -0,0 +1,293 @@
+use super::bignum::BigUint;
+use crate::der::*;
+use crate::prelude::*;
+
+/*
+ECDSA signatures over the NIST P-256 and P-384 curves (FIPS 186-4), y^2 = x^3 - 3x + b mod p.
+
+Public keys are uncompressed points: 04 || X || Y. Signatures are
+Ecdsa-Sig-Value ::= SEQUENCE { r INTEGER, s INTEGER }
+
+Verification computes u1 = z / s and u2 = r / s mod n and checks that the x coordinate of
+u1 * G + u2 * Q is r mod n, z being the digest cut to the bit length of n. Points are kept in
+Jacobian coordinates (X / Z^2, Y / Z^3) so additions don't need an inversion each.
+ */
+pub const OID_P256: &str = "1.2.840.10045.3.1.7";
+pub const OID_P384: &str = "1.3.132.0.34";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Curve {
+    P256,
+    P384,
+}
+
+struct CurveParams {
+    p: BigUint,
+    n: BigUint,
+    b: BigUint,
+    g: (BigUint, BigUint),
+}
+
+impl Curve {
+    pub fn from_oid(oid: &str) -> Option<Self> {
+        match oid {
+            OID_P256 => Some(Curve::P256),
+            OID_P384 => Some(Curve::P384),
+            _ => None,
+        }
+    }
+
+    // coordinate size in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Curve::P256 => 32,
+            Curve::P384 => 48,
+        }
+    }
+
+    fn params(&self) -> CurveParams {
+        let hex = |value: &str| BigUint::from_hex(value).unwrap();
+        match self {
+            Curve::P256 => CurveParams {
+                p: hex("ffffffff00000001000000000000000000000000ffffffffffffffffffffffff"),
+                n: hex("ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551"),
+                b: hex("5ac635d8aa3a93e7b3ebbd55769886bc651d06b0cc53b0f63bce3c3e27d2604b"),
+                g: (
+                    hex("6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296"),
+                    hex("4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5"),
+                ),
+            },
+            Curve::P384 => CurveParams {
+                p: hex(concat!(
+                    "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe",
+                    "ffffffff0000000000000000ffffffff"
+                )),
+                n: hex(concat!(
+                    "ffffffffffffffffffffffffffffffffffffffffffffffffc7634d81f4372ddf",
+                    "581a0db248b0a77aecec196accc52973"
+                )),
+                b: hex(concat!(
+                    "b3312fa7e23ee7e4988e056be3f82d19181d9c6efe8141120314088f5013875a",
+                    "c656398d8a2ed19d2a85c8edd3ec2aef"
+                )),
+                g: (
+                    hex(concat!(
+                        "aa87ca22be8b05378eb1c71ef320ad746e1d3b628ba79b9859f741e082542a38",
+                        "5502f25dbf55296c3a545e3872760ab7"
+                    )),
+                    hex(concat!(
+                        "3617de4a96262c6f5d9e98bf9292dc29f8f41dbd289a147ce9da3113b5f0b8c0",
+                        "0a60b1ce1d7e819d7a431d7c90ea0e5f"
+                    )),
+                ),
+            },
+        }
+    }
+}
+
+// Jacobian point, Z = 0 is the point at infinity
+#[derive(Clone)]
+struct Point {
+    x: BigUint,
+    y: BigUint,
+    z: BigUint,
+}
+
+impl Point {
+    fn infinity() -> Self {
+        Self {
+            x: BigUint::from_u32(1),
+            y: BigUint::from_u32(1),
+            z: BigUint::zero(),
+        }
+    }
+
+    fn affine(x: &BigUint, y: &BigUint) -> Self {
+        Self {
+            x: x.clone(),
+            y: y.clone(),
+            z: BigUint::from_u32(1),
+        }
+    }
+}
+
+struct Field<'a> {
+    p: &'a BigUint,
+}
+
+impl Field<'_> {
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.add_mod(b, self.p)
+    }
+
+    fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.sub_mod(b, self.p)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a.mul_mod(b, self.p)
+    }
+
+    fn small(&self, k: u32, a: &BigUint) -> BigUint {
+        self.mul(&BigUint::from_u32(k), a)
+    }
+
+    fn double(&self, point: &Point) -> Point {
+        if point.z.is_zero() || point.y.is_zero() {
+            return Point::infinity();
+        }
+        let delta = self.mul(&point.z, &point.z);
+        let gamma = self.mul(&point.y, &point.y);
+        let beta = self.mul(&point.x, &gamma);
+        let alpha = self.small(
+            3,
+            &self.mul(&self.sub(&point.x, &delta), &self.add(&point.x, &delta)),
+        );
+        let x = self.sub(&self.mul(&alpha, &alpha), &self.small(8, &beta));
+        let yz = self.add(&point.y, &point.z);
+        let z = self.sub(&self.sub(&self.mul(&yz, &yz), &gamma), &delta);
+        let y = self.sub(
+            &self.mul(&alpha, &self.sub(&self.small(4, &beta), &x)),
+            &self.small(8, &self.mul(&gamma, &gamma)),
+        );
+        Point { x, y, z }
+    }
+
+    fn add_points(&self, a: &Point, b: &Point) -> Point {
+        if a.z.is_zero() {
+            return b.clone();
+        }
+        if b.z.is_zero() {
+            return a.clone();
+        }
+        let z1z1 = self.mul(&a.z, &a.z);
+        let z2z2 = self.mul(&b.z, &b.z);
+        let u1 = self.mul(&a.x, &z2z2);
+        let u2 = self.mul(&b.x, &z1z1);
+        let s1 = self.mul(&self.mul(&a.y, &b.z), &z2z2);
+        let s2 = self.mul(&self.mul(&b.y, &a.z), &z1z1);
+        if u1 == u2 {
+            return if s1 == s2 {
+                self.double(a)
+            } else {
+                Point::infinity()
+            };
+        }
+        let h = self.sub(&u2, &u1);
+        let h2 = self.small(2, &h);
+        let i = self.mul(&h2, &h2);
+        let j = self.mul(&h, &i);
+        let r = self.small(2, &self.sub(&s2, &s1));
+        let v = self.mul(&u1, &i);
+        let x = self.sub(&self.sub(&self.mul(&r, &r), &j), &self.small(2, &v));
+        let y = self.sub(
+            &self.mul(&r, &self.sub(&v, &x)),
+            &self.small(2, &self.mul(&s1, &j)),
+        );
+        let zz = self.add(&a.z, &b.z);
+        let z = self.mul(&self.sub(&self.sub(&self.mul(&zz, &zz), &z1z1), &z2z2), &h);
+        Point { x, y, z }
+    }
+
+    // k1 * a + k2 * b, both at once (Shamir's trick)
+    fn mul_add(&self, k1: &BigUint, a: &Point, k2: &BigUint, b: &Point) -> Point {
+        let both = self.add_points(a, b);
+        let mut result = Point::infinity();
+        for i in (0..k1.bits().max(k2.bits())).rev() {
+            result = self.double(&result);
+            match (k1.bit(i), k2.bit(i)) {
+                (true, true) => result = self.add_points(&result, &both),
+                (true, false) => result = self.add_points(&result, a),
+                (false, true) => result = self.add_points(&result, b),
+                (false, false) => {}
+            }
+        }
+        result
+    }
+
+    // affine x coordinate
+    fn x(&self, point: &Point) -> Option<BigUint> {
+        if point.z.is_zero() {
+            return None;
+        }
+        let z_inv = point.z.inv_mod_prime(self.p);
+        Some(self.mul(&point.x, &self.mul(&z_inv, &z_inv)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EcPublicKey {
+    pub curve: Curve,
+    pub x: BigUint,
+    pub y: BigUint,
+}
+
+impl EcPublicKey {
+    // point is the uncompressed point from the certificate
+    pub fn new(curve: Curve, point: &[u8]) -> Result<Self, ParsingError> {
+        let size = curve.size();
+        if point.len() != 1 + 2 * size || point[0] != 0x04 {
+            return Err(ParsingError::Malformed {
+                reason: "EC public key isn't an uncompressed point".to_string(),
+            });
+        }
+        let key = Self {
+            curve,
+            x: BigUint::from_bytes_be(&point[1..1 + size]),
+            y: BigUint::from_bytes_be(&point[1 + size..]),
+        };
+
+        // y^2 = x^3 - 3x + b
+        let params = curve.params();
+        let field = Field { p: &params.p };
+        let x3 = field.mul(&field.mul(&key.x, &key.x), &key.x);
+        let rhs = field.add(&field.sub(&x3, &field.small(3, &key.x)), &params.b);
+        if key.x >= params.p || key.y >= params.p || field.mul(&key.y, &key.y) != rhs {
+            return Err(ParsingError::Malformed {
+                reason: "EC public key isn't on the curve".to_string(),
+            });
+        }
+        Ok(key)
+    }
+
+    // signature is a DER Ecdsa-Sig-Value
+    pub fn verify(&self, digest: &[u8], signature: &[u8]) -> bool {
+        let (r, s) = match Tlv::parse(signature)
+            .and_then(|tlv| tlv.expect(SEQUENCE))
+            .and_then(|tlv| tlv.children())
+        {
+            Ok(parts) => match parts.as_slice() {
+                [r, s] => match (r.integer(), s.integer()) {
+                    (Ok(r), Ok(s)) => (BigUint::from_bytes_be(r), BigUint::from_bytes_be(s)),
+                    _ => return false,
+                },
+                _ => return false,
+            },
+            Err(_) => return false,
+        };
+
+        let params = self.curve.params();
+        let n = &params.n;
+        if r.is_zero() || s.is_zero() || r >= *n || s >= *n {
+            return false;
+        }
+        // leftmost bits of the digest, n is a whole number of bytes on both curves
+        let z = BigUint::from_bytes_be(&digest[..digest.len().min(self.curve.size())]);
+        let z = z.rem(n);
+
+        let w = s.inv_mod_prime(n);
+        let u1 = z.mul_mod(&w, n);
+        let u2 = r.mul_mod(&w, n);
+        let field = Field { p: &params.p };
+        let point = field.mul_add(
+            &u1,
+            &Point::affine(&params.g.0, &params.g.1),
+            &u2,
+            &Point::affine(&self.x, &self.y),
+        );
+        match field.x(&point) {
+            Some(x) => x.rem(n) == r,
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+pub mod bignum;
+pub mod ecdsa;
+pub mod md5;
+pub mod rsa;
+pub mod sha1;
+pub mod sha2;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+// lowercase hex, the way digests are usually written
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    // the digest OIDs, which is how PKCS#7 names them
+    pub fn from_oid(oid: &str) -> Option<Self> {
+        match oid {
+            "1.2.840.113549.2.5" => Some(DigestAlgorithm::Md5),
+            "1.3.14.3.2.26" => Some(DigestAlgorithm::Sha1),
+            "2.16.840.1.101.3.4.2.1" => Some(DigestAlgorithm::Sha256),
+            "2.16.840.1.101.3.4.2.2" => Some(DigestAlgorithm::Sha384),
+            "2.16.840.1.101.3.4.2.3" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn oid(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "1.2.840.113549.2.5",
+            DigestAlgorithm::Sha1 => "1.3.14.3.2.26",
+            DigestAlgorithm::Sha256 => "2.16.840.1.101.3.4.2.1",
+            DigestAlgorithm::Sha384 => "2.16.840.1.101.3.4.2.2",
+            DigestAlgorithm::Sha512 => "2.16.840.1.101.3.4.2.3",
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            DigestAlgorithm::Md5 => 16,
+            DigestAlgorithm::Sha1 => 20,
+            DigestAlgorithm::Sha256 => 32,
+            DigestAlgorithm::Sha384 => 48,
+            DigestAlgorithm::Sha512 => 64,
+        }
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            DigestAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            DigestAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            DigestAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            DigestAlgorithm::Sha384 => Hasher::Sha512(Sha512::new_384()),
+            DigestAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+// any of the digests, for when the algorithm is only known at runtime
+#[derive(Clone)]
+pub enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    // SHA-384 too
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize(),
+        }
+    }
+}
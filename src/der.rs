@@ -0,0 +1,392 @@
+use crate::prelude::*;
+use chrono::{DateTime, NaiveDate, Utc};
+
+/*
+DER (X.690) encoding, as used by PKCS#7 and X.509.
+
+Every value is a TLV:
++00         identifier (bits 0-4 tag number, bit 5 constructed, bits 6-7 class)
++01         length, short form (< 0x80) or 0x80 | n followed by n big endian length bytes
+            content
+
+Only single byte identifiers and definite lengths are accepted, which is all DER allows for the
+structures Authenticode uses.
+ */
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OID: u8 = 0x06;
+pub const UTF8_STRING: u8 = 0x0c;
+pub const PRINTABLE_STRING: u8 = 0x13;
+pub const T61_STRING: u8 = 0x14;
+pub const IA5_STRING: u8 = 0x16;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const BMP_STRING: u8 = 0x1e;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+// [n] with the constructed bit, the usual form of EXPLICIT and IMPLICIT SET / SEQUENCE tags
+pub const fn context(n: u8) -> u8 {
+    0xa0 | n
+}
+
+// [n] without the constructed bit, for IMPLICIT primitive values
+pub const fn context_primitive(n: u8) -> u8 {
+    0x80 | n
+}
+
+// more than this and a length is certainly bogus
+const MAX_LENGTH_BYTES: usize = 4;
+
+fn malformed(reason: String) -> ParsingError {
+    ParsingError::Malformed { reason }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    // the value without its identifier and length
+    pub content: &'a [u8],
+    // the whole encoding, identifier and length included
+    pub raw: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    // reads the TLV at offset and moves offset past it
+    pub fn read(raw: &'a [u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        let start = *offset;
+        let tag = try_read_byte(raw, offset)?;
+        if tag & 0x1f == 0x1f {
+            return Err(malformed(format!(
+                "DER value at {:#x} has a multi byte tag",
+                start
+            )));
+        }
+        let first = try_read_byte(raw, offset)?;
+        let length = match first {
+            0..=0x7f => first as usize,
+            0x80 => {
+                return Err(malformed(format!(
+                    "DER value at {:#x} has an indefinite length",
+                    start
+                )))
+            }
+            _ => {
+                let count = (first & 0x7f) as usize;
+                if count > MAX_LENGTH_BYTES {
+                    return Err(malformed(format!(
+                        "DER value at {:#x} has a {} byte length",
+                        start, count
+                    )));
+                }
+                let length = try_slice(raw, *offset, count)?
+                    .iter()
+                    .fold(0usize, |length, b| (length << 8) | *b as usize);
+                *offset += count;
+                length
+            }
+        };
+        let content = try_slice(raw, *offset, length)?;
+        *offset += length;
+        Ok(Self {
+            tag,
+            content,
+            raw: &raw[start..*offset],
+        })
+    }
+
+    // the single TLV making up raw, anything after it is an error
+    pub fn parse(raw: &'a [u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let tlv = Self::read(raw, &mut offset)?;
+        if offset != raw.len() {
+            return Err(malformed(format!(
+                "{} bytes after the DER value",
+                raw.len() - offset
+            )));
+        }
+        Ok(tlv)
+    }
+
+    pub fn expect(self, tag: u8) -> Result<Self, ParsingError> {
+        if self.tag != tag {
+            return Err(malformed(format!(
+                "expected DER tag {:#04x}, found {:#04x}",
+                tag, self.tag
+            )));
+        }
+        Ok(self)
+    }
+
+    pub fn is_constructed(&self) -> bool {
+        self.tag & 0x20 != 0
+    }
+
+    // the TLVs inside a constructed value
+    pub fn children(&self) -> Result<Vec<Tlv<'a>>, ParsingError> {
+        if !self.is_constructed() {
+            return Err(malformed(format!(
+                "DER tag {:#04x} isn't constructed",
+                self.tag
+            )));
+        }
+        let mut children = Vec::new();
+        let mut offset = 0;
+        while offset < self.content.len() {
+            children.push(Tlv::read(self.content, &mut offset)?);
+        }
+        Ok(children)
+    }
+
+    // dotted form, e.g. 1.2.840.113549.1.7.2
+    pub fn oid(&self) -> Result<String, ParsingError> {
+        let content = self.expect(OID)?.content;
+        let mut arcs: Vec<u64> = Vec::new();
+        let mut arc = 0u64;
+        for (index, b) in content.iter().enumerate() {
+            if arc > u64::MAX >> 7 {
+                return Err(malformed("OID arc is too large".to_string()));
+            }
+            arc = (arc << 7) | (b & 0x7f) as u64;
+            if b & 0x80 != 0 {
+                if index + 1 == content.len() {
+                    return Err(malformed("OID ends inside an arc".to_string()));
+                }
+                continue;
+            }
+            if arcs.is_empty() {
+                // the first byte packs the first two arcs as 40 * first + second
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - first * 40);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+        if arcs.is_empty() {
+            return Err(malformed("empty OID".to_string()));
+        }
+        Ok(arcs
+            .iter()
+            .map(|arc| arc.to_string())
+            .collect::<Vec<_>>()
+            .join("."))
+    }
+
+    // big endian two's complement bytes, as stored
+    pub fn integer(&self) -> Result<&'a [u8], ParsingError> {
+        let content = self.expect(INTEGER)?.content;
+        if content.is_empty() {
+            return Err(malformed("empty INTEGER".to_string()));
+        }
+        Ok(content)
+    }
+
+    // small non-negative INTEGERs such as versions
+    pub fn small_integer(&self) -> Result<u32, ParsingError> {
+        let content = self.integer()?;
+        if content.len() > 4 || content[0] & 0x80 != 0 {
+            return Err(malformed(format!(
+                "INTEGER {:02x?} doesn't fit a u32",
+                content
+            )));
+        }
+        Ok(content.iter().fold(0, |value, b| (value << 8) | *b as u32))
+    }
+
+    // BIT STRING content without the unused bits count
+    pub fn bit_string(&self) -> Result<&'a [u8], ParsingError> {
+        let content = self.expect(BIT_STRING)?.content;
+        match content.split_first() {
+            Some((unused, bits)) if *unused < 8 => Ok(bits),
+            _ => Err(malformed("malformed BIT STRING".to_string())),
+        }
+    }
+
+    // the character string types that turn up in names and Authenticode attributes
+    pub fn string(&self) -> Result<String, ParsingError> {
+        match self.tag {
+            UTF8_STRING | PRINTABLE_STRING | IA5_STRING => {
+                Ok(String::from_utf8_lossy(self.content).into_owned())
+            }
+            // nominally T.61, in practice Latin-1
+            T61_STRING => Ok(self.content.iter().map(|b| *b as char).collect()),
+            BMP_STRING => {
+                let units: Vec<u16> = self
+                    .content
+                    .chunks_exact(WORD_SZ)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect();
+                Ok(String::from_utf16_lossy(&units))
+            }
+            tag => Err(malformed(format!("DER tag {:#04x} isn't a string", tag))),
+        }
+    }
+
+    // UTCTime (YYMMDDHHMMSSZ) or GeneralizedTime (YYYYMMDDHHMMSS[.f]Z)
+    pub fn time(&self) -> Result<DateTime<Utc>, ParsingError> {
+        let text = std::str::from_utf8(self.content)
+            .map_err(|_| malformed("time isn't ASCII".to_string()))?;
+        let digits = |range: std::ops::Range<usize>| -> Result<u32, ParsingError> {
+            text.get(range)
+                .filter(|part| part.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|part| part.parse().ok())
+                .ok_or(malformed(format!("malformed time {:?}", text)))
+        };
+        let (year, rest) = match self.tag {
+            UTC_TIME => {
+                let year = digits(0..2)?;
+                (if year >= 50 { 1900 + year } else { 2000 + year }, 2)
+            }
+            GENERALIZED_TIME => (digits(0..4)?, 4),
+            tag => return Err(malformed(format!("DER tag {:#04x} isn't a time", tag))),
+        };
+        if !text.ends_with('Z') {
+            return Err(malformed(format!("time {:?} isn't in UTC", text)));
+        }
+        NaiveDate::from_ymd_opt(
+            year as i32,
+            digits(rest..rest + 2)?,
+            digits(rest + 2..rest + 4)?,
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
+                digits(rest + 4..rest + 6).ok()?,
+                digits(rest + 6..rest + 8).ok()?,
+                digits(rest + 8..rest + 10).ok()?,
+            )
+        })
+        .map(|time| time.and_utc())
+        .ok_or(malformed(format!("malformed time {:?}", text)))
+    }
+}
+
+/*
+ * Encoding
+ */
+
+// TLV with a definite length in the shortest form
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut raw = vec![tag];
+    if content.len() < 0x80 {
+        raw.push(content.len() as u8);
+    } else {
+        let length: Vec<u8> = content
+            .len()
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        raw.push(0x80 | length.len() as u8);
+        raw.extend_from_slice(&length);
+    }
+    raw.extend_from_slice(content);
+    raw
+}
+
+// constructed value holding parts in order
+pub fn encode_all(tag: u8, parts: &[Vec<u8>]) -> Vec<u8> {
+    encode(tag, &parts.concat())
+}
+
+// SET OF, which DER wants sorted by encoding
+pub fn encode_set(parts: &[Vec<u8>]) -> Vec<u8> {
+    let mut parts = parts.to_vec();
+    parts.sort();
+    encode_all(SET, &parts)
+}
+
+// INTEGER for a big endian unsigned value
+pub fn encode_unsigned(bytes: &[u8]) -> Vec<u8> {
+    let mut content: Vec<u8> = bytes.iter().copied().skip_while(|b| *b == 0).collect();
+    if content.first().is_none_or(|b| b & 0x80 != 0) {
+        content.insert(0, 0);
+    }
+    encode(INTEGER, &content)
+}
+
+// dotted decimal OID
+pub fn encode_oid(oid: &str) -> Result<Vec<u8>, ParsingError> {
+    let arcs = oid
+        .split('.')
+        .map(|arc| arc.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|arcs| arcs.len() >= 2 && arcs[0] <= 2)
+        .ok_or(malformed(format!("malformed OID {}", oid)))?;
+
+    let mut content = Vec::new();
+    for arc in std::iter::once(arcs[0] * 40 + arcs[1]).chain(arcs[2..].iter().copied()) {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest != 0 {
+            bytes.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        bytes.reverse();
+        content.extend_from_slice(&bytes);
+    }
+    Ok(encode(OID, &content))
+}
+
+// AlgorithmIdentifier with NULL parameters
+pub fn encode_algorithm(oid: &str) -> Result<Vec<u8>, ParsingError> {
+    Ok(encode_all(SEQUENCE, &[encode_oid(oid)?, encode(NULL, &[])]))
+}
+
+// AlgorithmIdentifier ::= SEQUENCE { algorithm OID, parameters ANY OPTIONAL }
+pub fn algorithm(tlv: &Tlv) -> Result<String, ParsingError> {
+    let children = tlv.expect(SEQUENCE)?.children()?;
+    children
+        .first()
+        .ok_or(malformed("empty AlgorithmIdentifier".to_string()))?
+        .oid()
+}
+
+// names for the OIDs Authenticode uses, for display
+pub fn oid_name(oid: &str) -> Option<&'static str> {
+    Some(match oid {
+        "1.2.840.113549.2.5" => "md5",
+        "1.3.14.3.2.26" => "sha1",
+        "2.16.840.1.101.3.4.2.1" => "sha256",
+        "2.16.840.1.101.3.4.2.2" => "sha384",
+        "2.16.840.1.101.3.4.2.3" => "sha512",
+        "1.2.840.113549.1.1.1" => "rsaEncryption",
+        "1.2.840.113549.1.1.4" => "md5WithRSAEncryption",
+        "1.2.840.113549.1.1.5" => "sha1WithRSAEncryption",
+        "1.2.840.113549.1.1.11" => "sha256WithRSAEncryption",
+        "1.2.840.113549.1.1.12" => "sha384WithRSAEncryption",
+        "1.2.840.113549.1.1.13" => "sha512WithRSAEncryption",
+        "1.2.840.10045.2.1" => "ecPublicKey",
+        "1.2.840.10045.4.3.2" => "ecdsa-with-SHA256",
+        "1.2.840.10045.4.3.3" => "ecdsa-with-SHA384",
+        "1.2.840.113549.1.7.1" => "data",
+        "1.2.840.113549.1.7.2" => "signedData",
+        "1.2.840.113549.1.9.3" => "contentType",
+        "1.2.840.113549.1.9.4" => "messageDigest",
+        "1.2.840.113549.1.9.5" => "signingTime",
+        "1.2.840.113549.1.9.6" => "countersignature",
+        "1.2.840.113549.1.9.16.1.4" => "tstInfo",
+        "1.3.6.1.4.1.311.2.1.4" => "spcIndirectDataContent",
+        "1.3.6.1.4.1.311.2.1.12" => "spcSpOpusInfo",
+        "1.3.6.1.4.1.311.2.1.15" => "spcPeImageData",
+        "1.3.6.1.4.1.311.2.4.1" => "nestedSignature",
+        "1.3.6.1.4.1.311.3.3.1" => "rfc3161Timestamp",
+        "1.3.6.1.5.5.7.3.1" => "serverAuth",
+        "1.3.6.1.5.5.7.3.3" => "codeSigning",
+        "1.3.6.1.5.5.7.3.8" => "timeStamping",
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "serialNumber",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "1.2.840.113549.1.9.1" => "emailAddress",
+        _ => return None,
+    })
+}
@@ -0,0 +1,229 @@
+use crate::der::Tlv;
+use crate::prelude::*;
+use std::ops::Range;
+
+pub mod page_hashes;
+pub mod pkcs7;
+pub mod sign;
+pub mod verify;
+pub mod x509;
+
+use pkcs7::AuthenticodeSignature;
+
+/*
+Attribute certificate table (the security data directory, whose VirtualAddress is a file offset,
+not an RVA). The table isn't mapped into memory and sits at the end of the file.
+
+WIN_CERTIFICATE:
++00 DWORD   dwLength (of this entry, header included)
++04 WORD    wRevision
++06 WORD    wCertificateType
++08         bCertificate[dwLength - 8]
+
+Each entry starts on an 8 byte boundary, so dwLength is followed by padding up to the next one.
+ */
+const WIN_CERTIFICATE_HEADER_SZ: usize = 8;
+const WIN_CERTIFICATE_ALIGNMENT: usize = 8;
+
+pub const WIN_CERT_REVISION_1_0: u16 = 0x0100;
+pub const WIN_CERT_REVISION_2_0: u16 = 0x0200;
+
+pub const WIN_CERT_TYPE_X509: u16 = 0x0001;
+pub const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 0x0002;
+pub const WIN_CERT_TYPE_RESERVED_1: u16 = 0x0003;
+pub const WIN_CERT_TYPE_TS_STACK_SIGNED: u16 = 0x0004;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CertificateType {
+    X509,
+    // Authenticode, the only type Windows checks
+    PkcsSignedData,
+    Reserved,
+    TsStackSigned,
+    Unknown(u16),
+}
+
+impl CertificateType {
+    pub fn new(raw: u16) -> Self {
+        match raw {
+            WIN_CERT_TYPE_X509 => CertificateType::X509,
+            WIN_CERT_TYPE_PKCS_SIGNED_DATA => CertificateType::PkcsSignedData,
+            WIN_CERT_TYPE_RESERVED_1 => CertificateType::Reserved,
+            WIN_CERT_TYPE_TS_STACK_SIGNED => CertificateType::TsStackSigned,
+            other => CertificateType::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WinCertificate {
+    // file offset of the entry
+    pub offset: usize,
+    pub length: u32,
+    pub revision: u16,
+    pub certificate_type: CertificateType,
+    pub data: Vec<u8>,
+    // bytes between dwLength and the next 8 byte boundary, which should be zero
+    pub padding: Vec<u8>,
+}
+
+impl WinCertificate {
+    // the decoded signature, for PKCS_SIGNED_DATA entries
+    pub fn signature(&self) -> Option<Result<AuthenticodeSignature, ParsingError>> {
+        match self.certificate_type {
+            CertificateType::PkcsSignedData => Some(AuthenticodeSignature::new(&self.data)),
+            _ => None,
+        }
+    }
+}
+
+// raw is the table, starting at file offset table_offset
+pub fn win_certificates(
+    raw: &[u8],
+    table_offset: usize,
+) -> Result<Vec<WinCertificate>, ParsingError> {
+    let mut certificates = Vec::new();
+    let mut offset = 0;
+    while offset + WIN_CERTIFICATE_HEADER_SZ <= raw.len() {
+        let start = offset;
+        let length = try_read_dword(raw, &mut offset)?;
+        let revision = try_read_word(raw, &mut offset)?;
+        let certificate_type = CertificateType::new(try_read_word(raw, &mut offset)?);
+        let data_size = (length as usize)
+            .checked_sub(WIN_CERTIFICATE_HEADER_SZ)
+            .ok_or(ParsingError::Malformed {
+                reason: format!(
+                    "WIN_CERTIFICATE at {:#x} has length {}",
+                    table_offset + start,
+                    length
+                ),
+            })?;
+        let data = try_slice(raw, offset, data_size)?;
+        offset += data_size;
+
+        let aligned = offset.next_multiple_of(WIN_CERTIFICATE_ALIGNMENT);
+        let padding = &raw[offset..aligned.min(raw.len())];
+        offset = aligned;
+
+        certificates.push(WinCertificate {
+            offset: table_offset + start,
+            length,
+            revision,
+            certificate_type,
+            data: data.to_vec(),
+            padding: padding.to_vec(),
+        });
+    }
+    Ok(certificates)
+}
+
+/*
+ * Tampering
+ */
+
+// The certificate table isn't covered by the Authenticode digest, so anything hidden in it (or
+// tacked on after it) doesn't break the signature. Signers put the table at the end of the file,
+// 8 byte aligned, with each entry holding exactly one PKCS#7 ContentInfo plus at most 7 zero bytes
+// of alignment.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CertificateAnomaly {
+    // bytes after the PKCS#7 ContentInfo inside bCertificate, the CVE-2013-3900 pattern
+    DataInsideSignature { offset: usize, size: usize },
+    // a PKCS_SIGNED_DATA entry whose bCertificate isn't DER, so nothing in it can be accounted for
+    UndecodableSignature { offset: usize, size: usize },
+    // padding between dwLength and the next entry that isn't zero
+    NonZeroPadding { offset: usize, size: usize },
+    // dwLength too small for the header or running past the directory
+    BadLength { offset: usize, length: u32 },
+    // the entries, padding included, don't add up to the directory size
+    SizeMismatch { directory: u32, entries: usize },
+    // the table doesn't start on an 8 byte boundary
+    MisalignedTable { offset: usize },
+    // the table starts inside the headers or section data
+    InsideImage { offset: usize },
+    // the directory runs past the end of the file
+    PastEndOfFile { end: usize },
+    // unsigned bytes after the table, which isn't at the end of the file
+    DataAfterTable { offset: usize, size: usize },
+}
+
+// file is the whole file, table the directory's file range and image_end where the headers and
+// section data stop
+pub fn certificate_anomalies(
+    file: &[u8],
+    table: Range<usize>,
+    image_end: usize,
+) -> Vec<CertificateAnomaly> {
+    let mut anomalies = Vec::new();
+    if !table.start.is_multiple_of(WIN_CERTIFICATE_ALIGNMENT) {
+        anomalies.push(CertificateAnomaly::MisalignedTable {
+            offset: table.start,
+        });
+    }
+    if table.start < image_end {
+        anomalies.push(CertificateAnomaly::InsideImage {
+            offset: table.start,
+        });
+    }
+    if table.end > file.len() {
+        anomalies.push(CertificateAnomaly::PastEndOfFile { end: table.end });
+    } else if table.end < file.len() {
+        anomalies.push(CertificateAnomaly::DataAfterTable {
+            offset: table.end,
+            size: file.len() - table.end,
+        });
+    }
+    let raw = &file[table.start.min(file.len())..table.end.min(file.len())];
+
+    let mut offset = 0;
+    while offset + WIN_CERTIFICATE_HEADER_SZ <= raw.len() {
+        let start = offset;
+        let length = read_dword(raw, &mut offset);
+        offset += WORD_SZ;
+        let certificate_type = CertificateType::new(read_word(raw, &mut offset));
+        let end = start + length as usize;
+        if (length as usize) < WIN_CERTIFICATE_HEADER_SZ || end > raw.len() {
+            anomalies.push(CertificateAnomaly::BadLength {
+                offset: table.start + start,
+                length,
+            });
+            return anomalies;
+        }
+
+        // signers may round dwLength up to the alignment themselves
+        let data = &raw[start + WIN_CERTIFICATE_HEADER_SZ..end];
+        if certificate_type == CertificateType::PkcsSignedData {
+            match Tlv::read(data, &mut 0) {
+                Ok(content_info) => {
+                    let extra = &data[content_info.raw.len()..];
+                    if extra.len() >= WIN_CERTIFICATE_ALIGNMENT || extra.iter().any(|b| *b != 0) {
+                        anomalies.push(CertificateAnomaly::DataInsideSignature {
+                            offset: table.start + end - extra.len(),
+                            size: extra.len(),
+                        });
+                    }
+                }
+                Err(_) => anomalies.push(CertificateAnomaly::UndecodableSignature {
+                    offset: table.start + start + WIN_CERTIFICATE_HEADER_SZ,
+                    size: data.len(),
+                }),
+            }
+        }
+
+        offset = end.next_multiple_of(WIN_CERTIFICATE_ALIGNMENT);
+        let padding = &raw[end..offset.min(raw.len())];
+        if padding.iter().any(|b| *b != 0) {
+            anomalies.push(CertificateAnomaly::NonZeroPadding {
+                offset: table.start + end,
+                size: padding.len(),
+            });
+        }
+    }
+    if offset != raw.len() {
+        anomalies.push(CertificateAnomaly::SizeMismatch {
+            directory: table.len() as u32,
+            entries: offset,
+        });
+    }
+    anomalies
+}
@@ -0,0 +1,189 @@
+use crate::crypto::DigestAlgorithm;
+use crate::der::*;
+use crate::prelude::*;
+use std::collections::BTreeMap;
+
+/*
+Page hashes, which let the loader check each page of a signed image as it maps it. They sit in the
+SpcPeImageData of the signed SpcIndirectDataContent:
+
+SpcPeImageData ::= SEQUENCE { flags BIT STRING, file [0] EXPLICIT SpcLink }
+SpcLink ::= CHOICE { url [0] IA5String, moniker [1] IMPLICIT SpcSerializedObject, file [2] ... }
+SpcSerializedObject ::= SEQUENCE { classId OCTET STRING, serializedData OCTET STRING }
+
+With the page hash class id the serialized data is a
+SET OF SEQUENCE { type OID, values SET OF OCTET STRING }
+whose type says the digest (SHA-1 or SHA-256) and whose value is the table:
+
++00 DWORD   file offset of the page
++04         hash[digest size]
+
+The first entry is the headers, hashed the way the Authenticode digest hashes them and followed by
+4K - SizeOfHeaders zeros, so 12 bytes short of a page for the skipped CheckSum and security
+directory. Then come the section pages in file order, each hashed zero padded to 4K. The last entry
+is the end of the last section with an all zero hash.
+ */
+pub const PAGE_HASH_SIZE: usize = 0x1000;
+
+pub const OID_SPC_PE_IMAGE_PAGE_HASHES_V1: &str = "1.3.6.1.4.1.311.2.3.1";
+pub const OID_SPC_PE_IMAGE_PAGE_HASHES_V2: &str = "1.3.6.1.4.1.311.2.3.2";
+
+pub const SPC_SERIALIZED_OBJECT_PAGE_HASHES: [u8; 16] = [
+    0xa6, 0xb5, 0x86, 0xd5, 0xb4, 0xa1, 0x24, 0x66, 0xae, 0x05, 0xa2, 0x17, 0xda, 0x8e, 0x60, 0xd6,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageHash {
+    pub offset: u32,
+    pub hash: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageHashes {
+    pub algorithm: DigestAlgorithm,
+    // terminating entry included
+    pub pages: Vec<PageHash>,
+}
+
+impl PageHashes {
+    // raw is the DER SpcPeImageData, which only has page hashes if they were asked for at signing
+    pub fn new(raw: &[u8]) -> Result<Option<Self>, ParsingError> {
+        let link = match Tlv::parse(raw)?
+            .expect(SEQUENCE)?
+            .children()?
+            .into_iter()
+            .find(|field| field.tag == context(0))
+        {
+            Some(file) => Tlv::parse(file.content)?,
+            None => return Ok(None),
+        };
+        if link.tag != context(1) {
+            return Ok(None);
+        }
+        let (class_id, data) = match link.children()?.as_slice() {
+            [class_id, data] => (
+                class_id.expect(OCTET_STRING)?.content,
+                data.expect(OCTET_STRING)?.content,
+            ),
+            _ => {
+                return Err(ParsingError::Malformed {
+                    reason: "malformed SpcSerializedObject".to_string(),
+                })
+            }
+        };
+        if class_id != SPC_SERIALIZED_OBJECT_PAGE_HASHES {
+            return Ok(None);
+        }
+
+        for attribute in Tlv::parse(data)?.expect(SET)?.children()? {
+            let (kind, values) = match attribute.expect(SEQUENCE)?.children()?.as_slice() {
+                [kind, values] => (kind.oid()?, values.expect(SET)?.children()?),
+                _ => continue,
+            };
+            let algorithm = match kind.as_str() {
+                OID_SPC_PE_IMAGE_PAGE_HASHES_V1 => DigestAlgorithm::Sha1,
+                OID_SPC_PE_IMAGE_PAGE_HASHES_V2 => DigestAlgorithm::Sha256,
+                _ => continue,
+            };
+            let table = match values.first() {
+                Some(table) => table.expect(OCTET_STRING)?.content,
+                None => continue,
+            };
+            return Ok(Some(Self {
+                algorithm,
+                pages: page_hash_table(table, algorithm.size())?,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+fn page_hash_table(raw: &[u8], hash_size: usize) -> Result<Vec<PageHash>, ParsingError> {
+    let entry_size = DWORD_SZ + hash_size;
+    if !raw.len().is_multiple_of(entry_size) {
+        return Err(ParsingError::Malformed {
+            reason: format!(
+                "page hash table of {} bytes isn't made of {} byte entries",
+                raw.len(),
+                entry_size
+            ),
+        });
+    }
+    let mut pages = Vec::with_capacity(raw.len() / entry_size);
+    let mut offset = 0;
+    while offset < raw.len() {
+        pages.push(PageHash {
+            offset: try_read_dword(raw, &mut offset)?,
+            hash: try_slice(raw, offset, hash_size)?.to_vec(),
+        });
+        offset += hash_size;
+    }
+    Ok(pages)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageMismatch {
+    pub offset: u32,
+    // the section the page belongs to, None for the headers or outside any section
+    pub section: Option<String>,
+    // None when the page is only in one of the tables
+    pub signed: Option<Vec<u8>>,
+    pub actual: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageHashReport {
+    pub algorithm: DigestAlgorithm,
+    // entries in the signed table, the terminating one included
+    pub pages: usize,
+    pub mismatches: Vec<PageMismatch>,
+}
+
+impl PageHashReport {
+    pub fn new(signed: &PageHashes, actual: &[PageHash], sections: &[SectionHeader]) -> Self {
+        let signed_pages: BTreeMap<u32, &[u8]> = signed
+            .pages
+            .iter()
+            .map(|page| (page.offset, page.hash.as_slice()))
+            .collect();
+        let actual_pages: BTreeMap<u32, &[u8]> = actual
+            .iter()
+            .map(|page| (page.offset, page.hash.as_slice()))
+            .collect();
+        let mut offsets: Vec<u32> = signed_pages
+            .keys()
+            .chain(actual_pages.keys())
+            .copied()
+            .collect();
+        offsets.sort();
+        offsets.dedup();
+
+        let mismatches = offsets
+            .into_iter()
+            .filter(|offset| signed_pages.get(offset) != actual_pages.get(offset))
+            .map(|offset| PageMismatch {
+                offset,
+                section: sections
+                    .iter()
+                    .find(|section| {
+                        section.size_raw_data != 0
+                            && section.pointer_raw_data <= offset
+                            && offset - section.pointer_raw_data < section.size_raw_data
+                    })
+                    .map(|section| section.name.clone()),
+                signed: signed_pages.get(&offset).map(|hash| hash.to_vec()),
+                actual: actual_pages.get(&offset).map(|hash| hash.to_vec()),
+            })
+            .collect();
+
+        Self {
+            algorithm: signed.algorithm,
+            pages: signed.pages.len(),
+            mismatches,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
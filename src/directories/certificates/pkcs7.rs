@@ -0,0 +1,585 @@
+use super::page_hashes::PageHashes;
+use super::x509::{Certificate, Name};
+use crate::der::*;
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+
+/*
+PKCS#7 (RFC 2315) as Authenticode uses it:
+
+ContentInfo ::= SEQUENCE { contentType OID, content [0] EXPLICIT ANY }
+
+SignedData ::= SEQUENCE {
+    version                 INTEGER,
+    digestAlgorithms        SET OF AlgorithmIdentifier,
+    contentInfo             ContentInfo,
+    certificates        [0] IMPLICIT SET OF Certificate OPTIONAL,
+    crls                [1] IMPLICIT SET OF CertificateList OPTIONAL,
+    signerInfos             SET OF SignerInfo }
+
+SignerInfo ::= SEQUENCE {
+    version                     INTEGER,
+    sid                         IssuerAndSerialNumber (SEQUENCE { Name, INTEGER })
+                                or [0] IMPLICIT SubjectKeyIdentifier,
+    digestAlgorithm             AlgorithmIdentifier,
+    authenticatedAttributes [0] IMPLICIT SET OF Attribute OPTIONAL,
+    digestEncryptionAlgorithm   AlgorithmIdentifier,
+    encryptedDigest             OCTET STRING,
+    unauthenticatedAttributes [1] IMPLICIT SET OF Attribute OPTIONAL }
+
+Attribute ::= SEQUENCE { type OID, values SET OF ANY }
+
+The Authenticode content is an SpcIndirectDataContent:
+SEQUENCE {
+    data            SEQUENCE { type OID (SpcPeImageData for PE files), value ANY OPTIONAL },
+    messageDigest   SEQUENCE { digestAlgorithm AlgorithmIdentifier, digest OCTET STRING } }
+
+Timestamps and further signatures are unauthenticated attributes of the signer:
+countersignature (1.2.840.113549.1.9.6)     a SignerInfo over the encryptedDigest
+RFC 3161 timestamp (1.3.6.1.4.1.311.3.3.1)  a ContentInfo whose SignedData holds a TSTInfo
+nested signature (1.3.6.1.4.1.311.2.4.1)    a ContentInfo with another Authenticode signature
+
+TSTInfo ::= SEQUENCE {
+    version         INTEGER,
+    policy          OID,
+    messageImprint  SEQUENCE { hashAlgorithm AlgorithmIdentifier, hashedMessage OCTET STRING },
+    serialNumber    INTEGER,
+    genTime         GeneralizedTime,
+    ... }
+ */
+pub const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+pub const OID_CONTENT_TYPE: &str = "1.2.840.113549.1.9.3";
+pub const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+pub const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+pub const OID_COUNTERSIGNATURE: &str = "1.2.840.113549.1.9.6";
+pub const OID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+pub const OID_SPC_INDIRECT_DATA: &str = "1.3.6.1.4.1.311.2.1.4";
+pub const OID_SPC_SP_OPUS_INFO: &str = "1.3.6.1.4.1.311.2.1.12";
+pub const OID_SPC_PE_IMAGE_DATA: &str = "1.3.6.1.4.1.311.2.1.15";
+pub const OID_NESTED_SIGNATURE: &str = "1.3.6.1.4.1.311.2.4.1";
+pub const OID_RFC3161_TIMESTAMP: &str = "1.3.6.1.4.1.311.3.3.1";
+
+// signatures nested deeper than this are refused rather than recursed into
+const MAX_NESTING: usize = 8;
+
+fn malformed(reason: &str) -> ParsingError {
+    ParsingError::Malformed {
+        reason: reason.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub oid: String,
+    // each value's DER encoding
+    pub values: Vec<Vec<u8>>,
+}
+
+fn attributes(tlv: &Tlv) -> Result<Vec<Attribute>, ParsingError> {
+    tlv.children()?
+        .iter()
+        .map(|attribute| {
+            let parts = attribute.expect(SEQUENCE)?.children()?;
+            match parts.as_slice() {
+                [oid, values] => Ok(Attribute {
+                    oid: oid.oid()?,
+                    values: values
+                        .expect(SET)?
+                        .children()?
+                        .iter()
+                        .map(|value| value.raw.to_vec())
+                        .collect(),
+                }),
+                _ => Err(malformed("malformed attribute")),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignerId {
+    IssuerAndSerial { issuer: Name, serial: Vec<u8> },
+    SubjectKeyIdentifier(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignerInfo {
+    pub version: u32,
+    pub id: SignerId,
+    pub digest_algorithm: String,
+    pub authenticated_attributes: Vec<Attribute>,
+    // the attributes re-tagged as a SET, which is what encryptedDigest signs
+    pub authenticated_attributes_der: Option<Vec<u8>>,
+    pub digest_encryption_algorithm: String,
+    pub encrypted_digest: Vec<u8>,
+    pub unauthenticated_attributes: Vec<Attribute>,
+}
+
+impl SignerInfo {
+    pub fn new(tlv: &Tlv) -> Result<Self, ParsingError> {
+        let parts = tlv.expect(SEQUENCE)?.children()?;
+        let mut parts = parts.iter();
+        let mut next = || parts.next().ok_or(malformed("SignerInfo is truncated"));
+
+        let version = next()?.small_integer()?;
+        let sid = next()?;
+        let id = match sid.tag {
+            SEQUENCE => match sid.children()?.as_slice() {
+                [issuer, serial] => SignerId::IssuerAndSerial {
+                    issuer: Name::new(issuer)?,
+                    serial: serial.integer()?.to_vec(),
+                },
+                _ => return Err(malformed("malformed IssuerAndSerialNumber")),
+            },
+            tag if tag == context_primitive(0) => {
+                SignerId::SubjectKeyIdentifier(sid.content.to_vec())
+            }
+            _ => return Err(malformed("unknown SignerInfo identifier")),
+        };
+        let digest_algorithm = algorithm(next()?)?;
+
+        let mut field = next()?;
+        let (authenticated_attributes, authenticated_attributes_der) = if field.tag == context(0) {
+            let mut der = field.raw.to_vec();
+            der[0] = SET;
+            let parsed = attributes(field)?;
+            field = next()?;
+            (parsed, Some(der))
+        } else {
+            (Vec::new(), None)
+        };
+        let digest_encryption_algorithm = algorithm(field)?;
+        let encrypted_digest = next()?.expect(OCTET_STRING)?.content.to_vec();
+        let unauthenticated_attributes = match next() {
+            Ok(field) if field.tag == context(1) => attributes(field)?,
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            version,
+            id,
+            digest_algorithm,
+            authenticated_attributes,
+            authenticated_attributes_der,
+            digest_encryption_algorithm,
+            encrypted_digest,
+            unauthenticated_attributes,
+        })
+    }
+
+    pub fn authenticated(&self, oid: &str) -> Option<&Attribute> {
+        self.authenticated_attributes
+            .iter()
+            .find(|attribute| attribute.oid == oid)
+    }
+
+    pub fn unauthenticated<'a>(&'a self, oid: &'a str) -> impl Iterator<Item = &'a Vec<u8>> {
+        self.unauthenticated_attributes
+            .iter()
+            .filter(move |attribute| attribute.oid == oid)
+            .flat_map(|attribute| attribute.values.iter())
+    }
+
+    fn first_value(&self, oid: &str) -> Option<Tlv<'_>> {
+        let value = self.authenticated(oid)?.values.first()?;
+        Tlv::parse(value).ok()
+    }
+
+    // the digest of the signed content, from the messageDigest attribute
+    pub fn message_digest(&self) -> Option<Vec<u8>> {
+        let value = self.first_value(OID_MESSAGE_DIGEST)?;
+        Some(value.expect(OCTET_STRING).ok()?.content.to_vec())
+    }
+
+    pub fn content_type(&self) -> Option<String> {
+        self.first_value(OID_CONTENT_TYPE)?.oid().ok()
+    }
+
+    pub fn signing_time(&self) -> Option<DateTime<Utc>> {
+        self.first_value(OID_SIGNING_TIME)?.time().ok()
+    }
+
+    // programName and moreInfo from SpcSpOpusInfo, both optional
+    pub fn opus_info(&self) -> (Option<String>, Option<String>) {
+        let mut program_name = None;
+        let mut more_info = None;
+        let fields = self
+            .first_value(OID_SPC_SP_OPUS_INFO)
+            .and_then(|opus| opus.children().ok())
+            .unwrap_or_default();
+        for field in fields {
+            let inner = match Tlv::parse(field.content) {
+                Ok(inner) => inner,
+                Err(_) => continue,
+            };
+            let ascii = || Some(String::from_utf8_lossy(inner.content).into_owned());
+            // SpcString is [0] BMPString or [1] IA5String, SpcLink's url is [0] IA5String
+            match (field.tag, inner.tag) {
+                (0xa0, 0x80) => {
+                    program_name = Tlv {
+                        tag: BMP_STRING,
+                        ..inner
+                    }
+                    .string()
+                    .ok()
+                }
+                (0xa0, 0x81) => program_name = ascii(),
+                (0xa1, 0x80) => more_info = ascii(),
+                _ => {}
+            }
+        }
+        (program_name, more_info)
+    }
+
+    // whether cert is the one the identifier names
+    pub fn is_signed_by(&self, cert: &Certificate) -> bool {
+        match &self.id {
+            SignerId::IssuerAndSerial { issuer, serial } => {
+                issuer.raw == cert.issuer.raw && *serial == cert.serial
+            }
+            // subjectKeyIdentifier extension, an OCTET STRING inside the extension value
+            SignerId::SubjectKeyIdentifier(id) => cert
+                .extensions
+                .iter()
+                .filter(|extension| extension.oid == "2.5.29.14")
+                .filter_map(|extension| Tlv::parse(&extension.value).ok())
+                .any(|value| value.content == id.as_slice()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedData {
+    pub version: u32,
+    pub digest_algorithms: Vec<String>,
+    pub content_type: String,
+    // DER encoding of the content inside [0], if there is one
+    pub content: Option<Vec<u8>>,
+    pub certificates: Vec<Certificate>,
+    pub signers: Vec<SignerInfo>,
+}
+
+impl SignedData {
+    // tlv is the ContentInfo wrapping the SignedData
+    pub fn new(tlv: &Tlv) -> Result<Self, ParsingError> {
+        let info = tlv.expect(SEQUENCE)?.children()?;
+        let signed_data = match info.as_slice() {
+            [kind, content] if kind.oid()? == OID_SIGNED_DATA => {
+                Tlv::parse(content.expect(context(0))?.content)?
+            }
+            [kind, ..] => {
+                return Err(ParsingError::Malformed {
+                    reason: format!("ContentInfo type {} isn't signedData", kind.oid()?),
+                })
+            }
+            _ => return Err(malformed("malformed ContentInfo")),
+        };
+
+        let parts = signed_data.expect(SEQUENCE)?.children()?;
+        let mut parts = parts.iter();
+        let mut next = || parts.next().ok_or(malformed("SignedData is truncated"));
+        let version = next()?.small_integer()?;
+        let digest_algorithms = next()?
+            .expect(SET)?
+            .children()?
+            .iter()
+            .map(algorithm)
+            .collect::<Result<Vec<_>, _>>()?;
+        let content_info = next()?.expect(SEQUENCE)?.children()?;
+        let (content_type, content) = match content_info.as_slice() {
+            [kind] => (kind.oid()?, None),
+            [kind, content] => (
+                kind.oid()?,
+                Some(content.expect(context(0))?.content.to_vec()),
+            ),
+            _ => return Err(malformed("malformed SignedData content")),
+        };
+
+        let mut certificates = Vec::new();
+        let mut field = next()?;
+        if field.tag == context(0) {
+            for cert in field.children()? {
+                // attribute certificates and the like are skipped
+                if cert.tag == SEQUENCE {
+                    certificates.push(Certificate::new(&cert)?);
+                }
+            }
+            field = next()?;
+        }
+        if field.tag == context(1) {
+            field = next()?;
+        }
+        let signers = field
+            .expect(SET)?
+            .children()?
+            .iter()
+            .map(SignerInfo::new)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            version,
+            digest_algorithms,
+            content_type,
+            content,
+            certificates,
+            signers,
+        })
+    }
+
+    // the bytes the messageDigest attribute covers: an OCTET STRING's contents, and for
+    // Authenticode's SEQUENCE its contents without the tag and length
+    pub fn signed_content(&self) -> Option<&[u8]> {
+        let content = self.content.as_deref()?;
+        Tlv::parse(content).ok().map(|tlv| tlv.content)
+    }
+
+    pub fn certificate_for(&self, signer: &SignerInfo) -> Option<&Certificate> {
+        self.certificates
+            .iter()
+            .find(|cert| signer.is_signed_by(cert))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TstInfo {
+    pub version: u32,
+    pub policy: String,
+    pub hash_algorithm: String,
+    // digest of the timestamped signature's encryptedDigest
+    pub hashed_message: Vec<u8>,
+    pub serial: Vec<u8>,
+    pub time: DateTime<Utc>,
+}
+
+impl TstInfo {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let parts = Tlv::parse(raw)?.expect(SEQUENCE)?.children()?;
+        match parts.as_slice() {
+            [version, policy, imprint, serial, time, ..] => {
+                let (hash_algorithm, hashed_message) =
+                    match imprint.expect(SEQUENCE)?.children()?.as_slice() {
+                        [hash_algorithm, message] => (
+                            algorithm(hash_algorithm)?,
+                            message.expect(OCTET_STRING)?.content.to_vec(),
+                        ),
+                        _ => return Err(malformed("malformed TSTInfo messageImprint")),
+                    };
+                Ok(Self {
+                    version: version.small_integer()?,
+                    policy: policy.oid()?,
+                    hash_algorithm,
+                    hashed_message,
+                    serial: serial.integer()?.to_vec(),
+                    time: time.expect(GENERALIZED_TIME)?.time()?,
+                })
+            }
+            _ => Err(malformed("TSTInfo is truncated")),
+        }
+    }
+}
+
+// an unauthenticated attribute value that didn't decode, which anyone can add to a signature
+// without breaking it, so it's kept rather than failing the signature
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadAttribute {
+    pub oid: String,
+    pub value: Vec<u8>,
+    pub reason: String,
+}
+
+impl BadAttribute {
+    fn new(oid: &str, value: &[u8], error: ParsingError) -> Self {
+        Self {
+            oid: oid.to_string(),
+            value: value.to_vec(),
+            reason: error.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Timestamp {
+    // legacy Authenticode timestamp, its certificates are in the outer SignedData
+    Countersignature(SignerInfo),
+    Rfc3161 {
+        signed_data: SignedData,
+        info: TstInfo,
+    },
+    Undecodable(BadAttribute),
+}
+
+impl Timestamp {
+    fn countersignature(value: &[u8]) -> Result<Self, ParsingError> {
+        Ok(Timestamp::Countersignature(SignerInfo::new(&Tlv::parse(
+            value,
+        )?)?))
+    }
+
+    fn rfc3161(value: &[u8]) -> Result<Self, ParsingError> {
+        let signed_data = SignedData::new(&Tlv::parse(value)?)?;
+        if signed_data.content_type != OID_TST_INFO {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "timestamp content type {} isn't TSTInfo",
+                    signed_data.content_type
+                ),
+            });
+        }
+        let info = TstInfo::new(
+            signed_data
+                .signed_content()
+                .ok_or(malformed("timestamp has no TSTInfo"))?,
+        )?;
+        Ok(Timestamp::Rfc3161 { signed_data, info })
+    }
+
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Timestamp::Countersignature(signer) => signer.signing_time(),
+            Timestamp::Rfc3161 { info, .. } => Some(info.time),
+            Timestamp::Undecodable(_) => None,
+        }
+    }
+
+    pub fn signer(&self) -> Option<&SignerInfo> {
+        match self {
+            Timestamp::Countersignature(signer) => Some(signer),
+            Timestamp::Rfc3161 { signed_data, .. } => signed_data.signers.first(),
+            Timestamp::Undecodable(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticodeSignature {
+    pub signed_data: SignedData,
+    // type and DER value of SpcIndirectDataContent's data, SpcPeImageData for PE files
+    pub data_type: String,
+    pub data_value: Option<Vec<u8>>,
+    pub digest_algorithm: String,
+    // the image digest the signature vouches for
+    pub digest: Vec<u8>,
+    pub timestamps: Vec<Timestamp>,
+    pub nested: Vec<AuthenticodeSignature>,
+    // nested signatures that didn't decode, or went too deep
+    pub bad_nested: Vec<BadAttribute>,
+}
+
+impl AuthenticodeSignature {
+    // raw holds a DER ContentInfo, anything after it is ignored
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        Self::parse(&Tlv::read(raw, &mut 0)?, 0)
+    }
+
+    fn parse(tlv: &Tlv, depth: usize) -> Result<Self, ParsingError> {
+        let signed_data = SignedData::new(tlv)?;
+        if signed_data.content_type != OID_SPC_INDIRECT_DATA {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "signed content type {} isn't SpcIndirectDataContent",
+                    signed_data.content_type
+                ),
+            });
+        }
+        let signer = match signed_data.signers.as_slice() {
+            [signer] => signer,
+            signers => {
+                return Err(ParsingError::Malformed {
+                    reason: format!("Authenticode signature has {} signers", signers.len()),
+                })
+            }
+        };
+
+        let content = signed_data
+            .content
+            .as_deref()
+            .ok_or(malformed("Authenticode signature has no content"))?;
+        let parts = Tlv::parse(content)?.expect(SEQUENCE)?.children()?;
+        let (data, message_digest) = match parts.as_slice() {
+            [data, message_digest] => (data, message_digest),
+            _ => return Err(malformed("malformed SpcIndirectDataContent")),
+        };
+        let (data_type, data_value) = match data.expect(SEQUENCE)?.children()?.as_slice() {
+            [kind] => (kind.oid()?, None),
+            [kind, value] => (kind.oid()?, Some(value.raw.to_vec())),
+            _ => return Err(malformed("malformed SpcAttributeTypeAndOptionalValue")),
+        };
+        let (digest_algorithm, digest) =
+            match message_digest.expect(SEQUENCE)?.children()?.as_slice() {
+                [digest_algorithm, digest] => (
+                    algorithm(digest_algorithm)?,
+                    digest.expect(OCTET_STRING)?.content.to_vec(),
+                ),
+                _ => return Err(malformed("malformed Authenticode DigestInfo")),
+            };
+
+        // unauthenticated, so one that doesn't decode is kept aside rather than failing the rest
+        let mut timestamps = Vec::new();
+        for value in signer.unauthenticated(OID_COUNTERSIGNATURE) {
+            timestamps.push(Timestamp::countersignature(value).unwrap_or_else(|error| {
+                Timestamp::Undecodable(BadAttribute::new(OID_COUNTERSIGNATURE, value, error))
+            }));
+        }
+        for value in signer.unauthenticated(OID_RFC3161_TIMESTAMP) {
+            timestamps.push(Timestamp::rfc3161(value).unwrap_or_else(|error| {
+                Timestamp::Undecodable(BadAttribute::new(OID_RFC3161_TIMESTAMP, value, error))
+            }));
+        }
+
+        let mut nested = Vec::new();
+        let mut bad_nested = Vec::new();
+        for value in signer.unauthenticated(OID_NESTED_SIGNATURE) {
+            let parsed = match depth == MAX_NESTING {
+                true => Err(malformed("nested signatures go too deep")),
+                false => Tlv::parse(value).and_then(|tlv| Self::parse(&tlv, depth + 1)),
+            };
+            match parsed {
+                Ok(signature) => nested.push(signature),
+                Err(error) => {
+                    bad_nested.push(BadAttribute::new(OID_NESTED_SIGNATURE, value, error))
+                }
+            }
+        }
+
+        Ok(Self {
+            signed_data,
+            data_type,
+            data_value,
+            digest_algorithm,
+            digest,
+            timestamps,
+            nested,
+            bad_nested,
+        })
+    }
+
+    pub fn signer(&self) -> &SignerInfo {
+        &self.signed_data.signers[0]
+    }
+
+    pub fn certificates(&self) -> &[Certificate] {
+        &self.signed_data.certificates
+    }
+
+    // the certificate the signer names
+    pub fn signing_certificate(&self) -> Option<&Certificate> {
+        self.signed_data.certificate_for(self.signer())
+    }
+
+    // the page hashes in SpcPeImageData, if the image was signed with them
+    pub fn page_hashes(&self) -> Result<Option<PageHashes>, ParsingError> {
+        match (self.data_type.as_str(), &self.data_value) {
+            (OID_SPC_PE_IMAGE_DATA, Some(value)) => PageHashes::new(value),
+            _ => Ok(None),
+        }
+    }
+
+    // this signature followed by the nested ones, depth first
+    pub fn all(&self) -> Vec<&AuthenticodeSignature> {
+        let mut all = vec![self];
+        for nested in &self.nested {
+            all.extend(nested.all());
+        }
+        all
+    }
+}
@@ -0,0 +1,162 @@
+use super::pkcs7::{
+    OID_CONTENT_TYPE, OID_MESSAGE_DIGEST, OID_SIGNED_DATA, OID_SPC_INDIRECT_DATA,
+    OID_SPC_PE_IMAGE_DATA, OID_SPC_SP_OPUS_INFO,
+};
+use super::x509::Certificate;
+use super::{
+    WIN_CERTIFICATE_ALIGNMENT, WIN_CERTIFICATE_HEADER_SZ, WIN_CERT_REVISION_2_0,
+    WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+};
+use crate::crypto::rsa::{RsaPrivateKey, RsaPublicKey};
+use crate::crypto::DigestAlgorithm;
+use crate::der::*;
+use crate::prelude::*;
+
+/*
+Authenticode signing with a local RSA key, laid out the way signtool does it:
+
+ContentInfo signedData
+  SignedData version 1
+    digestAlgorithms    { the image digest algorithm }
+    contentInfo         SpcIndirectDataContent {
+                            SpcPeImageData { flags none, file [2] "<<<Obsolete>>>" },
+                            DigestInfo { algorithm, image digest } }
+    certificates        the chain, signer first
+    signerInfos         { one SignerInfo by issuer and serial, authenticated attributes
+                          contentType, SpcSpOpusInfo, SpcStatementType and messageDigest }
+
+No signing time or timestamp is added, so the signature stops checking out when the signer
+certificate expires.
+ */
+const OID_SPC_STATEMENT_TYPE: &str = "1.3.6.1.4.1.311.2.1.11";
+const OID_SPC_INDIVIDUAL_SP_KEY_PURPOSE: &str = "1.3.6.1.4.1.311.2.1.21";
+const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+
+const OBSOLETE: &str = "<<<Obsolete>>>";
+
+fn attribute(oid: &str, value: Vec<u8>) -> Result<Vec<u8>, ParsingError> {
+    Ok(encode_all(
+        SEQUENCE,
+        &[encode_oid(oid)?, encode_set(&[value])],
+    ))
+}
+
+// DER ContentInfo with an Authenticode signature by key over image_digest; chain starts with the
+// certificate for key
+pub fn authenticode_signed_data(
+    algorithm: DigestAlgorithm,
+    image_digest: &[u8],
+    key: &RsaPrivateKey,
+    chain: &[Certificate],
+) -> Result<Vec<u8>, ParsingError> {
+    let signer = chain.first().ok_or(ParsingError::Malformed {
+        reason: "no signing certificate".to_string(),
+    })?;
+    if signer.public_key_algorithm != OID_RSA_ENCRYPTION
+        || RsaPublicKey::new(&signer.public_key)? != key.public_key()
+    {
+        return Err(ParsingError::Malformed {
+            reason: format!("the key isn't the one certified for {}", signer.subject),
+        });
+    }
+
+    let obsolete: Vec<u8> = OBSOLETE
+        .encode_utf16()
+        .flat_map(|unit| unit.to_be_bytes())
+        .collect();
+    let image_data = encode_all(
+        SEQUENCE,
+        &[
+            encode(BIT_STRING, &[0]),
+            encode_all(
+                context(0),
+                &[encode_all(
+                    context(2),
+                    &[encode(context_primitive(0), &obsolete)],
+                )],
+            ),
+        ],
+    );
+    let content = encode_all(
+        SEQUENCE,
+        &[
+            encode_all(SEQUENCE, &[encode_oid(OID_SPC_PE_IMAGE_DATA)?, image_data]),
+            encode_all(
+                SEQUENCE,
+                &[
+                    encode_algorithm(algorithm.oid())?,
+                    encode(OCTET_STRING, image_digest),
+                ],
+            ),
+        ],
+    );
+
+    // messageDigest covers the content without its tag and length
+    let signed_content = Tlv::parse(&content)?.content;
+    let mut attributes = vec![
+        attribute(OID_CONTENT_TYPE, encode_oid(OID_SPC_INDIRECT_DATA)?)?,
+        attribute(OID_SPC_SP_OPUS_INFO, encode(SEQUENCE, &[]))?,
+        attribute(
+            OID_SPC_STATEMENT_TYPE,
+            encode_all(SEQUENCE, &[encode_oid(OID_SPC_INDIVIDUAL_SP_KEY_PURPOSE)?]),
+        )?,
+        attribute(
+            OID_MESSAGE_DIGEST,
+            encode(OCTET_STRING, &algorithm.digest(signed_content)),
+        )?,
+    ];
+    attributes.sort();
+    let signature = key.sign(algorithm, &algorithm.digest(&encode_all(SET, &attributes)))?;
+
+    let signer_info = encode_all(
+        SEQUENCE,
+        &[
+            encode(INTEGER, &[1]),
+            encode_all(
+                SEQUENCE,
+                &[signer.issuer.raw.clone(), encode(INTEGER, &signer.serial)],
+            ),
+            encode_algorithm(algorithm.oid())?,
+            encode_all(context(0), &attributes),
+            encode_algorithm(OID_RSA_ENCRYPTION)?,
+            encode(OCTET_STRING, &signature),
+        ],
+    );
+    let certificates: Vec<Vec<u8>> = chain.iter().map(|cert| cert.raw.clone()).collect();
+    let signed_data = encode_all(
+        SEQUENCE,
+        &[
+            encode(INTEGER, &[1]),
+            encode_set(&[encode_algorithm(algorithm.oid())?]),
+            encode_all(
+                SEQUENCE,
+                &[
+                    encode_oid(OID_SPC_INDIRECT_DATA)?,
+                    encode_all(context(0), &[content]),
+                ],
+            ),
+            encode_all(context(0), &certificates),
+            encode_set(&[signer_info]),
+        ],
+    );
+    Ok(encode_all(
+        SEQUENCE,
+        &[
+            encode_oid(OID_SIGNED_DATA)?,
+            encode_all(context(0), &[signed_data]),
+        ],
+    ))
+}
+
+// WIN_CERTIFICATE for signed_data, zero padded to the alignment with dwLength covering the padding
+pub fn win_certificate_entry(signed_data: &[u8]) -> Vec<u8> {
+    let length =
+        (WIN_CERTIFICATE_HEADER_SZ + signed_data.len()).next_multiple_of(WIN_CERTIFICATE_ALIGNMENT);
+    let mut entry = Vec::with_capacity(length);
+    entry.extend_from_slice(&(length as u32).to_le_bytes());
+    entry.extend_from_slice(&WIN_CERT_REVISION_2_0.to_le_bytes());
+    entry.extend_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+    entry.extend_from_slice(signed_data);
+    entry.resize(length, 0);
+    entry
+}
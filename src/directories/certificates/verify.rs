@@ -0,0 +1,508 @@
+use super::pkcs7::{
+    AuthenticodeSignature, SignerInfo, Timestamp, OID_SPC_INDIRECT_DATA, OID_TST_INFO,
+};
+use super::x509::{
+    Certificate, KEY_USAGE_DIGITAL_SIGNATURE, KEY_USAGE_KEY_CERT_SIGN,
+    OID_AUTHORITY_KEY_IDENTIFIER, OID_BASIC_CONSTRAINTS, OID_EXTENDED_KEY_USAGE, OID_KEY_USAGE,
+    OID_SUBJECT_ALT_NAME, OID_SUBJECT_KEY_IDENTIFIER,
+};
+use crate::crypto::ecdsa::{Curve, EcPublicKey};
+use crate::crypto::rsa::RsaPublicKey;
+use crate::crypto::DigestAlgorithm;
+use crate::der::*;
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+/*
+Offline Authenticode verification, roughly what WinVerifyTrust does minus revocation and policy:
+
+1. the image digest computed with the signature's algorithm equals SpcIndirectDataContent's
+2. the signer's authenticated attributes have contentType SpcIndirectDataContent and a
+   messageDigest of the content, and encryptedDigest is the signer certificate's signature over
+   the attributes (re-tagged as a SET)
+3. the signer certificate chains, certificate signatures checked, to one of the supplied roots.
+   Every issuer below the root has basicConstraints cA (a supplied root may be a v1 certificate
+   without it) and no keyUsage other than one allowing keyCertSign, pathLenConstraints hold, and
+   no certificate has a critical extension that isn't understood here. The signer's keyUsage has
+   to allow digitalSignature and its extKeyUsage code signing, when it has them
+4. every certificate in the chain is valid at the signing time: the time of a timestamp that
+   checks out, or the time the caller passed otherwise
+
+Timestamps vouch for the signer's encryptedDigest. A countersignature is a SignerInfo whose
+messageDigest is the digest of it and whose certificates live in the outer SignedData. An RFC 3161
+timestamp is a SignedData of its own holding a TSTInfo, whose messageImprint is the digest of it.
+Either way the timestamp's signer has to chain to a supplied root too, with timeStamping in place
+of code signing. A timestamp that doesn't check out is only a problem when the signature doesn't
+hold up at the caller's time either, otherwise it's a warning.
+
+Nested signatures are verified the same way, each against the image digest in its own algorithm.
+ */
+
+// longest chain that's followed before giving up on reaching a root
+const MAX_CHAIN_LENGTH: usize = 16;
+
+pub const OID_CODE_SIGNING: &str = "1.3.6.1.5.5.7.3.3";
+pub const OID_TIME_STAMPING: &str = "1.3.6.1.5.5.7.3.8";
+
+// extensions whose criticality is handled, any other marked critical fails the chain
+const KNOWN_EXTENSIONS: [&str; 6] = [
+    OID_SUBJECT_KEY_IDENTIFIER,
+    OID_KEY_USAGE,
+    OID_SUBJECT_ALT_NAME,
+    OID_BASIC_CONSTRAINTS,
+    OID_AUTHORITY_KEY_IDENTIFIER,
+    OID_EXTENDED_KEY_USAGE,
+];
+
+const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+const OID_EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum VerifyProblem {
+    #[error("unsupported algorithm {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("the image digest doesn't match the signed one")]
+    ImageDigestMismatch,
+    #[error("the signed content type isn't SpcIndirectDataContent")]
+    ContentTypeMismatch,
+    #[error("the messageDigest attribute doesn't match the signed content")]
+    MessageDigestMismatch,
+    #[error("the signer has no authenticated attributes")]
+    MissingAuthenticatedAttributes,
+    #[error("the signer's certificate isn't in the signature")]
+    SignerCertificateMissing,
+    #[error("the signer's signature doesn't verify")]
+    BadSignature,
+    #[error("the certificate chain doesn't reach a supplied root")]
+    IncompleteChain,
+    #[error("the certificate chain ends at a root that wasn't supplied")]
+    UntrustedRoot,
+    #[error("the signature on {subject} doesn't verify")]
+    BadCertificateSignature { subject: String },
+    #[error("{subject} issued a certificate without being a CA")]
+    NotCa { subject: String },
+    #[error("{subject} has more intermediates below it than its path length allows")]
+    PathTooLong { subject: String },
+    #[error("{subject} isn't allowed for {usage}")]
+    UsageNotAllowed { subject: String, usage: String },
+    #[error("{subject} has an unknown critical extension {oid}")]
+    UnknownCriticalExtension { subject: String, oid: String },
+    #[error("{subject} isn't valid at the signing time")]
+    CertificateNotValid { subject: String },
+    #[error("bad timestamp: {0}")]
+    BadTimestamp(String),
+    #[error("bad nested signature: {0}")]
+    BadNestedSignature(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub digest_algorithm: Option<DigestAlgorithm>,
+    // the digest computed over the image, empty when the algorithm isn't supported
+    pub image_digest: Vec<u8>,
+    pub signer: Option<Certificate>,
+    // signer certificate first, root last
+    pub chain: Vec<Certificate>,
+    // time of the first timestamp that checked out
+    pub timestamp: Option<DateTime<Utc>>,
+    // the time certificate validity was checked at
+    pub checked_at: DateTime<Utc>,
+    pub problems: Vec<VerifyProblem>,
+    // timestamps that didn't check out, when another did or the signature holds up without them,
+    // and nested signatures that didn't decode
+    pub warnings: Vec<VerifyProblem>,
+    pub nested: Vec<Verdict>,
+}
+
+impl Verdict {
+    // whether this signature verified, nested signatures have verdicts of their own
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ec(EcPublicKey),
+}
+
+impl PublicKey {
+    fn new(cert: &Certificate) -> Result<Self, VerifyProblem> {
+        let unsupported = || VerifyProblem::UnsupportedAlgorithm(cert.public_key_algorithm.clone());
+        match cert.public_key_algorithm.as_str() {
+            OID_RSA_ENCRYPTION => RsaPublicKey::new(&cert.public_key)
+                .map(PublicKey::Rsa)
+                .map_err(|_| unsupported()),
+            OID_EC_PUBLIC_KEY => {
+                let curve = cert
+                    .public_key_parameters
+                    .as_deref()
+                    .and_then(|parameters| Tlv::parse(parameters).ok()?.oid().ok())
+                    .and_then(|oid| Curve::from_oid(&oid))
+                    .ok_or_else(unsupported)?;
+                EcPublicKey::new(curve, &cert.public_key)
+                    .map(PublicKey::Ec)
+                    .map_err(|_| unsupported())
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+// (whether it's ECDSA, digest) for a signature algorithm OID, the digest is None for the bare key
+// algorithms SignerInfo's digestEncryptionAlgorithm usually holds
+fn signature_scheme(oid: &str) -> Option<(bool, Option<DigestAlgorithm>)> {
+    Some(match oid {
+        OID_RSA_ENCRYPTION => (false, None),
+        "1.2.840.113549.1.1.4" => (false, Some(DigestAlgorithm::Md5)),
+        "1.2.840.113549.1.1.5" => (false, Some(DigestAlgorithm::Sha1)),
+        "1.2.840.113549.1.1.11" => (false, Some(DigestAlgorithm::Sha256)),
+        "1.2.840.113549.1.1.12" => (false, Some(DigestAlgorithm::Sha384)),
+        "1.2.840.113549.1.1.13" => (false, Some(DigestAlgorithm::Sha512)),
+        OID_EC_PUBLIC_KEY => (true, None),
+        "1.2.840.10045.4.1" => (true, Some(DigestAlgorithm::Sha1)),
+        "1.2.840.10045.4.3.2" => (true, Some(DigestAlgorithm::Sha256)),
+        "1.2.840.10045.4.3.3" => (true, Some(DigestAlgorithm::Sha384)),
+        "1.2.840.10045.4.3.4" => (true, Some(DigestAlgorithm::Sha512)),
+        _ => return None,
+    })
+}
+
+fn digest_algorithm(oid: &str) -> Result<DigestAlgorithm, VerifyProblem> {
+    DigestAlgorithm::from_oid(oid).ok_or(VerifyProblem::UnsupportedAlgorithm(oid.to_string()))
+}
+
+// whether signature, made with the signature algorithm scheme (digest_oid naming the digest when
+// the scheme doesn't), is cert's signature over data
+fn check_signature(
+    cert: &Certificate,
+    scheme: &str,
+    digest_oid: Option<&str>,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<bool, VerifyProblem> {
+    let (ecdsa, digest) =
+        signature_scheme(scheme).ok_or(VerifyProblem::UnsupportedAlgorithm(scheme.to_string()))?;
+    let digest = match (digest, digest_oid) {
+        (Some(digest), _) => digest,
+        (None, Some(oid)) => digest_algorithm(oid)?,
+        (None, None) => return Err(VerifyProblem::UnsupportedAlgorithm(scheme.to_string())),
+    };
+    let hash = digest.digest(data);
+    Ok(match (PublicKey::new(cert)?, ecdsa) {
+        (PublicKey::Rsa(key), false) => key.verify(digest, &hash, signature),
+        (PublicKey::Ec(key), true) => key.verify(&hash, signature),
+        _ => false,
+    })
+}
+
+// whether issuer's key made subject's certificate signature
+fn signs(issuer: &Certificate, subject: &Certificate) -> bool {
+    subject.issuer.raw == issuer.subject.raw
+        && check_signature(
+            issuer,
+            &subject.signature_algorithm,
+            None,
+            &subject.tbs,
+            &subject.signature,
+        )
+        .unwrap_or(false)
+}
+
+// a SignerInfo's authenticated attributes against content, and its signature over them
+fn check_signer(
+    signer: &SignerInfo,
+    cert: &Certificate,
+    content: &[u8],
+    content_type: Option<&str>,
+) -> Result<(), VerifyProblem> {
+    let attributes = signer
+        .authenticated_attributes_der
+        .as_deref()
+        .ok_or(VerifyProblem::MissingAuthenticatedAttributes)?;
+    if let Some(content_type) = content_type {
+        if signer.content_type().as_deref() != Some(content_type) {
+            return Err(VerifyProblem::ContentTypeMismatch);
+        }
+    }
+    let digest = digest_algorithm(&signer.digest_algorithm)?;
+    if signer.message_digest() != Some(digest.digest(content)) {
+        return Err(VerifyProblem::MessageDigestMismatch);
+    }
+    match check_signature(
+        cert,
+        &signer.digest_encryption_algorithm,
+        Some(&signer.digest_algorithm),
+        attributes,
+        &signer.encrypted_digest,
+    )? {
+        true => Ok(()),
+        false => Err(VerifyProblem::BadSignature),
+    }
+}
+
+// the chain from leaf up to one of roots through certificates, and the problem if it doesn't get
+// there
+fn build_chain(
+    leaf: &Certificate,
+    certificates: &[Certificate],
+    roots: &[Certificate],
+) -> (Vec<Certificate>, Option<VerifyProblem>) {
+    let mut chain = vec![leaf.clone()];
+    loop {
+        let current = chain.last().unwrap();
+        if roots.iter().any(|root| root.raw == current.raw) {
+            return (chain, None);
+        }
+        if chain.len() == MAX_CHAIN_LENGTH {
+            return (chain, Some(VerifyProblem::IncompleteChain));
+        }
+
+        // roots first, so a cross-signed intermediate doesn't lead away from them
+        let candidates: Vec<&Certificate> = roots
+            .iter()
+            .chain(certificates)
+            .filter(|cert| cert.subject.raw == current.issuer.raw)
+            .filter(|cert| !chain.iter().any(|link| link.raw == cert.raw))
+            .collect();
+        match candidates.iter().find(|cert| signs(cert, current)) {
+            Some(issuer) => chain.push((*issuer).clone()),
+            None => {
+                let problem = if !candidates.is_empty() {
+                    VerifyProblem::BadCertificateSignature {
+                        subject: current.subject.to_string(),
+                    }
+                } else if current.is_self_issued() {
+                    VerifyProblem::UntrustedRoot
+                } else {
+                    VerifyProblem::IncompleteChain
+                };
+                return (chain, Some(problem));
+            }
+        }
+    }
+}
+
+// the first constraint chain (leaf first) breaks for a leaf used for purpose, an extKeyUsage OID
+fn check_constraints(
+    chain: &[Certificate],
+    roots: &[Certificate],
+    purpose: &str,
+) -> Option<VerifyProblem> {
+    let is_root = |cert: &Certificate| roots.iter().any(|root| root.raw == cert.raw);
+    let not_allowed = |cert: &Certificate, usage: &str| VerifyProblem::UsageNotAllowed {
+        subject: cert.subject.to_string(),
+        usage: oid_name(usage).unwrap_or(usage).to_string(),
+    };
+
+    // the roots' own extensions are up to whoever supplied them
+    for cert in chain.iter().filter(|cert| !is_root(cert)) {
+        if let Some(extension) = cert
+            .extensions
+            .iter()
+            .find(|extension| extension.critical && !KNOWN_EXTENSIONS.contains(&&*extension.oid))
+        {
+            return Some(VerifyProblem::UnknownCriticalExtension {
+                subject: cert.subject.to_string(),
+                oid: extension.oid.clone(),
+            });
+        }
+    }
+
+    let leaf = chain.first()?;
+    match leaf.key_usage() {
+        Ok(None) => {}
+        Ok(Some(usage)) if usage & KEY_USAGE_DIGITAL_SIGNATURE != 0 => {}
+        _ => return Some(not_allowed(leaf, "digitalSignature")),
+    }
+    match leaf.extended_key_usage() {
+        Ok(None) => {}
+        Ok(Some(purposes)) if purposes.iter().any(|oid| oid == purpose) => {}
+        _ => return Some(not_allowed(leaf, purpose)),
+    }
+
+    // non self-issued intermediates between the leaf and the issuer being checked
+    let mut intermediates = 0;
+    for issuer in &chain[1..] {
+        match issuer.basic_constraints() {
+            Ok(Some(constraints)) if constraints.ca => {
+                if constraints
+                    .path_length
+                    .is_some_and(|length| intermediates > length)
+                {
+                    return Some(VerifyProblem::PathTooLong {
+                        subject: issuer.subject.to_string(),
+                    });
+                }
+            }
+            Ok(None) if is_root(issuer) => {}
+            _ => {
+                return Some(VerifyProblem::NotCa {
+                    subject: issuer.subject.to_string(),
+                })
+            }
+        }
+        match issuer.key_usage() {
+            Ok(None) => {}
+            Ok(Some(usage)) if usage & KEY_USAGE_KEY_CERT_SIGN != 0 => {}
+            _ => return Some(not_allowed(issuer, "keyCertSign")),
+        }
+        if !issuer.is_self_issued() {
+            intermediates += 1;
+        }
+    }
+    None
+}
+
+// the first certificate in chain that isn't valid at time
+fn check_validity(chain: &[Certificate], time: DateTime<Utc>) -> Option<VerifyProblem> {
+    chain
+        .iter()
+        .find(|cert| !cert.is_valid_at(time))
+        .map(|cert| VerifyProblem::CertificateNotValid {
+            subject: cert.subject.to_string(),
+        })
+}
+
+// the time timestamp vouches for, if it's for signature and checks out
+fn check_timestamp(
+    signature: &AuthenticodeSignature,
+    timestamp: &Timestamp,
+    roots: &[Certificate],
+) -> Result<DateTime<Utc>, VerifyProblem> {
+    let bad = |reason: &str| VerifyProblem::BadTimestamp(reason.to_string());
+    let signed = &signature.signer().encrypted_digest;
+    // the signer, the certificates to find it in, and the content it signs
+    let (signer, certificates, content, content_type) = match timestamp {
+        Timestamp::Countersignature(signer) => {
+            (signer, signature.certificates(), signed.as_slice(), None)
+        }
+        Timestamp::Rfc3161 { signed_data, info } => {
+            if digest_algorithm(&info.hash_algorithm)?.digest(signed) != info.hashed_message {
+                return Err(bad("the message imprint isn't the signature's"));
+            }
+            (
+                signed_data.signers.first().ok_or(bad("no signer"))?,
+                signed_data.certificates.as_slice(),
+                signed_data.signed_content().unwrap_or_default(),
+                Some(OID_TST_INFO),
+            )
+        }
+        Timestamp::Undecodable(attribute) => return Err(bad(&attribute.reason)),
+    };
+    let time = timestamp.time().ok_or(bad("no time"))?;
+    let cert = certificates
+        .iter()
+        .find(|cert| signer.is_signed_by(cert))
+        .ok_or(bad("the timestamping certificate is missing"))?;
+
+    let result = check_signer(signer, cert, content, content_type);
+    if let Err(problem) = result {
+        return Err(VerifyProblem::BadTimestamp(problem.to_string()));
+    }
+
+    let (chain, problem) = build_chain(cert, certificates, roots);
+    match problem
+        .or_else(|| check_constraints(&chain, roots, OID_TIME_STAMPING))
+        .or_else(|| check_validity(&chain, time))
+    {
+        Some(problem) => Err(VerifyProblem::BadTimestamp(problem.to_string())),
+        None => Ok(time),
+    }
+}
+
+// signature and its nested signatures against the image, image_digest computing the image digest
+// with a given algorithm; time is when to check certificate validity if no timestamp checks out
+pub fn verify_signature<F>(
+    signature: &AuthenticodeSignature,
+    image_digest: &F,
+    roots: &[Certificate],
+    time: DateTime<Utc>,
+) -> Result<Verdict, ParsingError>
+where
+    F: Fn(DigestAlgorithm) -> Result<Vec<u8>, ParsingError>,
+{
+    let mut verdict = Verdict {
+        digest_algorithm: DigestAlgorithm::from_oid(&signature.digest_algorithm),
+        image_digest: Vec::new(),
+        signer: signature.signing_certificate().cloned(),
+        chain: Vec::new(),
+        timestamp: None,
+        checked_at: time,
+        problems: Vec::new(),
+        warnings: Vec::new(),
+        nested: Vec::new(),
+    };
+
+    match verdict.digest_algorithm {
+        Some(algorithm) => {
+            verdict.image_digest = image_digest(algorithm)?;
+            if verdict.image_digest != signature.digest {
+                verdict.problems.push(VerifyProblem::ImageDigestMismatch);
+            }
+        }
+        None => verdict.problems.push(VerifyProblem::UnsupportedAlgorithm(
+            signature.digest_algorithm.clone(),
+        )),
+    }
+
+    match &verdict.signer {
+        Some(cert) => {
+            let content = signature.signed_data.signed_content().unwrap_or_default();
+            if let Err(problem) = check_signer(
+                signature.signer(),
+                cert,
+                content,
+                Some(OID_SPC_INDIRECT_DATA),
+            ) {
+                verdict.problems.push(problem);
+            }
+
+            let (chain, problem) = build_chain(cert, signature.certificates(), roots);
+            verdict
+                .problems
+                .extend(problem.or_else(|| check_constraints(&chain, roots, OID_CODE_SIGNING)));
+            verdict.chain = chain;
+        }
+        None => verdict
+            .problems
+            .push(VerifyProblem::SignerCertificateMissing),
+    }
+
+    // a timestamp that doesn't check out only matters if there's no other to fall back on, and
+    // the signature doesn't hold up at the caller's time on its own
+    let mut timestamp_problems = Vec::new();
+    for timestamp in &signature.timestamps {
+        match check_timestamp(signature, timestamp, roots) {
+            Ok(time) => {
+                verdict.timestamp = Some(time);
+                break;
+            }
+            Err(problem) => timestamp_problems.push(problem),
+        }
+    }
+    if let Some(time) = verdict.timestamp {
+        verdict.checked_at = time;
+    }
+    verdict
+        .problems
+        .extend(check_validity(&verdict.chain, verdict.checked_at));
+    match verdict.timestamp.is_some() || verdict.problems.is_empty() {
+        true => verdict.warnings = timestamp_problems,
+        false => verdict.problems.extend(timestamp_problems),
+    }
+    verdict.warnings.extend(
+        signature
+            .bad_nested
+            .iter()
+            .map(|attribute| VerifyProblem::BadNestedSignature(attribute.reason.clone())),
+    );
+
+    for nested in &signature.nested {
+        verdict
+            .nested
+            .push(verify_signature(nested, image_digest, roots, time)?);
+    }
+    Ok(verdict)
+}
@@ -0,0 +1,302 @@
+use crate::der::*;
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+
+/*
+Certificate ::= SEQUENCE {
+    tbsCertificate          TBSCertificate,
+    signatureAlgorithm      AlgorithmIdentifier,
+    signatureValue          BIT STRING }
+
+TBSCertificate ::= SEQUENCE {
+    version             [0] EXPLICIT INTEGER DEFAULT v1,
+    serialNumber            INTEGER,
+    signature               AlgorithmIdentifier,
+    issuer                  Name,
+    validity                SEQUENCE { notBefore Time, notAfter Time },
+    subject                 Name,
+    subjectPublicKeyInfo    SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING },
+    issuerUniqueID      [1] IMPLICIT BIT STRING OPTIONAL,
+    subjectUniqueID     [2] IMPLICIT BIT STRING OPTIONAL,
+    extensions          [3] EXPLICIT SEQUENCE OF Extension OPTIONAL }
+
+Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value ANY }
+Extension ::= SEQUENCE { extnID OID, critical BOOLEAN DEFAULT FALSE, extnValue OCTET STRING }
+
+The extensions chain building cares about (RFC 5280 4.2.1):
+BasicConstraints ::= SEQUENCE { cA BOOLEAN DEFAULT FALSE, pathLenConstraint INTEGER OPTIONAL }
+KeyUsage ::= BIT STRING { digitalSignature (0), .., keyCertSign (5), .. }
+ExtKeyUsageSyntax ::= SEQUENCE OF KeyPurposeId (OID)
+ */
+pub const OID_SUBJECT_KEY_IDENTIFIER: &str = "2.5.29.14";
+pub const OID_KEY_USAGE: &str = "2.5.29.15";
+pub const OID_SUBJECT_ALT_NAME: &str = "2.5.29.17";
+pub const OID_BASIC_CONSTRAINTS: &str = "2.5.29.19";
+pub const OID_AUTHORITY_KEY_IDENTIFIER: &str = "2.5.29.35";
+pub const OID_EXTENDED_KEY_USAGE: &str = "2.5.29.37";
+
+// KeyUsage bits as the first two bytes of the BIT STRING, big endian
+pub const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 0x8000;
+pub const KEY_USAGE_KEY_CERT_SIGN: u16 = 0x0400;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Name {
+    // (type OID, value) in the order they're encoded
+    pub attributes: Vec<(String, String)>,
+    // the DER encoding, which is what issuer and subject are matched on
+    pub raw: Vec<u8>,
+}
+
+impl Name {
+    pub fn new(tlv: &Tlv) -> Result<Self, ParsingError> {
+        let mut attributes = Vec::new();
+        for rdn in tlv.expect(SEQUENCE)?.children()? {
+            for attribute in rdn.expect(SET)?.children()? {
+                let parts = attribute.expect(SEQUENCE)?.children()?;
+                let (kind, value) = match parts.as_slice() {
+                    [kind, value, ..] => (kind.oid()?, value),
+                    _ => {
+                        return Err(ParsingError::Malformed {
+                            reason: "name attribute without a value".to_string(),
+                        })
+                    }
+                };
+                // values that aren't strings are kept as hex
+                let value = value
+                    .string()
+                    .unwrap_or_else(|_| crate::crypto::to_hex(value.content));
+                attributes.push((kind, value));
+            }
+        }
+        Ok(Self {
+            attributes,
+            raw: tlv.raw.to_vec(),
+        })
+    }
+
+    pub fn get(&self, oid: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(kind, _)| kind == oid)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn common_name(&self) -> Option<&str> {
+        self.get("2.5.4.3")
+    }
+}
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = self
+            .attributes
+            .iter()
+            .map(|(kind, value)| format!("{}={}", oid_name(kind).unwrap_or(kind), value))
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extension {
+    pub oid: String,
+    pub critical: bool,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BasicConstraints {
+    pub ca: bool,
+    // how many non self-issued intermediates may follow this certificate
+    pub path_length: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Certificate {
+    pub version: u32,
+    pub serial: Vec<u8>,
+    pub issuer: Name,
+    pub subject: Name,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    pub public_key_algorithm: String,
+    // DER parameters of the key algorithm, for EC keys the named curve OID
+    pub public_key_parameters: Option<Vec<u8>>,
+    // subjectPublicKey, for RSA a DER RSAPublicKey
+    pub public_key: Vec<u8>,
+    pub extensions: Vec<Extension>,
+    pub signature_algorithm: String,
+    pub signature: Vec<u8>,
+    // the DER encoded TBSCertificate the signature is over
+    pub tbs: Vec<u8>,
+    pub raw: Vec<u8>,
+}
+
+impl Certificate {
+    pub fn new(tlv: &Tlv) -> Result<Self, ParsingError> {
+        let parts = tlv.expect(SEQUENCE)?.children()?;
+        let (tbs, signature_algorithm, signature) = match parts.as_slice() {
+            [tbs, algorithm, signature] => (tbs, algorithm, signature),
+            _ => {
+                return Err(ParsingError::Malformed {
+                    reason: format!("certificate has {} parts", parts.len()),
+                })
+            }
+        };
+
+        let fields = tbs.expect(SEQUENCE)?.children()?;
+        let mut fields = fields.iter();
+        let mut next = || {
+            fields.next().ok_or(ParsingError::Malformed {
+                reason: "TBSCertificate is truncated".to_string(),
+            })
+        };
+        let mut first = next()?;
+        let version = if first.tag == context(0) {
+            let version = Tlv::parse(first.content)?.small_integer()? + 1;
+            first = next()?;
+            version
+        } else {
+            1
+        };
+        let serial = first.integer()?.to_vec();
+        let _signature = next()?;
+        let issuer = Name::new(next()?)?;
+        let validity = next()?.expect(SEQUENCE)?.children()?;
+        let (not_before, not_after) = match validity.as_slice() {
+            [not_before, not_after] => (not_before.time()?, not_after.time()?),
+            _ => {
+                return Err(ParsingError::Malformed {
+                    reason: "malformed certificate validity".to_string(),
+                })
+            }
+        };
+        let subject = Name::new(next()?)?;
+        let key_info = next()?.expect(SEQUENCE)?.children()?;
+        let (public_key_algorithm, public_key_parameters, public_key) = match key_info.as_slice() {
+            [algorithm_id, key] => (
+                algorithm(algorithm_id)?,
+                algorithm_id
+                    .children()?
+                    .get(1)
+                    .filter(|parameters| parameters.tag != NULL)
+                    .map(|parameters| parameters.raw.to_vec()),
+                key.bit_string()?.to_vec(),
+            ),
+            _ => {
+                return Err(ParsingError::Malformed {
+                    reason: "malformed subject public key info".to_string(),
+                })
+            }
+        };
+
+        let mut extensions = Vec::new();
+        while let Ok(field) = next() {
+            if field.tag != context(3) {
+                continue;
+            }
+            for extension in Tlv::parse(field.content)?.expect(SEQUENCE)?.children()? {
+                let parts = extension.expect(SEQUENCE)?.children()?;
+                let (oid, critical, value) = match parts.as_slice() {
+                    [oid, value] => (oid, false, value),
+                    [oid, critical, value] => (oid, critical.content == [0xff], value),
+                    _ => {
+                        return Err(ParsingError::Malformed {
+                            reason: "malformed certificate extension".to_string(),
+                        })
+                    }
+                };
+                extensions.push(Extension {
+                    oid: oid.oid()?,
+                    critical,
+                    value: value.expect(OCTET_STRING)?.content.to_vec(),
+                });
+            }
+        }
+
+        Ok(Self {
+            version,
+            serial,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            public_key_algorithm,
+            public_key_parameters,
+            public_key,
+            extensions,
+            signature_algorithm: algorithm(signature_algorithm)?,
+            signature: signature.bit_string()?.to_vec(),
+            tbs: tbs.raw.to_vec(),
+            raw: tlv.raw.to_vec(),
+        })
+    }
+
+    pub fn extension(&self, oid: &str) -> Option<&Extension> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.oid == oid)
+    }
+
+    pub fn basic_constraints(&self) -> Result<Option<BasicConstraints>, ParsingError> {
+        let extension = match self.extension(OID_BASIC_CONSTRAINTS) {
+            Some(extension) => extension,
+            None => return Ok(None),
+        };
+        let mut constraints = BasicConstraints {
+            ca: false,
+            path_length: None,
+        };
+        for field in Tlv::parse(&extension.value)?.expect(SEQUENCE)?.children()? {
+            match field.tag {
+                BOOLEAN => constraints.ca = field.content == [0xff],
+                INTEGER => constraints.path_length = Some(field.small_integer()?),
+                _ => {
+                    return Err(ParsingError::Malformed {
+                        reason: "malformed BasicConstraints".to_string(),
+                    })
+                }
+            }
+        }
+        Ok(Some(constraints))
+    }
+
+    // KEY_USAGE_* bits
+    pub fn key_usage(&self) -> Result<Option<u16>, ParsingError> {
+        let extension = match self.extension(OID_KEY_USAGE) {
+            Some(extension) => extension,
+            None => return Ok(None),
+        };
+        let bits = Tlv::parse(&extension.value)?.bit_string()?;
+        let byte = |index: usize| bits.get(index).copied().unwrap_or(0);
+        Ok(Some(u16::from_be_bytes([byte(0), byte(1)])))
+    }
+
+    // the key purpose OIDs
+    pub fn extended_key_usage(&self) -> Result<Option<Vec<String>>, ParsingError> {
+        let extension = match self.extension(OID_EXTENDED_KEY_USAGE) {
+            Some(extension) => extension,
+            None => return Ok(None),
+        };
+        Tlv::parse(&extension.value)?
+            .expect(SEQUENCE)?
+            .children()?
+            .iter()
+            .map(|purpose| purpose.oid())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+    }
+
+    pub fn is_self_issued(&self) -> bool {
+        self.issuer.raw == self.subject.raw
+    }
+
+    pub fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        self.not_before <= time && time <= self.not_after
+    }
+
+    // serial number as it's usually displayed
+    pub fn serial_hex(&self) -> String {
+        crate::crypto::to_hex(&self.serial)
+    }
+}
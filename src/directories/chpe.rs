@@ -0,0 +1,234 @@
+use crate::prelude::*;
+
+/*
+IMAGE_ARM64EC_METADATA (CHPEMetadataPointer in the load config, all fields are RVAs or counts):
++00 DWORD   Version
++04 DWORD   CodeMap
++08 DWORD   CodeMapCount
++0C (12)    DWORD   CodeRangesToEntryPoints
++10 (16)    DWORD   RedirectionMetadata
++14 (20)    DWORD   __os_arm64x_dispatch_call_no_redirect
++18 (24)    DWORD   __os_arm64x_dispatch_ret
++1C (28)    DWORD   __os_arm64x_dispatch_call
++20 (32)    DWORD   __os_arm64x_dispatch_icall
++24 (36)    DWORD   __os_arm64x_dispatch_icall_cfg
++28 (40)    DWORD   AlternateEntryPoint
++2C (44)    DWORD   AuxiliaryIAT
++30 (48)    DWORD   CodeRangesToEntryPointsCount
++34 (52)    DWORD   RedirectionMetadataCount
++38 (56)    DWORD   GetX64InformationFunctionPointer
++3C (60)    DWORD   SetX64InformationFunctionPointer
++40 (64)    DWORD   ExtraRFETable
++44 (68)    DWORD   ExtraRFETableSize
++48 (72)    DWORD   __os_arm64x_dispatch_fptr
++4C (76)    DWORD   AuxiliaryIATCopy
+version 2 adds:
++50 (80)    DWORD   AuxDelayloadIAT
++54 (84)    DWORD   AuxDelayloadIATCopy
++58 (88)    DWORD   ReservedBitField
+
+IMAGE_CHPE_RANGE_ENTRY (CodeMap):
++00 DWORD   StartOffset (bits 0-1 are the code type: 0 = ARM64, 1 = ARM64EC, 2 = x64)
++04 DWORD   Length
+
+IMAGE_ARM64EC_CODE_RANGE_ENTRY_POINT:
++00 DWORD   StartRva
++04 DWORD   EndRva
++08 DWORD   EntryPoint
+
+IMAGE_ARM64EC_REDIRECTION_ENTRY:
++00 DWORD   Source
++04 DWORD   Destination
+
+The auxiliary IAT parallels the regular IAT and holds the addresses ARM64EC code calls through.
+ */
+const RANGE_ENTRY_SZ: usize = 8;
+const ENTRY_POINT_SZ: usize = 12;
+const REDIRECTION_SZ: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CodeKind {
+    Arm64,
+    Arm64Ec,
+    X64,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeRange {
+    pub start: u32,
+    pub length: u32,
+    pub kind: CodeKind,
+}
+
+impl CodeRange {
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.start && rva - self.start < self.length
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntryPointRange {
+    pub start: u32,
+    pub end: u32,
+    pub entry_point: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Redirection {
+    pub source: u32,
+    pub destination: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Arm64EcMetadata {
+    pub version: u32,
+    pub code_map: u32,
+    pub code_map_count: u32,
+    pub code_ranges_to_entry_points: u32,
+    pub redirection_metadata: u32,
+    pub dispatch_call_no_redirect: u32,
+    pub dispatch_ret: u32,
+    pub dispatch_call: u32,
+    pub dispatch_icall: u32,
+    pub dispatch_icall_cfg: u32,
+    pub alternate_entry_point: u32,
+    pub auxiliary_iat: u32,
+    pub code_ranges_to_entry_points_count: u32,
+    pub redirection_metadata_count: u32,
+    pub get_x64_information_function_pointer: u32,
+    pub set_x64_information_function_pointer: u32,
+    pub extra_rfe_table: u32,
+    pub extra_rfe_table_size: u32,
+    pub dispatch_fptr: u32,
+    pub auxiliary_iat_copy: u32,
+    // version 2 and later
+    pub aux_delayload_iat: u32,
+    pub aux_delayload_iat_copy: u32,
+    pub reserved_bit_field: u32,
+}
+
+impl Arm64EcMetadata {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let mut next = || try_read_dword(raw, &mut offset);
+        let mut metadata = Self {
+            version: next()?,
+            code_map: next()?,
+            code_map_count: next()?,
+            code_ranges_to_entry_points: next()?,
+            redirection_metadata: next()?,
+            dispatch_call_no_redirect: next()?,
+            dispatch_ret: next()?,
+            dispatch_call: next()?,
+            dispatch_icall: next()?,
+            dispatch_icall_cfg: next()?,
+            alternate_entry_point: next()?,
+            auxiliary_iat: next()?,
+            code_ranges_to_entry_points_count: next()?,
+            redirection_metadata_count: next()?,
+            get_x64_information_function_pointer: next()?,
+            set_x64_information_function_pointer: next()?,
+            extra_rfe_table: next()?,
+            extra_rfe_table_size: next()?,
+            dispatch_fptr: next()?,
+            auxiliary_iat_copy: next()?,
+            ..Self::default()
+        };
+        if metadata.version >= 2 {
+            metadata.aux_delayload_iat = next()?;
+            metadata.aux_delayload_iat_copy = next()?;
+            metadata.reserved_bit_field = next()?;
+        }
+        Ok(metadata)
+    }
+}
+
+fn table(raw: &[u8], count: u32, entry_size: usize) -> Result<&[u8], ParsingError> {
+    let size = (count as usize)
+        .checked_mul(entry_size)
+        .ok_or(ParsingError::Malformed {
+            reason: format!("CHPE table count {:#x} is too large", count),
+        })?;
+    try_slice(raw, 0, size)
+}
+
+fn dword(raw: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([raw[at], raw[at + 1], raw[at + 2], raw[at + 3]])
+}
+
+// raw starts at the code map
+pub fn code_map(raw: &[u8], count: u32) -> Result<Vec<CodeRange>, ParsingError> {
+    Ok(table(raw, count, RANGE_ENTRY_SZ)?
+        .chunks_exact(RANGE_ENTRY_SZ)
+        .map(|entry| {
+            let start = dword(entry, 0);
+            CodeRange {
+                start: start & !0x3,
+                length: dword(entry, 4),
+                kind: match start & 0x3 {
+                    0 => CodeKind::Arm64,
+                    1 => CodeKind::Arm64Ec,
+                    2 => CodeKind::X64,
+                    _ => CodeKind::Unknown,
+                },
+            }
+        })
+        .collect())
+}
+
+pub fn entry_points(raw: &[u8], count: u32) -> Result<Vec<EntryPointRange>, ParsingError> {
+    Ok(table(raw, count, ENTRY_POINT_SZ)?
+        .chunks_exact(ENTRY_POINT_SZ)
+        .map(|entry| EntryPointRange {
+            start: dword(entry, 0),
+            end: dword(entry, 4),
+            entry_point: dword(entry, 8),
+        })
+        .collect())
+}
+
+pub fn redirections(raw: &[u8], count: u32) -> Result<Vec<Redirection>, ParsingError> {
+    Ok(table(raw, count, REDIRECTION_SZ)?
+        .chunks_exact(REDIRECTION_SZ)
+        .map(|entry| Redirection {
+            source: dword(entry, 0),
+            destination: dword(entry, 4),
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chpe {
+    pub metadata: Arm64EcMetadata,
+    pub code_ranges: Vec<CodeRange>,
+    pub entry_points: Vec<EntryPointRange>,
+    pub redirections: Vec<Redirection>,
+    // VAs, one for each slot of the regular IAT
+    pub auxiliary_iat: Vec<u64>,
+}
+
+impl Chpe {
+    // the ranges holding x64 code, which run under emulation
+    pub fn x64_ranges(&self) -> impl Iterator<Item = &CodeRange> {
+        self.code_ranges
+            .iter()
+            .filter(|range| range.kind == CodeKind::X64)
+    }
+
+    pub fn code_kind(&self, rva: u32) -> Option<CodeKind> {
+        self.code_ranges
+            .iter()
+            .find(|range| range.contains(rva))
+            .map(|range| range.kind)
+    }
+
+    // where a call from x64 code into rva ends up
+    pub fn redirect(&self, rva: u32) -> u32 {
+        self.redirections
+            .iter()
+            .find(|redirection| redirection.source == rva)
+            .map(|redirection| redirection.destination)
+            .unwrap_or(rva)
+    }
+}
@@ -0,0 +1,117 @@
+use crate::prelude::*;
+
+/*
+CV_INFO_PDB70 (RSDS):
++00 DWORD   Signature ("RSDS")
++04 GUID    Guid
++14 (20)    DWORD   Age
++18 (24)    PdbFileName, null terminated UTF-8
+
+CV_INFO_PDB20 (NB10):
++00 DWORD   Signature ("NB10")
++04 DWORD   Offset (0)
++08 DWORD   Signature (timestamp)
++0C (12)    DWORD   Age
++10 (16)    PdbFileName, null terminated ANSI
+
+The symbol server stores a PDB under <name>/<key>/<name>, where the key is the uppercase GUID
+without dashes followed by the age in hex (or the NB10 timestamp as 8 hex digits then the age).
+Binaries themselves are keyed by TimeDateStamp as 8 hex digits followed by SizeOfImage in hex.
+ */
+const RSDS: &[u8; 4] = b"RSDS";
+const NB10: &[u8; 4] = b"NB10";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeView {
+    Pdb70 {
+        guid: Guid,
+        age: u32,
+        path: String,
+    },
+    Pdb20 {
+        offset: u32,
+        timestamp: u32,
+        age: u32,
+        path: String,
+    },
+    // older or unknown formats, e.g. NB09 and NB11 with the symbols embedded
+    Other {
+        signature: [u8; 4],
+        data: Vec<u8>,
+    },
+}
+
+fn read_path(raw: &[u8], offset: usize) -> Result<String, ParsingError> {
+    let rest = try_slice(raw, offset, raw.len().saturating_sub(offset))?;
+    let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+impl CodeView {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut signature = [0; 4];
+        signature.copy_from_slice(try_slice(raw, 0, DWORD_SZ)?);
+        let mut offset = DWORD_SZ;
+
+        match &signature {
+            RSDS => {
+                let guid = Guid::new(raw, &mut offset)?;
+                let age = try_read_dword(raw, &mut offset)?;
+                Ok(Self::Pdb70 {
+                    guid,
+                    age,
+                    path: read_path(raw, offset)?,
+                })
+            }
+            NB10 => {
+                let pdb_offset = try_read_dword(raw, &mut offset)?;
+                let timestamp = try_read_dword(raw, &mut offset)?;
+                let age = try_read_dword(raw, &mut offset)?;
+                Ok(Self::Pdb20 {
+                    offset: pdb_offset,
+                    timestamp,
+                    age,
+                    path: read_path(raw, offset)?,
+                })
+            }
+            _ => Ok(Self::Other {
+                signature,
+                data: raw[offset..].to_vec(),
+            }),
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::Pdb70 { path, .. } | Self::Pdb20 { path, .. } => Some(path),
+            Self::Other { .. } => None,
+        }
+    }
+
+    // file name of the PDB, which the path may give relative to the build machine
+    pub fn pdb_name(&self) -> Option<&str> {
+        self.path()
+            .map(|path| path.rsplit(['\\', '/']).next().unwrap_or(path))
+    }
+
+    pub fn symbol_key(&self) -> Option<String> {
+        match self {
+            Self::Pdb70 { guid, age, .. } => {
+                Some(format!("{}{:X}", guid.to_string().replace('-', ""), age))
+            }
+            Self::Pdb20 { timestamp, age, .. } => Some(format!("{:08X}{:X}", timestamp, age)),
+            Self::Other { .. } => None,
+        }
+    }
+
+    // relative path of the PDB on a symbol server
+    pub fn symbol_path(&self) -> Option<String> {
+        let name = self.pdb_name()?;
+        Some(format!("{}/{}/{}", name, self.symbol_key()?, name))
+    }
+}
+
+// key of the binary itself on a symbol server
+pub fn image_symbol_key(timestamp: u32, size_of_image: u32) -> String {
+    format!("{:08X}{:X}", timestamp, size_of_image)
+}
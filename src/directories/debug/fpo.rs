@@ -0,0 +1,89 @@
+use crate::prelude::*;
+
+/*
+FPO_DATA (x86 frame pointer omission records):
++00 DWORD   ulOffStart (RVA of the function)
++04 DWORD   cbProcSize
++08 DWORD   cdwLocals (in DWORDs)
++0C (12)    WORD    cdwParams (in DWORDs)
++0E (14)    WORD    bits 0-7   cbProlog
+                    bits 8-10  cbRegs (saved registers)
+                    bit  11    fHasSEH
+                    bit  12    fUseBP
+                    bit  13    reserved
+                    bits 14-15 cbFrame (0 = FPO, 1 = TRAP, 2 = TSS, 3 = NONFPO)
+ */
+pub const FPO_DATA_SZ: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameType {
+    Fpo,
+    Trap,
+    Tss,
+    NonFpo,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FpoData {
+    pub start: u32,
+    pub proc_size: u32,
+    // in bytes
+    pub locals_size: u32,
+    // in bytes
+    pub params_size: u32,
+    pub prolog_size: u8,
+    pub saved_registers: u8,
+    pub has_seh: bool,
+    pub uses_bp: bool,
+    pub frame: FrameType,
+}
+
+impl FpoData {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        let start = try_read_dword(raw, offset)?;
+        let proc_size = try_read_dword(raw, offset)?;
+        let locals = try_read_dword(raw, offset)?;
+        let params = try_read_word(raw, offset)?;
+        let bits = try_read_word(raw, offset)?;
+
+        Ok(Self {
+            start,
+            proc_size,
+            locals_size: locals.saturating_mul(4),
+            params_size: params as u32 * 4,
+            prolog_size: (bits & 0xff) as u8,
+            saved_registers: ((bits >> 8) & 0x7) as u8,
+            has_seh: bits & (1 << 11) != 0,
+            uses_bp: bits & (1 << 12) != 0,
+            frame: match bits >> 14 {
+                0 => FrameType::Fpo,
+                1 => FrameType::Trap,
+                2 => FrameType::Tss,
+                _ => FrameType::NonFpo,
+            },
+        })
+    }
+
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.start && rva - self.start < self.proc_size
+    }
+}
+
+pub fn fpo_table(raw: &[u8]) -> Result<Vec<FpoData>, ParsingError> {
+    if !raw.len().is_multiple_of(FPO_DATA_SZ) {
+        return Err(ParsingError::Malformed {
+            reason: format!(
+                "FPO data size {:#x} isn't a multiple of {}",
+                raw.len(),
+                FPO_DATA_SZ
+            ),
+        });
+    }
+
+    let mut records = Vec::with_capacity(raw.len() / FPO_DATA_SZ);
+    let mut offset = 0;
+    while offset < raw.len() {
+        records.push(FpoData::new(raw, &mut offset)?);
+    }
+    Ok(records)
+}
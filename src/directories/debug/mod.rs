@@ -0,0 +1,288 @@
+pub mod codeview;
+pub mod fpo;
+pub mod pogo;
+pub mod portable_pdb;
+
+use crate::prelude::*;
+use codeview::CodeView;
+use fpo::{fpo_table, FpoData};
+use pogo::Pogo;
+use portable_pdb::{EmbeddedPdb, PdbChecksum};
+
+/*
+IMAGE_DEBUG_DIRECTORY:
++00 DWORD   Characteristics (0)
++04 DWORD   TimeDateStamp
++08 WORD    MajorVersion
++0A (10)    WORD    MinorVersion
++0C (12)    DWORD   Type
++10 (16)    DWORD   SizeOfData
++14 (20)    DWORD   AddressOfRawData (RVA, 0 when the data isn't mapped)
++18 (24)    DWORD   PointerToRawData (file offset)
+
+The debug data directory is an array of these, and each one points at data whose layout depends
+on its Type.
+
+VC_FEATURE:
++00 DWORD   Pre-VC++ 11.00 object count
++04 DWORD   C/C++ object count
++08 DWORD   /GS object count
++0C (12)    DWORD   /sdl object count
++10 (16)    DWORD   guardN object count
+
+REPRO (empty when TimeDateStamp already holds the hash):
++00 DWORD   Length
++04         Hash[Length]
+
+EX_DLLCHARACTERISTICS:
++00 DWORD   IMAGE_DLLCHARACTERISTICS_EX_* flags
+
+IMAGE_DEBUG_MISC:
++00 DWORD   DataType (1 = IMAGE_DEBUG_MISC_EXENAME)
++04 DWORD   Length (of the whole record, a multiple of 4)
++08 BYTE    Unicode
++09 BYTE    Reserved[3]
++0C (12)    Data, a null terminated name for EXENAME
+ */
+pub const DEBUG_DIRECTORY_SZ: usize = 28;
+const MISC_HEADER_SZ: usize = 12;
+
+pub const IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT: u32 = 0x01;
+pub const IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT_STRICT_MODE: u32 = 0x02;
+pub const IMAGE_DLLCHARACTERISTICS_EX_CET_SET_CONTEXT_IP_VALIDATION_RELAXED_MODE: u32 = 0x04;
+pub const IMAGE_DLLCHARACTERISTICS_EX_CET_DYNAMIC_APIS_ALLOW_IN_PROC: u32 = 0x08;
+pub const IMAGE_DLLCHARACTERISTICS_EX_CET_RESERVED_1: u32 = 0x10;
+pub const IMAGE_DLLCHARACTERISTICS_EX_CET_RESERVED_2: u32 = 0x20;
+pub const IMAGE_DLLCHARACTERISTICS_EX_FORWARD_CFI_COMPAT: u32 = 0x40;
+pub const IMAGE_DLLCHARACTERISTICS_EX_HOTPATCH_COMPATIBLE: u32 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugType {
+    Unknown,
+    Coff,
+    CodeView,
+    Fpo,
+    Misc,
+    Exception,
+    Fixup,
+    OmapToSrc,
+    OmapFromSrc,
+    Borland,
+    Reserved10,
+    Clsid,
+    VcFeature,
+    Pogo,
+    Iltcg,
+    Mpx,
+    Repro,
+    EmbeddedPortablePdb,
+    Spgo,
+    PdbChecksum,
+    ExDllCharacteristics,
+    Other(u32),
+}
+
+impl DebugType {
+    pub fn new(raw: u32) -> Self {
+        match raw {
+            0 => Self::Unknown,
+            1 => Self::Coff,
+            2 => Self::CodeView,
+            3 => Self::Fpo,
+            4 => Self::Misc,
+            5 => Self::Exception,
+            6 => Self::Fixup,
+            7 => Self::OmapToSrc,
+            8 => Self::OmapFromSrc,
+            9 => Self::Borland,
+            10 => Self::Reserved10,
+            11 => Self::Clsid,
+            12 => Self::VcFeature,
+            13 => Self::Pogo,
+            14 => Self::Iltcg,
+            15 => Self::Mpx,
+            16 => Self::Repro,
+            17 => Self::EmbeddedPortablePdb,
+            18 => Self::Spgo,
+            19 => Self::PdbChecksum,
+            20 => Self::ExDllCharacteristics,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugDirectory {
+    pub characteristics: u32,
+    pub timestamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub kind: DebugType,
+    pub size_of_data: u32,
+    pub address_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+}
+
+impl DebugDirectory {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        Ok(Self {
+            characteristics: try_read_dword(raw, offset)?,
+            timestamp: try_read_dword(raw, offset)?,
+            major_version: try_read_word(raw, offset)?,
+            minor_version: try_read_word(raw, offset)?,
+            kind: DebugType::new(try_read_dword(raw, offset)?),
+            size_of_data: try_read_dword(raw, offset)?,
+            address_of_raw_data: try_read_dword(raw, offset)?,
+            pointer_to_raw_data: try_read_dword(raw, offset)?,
+        })
+    }
+}
+
+pub fn debug_directories(raw: &[u8]) -> Result<Vec<DebugDirectory>, ParsingError> {
+    if !raw.len().is_multiple_of(DEBUG_DIRECTORY_SZ) {
+        return Err(ParsingError::Malformed {
+            reason: format!(
+                "debug directory size {:#x} isn't a multiple of {}",
+                raw.len(),
+                DEBUG_DIRECTORY_SZ
+            ),
+        });
+    }
+
+    let mut directories = Vec::with_capacity(raw.len() / DEBUG_DIRECTORY_SZ);
+    let mut offset = 0;
+    while offset < raw.len() {
+        directories.push(DebugDirectory::new(raw, &mut offset)?);
+    }
+    Ok(directories)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VcFeature {
+    pub pre_vc11: u32,
+    pub c_cpp: u32,
+    pub gs: u32,
+    pub sdl: u32,
+    pub guard_n: u32,
+}
+
+impl VcFeature {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        Ok(Self {
+            pre_vc11: try_read_dword(raw, &mut offset)?,
+            c_cpp: try_read_dword(raw, &mut offset)?,
+            gs: try_read_dword(raw, &mut offset)?,
+            sdl: try_read_dword(raw, &mut offset)?,
+            guard_n: try_read_dword(raw, &mut offset)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExDllCharacteristics(pub u32);
+
+impl ExDllCharacteristics {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        Ok(Self(try_read_dword(raw, &mut 0)?))
+    }
+
+    pub fn cet_compatible(&self) -> bool {
+        self.0 & IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT != 0
+    }
+
+    pub fn cet_strict(&self) -> bool {
+        self.0 & IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT_STRICT_MODE != 0
+    }
+
+    pub fn forward_cfi_compatible(&self) -> bool {
+        self.0 & IMAGE_DLLCHARACTERISTICS_EX_FORWARD_CFI_COMPAT != 0
+    }
+
+    pub fn hotpatch_compatible(&self) -> bool {
+        self.0 & IMAGE_DLLCHARACTERISTICS_EX_HOTPATCH_COMPATIBLE != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Misc {
+    pub data_type: u32,
+    pub unicode: bool,
+    // the executable name for IMAGE_DEBUG_MISC_EXENAME
+    pub name: String,
+}
+
+impl Misc {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let data_type = try_read_dword(raw, &mut offset)?;
+        let length = try_read_dword(raw, &mut offset)? as usize;
+        let unicode = try_read_byte(raw, &mut offset)? != 0;
+        let data = try_slice(raw, MISC_HEADER_SZ, length.saturating_sub(MISC_HEADER_SZ))?;
+
+        let name = match unicode {
+            true => try_read_utf16_nul(data, &mut 0).unwrap_or_default(),
+            false => {
+                let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                String::from_utf8_lossy(&data[..end]).into_owned()
+            }
+        };
+        Ok(Self {
+            data_type,
+            unicode,
+            name,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DebugData {
+    CodeView(CodeView),
+    Pogo(Pogo),
+    VcFeature(VcFeature),
+    // the hash identifying a reproducible build, empty when TimeDateStamp is the hash
+    Repro(Vec<u8>),
+    ExDllCharacteristics(ExDllCharacteristics),
+    Fpo(Vec<FpoData>),
+    Misc(Misc),
+    EmbeddedPortablePdb(EmbeddedPdb),
+    PdbChecksum(PdbChecksum),
+    // types that aren't decoded, or have no data
+    Raw(Vec<u8>),
+}
+
+impl DebugData {
+    // data that doesn't decode as its type says is kept raw rather than failing the directory
+    pub fn new(kind: DebugType, raw: &[u8]) -> Self {
+        Self::decode(kind, raw).unwrap_or_else(|_| Self::Raw(raw.to_vec()))
+    }
+
+    fn decode(kind: DebugType, raw: &[u8]) -> Result<Self, ParsingError> {
+        Ok(match kind {
+            DebugType::CodeView => Self::CodeView(CodeView::new(raw)?),
+            DebugType::Pogo => Self::Pogo(Pogo::new(raw)?),
+            DebugType::VcFeature => Self::VcFeature(VcFeature::new(raw)?),
+            DebugType::Repro => match raw.is_empty() {
+                true => Self::Repro(Vec::new()),
+                false => {
+                    let length = try_read_dword(raw, &mut 0)? as usize;
+                    Self::Repro(try_slice(raw, DWORD_SZ, length)?.to_vec())
+                }
+            },
+            DebugType::ExDllCharacteristics => {
+                Self::ExDllCharacteristics(ExDllCharacteristics::new(raw)?)
+            }
+            DebugType::Fpo => Self::Fpo(fpo_table(raw)?),
+            DebugType::Misc => Self::Misc(Misc::new(raw)?),
+            DebugType::EmbeddedPortablePdb => Self::EmbeddedPortablePdb(EmbeddedPdb::new(raw)?),
+            DebugType::PdbChecksum => Self::PdbChecksum(PdbChecksum::new(raw)?),
+            _ => Self::Raw(raw.to_vec()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugEntry {
+    pub directory: DebugDirectory,
+    pub data: DebugData,
+}
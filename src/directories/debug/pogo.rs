@@ -0,0 +1,68 @@
+use crate::prelude::*;
+
+/*
+POGO debug data:
++00 DWORD   Signature ("LTCG", "PGI", "PGO" or "PGU", stored big endian as text)
++04         Entries, until the end of the data:
+    +00 DWORD   Rva
+    +04 DWORD   Size
+    +08         Name, null terminated and padded to a DWORD boundary
+
+Each entry is a contribution the linker placed in a section, e.g. ".text$mn" or ".rdata$zzzdbg".
+ */
+const POGO_ENTRY_HEADER_SZ: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PogoEntry {
+    pub rva: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pogo {
+    pub signature: u32,
+    pub entries: Vec<PogoEntry>,
+}
+
+impl Pogo {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let signature = try_read_dword(raw, &mut offset)?;
+
+        let mut entries = Vec::new();
+        // the data is often padded out with zeroes after the last entry
+        while offset + POGO_ENTRY_HEADER_SZ < raw.len() {
+            let rva = try_read_dword(raw, &mut offset)?;
+            let size = try_read_dword(raw, &mut offset)?;
+            let rest = &raw[offset..];
+            let len = rest
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or(ParsingError::Malformed {
+                    reason: "POGO entry name isn't terminated".to_string(),
+                })?;
+            if rva == 0 && size == 0 && len == 0 {
+                break;
+            }
+            entries.push(PogoEntry {
+                rva,
+                size,
+                name: String::from_utf8_lossy(&rest[..len]).into_owned(),
+            });
+            offset = (offset + len + 1 + 3) & !3;
+        }
+
+        Ok(Self { signature, entries })
+    }
+
+    // the signature as text, e.g. "PGU" for a PGO instrumented build that's been updated
+    pub fn kind(&self) -> String {
+        self.signature
+            .to_be_bytes()
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| *b as char)
+            .collect()
+    }
+}
@@ -0,0 +1,176 @@
+use crate::crypto::sha2::{sha256, sha384, sha512};
+use crate::inflate::inflate;
+use crate::prelude::*;
+
+/*
+EmbeddedPortablePdb debug data:
++00 DWORD   Signature ("MPDB")
++04 DWORD   UncompressedSize
++08         raw DEFLATE compressed portable PDB
+
+PdbChecksum debug data:
++00         AlgorithmName, null terminated UTF-8 ("SHA256", "SHA384" or "SHA512")
+            Checksum, the rest of the data
+
+Portable PDB metadata root:
++00 DWORD   Signature ("BSJB")
++04 WORD    MajorVersion
++06 WORD    MinorVersion
++08 DWORD   Reserved
++0C (12)    DWORD   Length (of the version string, a multiple of 4)
++10 (16)    Version[Length]
+            WORD    Flags
+            WORD    Streams
+            Stream headers:
+    +00 DWORD   Offset (from the metadata root)
+    +04 DWORD   Size
+    +08         Name, null terminated and padded to a DWORD boundary
+
+The #Pdb stream starts with the 20 byte PDB id, a GUID and a timestamp matching the CodeView
+record. The checksum is taken over the whole PDB with the id zeroed.
+ */
+const MPDB: u32 = 0x4244_504d;
+const BSJB: u32 = 0x424a_5342;
+const PDB_ID_SZ: usize = 20;
+// DEFLATE can't do better than about 1032:1, anything claiming more isn't inflated at all
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedPdb {
+    pub uncompressed_size: u32,
+    pub compressed: Vec<u8>,
+}
+
+impl EmbeddedPdb {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        if try_read_dword(raw, &mut offset)? != MPDB {
+            return Err(ParsingError::InvalidMagic {
+                header: "MPDB".to_string(),
+                offset: 0,
+            });
+        }
+        let uncompressed_size = try_read_dword(raw, &mut offset)?;
+
+        Ok(Self {
+            uncompressed_size,
+            compressed: raw[offset..].to_vec(),
+        })
+    }
+
+    pub fn decompress(&self) -> Result<Vec<u8>, ParsingError> {
+        let limit = self.compressed.len().saturating_mul(MAX_DEFLATE_RATIO);
+        if self.uncompressed_size as usize > limit {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "embedded PDB claims {:#x} bytes from {:#x} compressed",
+                    self.uncompressed_size,
+                    self.compressed.len()
+                ),
+            });
+        }
+        let pdb = inflate(&self.compressed, self.uncompressed_size as usize)?;
+        if pdb.len() != self.uncompressed_size as usize {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "embedded PDB inflated to {:#x} bytes instead of {:#x}",
+                    pdb.len(),
+                    self.uncompressed_size
+                ),
+            });
+        }
+        Ok(pdb)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdbChecksum {
+    pub algorithm: String,
+    pub checksum: Vec<u8>,
+}
+
+impl PdbChecksum {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let end = raw
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or(ParsingError::Malformed {
+                reason: "PDB checksum algorithm name isn't terminated".to_string(),
+            })?;
+
+        Ok(Self {
+            algorithm: String::from_utf8_lossy(&raw[..end]).into_owned(),
+            checksum: raw[end + 1..].to_vec(),
+        })
+    }
+
+    pub fn compute(&self, pdb: &[u8]) -> Result<Vec<u8>, ParsingError> {
+        let offset = pdb_id_offset(pdb)?;
+        let mut zeroed = pdb.to_vec();
+        zeroed[offset..offset + PDB_ID_SZ].fill(0);
+
+        match self.algorithm.as_str() {
+            "SHA256" => Ok(sha256(&zeroed).to_vec()),
+            "SHA384" => Ok(sha384(&zeroed)),
+            "SHA512" => Ok(sha512(&zeroed)),
+            other => Err(ParsingError::Malformed {
+                reason: format!("unsupported PDB checksum algorithm {}", other),
+            }),
+        }
+    }
+
+    pub fn verify(&self, pdb: &[u8]) -> Result<bool, ParsingError> {
+        Ok(self.compute(pdb)? == self.checksum)
+    }
+}
+
+// offset of the PDB id, at the start of the #Pdb stream
+pub fn pdb_id_offset(pdb: &[u8]) -> Result<usize, ParsingError> {
+    let mut offset = 0;
+    if try_read_dword(pdb, &mut offset)? != BSJB {
+        return Err(ParsingError::InvalidMagic {
+            header: "portable PDB metadata".to_string(),
+            offset: 0,
+        });
+    }
+    offset += 2 * WORD_SZ + DWORD_SZ;
+    let version_length = try_read_dword(pdb, &mut offset)? as usize;
+    offset = offset
+        .checked_add(version_length)
+        .ok_or(ParsingError::PointerAccessError { byte: offset })?;
+    let _flags = try_read_word(pdb, &mut offset)?;
+    let streams = try_read_word(pdb, &mut offset)?;
+
+    for _ in 0..streams {
+        let stream_offset = try_read_dword(pdb, &mut offset)? as usize;
+        let size = try_read_dword(pdb, &mut offset)? as usize;
+        let rest = try_slice(pdb, offset, pdb.len().saturating_sub(offset))?;
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or(ParsingError::Malformed {
+                reason: "metadata stream name isn't terminated".to_string(),
+            })?;
+        if &rest[..len] == b"#Pdb" {
+            if size < PDB_ID_SZ {
+                return Err(ParsingError::Malformed {
+                    reason: "#Pdb stream is too small for the PDB id".to_string(),
+                });
+            }
+            try_slice(pdb, stream_offset, PDB_ID_SZ)?;
+            return Ok(stream_offset);
+        }
+        offset = (offset + len + 1 + 3) & !3;
+    }
+
+    Err(ParsingError::Malformed {
+        reason: "portable PDB has no #Pdb stream".to_string(),
+    })
+}
+
+// the GUID and timestamp identifying the PDB
+pub fn pdb_id(pdb: &[u8]) -> Result<(Guid, u32), ParsingError> {
+    let mut offset = pdb_id_offset(pdb)?;
+    let guid = Guid::new(pdb, &mut offset)?;
+    Ok((guid, try_read_dword(pdb, &mut offset)?))
+}
@@ -0,0 +1,589 @@
+use crate::directories::relocations::BaseRelocation;
+use crate::prelude::*;
+
+/*
+IMAGE_DYNAMIC_RELOCATION_TABLE (DVRT, located by the load config):
++00 DWORD   Version (1 or 2)
++04 DWORD   Size (of the relocations that follow)
+
+Version 1 relocations, IMAGE_DYNAMIC_RELOCATION32 / IMAGE_DYNAMIC_RELOCATION64:
++00         Symbol (DWORD / ULONGLONG)
++04 / +08   DWORD   BaseRelocSize
++08 / +0C   fixup info, BaseRelocSize bytes in all
+
+Version 2 relocations, IMAGE_DYNAMIC_RELOCATION32_V2 / IMAGE_DYNAMIC_RELOCATION64_V2:
++00         DWORD   HeaderSize (of this header, including the symbol specific part)
++04         DWORD   FixupInfoSize
++08         Symbol (DWORD / ULONGLONG)
++0C / +10   DWORD   SymbolGroup
++10 / +14   DWORD   Flags
++14 / +18   symbol specific header, up to HeaderSize
+            fixup info, FixupInfoSize bytes
+
+The symbol says what the fixup info describes:
+1   GUARD_RF_PROLOGUE
+2   GUARD_RF_EPILOGUE
+3   GUARD_IMPORT_CONTROL_TRANSFER       DWORD entries
+4   GUARD_INDIR_CONTROL_TRANSFER        WORD entries
+5   GUARD_SWITCHTABLE_BRANCH            WORD entries
+6   ARM64X                              variable length entries
+7   FUNCTION_OVERRIDE
+8   ARM64_KERNEL_IMPORT_CALL_TRANSFER   DWORD entries
+
+Apart from function overrides the fixup info is made of blocks with the same PageRVA and
+BlockSize header as base relocations. Blocks of WORD entries are padded to a DWORD boundary with a
+zero WORD.
+
+Import control transfer entries:
+bits 0-11   PageRelativeOffset
+bit 12      IndirectCall
+bits 13-31  IATIndex
+
+Indirect control transfer entries:
+bits 0-11   PageRelativeOffset
+bit 12      IndirectCall
+bit 13      RexWPrefix
+bit 14      CfgCheck
+
+Switch table branch entries:
+bits 0-11   PageRelativeOffset
+bits 12-15  RegisterNumber
+
+ARM64 kernel import call transfer entries:
+bits 0-9    PageRelativeOffset (in instructions, so 4 bytes each)
+bit 10      IndirectCall
+bits 11-15  RegisterIndex
+bit 16      ImportType
+bits 17-31  IATIndex
+
+ARM64X entries start with a WORD:
+bits 0-11   Offset (from PageRVA)
+bits 12-13  Type (0 = zero fill, 1 = assign value, 2 = add delta)
+bits 14-15  Size (1 << n bytes) for zero fill and value, for delta bit 14 negates the delta and
+            bit 15 scales it by 8 rather than 4
+Values follow the WORD, and deltas are a further WORD.
+
+Function override fixup info:
++00 DWORD   FuncOverrideSize
++04         IMAGE_FUNCTION_OVERRIDE_DYNAMIC_RELOCATION entries, FuncOverrideSize bytes in all:
+            +00 DWORD   OriginalRva
+            +04 DWORD   BDDOffset (into the BDD infos)
+            +08 DWORD   RvaSize
+            +0C DWORD   BaseRelocSize
+            +10 DWORD   Rvas[RvaSize / 4]
+                        base relocation blocks, BaseRelocSize bytes in all
+            IMAGE_BDD_INFO entries for the rest:
+            +00 DWORD   Version
+            +04 DWORD   BDDSize
+            +08         IMAGE_BDD_DYNAMIC_RELOCATION nodes (WORD Left, WORD Right, DWORD Value)
+ */
+const DVRT_HEADER_SZ: usize = 8;
+const BLOCK_HEADER_SZ: usize = 8;
+const BDD_HEADER_SZ: usize = 8;
+const BDD_NODE_SZ: usize = 8;
+
+pub const IMAGE_DYNAMIC_RELOCATION_GUARD_RF_PROLOGUE: u64 = 1;
+pub const IMAGE_DYNAMIC_RELOCATION_GUARD_RF_EPILOGUE: u64 = 2;
+pub const IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER: u64 = 3;
+pub const IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER: u64 = 4;
+pub const IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH: u64 = 5;
+pub const IMAGE_DYNAMIC_RELOCATION_ARM64X: u64 = 6;
+pub const IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE: u64 = 7;
+pub const IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER: u64 = 8;
+
+// one (PageRVA, entries) pair for each block
+fn page_blocks(raw: &[u8]) -> Result<Vec<(u32, &[u8])>, ParsingError> {
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while offset < raw.len() {
+        let page_rva = try_read_dword(raw, &mut offset)?;
+        let block_size = try_read_dword(raw, &mut offset)? as usize;
+        if block_size < BLOCK_HEADER_SZ {
+            return Err(ParsingError::Malformed {
+                reason: format!("dynamic relocation block has size {}", block_size),
+            });
+        }
+        let block = try_slice(raw, offset, block_size - BLOCK_HEADER_SZ)?;
+        offset += block.len();
+        blocks.push((page_rva, block));
+    }
+    Ok(blocks)
+}
+
+// WORD entries without the padding at the end of the block
+fn word_entries(block: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    let count = block.len() / WORD_SZ;
+    block
+        .chunks_exact(WORD_SZ)
+        .enumerate()
+        .filter(move |(index, entry)| !(index + 1 == count && *entry == [0, 0]))
+        .map(|(_, entry)| u16::from_le_bytes([entry[0], entry[1]]))
+}
+
+fn dword_entries(block: &[u8]) -> impl Iterator<Item = u32> + '_ {
+    block
+        .chunks_exact(DWORD_SZ)
+        .map(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]))
+}
+
+// a call or jump through the IAT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportControlTransfer {
+    pub rva: u32,
+    pub indirect_call: bool,
+    pub iat_index: u32,
+}
+
+pub fn import_control_transfers(raw: &[u8]) -> Result<Vec<ImportControlTransfer>, ParsingError> {
+    let mut transfers = Vec::new();
+    for (page_rva, block) in page_blocks(raw)? {
+        transfers.extend(dword_entries(block).map(|entry| ImportControlTransfer {
+            rva: page_rva.wrapping_add(entry & 0xfff),
+            indirect_call: entry & 0x1000 != 0,
+            iat_index: entry >> 13,
+        }));
+    }
+    Ok(transfers)
+}
+
+// an indirect call or jump through a register or memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndirectControlTransfer {
+    pub rva: u32,
+    pub indirect_call: bool,
+    pub rex_w_prefix: bool,
+    pub cfg_check: bool,
+}
+
+pub fn indirect_control_transfers(
+    raw: &[u8],
+) -> Result<Vec<IndirectControlTransfer>, ParsingError> {
+    let mut transfers = Vec::new();
+    for (page_rva, block) in page_blocks(raw)? {
+        transfers.extend(word_entries(block).map(|entry| IndirectControlTransfer {
+            rva: page_rva.wrapping_add((entry & 0xfff) as u32),
+            indirect_call: entry & 0x1000 != 0,
+            rex_w_prefix: entry & 0x2000 != 0,
+            cfg_check: entry & 0x4000 != 0,
+        }));
+    }
+    Ok(transfers)
+}
+
+// a jump through a switch table, register is the one holding the target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwitchTableBranch {
+    pub rva: u32,
+    pub register: u8,
+}
+
+pub fn switch_table_branches(raw: &[u8]) -> Result<Vec<SwitchTableBranch>, ParsingError> {
+    let mut branches = Vec::new();
+    for (page_rva, block) in page_blocks(raw)? {
+        branches.extend(word_entries(block).map(|entry| SwitchTableBranch {
+            rva: page_rva.wrapping_add((entry & 0xfff) as u32),
+            register: (entry >> 12) as u8,
+        }));
+    }
+    Ok(branches)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arm64ImportCallTransfer {
+    pub rva: u32,
+    pub indirect_call: bool,
+    pub register: u8,
+    pub import_type: u8,
+    pub iat_index: u16,
+}
+
+pub fn arm64_import_call_transfers(
+    raw: &[u8],
+) -> Result<Vec<Arm64ImportCallTransfer>, ParsingError> {
+    let mut transfers = Vec::new();
+    for (page_rva, block) in page_blocks(raw)? {
+        transfers.extend(dword_entries(block).map(|entry| Arm64ImportCallTransfer {
+            rva: page_rva.wrapping_add((entry & 0x3ff) * 4),
+            indirect_call: entry & 0x400 != 0,
+            register: ((entry >> 11) & 0x1f) as u8,
+            import_type: ((entry >> 16) & 0x1) as u8,
+            iat_index: (entry >> 17) as u16,
+        }));
+    }
+    Ok(transfers)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arm64XFixupKind {
+    ZeroFill { size: usize },
+    Value { size: usize, value: u64 },
+    // added to the DWORD at the target
+    Delta { delta: i64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arm64XFixup {
+    pub rva: u32,
+    pub kind: Arm64XFixupKind,
+}
+
+impl Arm64XFixup {
+    // applies the fixup to bytes holding the image at offset at
+    pub fn apply(&self, bytes: &mut [u8], at: usize) -> Result<(), ParsingError> {
+        let size = match self.kind {
+            Arm64XFixupKind::ZeroFill { size } | Arm64XFixupKind::Value { size, .. } => size,
+            Arm64XFixupKind::Delta { .. } => DWORD_SZ,
+        };
+        let target = at
+            .checked_add(size)
+            .and_then(|end| bytes.get_mut(at..end))
+            .ok_or(ParsingError::PointerAccessError { byte: at })?;
+        match self.kind {
+            Arm64XFixupKind::ZeroFill { .. } => target.fill(0),
+            Arm64XFixupKind::Value { value, .. } => {
+                target.copy_from_slice(&value.to_le_bytes()[..size])
+            }
+            Arm64XFixupKind::Delta { delta } => {
+                let value = u32::from_le_bytes([target[0], target[1], target[2], target[3]]);
+                target.copy_from_slice(&(value as i64).wrapping_add(delta).to_le_bytes()[..4]);
+            }
+        }
+        Ok(())
+    }
+}
+
+// raw covers all of the symbol's blocks
+pub fn arm64x_fixups(raw: &[u8]) -> Result<Vec<Arm64XFixup>, ParsingError> {
+    let mut fixups = Vec::new();
+    for (page_rva, block) in page_blocks(raw)? {
+        let mut at = 0;
+        while at < block.len() {
+            let header = try_read_word(block, &mut at)?;
+            // padding at the end of the block
+            if header == 0 && at == block.len() {
+                break;
+            }
+            let size = 1usize << (header >> 14);
+            let kind = match (header >> 12) & 0x3 {
+                0 => Arm64XFixupKind::ZeroFill { size },
+                1 => {
+                    let bytes = try_slice(block, at, size)?;
+                    at += size;
+                    let mut value = [0; 8];
+                    value[..size].copy_from_slice(bytes);
+                    Arm64XFixupKind::Value {
+                        size,
+                        value: u64::from_le_bytes(value),
+                    }
+                }
+                2 => {
+                    let scale = if header & 0x8000 != 0 { 8 } else { 4 };
+                    let delta = try_read_word(block, &mut at)? as i64 * scale;
+                    Arm64XFixupKind::Delta {
+                        delta: if header & 0x4000 != 0 { -delta } else { delta },
+                    }
+                }
+                _ => {
+                    return Err(ParsingError::Malformed {
+                        reason: format!("ARM64X fixup {:#06x} has reserved type 3", header),
+                    })
+                }
+            };
+            fixups.push(Arm64XFixup {
+                rva: page_rva.wrapping_add((header & 0xfff) as u32),
+                kind,
+            });
+        }
+    }
+    Ok(fixups)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionOverride {
+    pub original_rva: u32,
+    pub bdd_offset: u32,
+    // call sites that get pointed at the replacement
+    pub rvas: Vec<u32>,
+    pub relocations: Vec<BaseRelocation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BddNode {
+    pub left: u16,
+    pub right: u16,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BddInfo {
+    // from the start of the BDD infos, what BDDOffset refers to
+    pub offset: u32,
+    pub version: u32,
+    pub nodes: Vec<BddNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionOverrides {
+    pub overrides: Vec<FunctionOverride>,
+    pub bdd_infos: Vec<BddInfo>,
+}
+
+impl FunctionOverrides {
+    pub fn new(raw: &[u8], machine: u16) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let size = try_read_dword(raw, &mut offset)? as usize;
+        let entries = try_slice(raw, offset, size)?;
+        let bdd = &raw[offset + size..];
+
+        let mut overrides = Vec::new();
+        let mut offset = 0;
+        while offset < entries.len() {
+            let original_rva = try_read_dword(entries, &mut offset)?;
+            let bdd_offset = try_read_dword(entries, &mut offset)?;
+            let rva_size = try_read_dword(entries, &mut offset)? as usize;
+            let reloc_size = try_read_dword(entries, &mut offset)? as usize;
+            let rvas = dword_entries(try_slice(entries, offset, rva_size)?).collect();
+            offset += rva_size;
+            let relocations =
+                BaseRelocationTable::new(try_slice(entries, offset, reloc_size)?, machine)?
+                    .fixups()
+                    .cloned()
+                    .collect();
+            offset += reloc_size;
+            overrides.push(FunctionOverride {
+                original_rva,
+                bdd_offset,
+                rvas,
+                relocations,
+            });
+        }
+
+        let mut bdd_infos = Vec::new();
+        let mut offset = 0;
+        while offset + BDD_HEADER_SZ <= bdd.len() {
+            let start = offset as u32;
+            let version = try_read_dword(bdd, &mut offset)?;
+            let bdd_size = try_read_dword(bdd, &mut offset)? as usize;
+            let nodes = try_slice(bdd, offset, bdd_size)?
+                .chunks_exact(BDD_NODE_SZ)
+                .map(|node| BddNode {
+                    left: u16::from_le_bytes([node[0], node[1]]),
+                    right: u16::from_le_bytes([node[2], node[3]]),
+                    value: u32::from_le_bytes([node[4], node[5], node[6], node[7]]),
+                })
+                .collect();
+            offset += bdd_size;
+            bdd_infos.push(BddInfo {
+                offset: start,
+                version,
+                nodes,
+            });
+        }
+
+        Ok(Self {
+            overrides,
+            bdd_infos,
+        })
+    }
+
+    // the BDD deciding whether an override applies
+    pub fn bdd(&self, function: &FunctionOverride) -> Option<&BddInfo> {
+        self.bdd_infos
+            .iter()
+            .find(|info| info.offset == function.bdd_offset)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynamicRelocationData {
+    ImportControlTransfer(Vec<ImportControlTransfer>),
+    IndirectControlTransfer(Vec<IndirectControlTransfer>),
+    SwitchTableBranch(Vec<SwitchTableBranch>),
+    Arm64X(Vec<Arm64XFixup>),
+    FunctionOverride(FunctionOverrides),
+    Arm64ImportCallTransfer(Vec<Arm64ImportCallTransfer>),
+    // RF prologue and epilogue records and symbols that aren't known
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicRelocation {
+    pub symbol: u64,
+    // version 2 only, 0 otherwise
+    pub symbol_group: u32,
+    pub flags: u32,
+    // the symbol specific part of a version 2 header
+    pub header: Vec<u8>,
+    pub data: DynamicRelocationData,
+}
+
+impl DynamicRelocation {
+    fn new(symbol: u64, fixups: &[u8], machine: u16) -> Result<Self, ParsingError> {
+        let data = match symbol {
+            IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER => {
+                DynamicRelocationData::ImportControlTransfer(import_control_transfers(fixups)?)
+            }
+            IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER => {
+                DynamicRelocationData::IndirectControlTransfer(indirect_control_transfers(fixups)?)
+            }
+            IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH => {
+                DynamicRelocationData::SwitchTableBranch(switch_table_branches(fixups)?)
+            }
+            IMAGE_DYNAMIC_RELOCATION_ARM64X => {
+                DynamicRelocationData::Arm64X(arm64x_fixups(fixups)?)
+            }
+            IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE => {
+                DynamicRelocationData::FunctionOverride(FunctionOverrides::new(fixups, machine)?)
+            }
+            IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER => {
+                DynamicRelocationData::Arm64ImportCallTransfer(arm64_import_call_transfers(fixups)?)
+            }
+            _ => DynamicRelocationData::Raw(fixups.to_vec()),
+        };
+        Ok(Self {
+            symbol,
+            symbol_group: 0,
+            flags: 0,
+            header: Vec::new(),
+            data,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.symbol {
+            IMAGE_DYNAMIC_RELOCATION_GUARD_RF_PROLOGUE => "GUARD_RF_PROLOGUE",
+            IMAGE_DYNAMIC_RELOCATION_GUARD_RF_EPILOGUE => "GUARD_RF_EPILOGUE",
+            IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER => {
+                "GUARD_IMPORT_CONTROL_TRANSFER"
+            }
+            IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER => "GUARD_INDIR_CONTROL_TRANSFER",
+            IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH => "GUARD_SWITCHTABLE_BRANCH",
+            IMAGE_DYNAMIC_RELOCATION_ARM64X => "ARM64X",
+            IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE => "FUNCTION_OVERRIDE",
+            IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER => {
+                "ARM64_KERNEL_IMPORT_CALL_TRANSFER"
+            }
+            _ => "UNKNOWN",
+        }
+    }
+
+    // RVAs this record may patch
+    pub fn targets(&self) -> Vec<u32> {
+        match &self.data {
+            DynamicRelocationData::ImportControlTransfer(entries) => {
+                entries.iter().map(|entry| entry.rva).collect()
+            }
+            DynamicRelocationData::IndirectControlTransfer(entries) => {
+                entries.iter().map(|entry| entry.rva).collect()
+            }
+            DynamicRelocationData::SwitchTableBranch(entries) => {
+                entries.iter().map(|entry| entry.rva).collect()
+            }
+            DynamicRelocationData::Arm64X(entries) => {
+                entries.iter().map(|entry| entry.rva).collect()
+            }
+            DynamicRelocationData::Arm64ImportCallTransfer(entries) => {
+                entries.iter().map(|entry| entry.rva).collect()
+            }
+            DynamicRelocationData::FunctionOverride(overrides) => overrides
+                .overrides
+                .iter()
+                .flat_map(|function| {
+                    function.rvas.iter().copied().chain(
+                        function
+                            .relocations
+                            .iter()
+                            .map(|relocation| relocation.target_rva),
+                    )
+                })
+                .collect(),
+            DynamicRelocationData::Raw(_) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicRelocationTable {
+    pub version: u32,
+    pub relocations: Vec<DynamicRelocation>,
+}
+
+impl DynamicRelocationTable {
+    // raw starts at the table header
+    pub fn new(raw: &[u8], magic: &PeFormat, machine: u16) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let version = try_read_dword(raw, &mut offset)?;
+        let size = try_read_dword(raw, &mut offset)? as usize;
+        if version != 1 && version != 2 {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "dynamic relocation table version {} isn't supported",
+                    version
+                ),
+            });
+        }
+        let raw = try_slice(raw, DVRT_HEADER_SZ, size)?;
+
+        let mut relocations = Vec::new();
+        let mut offset = 0;
+        while offset < raw.len() {
+            let relocation = if version == 1 {
+                let symbol = ArchDependentSized::try_new(raw, &mut offset, magic)?.value();
+                let fixups_size = try_read_dword(raw, &mut offset)? as usize;
+                let fixups = try_slice(raw, offset, fixups_size)?;
+                offset += fixups_size;
+                DynamicRelocation::new(symbol, fixups, machine)?
+            } else {
+                let start = offset;
+                let header_size = try_read_dword(raw, &mut offset)? as usize;
+                let fixups_size = try_read_dword(raw, &mut offset)? as usize;
+                let symbol = ArchDependentSized::try_new(raw, &mut offset, magic)?.value();
+                let symbol_group = try_read_dword(raw, &mut offset)?;
+                let flags = try_read_dword(raw, &mut offset)?;
+                let header = header_size
+                    .checked_sub(offset - start)
+                    .ok_or(ParsingError::Malformed {
+                        reason: format!("dynamic relocation header has size {}", header_size),
+                    })
+                    .and_then(|len| try_slice(raw, offset, len))?;
+                offset += header.len();
+                let fixups = try_slice(raw, offset, fixups_size)?;
+                offset += fixups_size;
+                DynamicRelocation {
+                    symbol_group,
+                    flags,
+                    header: header.to_vec(),
+                    ..DynamicRelocation::new(symbol, fixups, machine)?
+                }
+            };
+            relocations.push(relocation);
+        }
+
+        Ok(Self {
+            version,
+            relocations,
+        })
+    }
+
+    pub fn arm64x_fixups(&self) -> impl Iterator<Item = &Arm64XFixup> {
+        self.relocations
+            .iter()
+            .filter_map(|relocation| match &relocation.data {
+                DynamicRelocationData::Arm64X(fixups) => Some(fixups),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    // every RVA the loader or kernel may patch, sorted
+    pub fn targets(&self) -> Vec<u32> {
+        let mut targets: Vec<u32> = self
+            .relocations
+            .iter()
+            .flat_map(|relocation| relocation.targets())
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+}
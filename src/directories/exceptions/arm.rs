@@ -0,0 +1,428 @@
+use super::ExceptionHandler;
+use crate::prelude::*;
+
+/*
+RUNTIME_FUNCTION (ARMv7, Thumb-2):
++00 DWORD   BeginAddress (bit 0 is set for Thumb code)
++04 DWORD   UnwindData, where bits 0-1 are the Flag:
+            0 = RVA of an .xdata record
+            1 = packed unwind data
+            2 = packed unwind data for a fragment with no prolog
+            3 = reserved
+
+Packed unwind data:
+bits 2-12   Function Length (in 2 byte units)
+bits 13-14  Ret (0 = pop {pc}, 1 = 16 bit branch, 2 = 32 bit branch, 3 = no epilog)
+bit  15     H (r0-r3 are homed)
+bits 16-18  Reg (r4 to r(4+Reg) are saved)
+bit  19     R (the saved registers are d8 to d(8+Reg) instead)
+bit  20     L (lr is saved)
+bit  21     C (r11 is set up as a frame pointer)
+bits 22-31  Stack Adjust (in 4 byte units, at 0x3F4 and above the low bits flag a folded adjustment)
+
+.xdata header:
+bits 0-17   Function Length (in 2 byte units)
+bits 18-19  Vers (0)
+bit  20     X (an exception handler follows the unwind codes)
+bit  21     E (a single epilog, whose first unwind code is given by the Epilog Count field)
+bit  22     F (a fragment with no prolog)
+bits 23-27  Epilog Count
+bits 28-31  Code Words (unwind code bytes in 4 byte units)
+When both Epilog Count and Code Words are 0, a second DWORD has the extended Epilog Count in bits
+0-15 and Code Words in bits 16-23.
+
+Epilog scope, one DWORD for each epilog unless E is set:
+bits 0-17   Epilog Start Offset (in 2 byte units from the start of the function)
+bits 20-23  Condition (0xE = always)
+bits 24-31  Epilog Start Index (byte index of the epilog's first unwind code)
+ */
+pub const RUNTIME_FUNCTION_SZ: usize = 8;
+const EPILOG_SCOPE_SZ: usize = 4;
+const LR: u8 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuntimeFunction {
+    pub begin: u32,
+    pub unwind_data: u32,
+}
+
+impl RuntimeFunction {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        Ok(Self {
+            begin: try_read_dword(raw, offset)?,
+            unwind_data: try_read_dword(raw, offset)?,
+        })
+    }
+
+    // the function's RVA without the Thumb bit
+    pub fn start(&self) -> u32 {
+        self.begin & !1
+    }
+
+    pub fn flag(&self) -> u8 {
+        (self.unwind_data & 0x3) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedUnwind {
+    // in bytes
+    pub function_length: u32,
+    pub fragment: bool,
+    pub ret: u8,
+    pub home_params: bool,
+    pub reg: u8,
+    pub r: bool,
+    pub link_register: bool,
+    pub chain: bool,
+    pub stack_adjust: u16,
+}
+
+impl PackedUnwind {
+    pub fn new(unwind_data: u32) -> Self {
+        Self {
+            function_length: ((unwind_data >> 2) & 0x7ff) * 2,
+            fragment: unwind_data & 0x3 == 2,
+            ret: ((unwind_data >> 13) & 0x3) as u8,
+            home_params: unwind_data & (1 << 15) != 0,
+            reg: ((unwind_data >> 16) & 0x7) as u8,
+            r: unwind_data & (1 << 19) != 0,
+            link_register: unwind_data & (1 << 20) != 0,
+            chain: unwind_data & (1 << 21) != 0,
+            stack_adjust: (unwind_data >> 22) as u16,
+        }
+    }
+
+    // bytes of local stack, folded adjustments only count the words pushed with the registers
+    pub fn stack_size(&self) -> u32 {
+        match self.stack_adjust {
+            0x3f4.. => ((self.stack_adjust as u32 & 0x3) + 1) * 4,
+            adjust => adjust as u32 * 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnwindOp {
+    // add sp, sp, #size (also addw and the 16 and 24 bit forms)
+    AllocStack { size: u32, wide: bool },
+    // pop of the integer registers in the mask, bit n for rn
+    PopRegisters { mask: u16, wide: bool },
+    // mov sp, rX
+    SetStackPointer { register: u8 },
+    // vpop {dFirst-dLast}
+    PopFloatRegisters { first: u8, last: u8 },
+    // ldr lr, [sp], #offset
+    LoadLinkRegister { offset: u32 },
+    MicrosoftSpecific(u8),
+    Nop { wide: bool },
+    // end, with the final nop of the epilog when there is one
+    End { nop: Option<bool> },
+    Reserved(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwindCode {
+    pub index: usize,
+    pub op: UnwindOp,
+}
+
+fn code_size(first: u8) -> usize {
+    match first {
+        0x80..=0xbf | 0xe8..=0xef | 0xf5 | 0xf6 => 2,
+        0xf7 | 0xf9 => 3,
+        0xf8 | 0xfa => 4,
+        _ => 1,
+    }
+}
+
+// registers r4 to r(4+count), with lr
+fn register_range(first: u8, count: u8, lr: bool) -> u16 {
+    let mut mask = (((1u32 << (count + 1)) - 1) << first) as u16;
+    if lr {
+        mask |= 1 << LR;
+    }
+    mask
+}
+
+pub fn decode_codes(raw: &[u8]) -> Result<Vec<UnwindCode>, ParsingError> {
+    let mut codes = Vec::new();
+    let mut index = 0;
+    while index < raw.len() {
+        let bytes = try_slice(raw, index, code_size(raw[index]))?;
+        let b0 = bytes[0];
+        let big_endian = bytes
+            .iter()
+            .skip(1)
+            .fold(0u32, |value, byte| value << 8 | *byte as u32);
+        let op = match b0 {
+            0x00..=0x7f => UnwindOp::AllocStack {
+                size: b0 as u32 * 4,
+                wide: false,
+            },
+            0x80..=0xbf => {
+                let value = (b0 as u16) << 8 | bytes[1] as u16;
+                let mut mask = value & 0x1fff;
+                if value & 0x2000 != 0 {
+                    mask |= 1 << LR;
+                }
+                UnwindOp::PopRegisters { mask, wide: true }
+            }
+            0xc0..=0xcf => UnwindOp::SetStackPointer { register: b0 & 0xf },
+            0xd0..=0xd7 => UnwindOp::PopRegisters {
+                mask: register_range(4, b0 & 0x3, b0 & 0x4 != 0),
+                wide: false,
+            },
+            0xd8..=0xdf => UnwindOp::PopRegisters {
+                mask: register_range(4, 4 + (b0 & 0x3), b0 & 0x4 != 0),
+                wide: true,
+            },
+            0xe0..=0xe7 => UnwindOp::PopFloatRegisters {
+                first: 8,
+                last: 8 + (b0 & 0x7),
+            },
+            0xe8..=0xeb => UnwindOp::AllocStack {
+                size: (((b0 as u32 & 0x3) << 8) | bytes[1] as u32) * 4,
+                wide: true,
+            },
+            0xec..=0xed => {
+                let mut mask = bytes[1] as u16;
+                if b0 & 0x1 != 0 {
+                    mask |= 1 << LR;
+                }
+                UnwindOp::PopRegisters { mask, wide: false }
+            }
+            0xee => UnwindOp::MicrosoftSpecific(bytes[1]),
+            0xef => UnwindOp::LoadLinkRegister {
+                offset: (bytes[1] as u32 & 0xf) * 4,
+            },
+            0xf5 => UnwindOp::PopFloatRegisters {
+                first: bytes[1] >> 4,
+                last: bytes[1] & 0xf,
+            },
+            0xf6 => UnwindOp::PopFloatRegisters {
+                first: 16 + (bytes[1] >> 4),
+                last: 16 + (bytes[1] & 0xf),
+            },
+            0xf7..=0xfa => UnwindOp::AllocStack {
+                size: big_endian * 4,
+                wide: b0 >= 0xf9,
+            },
+            0xfb => UnwindOp::Nop { wide: false },
+            0xfc => UnwindOp::Nop { wide: true },
+            0xfd => UnwindOp::End { nop: Some(false) },
+            0xfe => UnwindOp::End { nop: Some(true) },
+            0xff => UnwindOp::End { nop: None },
+            _ => UnwindOp::Reserved(bytes.to_vec()),
+        };
+        codes.push(UnwindCode { index, op });
+        index += bytes.len();
+    }
+    Ok(codes)
+}
+
+// bytes the given codes move sp by, counting pushed registers
+pub fn stack_size(codes: &[UnwindCode]) -> u32 {
+    codes
+        .iter()
+        .map(|code| match code.op {
+            UnwindOp::AllocStack { size, .. } => size,
+            UnwindOp::PopRegisters { mask, .. } => mask.count_ones() * 4,
+            UnwindOp::PopFloatRegisters { first, last } if last >= first => {
+                (last - first + 1) as u32 * 8
+            }
+            _ => 0,
+        })
+        .fold(0u32, |total, size| total.saturating_add(size))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpilogScope {
+    // in bytes from the start of the function
+    pub start_offset: u32,
+    pub condition: u8,
+    pub start_index: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwindData {
+    // in bytes
+    pub function_length: u32,
+    pub version: u8,
+    pub fragment: bool,
+    pub epilogs: Vec<EpilogScope>,
+    // with the E bit set, the only epilog starts at this unwind code and has no scope
+    pub single_epilog_index: Option<u16>,
+    pub codes: Vec<UnwindCode>,
+    pub handler: Option<ExceptionHandler>,
+}
+
+impl UnwindData {
+    pub fn new(raw: &[u8], rva: u32) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let header = try_read_dword(raw, &mut offset)?;
+        let version = ((header >> 18) & 0x3) as u8;
+        if version != 0 {
+            return Err(ParsingError::Malformed {
+                reason: format!("unwind data at {:#x} has version {}", rva, version),
+            });
+        }
+        let mut epilog_count = (header >> 23) & 0x1f;
+        let mut code_words = header >> 28;
+        if epilog_count == 0 && code_words == 0 {
+            let extension = try_read_dword(raw, &mut offset)?;
+            epilog_count = extension & 0xffff;
+            code_words = (extension >> 16) & 0xff;
+        }
+
+        let (epilogs, single_epilog_index) = match header & (1 << 21) {
+            0 => {
+                let scopes = try_slice(raw, offset, epilog_count as usize * EPILOG_SCOPE_SZ)?;
+                offset += scopes.len();
+                let epilogs = scopes
+                    .chunks_exact(EPILOG_SCOPE_SZ)
+                    .map(|scope| {
+                        let scope = u32::from_le_bytes([scope[0], scope[1], scope[2], scope[3]]);
+                        EpilogScope {
+                            start_offset: (scope & 0x3ffff) * 2,
+                            condition: ((scope >> 20) & 0xf) as u8,
+                            start_index: (scope >> 24) as u8,
+                        }
+                    })
+                    .collect();
+                (epilogs, None)
+            }
+            _ => (Vec::new(), Some(epilog_count as u16)),
+        };
+
+        let code_bytes = try_slice(raw, offset, code_words as usize * DWORD_SZ)?;
+        offset += code_bytes.len();
+        let codes = decode_codes(code_bytes)?;
+
+        let handler = match header & (1 << 20) {
+            0 => None,
+            _ => Some(ExceptionHandler {
+                rva: try_read_dword(raw, &mut offset)?,
+                data_rva: rva.wrapping_add(offset as u32),
+            }),
+        };
+
+        Ok(Self {
+            function_length: (header & 0x3ffff) * 2,
+            version,
+            fragment: header & (1 << 22) != 0,
+            epilogs,
+            single_epilog_index,
+            codes,
+            handler,
+        })
+    }
+
+    // the codes starting at the given byte index, up to and including the end code
+    pub fn codes_from(&self, index: usize) -> &[UnwindCode] {
+        let start = match self.codes.iter().position(|code| code.index == index) {
+            Some(start) => start,
+            None => return &[],
+        };
+        let end = self.codes[start..]
+            .iter()
+            .position(|code| matches!(code.op, UnwindOp::End { .. }))
+            .map(|end| start + end + 1)
+            .unwrap_or(self.codes.len());
+        &self.codes[start..end]
+    }
+
+    pub fn prolog(&self) -> &[UnwindCode] {
+        self.codes_from(0)
+    }
+
+    pub fn epilog(&self, scope: &EpilogScope) -> &[UnwindCode] {
+        self.codes_from(scope.start_index as usize)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unwind {
+    Packed(PackedUnwind),
+    Unpacked(UnwindData),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionEntry {
+    pub function: RuntimeFunction,
+    pub unwind: Unwind,
+}
+
+impl FunctionEntry {
+    pub fn new<'a>(
+        function: RuntimeFunction,
+        read: &impl Fn(u32) -> Result<&'a [u8], ParsingError>,
+    ) -> Result<Self, ParsingError> {
+        let unwind = match function.flag() {
+            0 => Unwind::Unpacked(UnwindData::new(
+                read(function.unwind_data)?,
+                function.unwind_data,
+            )?),
+            1 | 2 => Unwind::Packed(PackedUnwind::new(function.unwind_data)),
+            flag => {
+                return Err(ParsingError::Malformed {
+                    reason: format!(
+                        "function {:#x} has reserved unwind flag {}",
+                        function.start(),
+                        flag
+                    ),
+                })
+            }
+        };
+        Ok(Self { function, unwind })
+    }
+
+    pub fn length(&self) -> u32 {
+        match &self.unwind {
+            Unwind::Packed(packed) => packed.function_length,
+            Unwind::Unpacked(data) => data.function_length,
+        }
+    }
+
+    pub fn contains(&self, rva: u32) -> bool {
+        let start = self.function.start();
+        rva >= start && rva - start < self.length()
+    }
+
+    pub fn stack_size(&self) -> u32 {
+        match &self.unwind {
+            Unwind::Packed(packed) => packed.stack_size(),
+            Unwind::Unpacked(data) => stack_size(data.prolog()),
+        }
+    }
+
+    pub fn handler(&self) -> Option<ExceptionHandler> {
+        match &self.unwind {
+            Unwind::Packed(_) => None,
+            Unwind::Unpacked(data) => data.handler,
+        }
+    }
+}
+
+pub fn function_table<'a>(
+    raw: &[u8],
+    read: impl Fn(u32) -> Result<&'a [u8], ParsingError>,
+) -> Result<Vec<FunctionEntry>, ParsingError> {
+    if !raw.len().is_multiple_of(RUNTIME_FUNCTION_SZ) {
+        return Err(ParsingError::Malformed {
+            reason: format!(
+                "exception directory size {:#x} isn't a multiple of {}",
+                raw.len(),
+                RUNTIME_FUNCTION_SZ
+            ),
+        });
+    }
+
+    let mut entries = Vec::with_capacity(raw.len() / RUNTIME_FUNCTION_SZ);
+    let mut offset = 0;
+    while offset < raw.len() {
+        let function = RuntimeFunction::new(raw, &mut offset)?;
+        entries.push(FunctionEntry::new(function, &read)?);
+    }
+    Ok(entries)
+}
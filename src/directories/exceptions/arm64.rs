@@ -0,0 +1,485 @@
+use super::ExceptionHandler;
+use crate::prelude::*;
+
+/*
+RUNTIME_FUNCTION (ARM64):
++00 DWORD   BeginAddress
++04 DWORD   UnwindData, where bits 0-1 are the Flag:
+            0 = RVA of an .xdata record
+            1 = packed unwind data
+            2 = packed unwind data for a fragment with no prolog
+            3 = reserved
+
+Packed unwind data:
+bits 2-12   Function Length (in 4 byte units)
+bits 13-15  RegF (number of saved d8-d15 registers, minus one)
+bits 16-19  RegI (number of saved x19-x28 registers)
+bit  20     H (x0-x7 are homed)
+bits 21-22  CR (0 = lr not saved, 1 = lr saved alone, 2 = pac signed lr with x29, 3 = x29/lr
+            saved with a frame chain)
+bits 23-31  Frame Size (in 16 byte units)
+
+.xdata header:
+bits 0-17   Function Length (in 4 byte units)
+bits 18-19  Vers (0)
+bit  20     X (an exception handler follows the unwind codes)
+bit  21     E (a single epilog, whose first unwind code is given by the Epilog Count field)
+bits 22-26  Epilog Count
+bits 27-31  Code Words (unwind code bytes in 4 byte units)
+When both Epilog Count and Code Words are 0, a second DWORD has the extended Epilog Count in bits
+0-15 and Code Words in bits 16-23.
+
+Epilog scope, one DWORD for each epilog unless E is set:
+bits 0-17   Epilog Start Offset (in 4 byte units from the start of the function)
+bits 22-31  Epilog Start Index (byte index of the epilog's first unwind code)
+
+The unwind codes come next, then with X set the DWORD handler RVA and the handler's own data.
+Codes are one to five bytes, with the size given by the first byte.
+ */
+pub const RUNTIME_FUNCTION_SZ: usize = 8;
+const EPILOG_SCOPE_SZ: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuntimeFunction {
+    pub begin: u32,
+    pub unwind_data: u32,
+}
+
+impl RuntimeFunction {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        Ok(Self {
+            begin: try_read_dword(raw, offset)?,
+            unwind_data: try_read_dword(raw, offset)?,
+        })
+    }
+
+    pub fn flag(&self) -> u8 {
+        (self.unwind_data & 0x3) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedUnwind {
+    // in bytes
+    pub function_length: u32,
+    // Flag 2, the function has no prolog of its own
+    pub fragment: bool,
+    pub reg_f: u8,
+    pub reg_i: u8,
+    pub home_params: bool,
+    pub cr: u8,
+    // in bytes
+    pub frame_size: u32,
+}
+
+impl PackedUnwind {
+    pub fn new(unwind_data: u32) -> Self {
+        Self {
+            function_length: ((unwind_data >> 2) & 0x7ff) * 4,
+            fragment: unwind_data & 0x3 == 2,
+            reg_f: ((unwind_data >> 13) & 0x7) as u8,
+            reg_i: ((unwind_data >> 16) & 0xf) as u8,
+            home_params: unwind_data & (1 << 20) != 0,
+            cr: ((unwind_data >> 21) & 0x3) as u8,
+            frame_size: ((unwind_data >> 23) & 0x1ff) * 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnwindOp {
+    // alloc_s, alloc_m and alloc_l
+    AllocStack {
+        size: u32,
+    },
+    // save_r19r20_x, stp x19, x20, [sp, #-offset]!
+    SaveR19R20 {
+        offset: u32,
+    },
+    // save_fplr and save_fplr_x
+    SaveFpLr {
+        offset: u32,
+        pre_index: bool,
+    },
+    // save_regp and save_regp_x, register is the first of the x registers in the pair
+    SaveRegPair {
+        register: u8,
+        offset: u32,
+        pre_index: bool,
+    },
+    // save_reg and save_reg_x
+    SaveReg {
+        register: u8,
+        offset: u32,
+        pre_index: bool,
+    },
+    // save_lrpair, the register and lr
+    SaveLrPair {
+        register: u8,
+        offset: u32,
+    },
+    // save_fregp and save_fregp_x, register is the first of the d registers in the pair
+    SaveFRegPair {
+        register: u8,
+        offset: u32,
+        pre_index: bool,
+    },
+    // save_freg and save_freg_x
+    SaveFReg {
+        register: u8,
+        offset: u32,
+        pre_index: bool,
+    },
+    // alloc_z, in multiples of the SVE vector length
+    AllocScalable {
+        vector_lengths: u8,
+    },
+    SetFp,
+    AddFp {
+        offset: u32,
+    },
+    Nop,
+    End,
+    EndChained,
+    SaveNext,
+    // save_any_reg, with its two operand bytes
+    SaveAnyReg {
+        operand: u16,
+    },
+    TrapFrame,
+    MachineFrame,
+    Context,
+    EcContext,
+    ClearUnwoundToCall,
+    PacSignLr,
+    Reserved(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwindCode {
+    // byte index among the unwind codes, which is what epilog scopes refer to
+    pub index: usize,
+    pub op: UnwindOp,
+}
+
+fn code_size(first: u8) -> usize {
+    match first {
+        0xc0..=0xdf => 2,
+        0xe0 => 4,
+        0xe2 => 2,
+        0xe7 => 3,
+        0xf8 => 2,
+        0xf9 => 3,
+        0xfa => 4,
+        0xfb => 5,
+        _ => 1,
+    }
+}
+
+pub fn decode_codes(raw: &[u8]) -> Result<Vec<UnwindCode>, ParsingError> {
+    let mut codes = Vec::new();
+    let mut index = 0;
+    while index < raw.len() {
+        let bytes = try_slice(raw, index, code_size(raw[index]))?;
+        let b0 = bytes[0];
+        // most two byte codes are read as one big endian value
+        let v = match bytes.len() {
+            1 => b0 as u32,
+            _ => (b0 as u32) << 8 | bytes[1] as u32,
+        };
+        let op = match b0 {
+            0x00..=0x1f => UnwindOp::AllocStack {
+                size: (b0 & 0x1f) as u32 * 16,
+            },
+            0x20..=0x3f => UnwindOp::SaveR19R20 {
+                offset: (b0 & 0x1f) as u32 * 8,
+            },
+            0x40..=0x7f => UnwindOp::SaveFpLr {
+                offset: (b0 & 0x3f) as u32 * 8,
+                pre_index: false,
+            },
+            0x80..=0xbf => UnwindOp::SaveFpLr {
+                offset: ((b0 & 0x3f) as u32 + 1) * 8,
+                pre_index: true,
+            },
+            0xc0..=0xc7 => UnwindOp::AllocStack {
+                size: (v & 0x7ff) * 16,
+            },
+            0xc8..=0xcf => UnwindOp::SaveRegPair {
+                register: 19 + ((v >> 6) & 0xf) as u8,
+                offset: (v & 0x3f) * 8 + if b0 >= 0xcc { 8 } else { 0 },
+                pre_index: b0 >= 0xcc,
+            },
+            0xd0..=0xd3 => UnwindOp::SaveReg {
+                register: 19 + ((v >> 6) & 0xf) as u8,
+                offset: (v & 0x3f) * 8,
+                pre_index: false,
+            },
+            0xd4..=0xd5 => UnwindOp::SaveReg {
+                register: 19 + ((v >> 5) & 0xf) as u8,
+                offset: ((v & 0x1f) + 1) * 8,
+                pre_index: true,
+            },
+            0xd6..=0xd7 => UnwindOp::SaveLrPair {
+                register: 19 + 2 * ((v >> 6) & 0x7) as u8,
+                offset: (v & 0x3f) * 8,
+            },
+            0xd8..=0xdb => UnwindOp::SaveFRegPair {
+                register: 8 + ((v >> 6) & 0x7) as u8,
+                offset: (v & 0x3f) * 8 + if b0 >= 0xda { 8 } else { 0 },
+                pre_index: b0 >= 0xda,
+            },
+            0xdc..=0xdd => UnwindOp::SaveFReg {
+                register: 8 + ((v >> 6) & 0x7) as u8,
+                offset: (v & 0x3f) * 8,
+                pre_index: false,
+            },
+            0xde => UnwindOp::SaveFReg {
+                register: 8 + ((v >> 5) & 0x7) as u8,
+                offset: ((v & 0x1f) + 1) * 8,
+                pre_index: true,
+            },
+            0xdf => UnwindOp::AllocScalable {
+                vector_lengths: bytes[1],
+            },
+            0xe0 => UnwindOp::AllocStack {
+                size: ((bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32) * 16,
+            },
+            0xe1 => UnwindOp::SetFp,
+            0xe2 => UnwindOp::AddFp {
+                offset: bytes[1] as u32 * 8,
+            },
+            0xe3 => UnwindOp::Nop,
+            0xe4 => UnwindOp::End,
+            0xe5 => UnwindOp::EndChained,
+            0xe6 => UnwindOp::SaveNext,
+            0xe7 => UnwindOp::SaveAnyReg {
+                operand: (bytes[1] as u16) << 8 | bytes[2] as u16,
+            },
+            0xe8 => UnwindOp::TrapFrame,
+            0xe9 => UnwindOp::MachineFrame,
+            0xea => UnwindOp::Context,
+            0xeb => UnwindOp::EcContext,
+            0xec => UnwindOp::ClearUnwoundToCall,
+            0xfc => UnwindOp::PacSignLr,
+            _ => UnwindOp::Reserved(bytes.to_vec()),
+        };
+        codes.push(UnwindCode { index, op });
+        index += bytes.len();
+    }
+    Ok(codes)
+}
+
+// bytes the given codes move sp by
+pub fn stack_size(codes: &[UnwindCode]) -> u32 {
+    codes
+        .iter()
+        .map(|code| match code.op {
+            UnwindOp::AllocStack { size } => size,
+            UnwindOp::SaveR19R20 { offset } => offset,
+            UnwindOp::SaveFpLr { offset, pre_index }
+            | UnwindOp::SaveRegPair {
+                offset, pre_index, ..
+            }
+            | UnwindOp::SaveReg {
+                offset, pre_index, ..
+            }
+            | UnwindOp::SaveFRegPair {
+                offset, pre_index, ..
+            }
+            | UnwindOp::SaveFReg {
+                offset, pre_index, ..
+            } if pre_index => offset,
+            _ => 0,
+        })
+        .fold(0u32, |total, size| total.saturating_add(size))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpilogScope {
+    // in bytes from the start of the function
+    pub start_offset: u32,
+    pub start_index: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwindData {
+    // in bytes
+    pub function_length: u32,
+    pub version: u8,
+    pub epilogs: Vec<EpilogScope>,
+    // with the E bit set, the only epilog starts at this unwind code and has no scope
+    pub single_epilog_index: Option<u16>,
+    pub codes: Vec<UnwindCode>,
+    pub handler: Option<ExceptionHandler>,
+}
+
+impl UnwindData {
+    // raw starts at the .xdata record, rva is where that is so the handler data can be located
+    pub fn new(raw: &[u8], rva: u32) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let header = try_read_dword(raw, &mut offset)?;
+        let version = ((header >> 18) & 0x3) as u8;
+        if version != 0 {
+            return Err(ParsingError::Malformed {
+                reason: format!("unwind data at {:#x} has version {}", rva, version),
+            });
+        }
+        let mut epilog_count = (header >> 22) & 0x1f;
+        let mut code_words = (header >> 27) & 0x1f;
+        if epilog_count == 0 && code_words == 0 {
+            let extension = try_read_dword(raw, &mut offset)?;
+            epilog_count = extension & 0xffff;
+            code_words = (extension >> 16) & 0xff;
+        }
+
+        let (epilogs, single_epilog_index) = match header & (1 << 21) {
+            0 => {
+                let count = epilog_count as usize;
+                let scopes = try_slice(raw, offset, count * EPILOG_SCOPE_SZ)?;
+                offset += scopes.len();
+                let epilogs = scopes
+                    .chunks_exact(EPILOG_SCOPE_SZ)
+                    .map(|scope| {
+                        let scope = u32::from_le_bytes([scope[0], scope[1], scope[2], scope[3]]);
+                        EpilogScope {
+                            start_offset: (scope & 0x3ffff) * 4,
+                            start_index: (scope >> 22) as u16,
+                        }
+                    })
+                    .collect();
+                (epilogs, None)
+            }
+            _ => (Vec::new(), Some(epilog_count as u16)),
+        };
+
+        let code_bytes = try_slice(raw, offset, code_words as usize * DWORD_SZ)?;
+        offset += code_bytes.len();
+        let codes = decode_codes(code_bytes)?;
+
+        let handler = match header & (1 << 20) {
+            0 => None,
+            _ => Some(ExceptionHandler {
+                rva: try_read_dword(raw, &mut offset)?,
+                data_rva: rva.wrapping_add(offset as u32),
+            }),
+        };
+
+        Ok(Self {
+            function_length: (header & 0x3ffff) * 4,
+            version,
+            epilogs,
+            single_epilog_index,
+            codes,
+            handler,
+        })
+    }
+
+    // the codes starting at the given byte index, up to and including the end code
+    pub fn codes_from(&self, index: usize) -> &[UnwindCode] {
+        let start = match self.codes.iter().position(|code| code.index == index) {
+            Some(start) => start,
+            None => return &[],
+        };
+        let end = self.codes[start..]
+            .iter()
+            .position(|code| matches!(code.op, UnwindOp::End | UnwindOp::EndChained))
+            .map(|end| start + end + 1)
+            .unwrap_or(self.codes.len());
+        &self.codes[start..end]
+    }
+
+    pub fn prolog(&self) -> &[UnwindCode] {
+        self.codes_from(0)
+    }
+
+    pub fn epilog(&self, scope: &EpilogScope) -> &[UnwindCode] {
+        self.codes_from(scope.start_index as usize)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unwind {
+    Packed(PackedUnwind),
+    Unpacked(UnwindData),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionEntry {
+    pub function: RuntimeFunction,
+    pub unwind: Unwind,
+}
+
+impl FunctionEntry {
+    // read maps an RVA to the bytes from there onwards
+    pub fn new<'a>(
+        function: RuntimeFunction,
+        read: &impl Fn(u32) -> Result<&'a [u8], ParsingError>,
+    ) -> Result<Self, ParsingError> {
+        let unwind = match function.flag() {
+            0 => Unwind::Unpacked(UnwindData::new(
+                read(function.unwind_data)?,
+                function.unwind_data,
+            )?),
+            1 | 2 => Unwind::Packed(PackedUnwind::new(function.unwind_data)),
+            flag => {
+                return Err(ParsingError::Malformed {
+                    reason: format!(
+                        "function {:#x} has reserved unwind flag {}",
+                        function.begin, flag
+                    ),
+                })
+            }
+        };
+        Ok(Self { function, unwind })
+    }
+
+    pub fn length(&self) -> u32 {
+        match &self.unwind {
+            Unwind::Packed(packed) => packed.function_length,
+            Unwind::Unpacked(data) => data.function_length,
+        }
+    }
+
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.function.begin && rva - self.function.begin < self.length()
+    }
+
+    // size of the stack frame the prolog sets up
+    pub fn stack_size(&self) -> u32 {
+        match &self.unwind {
+            Unwind::Packed(packed) => packed.frame_size,
+            Unwind::Unpacked(data) => stack_size(data.prolog()),
+        }
+    }
+
+    pub fn handler(&self) -> Option<ExceptionHandler> {
+        match &self.unwind {
+            Unwind::Packed(_) => None,
+            Unwind::Unpacked(data) => data.handler,
+        }
+    }
+}
+
+pub fn function_table<'a>(
+    raw: &[u8],
+    read: impl Fn(u32) -> Result<&'a [u8], ParsingError>,
+) -> Result<Vec<FunctionEntry>, ParsingError> {
+    if !raw.len().is_multiple_of(RUNTIME_FUNCTION_SZ) {
+        return Err(ParsingError::Malformed {
+            reason: format!(
+                "exception directory size {:#x} isn't a multiple of {}",
+                raw.len(),
+                RUNTIME_FUNCTION_SZ
+            ),
+        });
+    }
+
+    let mut entries = Vec::with_capacity(raw.len() / RUNTIME_FUNCTION_SZ);
+    let mut offset = 0;
+    while offset < raw.len() {
+        let function = RuntimeFunction::new(raw, &mut offset)?;
+        entries.push(FunctionEntry::new(function, &read)?);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,18 @@
+pub mod arm;
+pub mod arm64;
+pub mod x64;
+
+// the exception directory's layout depends on the machine
+#[derive(Debug, Clone)]
+pub enum ExceptionTable {
+    X64(Vec<x64::FunctionEntry>),
+    Arm64(Vec<arm64::FunctionEntry>),
+    Arm(Vec<arm::FunctionEntry>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExceptionHandler {
+    pub rva: u32,
+    // the language specific data following the handler RVA
+    pub data_rva: u32,
+}
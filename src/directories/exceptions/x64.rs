@@ -0,0 +1,382 @@
+use super::ExceptionHandler;
+use crate::prelude::*;
+use std::collections::HashSet;
+
+/*
+RUNTIME_FUNCTION (x64):
++00 DWORD   BeginAddress
++04 DWORD   EndAddress
++08 DWORD   UnwindInfoAddress (with the low bit set, the RVA of another RUNTIME_FUNCTION whose
+            unwind info is shared)
+
+UNWIND_INFO:
++00 BYTE    Version (bits 0-2), Flags (bits 3-7)
++01 BYTE    SizeOfProlog
++02 BYTE    CountOfCodes
++03 BYTE    FrameRegister (bits 0-3), FrameOffset (bits 4-7, scaled by 16)
++04         UNWIND_CODE[CountOfCodes], padded to an even count
+            with UNW_FLAG_EHANDLER or UNW_FLAG_UHANDLER: DWORD handler RVA, then the handler's
+            own data
+            with UNW_FLAG_CHAININFO: the RUNTIME_FUNCTION of the parent, whose unwind info
+            continues this one
+
+UNWIND_CODE:
++00 BYTE    CodeOffset (end of the prolog instruction)
++01 BYTE    UnwindOp (bits 0-3), OpInfo (bits 4-7)
+Some ops use the following one or two slots as an operand.
+
+C_SCOPE_TABLE (handler data of __C_specific_handler):
++00 DWORD   Count
++04         Count entries of DWORD BeginAddress, EndAddress, HandlerAddress, JumpTarget
+ */
+pub const RUNTIME_FUNCTION_SZ: usize = 12;
+const UNWIND_CODE_SZ: usize = 2;
+const SCOPE_RECORD_SZ: usize = 16;
+const MAX_CHAIN: usize = 32;
+
+pub const UNW_FLAG_EHANDLER: u8 = 0x1;
+pub const UNW_FLAG_UHANDLER: u8 = 0x2;
+pub const UNW_FLAG_CHAININFO: u8 = 0x4;
+
+const REGISTERS: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+pub fn register_name(register: u8) -> &'static str {
+    REGISTERS[(register & 0xf) as usize]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuntimeFunction {
+    pub begin: u32,
+    pub end: u32,
+    pub unwind_rva: u32,
+}
+
+impl RuntimeFunction {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        Ok(Self {
+            begin: try_read_dword(raw, offset)?,
+            end: try_read_dword(raw, offset)?,
+            unwind_rva: try_read_dword(raw, offset)?,
+        })
+    }
+
+    // the entry borrows the unwind info of the RUNTIME_FUNCTION at unwind_rva & !1
+    pub fn is_indirect(&self) -> bool {
+        self.unwind_rva & 1 != 0
+    }
+
+    pub fn contains(&self, rva: u32) -> bool {
+        rva >= self.begin && rva < self.end
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnwindOp {
+    PushNonVolatile { register: u8 },
+    AllocSmall { size: u32 },
+    // 16 bit size scaled by 8, or a full 32 bit size
+    AllocLarge { size: u32 },
+    SetFramePointer,
+    SaveNonVolatile { register: u8, offset: u32 },
+    SaveXmm128 { register: u8, offset: u32 },
+    PushMachineFrame { error_code: bool },
+    // version 2 epilog descriptors, kept as is
+    Epilog { code_offset: u8, info: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwindCode {
+    // offset from the start of the function to the end of the prolog instruction
+    pub prolog_offset: u8,
+    pub op: UnwindOp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnwindInfo {
+    pub version: u8,
+    pub flags: u8,
+    pub prolog_size: u8,
+    pub frame_register: Option<u8>,
+    pub frame_offset: u32,
+    pub codes: Vec<UnwindCode>,
+    pub handler: Option<ExceptionHandler>,
+    pub chained: Option<RuntimeFunction>,
+}
+
+impl UnwindInfo {
+    // raw starts at the UNWIND_INFO, rva is where that is so the handler data can be located
+    pub fn new(raw: &[u8], rva: u32) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let first = try_read_byte(raw, &mut offset)?;
+        let (version, flags) = (first & 0x7, first >> 3);
+        if version != 1 && version != 2 {
+            return Err(ParsingError::Malformed {
+                reason: format!("unwind info at {:#x} has version {}", rva, version),
+            });
+        }
+        let prolog_size = try_read_byte(raw, &mut offset)?;
+        let count = try_read_byte(raw, &mut offset)? as usize;
+        let frame = try_read_byte(raw, &mut offset)?;
+
+        let slots = try_slice(raw, offset, count * UNWIND_CODE_SZ)?;
+        let codes = decode_codes(slots, version)?;
+        offset += align2(count) * UNWIND_CODE_SZ;
+
+        // a chained entry has no handler of its own
+        let (handler, chained) = if flags & UNW_FLAG_CHAININFO != 0 {
+            (None, Some(RuntimeFunction::new(raw, &mut offset)?))
+        } else if flags & (UNW_FLAG_EHANDLER | UNW_FLAG_UHANDLER) != 0 {
+            let handler = ExceptionHandler {
+                rva: try_read_dword(raw, &mut offset)?,
+                data_rva: rva.wrapping_add(offset as u32),
+            };
+            (Some(handler), None)
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            version,
+            flags,
+            prolog_size,
+            frame_register: match frame & 0xf {
+                0 => None,
+                register => Some(register),
+            },
+            frame_offset: (frame >> 4) as u32 * 16,
+            codes,
+            handler,
+            chained,
+        })
+    }
+
+    // bytes the prolog described here moves rsp by, not counting the return address
+    pub fn stack_size(&self) -> u32 {
+        self.codes
+            .iter()
+            .map(|code| match code.op {
+                UnwindOp::PushNonVolatile { .. } => 8,
+                UnwindOp::AllocSmall { size } | UnwindOp::AllocLarge { size } => size,
+                // SS, RSP, EFLAGS, CS and RIP, plus the error code when there is one
+                UnwindOp::PushMachineFrame { error_code } => 40 + 8 * error_code as u32,
+                _ => 0,
+            })
+            .fold(0u32, |total, size| total.saturating_add(size))
+    }
+}
+
+fn align2(count: usize) -> usize {
+    (count + 1) & !1
+}
+
+fn decode_codes(slots: &[u8], version: u8) -> Result<Vec<UnwindCode>, ParsingError> {
+    let slot = |index: usize| -> Result<u16, ParsingError> {
+        let mut offset = index * UNWIND_CODE_SZ;
+        try_read_word(slots, &mut offset)
+    };
+    let operand32 = |index: usize| -> Result<u32, ParsingError> {
+        Ok(slot(index)? as u32 | (slot(index + 1)? as u32) << 16)
+    };
+
+    let count = slots.len() / UNWIND_CODE_SZ;
+    let mut codes = Vec::with_capacity(count);
+    let mut index = 0;
+    while index < count {
+        let code = slot(index)?;
+        let prolog_offset = code as u8;
+        let (op, info) = ((code >> 8) as u8 & 0xf, (code >> 12) as u8);
+        let (op, used) = match op {
+            0 => (UnwindOp::PushNonVolatile { register: info }, 1),
+            1 if info == 0 => (
+                UnwindOp::AllocLarge {
+                    size: slot(index + 1)? as u32 * 8,
+                },
+                2,
+            ),
+            1 if info == 1 => (
+                UnwindOp::AllocLarge {
+                    size: operand32(index + 1)?,
+                },
+                3,
+            ),
+            2 => (
+                UnwindOp::AllocSmall {
+                    size: info as u32 * 8 + 8,
+                },
+                1,
+            ),
+            3 => (UnwindOp::SetFramePointer, 1),
+            4 => (
+                UnwindOp::SaveNonVolatile {
+                    register: info,
+                    offset: slot(index + 1)? as u32 * 8,
+                },
+                2,
+            ),
+            5 => (
+                UnwindOp::SaveNonVolatile {
+                    register: info,
+                    offset: operand32(index + 1)?,
+                },
+                3,
+            ),
+            6 if version == 2 => (
+                UnwindOp::Epilog {
+                    code_offset: prolog_offset,
+                    info,
+                },
+                1,
+            ),
+            8 => (
+                UnwindOp::SaveXmm128 {
+                    register: info,
+                    offset: slot(index + 1)? as u32 * 16,
+                },
+                2,
+            ),
+            9 => (
+                UnwindOp::SaveXmm128 {
+                    register: info,
+                    offset: operand32(index + 1)?,
+                },
+                3,
+            ),
+            10 => (
+                UnwindOp::PushMachineFrame {
+                    error_code: info != 0,
+                },
+                1,
+            ),
+            // the size of anything else is unknown, so the rest can't be decoded
+            _ => {
+                return Err(ParsingError::Malformed {
+                    reason: format!("unknown unwind op {} (info {})", op, info),
+                })
+            }
+        };
+        codes.push(UnwindCode { prolog_offset, op });
+        index += used;
+    }
+    Ok(codes)
+}
+
+// a function along with its unwind info, followed by that of every function it's chained to
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionEntry {
+    pub function: RuntimeFunction,
+    pub unwind: Vec<UnwindInfo>,
+}
+
+impl FunctionEntry {
+    // read maps an RVA to the bytes from there onwards
+    pub fn new<'a>(
+        function: RuntimeFunction,
+        read: &impl Fn(u32) -> Result<&'a [u8], ParsingError>,
+    ) -> Result<Self, ParsingError> {
+        let mut unwind_rva = function.unwind_rva;
+        if function.is_indirect() {
+            let mut offset = 0;
+            unwind_rva = RuntimeFunction::new(read(unwind_rva & !1)?, &mut offset)?.unwind_rva;
+        }
+
+        let mut unwind = Vec::new();
+        let mut visited = HashSet::new();
+        loop {
+            if !visited.insert(unwind_rva) || unwind.len() >= MAX_CHAIN {
+                return Err(ParsingError::Malformed {
+                    reason: format!(
+                        "unwind info chain of function {:#x} loops or is too long",
+                        function.begin
+                    ),
+                });
+            }
+            let info = UnwindInfo::new(read(unwind_rva)?, unwind_rva)?;
+            let chained = info.chained;
+            unwind.push(info);
+            match chained {
+                Some(parent) => unwind_rva = parent.unwind_rva & !1,
+                None => return Ok(Self { function, unwind }),
+            }
+        }
+    }
+
+    // size of the fixed stack frame below the return address, over the whole chain
+    pub fn stack_size(&self) -> u32 {
+        self.unwind
+            .iter()
+            .fold(0u32, |total, info| total.saturating_add(info.stack_size()))
+    }
+
+    // the register holding the frame pointer, if the function establishes one
+    pub fn frame_register(&self) -> Option<(u8, u32)> {
+        self.unwind
+            .iter()
+            .find_map(|info| info.frame_register.map(|r| (r, info.frame_offset)))
+    }
+
+    pub fn handler(&self) -> Option<ExceptionHandler> {
+        self.unwind.first().and_then(|info| info.handler)
+    }
+}
+
+pub fn function_table<'a>(
+    raw: &[u8],
+    read: impl Fn(u32) -> Result<&'a [u8], ParsingError>,
+) -> Result<Vec<FunctionEntry>, ParsingError> {
+    if !raw.len().is_multiple_of(RUNTIME_FUNCTION_SZ) {
+        return Err(ParsingError::Malformed {
+            reason: format!(
+                "exception directory size {:#x} isn't a multiple of {}",
+                raw.len(),
+                RUNTIME_FUNCTION_SZ
+            ),
+        });
+    }
+
+    let mut entries = Vec::with_capacity(raw.len() / RUNTIME_FUNCTION_SZ);
+    let mut offset = 0;
+    while offset < raw.len() {
+        let function = RuntimeFunction::new(raw, &mut offset)?;
+        if function.end < function.begin {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "function {:#x} ends before it starts at {:#x}",
+                    function.begin, function.end
+                ),
+            });
+        }
+        entries.push(FunctionEntry::new(function, &read)?);
+    }
+    Ok(entries)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScopeRecord {
+    pub begin: u32,
+    pub end: u32,
+    // filter or termination handler, 1 for a catch-all __except (EXCEPTION_EXECUTE_HANDLER)
+    pub handler: u32,
+    // 0 for a __finally block
+    pub target: u32,
+}
+
+// handler data in the format __C_specific_handler uses
+pub fn scope_table(raw: &[u8]) -> Result<Vec<ScopeRecord>, ParsingError> {
+    let mut offset = 0;
+    let count = try_read_dword(raw, &mut offset)? as usize;
+    try_slice(raw, offset, count.saturating_mul(SCOPE_RECORD_SZ))?;
+
+    let mut records = Vec::with_capacity(count);
+    for _ in 0..count {
+        records.push(ScopeRecord {
+            begin: read_dword(raw, &mut offset),
+            end: read_dword(raw, &mut offset),
+            handler: read_dword(raw, &mut offset),
+            target: read_dword(raw, &mut offset),
+        });
+    }
+    Ok(records)
+}
@@ -0,0 +1,173 @@
+use crate::prelude::*;
+
+/*
+Control Flow Guard tables, pointed to by the load config:
+GuardCFFunctionTable            valid indirect call targets
+GuardAddressTakenIatEntryTable  IAT entries whose imports are called indirectly
+GuardLongJumpTargetTable        valid longjmp targets
+GuardEHContinuationTable        valid exception handling continuation targets
+
+Each table is sorted and has one entry per RVA:
++00 DWORD   Rva
++04         Metadata[n], where n is the stride from bits 28-31 of GuardFlags
+
+The first metadata byte holds IMAGE_GUARD_FLAG_* values.
+ */
+pub const IMAGE_GUARD_CF_INSTRUMENTED: u32 = 0x0000_0100;
+pub const IMAGE_GUARD_CFW_INSTRUMENTED: u32 = 0x0000_0200;
+pub const IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT: u32 = 0x0000_0400;
+pub const IMAGE_GUARD_SECURITY_COOKIE_UNUSED: u32 = 0x0000_0800;
+pub const IMAGE_GUARD_PROTECT_DELAYLOAD_IAT: u32 = 0x0000_1000;
+pub const IMAGE_GUARD_DELAYLOAD_IAT_IN_ITS_OWN_SECTION: u32 = 0x0000_2000;
+pub const IMAGE_GUARD_CF_EXPORT_SUPPRESSION_INFO_PRESENT: u32 = 0x0000_4000;
+pub const IMAGE_GUARD_CF_ENABLE_EXPORT_SUPPRESSION: u32 = 0x0000_8000;
+pub const IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT: u32 = 0x0001_0000;
+pub const IMAGE_GUARD_RF_INSTRUMENTED: u32 = 0x0002_0000;
+pub const IMAGE_GUARD_RF_ENABLE: u32 = 0x0004_0000;
+pub const IMAGE_GUARD_RF_STRICT: u32 = 0x0008_0000;
+pub const IMAGE_GUARD_RETPOLINE_PRESENT: u32 = 0x0010_0000;
+pub const IMAGE_GUARD_EH_CONTINUATION_TABLE_PRESENT: u32 = 0x0040_0000;
+pub const IMAGE_GUARD_XFG_ENABLED: u32 = 0x0080_0000;
+pub const IMAGE_GUARD_CASTGUARD_PRESENT: u32 = 0x0100_0000;
+pub const IMAGE_GUARD_MEMCPY_PRESENT: u32 = 0x0200_0000;
+pub const IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_MASK: u32 = 0xf000_0000;
+const IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_SHIFT: u32 = 28;
+
+pub const IMAGE_GUARD_FLAG_FID_SUPPRESSED: u8 = 0x01;
+pub const IMAGE_GUARD_FLAG_EXPORT_SUPPRESSED: u8 = 0x02;
+pub const IMAGE_GUARD_FLAG_FID_LANGEXCPTHANDLER: u8 = 0x04;
+pub const IMAGE_GUARD_FLAG_FID_XFG: u8 = 0x08;
+
+const FLAG_NAMES: [(u32, &str); 17] = [
+    (IMAGE_GUARD_CF_INSTRUMENTED, "CF_INSTRUMENTED"),
+    (IMAGE_GUARD_CFW_INSTRUMENTED, "CFW_INSTRUMENTED"),
+    (
+        IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT,
+        "CF_FUNCTION_TABLE_PRESENT",
+    ),
+    (IMAGE_GUARD_SECURITY_COOKIE_UNUSED, "SECURITY_COOKIE_UNUSED"),
+    (IMAGE_GUARD_PROTECT_DELAYLOAD_IAT, "PROTECT_DELAYLOAD_IAT"),
+    (
+        IMAGE_GUARD_DELAYLOAD_IAT_IN_ITS_OWN_SECTION,
+        "DELAYLOAD_IAT_IN_ITS_OWN_SECTION",
+    ),
+    (
+        IMAGE_GUARD_CF_EXPORT_SUPPRESSION_INFO_PRESENT,
+        "CF_EXPORT_SUPPRESSION_INFO_PRESENT",
+    ),
+    (
+        IMAGE_GUARD_CF_ENABLE_EXPORT_SUPPRESSION,
+        "CF_ENABLE_EXPORT_SUPPRESSION",
+    ),
+    (
+        IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT,
+        "CF_LONGJUMP_TABLE_PRESENT",
+    ),
+    (IMAGE_GUARD_RF_INSTRUMENTED, "RF_INSTRUMENTED"),
+    (IMAGE_GUARD_RF_ENABLE, "RF_ENABLE"),
+    (IMAGE_GUARD_RF_STRICT, "RF_STRICT"),
+    (IMAGE_GUARD_RETPOLINE_PRESENT, "RETPOLINE_PRESENT"),
+    (
+        IMAGE_GUARD_EH_CONTINUATION_TABLE_PRESENT,
+        "EH_CONTINUATION_TABLE_PRESENT",
+    ),
+    (IMAGE_GUARD_XFG_ENABLED, "XFG_ENABLED"),
+    (IMAGE_GUARD_CASTGUARD_PRESENT, "CASTGUARD_PRESENT"),
+    (IMAGE_GUARD_MEMCPY_PRESENT, "MEMCPY_PRESENT"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuardFlags(pub u32);
+
+impl GuardFlags {
+    pub fn has(&self, flag: u32) -> bool {
+        self.0 & flag != 0
+    }
+
+    pub fn cf_instrumented(&self) -> bool {
+        self.has(IMAGE_GUARD_CF_INSTRUMENTED)
+    }
+
+    // number of metadata bytes following each RVA in the guard tables
+    pub fn stride(&self) -> usize {
+        ((self.0 & IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_MASK)
+            >> IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_SHIFT) as usize
+    }
+
+    // names of the set flags, without the IMAGE_GUARD_ prefix
+    pub fn names(&self) -> Vec<&'static str> {
+        FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.has(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardEntry {
+    pub rva: u32,
+    pub metadata: Vec<u8>,
+}
+
+impl GuardEntry {
+    pub fn flags(&self) -> u8 {
+        self.metadata.first().copied().unwrap_or(0)
+    }
+
+    // valid target, but not for calls made through CFG checks (SuppressedCall)
+    pub fn is_suppressed(&self) -> bool {
+        self.flags() & IMAGE_GUARD_FLAG_FID_SUPPRESSED != 0
+    }
+
+    pub fn is_export_suppressed(&self) -> bool {
+        self.flags() & IMAGE_GUARD_FLAG_EXPORT_SUPPRESSED != 0
+    }
+
+    pub fn is_language_handler(&self) -> bool {
+        self.flags() & IMAGE_GUARD_FLAG_FID_LANGEXCPTHANDLER != 0
+    }
+
+    pub fn is_xfg(&self) -> bool {
+        self.flags() & IMAGE_GUARD_FLAG_FID_XFG != 0
+    }
+}
+
+// raw starts at the table
+pub fn guard_table(raw: &[u8], count: u64, stride: usize) -> Result<Vec<GuardEntry>, ParsingError> {
+    let entry_size = DWORD_SZ + stride;
+    let size = usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(entry_size))
+        .ok_or(ParsingError::Malformed {
+            reason: format!("guard table count {:#x} is too large", count),
+        })?;
+    let table = try_slice(raw, 0, size)?;
+
+    Ok(table
+        .chunks_exact(entry_size)
+        .map(|entry| GuardEntry {
+            rva: u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]),
+            metadata: entry[DWORD_SZ..].to_vec(),
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuardTables {
+    pub flags: GuardFlags,
+    pub functions: Vec<GuardEntry>,
+    pub address_taken_iat_entries: Vec<GuardEntry>,
+    pub long_jump_targets: Vec<GuardEntry>,
+    pub eh_continuation_targets: Vec<GuardEntry>,
+}
+
+impl GuardTables {
+    // whether an indirect call to rva passes the CFG check, the table is sorted by RVA
+    pub fn is_valid_target(&self, rva: u32) -> bool {
+        match self.functions.binary_search_by_key(&rva, |entry| entry.rva) {
+            Ok(index) => !self.functions[index].is_suppressed(),
+            Err(_) => false,
+        }
+    }
+}
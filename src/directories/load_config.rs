@@ -0,0 +1,233 @@
+use crate::prelude::*;
+
+/*
+IMAGE_LOAD_CONFIG_DIRECTORY32 / IMAGE_LOAD_CONFIG_DIRECTORY64 (PTR fields are DWORD / ULONGLONG):
++00 / +00   DWORD   Size
++04 / +04   DWORD   TimeDateStamp
++08 / +08   WORD    MajorVersion
++0A / +0A   WORD    MinorVersion
++0C / +0C   DWORD   GlobalFlagsClear
++10 / +10   DWORD   GlobalFlagsSet
++14 / +14   DWORD   CriticalSectionDefaultTimeout
++18 / +18   PTR     DeCommitFreeBlockThreshold
++1C / +20   PTR     DeCommitTotalFreeThreshold
++20 / +28   PTR     LockPrefixTable (VA)
++24 / +30   PTR     MaximumAllocationSize
++28 / +38   PTR     VirtualMemoryThreshold
++2C / +40   DWORD ProcessHeapFlags, then DWORD ProcessAffinityMask (32 bit)
+            ULONGLONG ProcessAffinityMask, then DWORD ProcessHeapFlags (64 bit)
++34 / +4C   WORD    CSDVersion
++36 / +4E   WORD    DependentLoadFlags
++38 / +50   PTR     EditList (VA)
++3C / +58   PTR     SecurityCookie (VA)
++40 / +60   PTR     SEHandlerTable (VA)
++44 / +68   PTR     SEHandlerCount
++48 / +70   PTR     GuardCFCheckFunctionPointer (VA)
++4C / +78   PTR     GuardCFDispatchFunctionPointer (VA)
++50 / +80   PTR     GuardCFFunctionTable (VA)
++54 / +88   PTR     GuardCFFunctionCount
++58 / +90   DWORD   GuardFlags
++5C / +94   IMAGE_LOAD_CONFIG_CODE_INTEGRITY (WORD Flags, WORD Catalog, DWORD CatalogOffset,
+                    DWORD Reserved)
++68 / +A0   PTR     GuardAddressTakenIatEntryTable (VA)
++6C / +A8   PTR     GuardAddressTakenIatEntryCount
++70 / +B0   PTR     GuardLongJumpTargetTable (VA)
++74 / +B8   PTR     GuardLongJumpTargetCount
++78 / +C0   PTR     DynamicValueRelocTable (VA)
++7C / +C8   PTR     CHPEMetadataPointer (VA)
++80 / +D0   PTR     GuardRFFailureRoutine (VA)
++84 / +D8   PTR     GuardRFFailureRoutineFunctionPointer (VA)
++88 / +E0   DWORD   DynamicValueRelocTableOffset
++8C / +E4   WORD    DynamicValueRelocTableSection
++8E / +E6   WORD    Reserved2
++90 / +E8   PTR     GuardRFVerifyStackPointerFunctionPointer (VA)
++94 / +F0   DWORD   HotPatchTableOffset
++98 / +F4   DWORD   Reserved3
++9C / +F8   PTR     EnclaveConfigurationPointer (VA)
++A0 / +100  PTR     VolatileMetadataPointer (VA)
++A4 / +108  PTR     GuardEHContinuationTable (VA)
++A8 / +110  PTR     GuardEHContinuationCount
++AC / +118  PTR     GuardXFGCheckFunctionPointer (VA)
++B0 / +120  PTR     GuardXFGDispatchFunctionPointer (VA)
++B4 / +128  PTR     GuardXFGTableDispatchFunctionPointer (VA)
++B8 / +130  PTR     CastGuardOsDeterminedFailureMode (VA)
++BC / +138  PTR     GuardMemcpyFunctionPointer (VA)
++C0 / +140  PTR     UmaFunctionPointers (VA)
+
+The structure has grown with almost every Windows release, and Size says how much of it is
+present. Fields past Size read as 0, the same as the loader treats them.
+ */
+pub const LOAD_CONFIG32_SZ: usize = 0xc4;
+pub const LOAD_CONFIG64_SZ: usize = 0x148;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CodeIntegrity {
+    pub flags: u16,
+    pub catalog: u16,
+    pub catalog_offset: u32,
+    pub reserved: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LoadConfig {
+    pub size: u32,
+    pub timestamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub global_flags_clear: u32,
+    pub global_flags_set: u32,
+    pub critical_section_default_timeout: u32,
+    pub decommit_free_block_threshold: u64,
+    pub decommit_total_free_threshold: u64,
+    pub lock_prefix_table: u64,
+    pub maximum_allocation_size: u64,
+    pub virtual_memory_threshold: u64,
+    pub process_heap_flags: u32,
+    pub process_affinity_mask: u64,
+    pub csd_version: u16,
+    pub dependent_load_flags: u16,
+    pub edit_list: u64,
+    pub security_cookie: u64,
+    pub se_handler_table: u64,
+    pub se_handler_count: u64,
+    pub guard_cf_check_function_pointer: u64,
+    pub guard_cf_dispatch_function_pointer: u64,
+    pub guard_cf_function_table: u64,
+    pub guard_cf_function_count: u64,
+    pub guard_flags: u32,
+    pub code_integrity: CodeIntegrity,
+    pub guard_address_taken_iat_entry_table: u64,
+    pub guard_address_taken_iat_entry_count: u64,
+    pub guard_long_jump_target_table: u64,
+    pub guard_long_jump_target_count: u64,
+    pub dynamic_value_reloc_table: u64,
+    pub chpe_metadata_pointer: u64,
+    pub guard_rf_failure_routine: u64,
+    pub guard_rf_failure_routine_function_pointer: u64,
+    pub dynamic_value_reloc_table_offset: u32,
+    pub dynamic_value_reloc_table_section: u16,
+    pub guard_rf_verify_stack_pointer_function_pointer: u64,
+    pub hot_patch_table_offset: u32,
+    pub enclave_configuration_pointer: u64,
+    pub volatile_metadata_pointer: u64,
+    pub guard_eh_continuation_table: u64,
+    pub guard_eh_continuation_count: u64,
+    pub guard_xfg_check_function_pointer: u64,
+    pub guard_xfg_dispatch_function_pointer: u64,
+    pub guard_xfg_table_dispatch_function_pointer: u64,
+    pub cast_guard_os_determined_failure_mode: u64,
+    pub guard_memcpy_function_pointer: u64,
+    pub uma_function_pointers: u64,
+    // whatever Size covers beyond the fields above, from newer versions of the structure
+    pub trailing: Vec<u8>,
+}
+
+impl LoadConfig {
+    // raw starts at the directory and may run past it, only Size bytes are used
+    pub fn new(raw: &[u8], magic: &PeFormat) -> Result<Self, ParsingError> {
+        let size = try_read_dword(raw, &mut 0)? as usize;
+        let present = try_slice(raw, 0, size)?;
+        let known = match magic {
+            PeFormat::PE32 => LOAD_CONFIG32_SZ,
+            PeFormat::PE32P => LOAD_CONFIG64_SZ,
+        };
+        // missing fields read as zeroes
+        let mut padded = present.to_vec();
+        if padded.len() < known {
+            padded.resize(known, 0);
+        }
+        let raw = &padded[..];
+
+        let mut offset = 0;
+        let ptr = |offset: &mut usize| -> Result<u64, ParsingError> {
+            Ok(ArchDependentSized::try_new(raw, offset, magic)?.value())
+        };
+        let mut config = Self {
+            size: try_read_dword(raw, &mut offset)?,
+            timestamp: try_read_dword(raw, &mut offset)?,
+            major_version: try_read_word(raw, &mut offset)?,
+            minor_version: try_read_word(raw, &mut offset)?,
+            global_flags_clear: try_read_dword(raw, &mut offset)?,
+            global_flags_set: try_read_dword(raw, &mut offset)?,
+            critical_section_default_timeout: try_read_dword(raw, &mut offset)?,
+            decommit_free_block_threshold: ptr(&mut offset)?,
+            decommit_total_free_threshold: ptr(&mut offset)?,
+            lock_prefix_table: ptr(&mut offset)?,
+            maximum_allocation_size: ptr(&mut offset)?,
+            virtual_memory_threshold: ptr(&mut offset)?,
+            ..Self::default()
+        };
+        match magic {
+            PeFormat::PE32 => {
+                config.process_heap_flags = try_read_dword(raw, &mut offset)?;
+                config.process_affinity_mask = try_read_dword(raw, &mut offset)? as u64;
+            }
+            PeFormat::PE32P => {
+                config.process_affinity_mask = try_read_dwordlong(raw, &mut offset)?;
+                config.process_heap_flags = try_read_dword(raw, &mut offset)?;
+            }
+        }
+        config.csd_version = try_read_word(raw, &mut offset)?;
+        config.dependent_load_flags = try_read_word(raw, &mut offset)?;
+        config.edit_list = ptr(&mut offset)?;
+        config.security_cookie = ptr(&mut offset)?;
+        config.se_handler_table = ptr(&mut offset)?;
+        config.se_handler_count = ptr(&mut offset)?;
+        config.guard_cf_check_function_pointer = ptr(&mut offset)?;
+        config.guard_cf_dispatch_function_pointer = ptr(&mut offset)?;
+        config.guard_cf_function_table = ptr(&mut offset)?;
+        config.guard_cf_function_count = ptr(&mut offset)?;
+        config.guard_flags = try_read_dword(raw, &mut offset)?;
+        config.code_integrity = CodeIntegrity {
+            flags: try_read_word(raw, &mut offset)?,
+            catalog: try_read_word(raw, &mut offset)?,
+            catalog_offset: try_read_dword(raw, &mut offset)?,
+            reserved: try_read_dword(raw, &mut offset)?,
+        };
+        config.guard_address_taken_iat_entry_table = ptr(&mut offset)?;
+        config.guard_address_taken_iat_entry_count = ptr(&mut offset)?;
+        config.guard_long_jump_target_table = ptr(&mut offset)?;
+        config.guard_long_jump_target_count = ptr(&mut offset)?;
+        config.dynamic_value_reloc_table = ptr(&mut offset)?;
+        config.chpe_metadata_pointer = ptr(&mut offset)?;
+        config.guard_rf_failure_routine = ptr(&mut offset)?;
+        config.guard_rf_failure_routine_function_pointer = ptr(&mut offset)?;
+        config.dynamic_value_reloc_table_offset = try_read_dword(raw, &mut offset)?;
+        config.dynamic_value_reloc_table_section = try_read_word(raw, &mut offset)?;
+        let _reserved2 = try_read_word(raw, &mut offset)?;
+        config.guard_rf_verify_stack_pointer_function_pointer = ptr(&mut offset)?;
+        config.hot_patch_table_offset = try_read_dword(raw, &mut offset)?;
+        let _reserved3 = try_read_dword(raw, &mut offset)?;
+        config.enclave_configuration_pointer = ptr(&mut offset)?;
+        config.volatile_metadata_pointer = ptr(&mut offset)?;
+        config.guard_eh_continuation_table = ptr(&mut offset)?;
+        config.guard_eh_continuation_count = ptr(&mut offset)?;
+        config.guard_xfg_check_function_pointer = ptr(&mut offset)?;
+        config.guard_xfg_dispatch_function_pointer = ptr(&mut offset)?;
+        config.guard_xfg_table_dispatch_function_pointer = ptr(&mut offset)?;
+        config.cast_guard_os_determined_failure_mode = ptr(&mut offset)?;
+        config.guard_memcpy_function_pointer = ptr(&mut offset)?;
+        config.uma_function_pointers = ptr(&mut offset)?;
+        config.trailing = present.get(known..).unwrap_or_default().to_vec();
+
+        Ok(config)
+    }
+
+    // whether Size covers the field at the given offset, for telling a zero value apart from a
+    // field this version of the structure doesn't have
+    pub fn covers(&self, offset: usize, len: usize) -> bool {
+        offset + len <= self.size as usize
+    }
+}
+
+// raw starts at the SafeSEH handler table, an array of handler RVAs
+pub fn safe_seh_handlers(raw: &[u8], count: u64) -> Result<Vec<u32>, ParsingError> {
+    let count = usize::try_from(count).map_err(|_| ParsingError::Malformed {
+        reason: format!("SafeSEH handler count {:#x} is too large", count),
+    })?;
+    let table = try_slice(raw, 0, count.saturating_mul(DWORD_SZ))?;
+    Ok(table
+        .chunks_exact(DWORD_SZ)
+        .map(|rva| u32::from_le_bytes([rva[0], rva[1], rva[2], rva[3]]))
+        .collect())
+}
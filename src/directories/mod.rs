@@ -0,0 +1,10 @@
+pub mod certificates;
+pub mod chpe;
+pub mod debug;
+pub mod dynamic_relocations;
+pub mod exceptions;
+pub mod guard;
+pub mod load_config;
+pub mod relocations;
+pub mod resources;
+pub mod tls;
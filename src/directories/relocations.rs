@@ -0,0 +1,430 @@
+use crate::headers::coff::*;
+use crate::prelude::*;
+
+/*
+Base Relocation Block:
++00 DWORD   PageRVA
++04 DWORD   BlockSize (includes this 8 byte header)
++08 WORD    Entries[(BlockSize - 8) / 2]
+
+Each entry packs the relocation type into the high 4 bits and the offset from PageRVA into the
+low 12 bits. Blocks are 32 bit aligned, so a block with an odd number of entries is padded with an
+ABSOLUTE entry.
+ */
+const BLOCK_HEADER_SZ: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocationType {
+    Absolute,
+    High,
+    Low,
+    HighLow,
+    // consumes the following entry as the low 16 bits of the adjustment
+    HighAdj,
+    MipsJmpAddr,
+    ArmMov32,
+    RiscvHigh20,
+    ThumbMov32,
+    RiscvLow12I,
+    RiscvLow12S,
+    LoongArch32MarkLa,
+    LoongArch64MarkLa,
+    MipsJmpAddr16,
+    Ia64Imm64,
+    Dir64,
+    // reserved values, or machine specific values with no meaning for this machine
+    Unknown(u8),
+}
+
+impl RelocationType {
+    // types 5, 7, 8 and 9 are reused across architectures, so the machine from the COFF header is
+    // needed to tell them apart
+    pub fn new(raw: u8, machine: u16) -> Self {
+        match (raw, machine) {
+            (0, _) => Self::Absolute,
+            (1, _) => Self::High,
+            (2, _) => Self::Low,
+            (3, _) => Self::HighLow,
+            (4, _) => Self::HighAdj,
+            (5, m) if is_mips(m) => Self::MipsJmpAddr,
+            (5, IMAGE_FILE_MACHINE_ARM | IMAGE_FILE_MACHINE_THUMB | IMAGE_FILE_MACHINE_ARMNT) => {
+                Self::ArmMov32
+            }
+            (5, m) if is_riscv(m) => Self::RiscvHigh20,
+            (7, IMAGE_FILE_MACHINE_ARM | IMAGE_FILE_MACHINE_THUMB | IMAGE_FILE_MACHINE_ARMNT) => {
+                Self::ThumbMov32
+            }
+            (7, m) if is_riscv(m) => Self::RiscvLow12I,
+            (8, m) if is_riscv(m) => Self::RiscvLow12S,
+            (8, IMAGE_FILE_MACHINE_LOONGARCH32) => Self::LoongArch32MarkLa,
+            (8, IMAGE_FILE_MACHINE_LOONGARCH64) => Self::LoongArch64MarkLa,
+            (9, m) if is_mips(m) => Self::MipsJmpAddr16,
+            (9, IMAGE_FILE_MACHINE_IA64) => Self::Ia64Imm64,
+            (10, _) => Self::Dir64,
+            (other, _) => Self::Unknown(other),
+        }
+    }
+
+    // number of bytes at the target that the loader rewrites
+    pub fn fixup_size(&self) -> usize {
+        match self {
+            Self::Absolute | Self::Unknown(_) => 0,
+            Self::High | Self::Low | Self::HighAdj => WORD_SZ,
+            Self::HighLow
+            | Self::MipsJmpAddr
+            | Self::MipsJmpAddr16
+            | Self::RiscvHigh20
+            | Self::RiscvLow12I
+            | Self::RiscvLow12S => DWORD_SZ,
+            // movw/movt and lu12i.w/ori instruction pairs
+            Self::ArmMov32 | Self::ThumbMov32 | Self::LoongArch32MarkLa | Self::Dir64 => {
+                DWORDLONG_SZ
+            }
+            // lu12i.w/ori/lu32i.d/lu52i.d, and a full IA64 bundle
+            Self::LoongArch64MarkLa | Self::Ia64Imm64 => 16,
+        }
+    }
+}
+
+fn is_mips(machine: u16) -> bool {
+    matches!(
+        machine,
+        IMAGE_FILE_MACHINE_R4000
+            | IMAGE_FILE_MACHINE_WCEMIPSV2
+            | IMAGE_FILE_MACHINE_MIPS16
+            | IMAGE_FILE_MACHINE_MIPSFPU
+            | IMAGE_FILE_MACHINE_MIPSFPU16
+    )
+}
+
+fn is_riscv(machine: u16) -> bool {
+    matches!(
+        machine,
+        IMAGE_FILE_MACHINE_RISCV32 | IMAGE_FILE_MACHINE_RISCV64 | IMAGE_FILE_MACHINE_RISCV128
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseRelocation {
+    pub kind: RelocationType,
+    pub offset: u16,
+    pub target_rva: u32,
+    // low 16 bits of the adjustment for HIGHADJ, taken from the entry that follows it
+    pub param: Option<u16>,
+}
+
+#[derive(Debug)]
+pub struct BaseRelocationBlock {
+    pub page_rva: u32,
+    pub block_size: u32,
+    pub entries: Vec<BaseRelocation>,
+}
+
+impl BaseRelocationBlock {
+    pub fn new(raw: &[u8], offset: &mut usize, machine: u16) -> Result<Self, ParsingError> {
+        let block_start = *offset;
+        let page_rva = try_read_dword(raw, offset)?;
+        let block_size = try_read_dword(raw, offset)?;
+
+        // a size smaller than the header would have the caller spin on the same block forever
+        if (block_size as usize) < BLOCK_HEADER_SZ {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "relocation block at {:#x} has size {} smaller than its header",
+                    block_start, block_size
+                ),
+            });
+        }
+        if !(block_size as usize).is_multiple_of(DWORD_SZ) {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "relocation block at {:#x} has size {} which is not 32 bit aligned",
+                    block_start, block_size
+                ),
+            });
+        }
+        let block_end = block_start + block_size as usize;
+        if block_end > raw.len() {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "relocation block at {:#x} with size {} runs past the end of the directory",
+                    block_start, block_size
+                ),
+            });
+        }
+
+        let mut entries = Vec::with_capacity((block_size as usize - BLOCK_HEADER_SZ) / WORD_SZ);
+        while *offset < block_end {
+            let entry = read_word(raw, offset);
+            let kind = RelocationType::new((entry >> 12) as u8, machine);
+            let entry_offset = entry & 0xfff;
+            let target_rva = page_rva.checked_add(entry_offset as u32).ok_or_else(|| {
+                ParsingError::Malformed {
+                    reason: format!("relocation target overflows at page {:#x}", page_rva),
+                }
+            })?;
+
+            let param = match kind {
+                RelocationType::HighAdj => {
+                    if *offset >= block_end {
+                        return Err(ParsingError::Malformed {
+                            reason: format!(
+                                "HIGHADJ relocation at {:#x} is missing its parameter entry",
+                                target_rva
+                            ),
+                        });
+                    }
+                    Some(read_word(raw, offset))
+                }
+                _ => None,
+            };
+
+            entries.push(BaseRelocation {
+                kind,
+                offset: entry_offset,
+                target_rva,
+                param,
+            });
+        }
+
+        Ok(Self {
+            page_rva,
+            block_size,
+            entries,
+        })
+    }
+
+    // entries the loader actually applies, without the ABSOLUTE padding
+    pub fn fixups(&self) -> impl Iterator<Item = &BaseRelocation> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind != RelocationType::Absolute)
+    }
+}
+
+#[derive(Debug)]
+pub struct BaseRelocationTable {
+    pub blocks: Vec<BaseRelocationBlock>,
+}
+
+impl BaseRelocationTable {
+    pub fn new(raw: &[u8], machine: u16) -> Result<Self, ParsingError> {
+        let mut offset: usize = 0;
+        let mut blocks = Vec::new();
+
+        while offset < raw.len() {
+            // the directory size is sometimes rounded up, leaving zeroed bytes that are either too
+            // short to be a block or an empty block header acting as a terminator
+            let remaining = &raw[offset..];
+            if remaining.len() < BLOCK_HEADER_SZ || remaining[..BLOCK_HEADER_SZ] == [0; 8] {
+                if remaining.iter().all(|b| *b == 0) {
+                    break;
+                }
+                if remaining.len() >= BLOCK_HEADER_SZ {
+                    return Err(ParsingError::Malformed {
+                        reason: format!("empty relocation block at {:#x}", offset),
+                    });
+                }
+                return Err(ParsingError::Malformed {
+                    reason: format!(
+                        "{} trailing bytes after the last relocation block",
+                        remaining.len()
+                    ),
+                });
+            }
+
+            blocks.push(BaseRelocationBlock::new(raw, &mut offset, machine)?);
+        }
+
+        Ok(Self { blocks })
+    }
+
+    pub fn fixups(&self) -> impl Iterator<Item = &BaseRelocation> {
+        self.blocks.iter().flat_map(|block| block.fixups())
+    }
+}
+
+/*
+ * Rebasing
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocationProblem {
+    // target isn't covered by any section header (headers, gaps, past SizeOfImage)
+    OutsideSections,
+    // the bytes being patched start in one section and end in another
+    StraddlesSections,
+    // target lies in the zero filled tail of a section, which has no bytes in the file
+    NotInFile,
+    // the fixup can't be applied for this type, or for this delta
+    Unsupported,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelocationIssue {
+    pub target_rva: u32,
+    pub kind: RelocationType,
+    pub problem: RelocationProblem,
+}
+
+#[derive(Debug)]
+pub struct RebasedImage {
+    pub image_base: u64,
+    pub bytes: Vec<u8>,
+    pub issues: Vec<RelocationIssue>,
+}
+
+impl BaseRelocation {
+    // patch the fixup located at bytes[at..]. delta is the difference between the new and the
+    // preferred image base, wrapped to 64 bits.
+    pub fn apply(&self, bytes: &mut [u8], at: usize, delta: u64) -> Result<(), RelocationProblem> {
+        let size = self.kind.fixup_size();
+        let end = at
+            .checked_add(size)
+            .ok_or(RelocationProblem::OutsideSections)?;
+        let target = bytes
+            .get_mut(at..end)
+            .ok_or(RelocationProblem::OutsideSections)?;
+        let delta32 = delta as u32;
+
+        match self.kind {
+            RelocationType::Absolute => {}
+            RelocationType::High => {
+                let value = (get16(target, 0) as u32) << 16;
+                put16(target, 0, (value.wrapping_add(delta32) >> 16) as u16);
+            }
+            RelocationType::Low => {
+                put16(target, 0, get16(target, 0).wrapping_add(delta32 as u16));
+            }
+            RelocationType::HighLow => {
+                put32(target, 0, get32(target, 0).wrapping_add(delta32));
+            }
+            RelocationType::HighAdj => {
+                // the parameter is sign extended and rounding is applied before taking the high
+                // half, the same way the loader does it
+                let mut value = (get16(target, 0) as u32) << 16;
+                value = value.wrapping_add(self.param.unwrap_or(0) as i16 as i32 as u32);
+                value = value.wrapping_add(delta32).wrapping_add(0x8000);
+                put16(target, 0, (value >> 16) as u16);
+            }
+            RelocationType::Dir64 => {
+                let value = u64::from_le_bytes(target[..8].try_into().unwrap());
+                target.copy_from_slice(&value.wrapping_add(delta).to_le_bytes());
+            }
+            RelocationType::MipsJmpAddr => {
+                let instr = get32(target, 0);
+                let address = ((instr & 0x3ff_ffff) << 2).wrapping_add(delta32);
+                put32(
+                    target,
+                    0,
+                    (instr & !0x3ff_ffff) | ((address >> 2) & 0x3ff_ffff),
+                );
+            }
+            RelocationType::ArmMov32 => {
+                let (movw, movt) = (get32(target, 0), get32(target, 4));
+                let value =
+                    (decode_arm_imm16(movw) | (decode_arm_imm16(movt) << 16)).wrapping_add(delta32);
+                put32(target, 0, encode_arm_imm16(movw, value as u16));
+                put32(target, 4, encode_arm_imm16(movt, (value >> 16) as u16));
+            }
+            RelocationType::ThumbMov32 => {
+                let value = (decode_thumb_imm16(target, 0) | (decode_thumb_imm16(target, 4) << 16))
+                    .wrapping_add(delta32);
+                encode_thumb_imm16(target, 0, value as u16);
+                encode_thumb_imm16(target, 4, (value >> 16) as u16);
+            }
+            // the RISC-V pairs can only be patched independently when the low 12 bits of the
+            // delta are zero, which is always true for a 64K aligned image base
+            RelocationType::RiscvHigh20 => {
+                if delta & 0xfff != 0 {
+                    return Err(RelocationProblem::Unsupported);
+                }
+                let instr = get32(target, 0);
+                let imm = (instr >> 12).wrapping_add(delta32 >> 12) & 0xf_ffff;
+                put32(target, 0, (instr & 0xfff) | (imm << 12));
+            }
+            RelocationType::RiscvLow12I | RelocationType::RiscvLow12S => {
+                if delta & 0xfff != 0 {
+                    return Err(RelocationProblem::Unsupported);
+                }
+            }
+            RelocationType::LoongArch32MarkLa => {
+                let (lu12i, ori) = (get32(target, 0), get32(target, 4));
+                let value = ((((lu12i >> 5) & 0xf_ffff) << 12) | ((ori >> 10) & 0xfff))
+                    .wrapping_add(delta32);
+                put32(target, 0, (lu12i & !(0xf_ffff << 5)) | ((value >> 12) << 5));
+                put32(target, 4, (ori & !(0xfff << 10)) | ((value & 0xfff) << 10));
+            }
+            RelocationType::LoongArch64MarkLa => {
+                let (lu12i, ori) = (get32(target, 0), get32(target, 4));
+                let (lu32i, lu52i) = (get32(target, 8), get32(target, 12));
+                let value = ((((lu12i >> 5) & 0xf_ffff) as u64) << 12)
+                    | (((ori >> 10) & 0xfff) as u64)
+                    | ((((lu32i >> 5) & 0xf_ffff) as u64) << 32)
+                    | ((((lu52i >> 10) & 0xfff) as u64) << 52);
+                let value = value.wrapping_add(delta);
+                let field = |bits: u64, width: u32| (bits & ((1 << width) - 1)) as u32;
+                put32(
+                    target,
+                    0,
+                    (lu12i & !(0xf_ffff << 5)) | (field(value >> 12, 20) << 5),
+                );
+                put32(target, 4, (ori & !(0xfff << 10)) | (field(value, 12) << 10));
+                put32(
+                    target,
+                    8,
+                    (lu32i & !(0xf_ffff << 5)) | (field(value >> 32, 20) << 5),
+                );
+                put32(
+                    target,
+                    12,
+                    (lu52i & !(0xfff << 10)) | (field(value >> 52, 12) << 10),
+                );
+            }
+            RelocationType::MipsJmpAddr16
+            | RelocationType::Ia64Imm64
+            | RelocationType::Unknown(_) => return Err(RelocationProblem::Unsupported),
+        }
+        Ok(())
+    }
+}
+
+fn get16(raw: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([raw[at], raw[at + 1]])
+}
+
+fn put16(raw: &mut [u8], at: usize, value: u16) {
+    raw[at..at + WORD_SZ].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get32(raw: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(raw[at..at + DWORD_SZ].try_into().unwrap())
+}
+
+fn put32(raw: &mut [u8], at: usize, value: u32) {
+    raw[at..at + DWORD_SZ].copy_from_slice(&value.to_le_bytes());
+}
+
+// A1 encoded movw/movt: imm4 in bits 19:16, imm12 in bits 11:0
+fn decode_arm_imm16(instr: u32) -> u32 {
+    ((instr >> 4) & 0xf000) | (instr & 0xfff)
+}
+
+fn encode_arm_imm16(instr: u32, imm: u16) -> u32 {
+    let imm = imm as u32;
+    (instr & 0xfff0_f000) | ((imm & 0xf000) << 4) | (imm & 0xfff)
+}
+
+// T3 encoded movw/movt split over two halfwords: imm4 and i in the first, imm3 and imm8 in the
+// second
+fn decode_thumb_imm16(raw: &[u8], at: usize) -> u32 {
+    let (hw1, hw2) = (get16(raw, at) as u32, get16(raw, at + 2) as u32);
+    ((hw1 & 0xf) << 12) | (((hw1 >> 10) & 1) << 11) | (((hw2 >> 12) & 7) << 8) | (hw2 & 0xff)
+}
+
+fn encode_thumb_imm16(raw: &mut [u8], at: usize, imm: u16) {
+    let (hw1, hw2) = (get16(raw, at), get16(raw, at + 2));
+    let hw1 = (hw1 & 0xfbf0) | ((imm >> 12) & 0xf) | (((imm >> 11) & 1) << 10);
+    let hw2 = (hw2 & 0x8f00) | (((imm >> 8) & 7) << 12) | (imm & 0xff);
+    put16(raw, at, hw1);
+    put16(raw, at + 2, hw2);
+}
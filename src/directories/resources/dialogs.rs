@@ -0,0 +1,350 @@
+use super::ResourceId;
+use crate::prelude::*;
+use std::fmt;
+
+/*
+DLGTEMPLATE:
++00 DWORD   style
++04 DWORD   dwExtendedStyle
++08 WORD    cdit
++0A (10)    short   x, y, cx, cy
++12 (18)    menu, windowClass (sz_Or_Ord), title (sz)
+            if DS_SETFONT: WORD pointsize, sz typeface
+            DLGITEMTEMPLATE[cdit], each DWORD aligned
+
+DLGITEMTEMPLATE:
++00 DWORD   style
++04 DWORD   dwExtendedStyle
++08 short   x, y, cx, cy
++10 (16)    WORD    id
++12 (18)    windowClass, title (sz_Or_Ord), WORD extraCount, creation data
+
+DLGTEMPLATEEX:
++00 WORD    dlgVer (1)
++02 WORD    signature (0xFFFF)
++04 DWORD   helpID
++08 DWORD   exStyle
++0C (12)    DWORD   style
++10 (16)    WORD    cDlgItems
++12 (18)    short   x, y, cx, cy
++1A (26)    menu, windowClass (sz_Or_Ord), title (sz)
+            if DS_SETFONT: WORD pointsize, WORD weight, BYTE italic, BYTE charset, sz typeface
+            DLGITEMTEMPLATEEX[cDlgItems], each DWORD aligned
+
+DLGITEMTEMPLATEEX:
++00 DWORD   helpID
++04 DWORD   exStyle
++08 DWORD   style
++0C (12)    short   x, y, cx, cy
++14 (20)    DWORD   id
++18 (24)    windowClass, title (sz_Or_Ord), WORD extraCount, creation data
+
+sz_Or_Ord is either 0x0000 (nothing), 0xFFFF followed by a WORD ordinal, or a null terminated
+UTF-16 string. Alignment is relative to the start of the template.
+ */
+const DS_SETFONT: u32 = 0x40;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameOrOrdinal {
+    None,
+    Ordinal(u16),
+    Name(String),
+}
+
+impl NameOrOrdinal {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        let mut peek = *offset;
+        match try_read_word(raw, &mut peek)? {
+            0 => {
+                *offset = peek;
+                Ok(Self::None)
+            }
+            0xffff => {
+                let ordinal = try_read_word(raw, &mut peek)?;
+                *offset = peek;
+                Ok(Self::Ordinal(ordinal))
+            }
+            _ => Ok(Self::Name(try_read_utf16_nul(raw, offset)?)),
+        }
+    }
+
+    // controls may name their class with one of the predefined atoms
+    pub fn class_name(&self) -> Option<String> {
+        match self {
+            Self::None => None,
+            Self::Ordinal(0x80) => Some("BUTTON".to_string()),
+            Self::Ordinal(0x81) => Some("EDIT".to_string()),
+            Self::Ordinal(0x82) => Some("STATIC".to_string()),
+            Self::Ordinal(0x83) => Some("LISTBOX".to_string()),
+            Self::Ordinal(0x84) => Some("SCROLLBAR".to_string()),
+            Self::Ordinal(0x85) => Some("COMBOBOX".to_string()),
+            Self::Ordinal(ordinal) => Some(format!("{:#x}", ordinal)),
+            Self::Name(name) => Some(name.clone()),
+        }
+    }
+}
+
+impl fmt::Display for NameOrOrdinal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "\"\""),
+            Self::Ordinal(ordinal) => write!(f, "{}", ordinal),
+            Self::Name(name) => write!(f, "{}", quote(name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialogFont {
+    pub point_size: u16,
+    // weight, italic and charset are only present in DLGTEMPLATEEX
+    pub weight: u16,
+    pub italic: bool,
+    pub charset: u8,
+    pub typeface: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialogControl {
+    pub help_id: u32,
+    pub ex_style: u32,
+    pub style: u32,
+    pub x: i16,
+    pub y: i16,
+    pub cx: i16,
+    pub cy: i16,
+    pub id: u32,
+    pub class: NameOrOrdinal,
+    pub title: NameOrOrdinal,
+    pub creation_data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dialog {
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub extended: bool,
+    pub help_id: u32,
+    pub ex_style: u32,
+    pub style: u32,
+    pub x: i16,
+    pub y: i16,
+    pub cx: i16,
+    pub cy: i16,
+    pub menu: NameOrOrdinal,
+    pub class: NameOrOrdinal,
+    pub title: String,
+    pub font: Option<DialogFont>,
+    pub controls: Vec<DialogControl>,
+}
+
+impl Dialog {
+    pub fn new(raw: &[u8], name: ResourceId, language: ResourceId) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let mut peek = 0;
+        let extended =
+            try_read_word(raw, &mut peek)? == 1 && try_read_word(raw, &mut peek)? == 0xffff;
+
+        let (help_id, ex_style, style) = match extended {
+            true => {
+                offset = peek;
+                let help_id = try_read_dword(raw, &mut offset)?;
+                let ex_style = try_read_dword(raw, &mut offset)?;
+                (help_id, ex_style, try_read_dword(raw, &mut offset)?)
+            }
+            false => {
+                let style = try_read_dword(raw, &mut offset)?;
+                (0, try_read_dword(raw, &mut offset)?, style)
+            }
+        };
+        let count = try_read_word(raw, &mut offset)?;
+        let (x, y, cx, cy) = read_rect(raw, &mut offset)?;
+        let menu = NameOrOrdinal::new(raw, &mut offset)?;
+        let class = NameOrOrdinal::new(raw, &mut offset)?;
+        let title = try_read_utf16_nul(raw, &mut offset)?;
+
+        let font = match style & DS_SETFONT {
+            0 => None,
+            _ => {
+                let point_size = try_read_word(raw, &mut offset)?;
+                let (weight, italic, charset) = match extended {
+                    true => (
+                        try_read_word(raw, &mut offset)?,
+                        try_read_byte(raw, &mut offset)? != 0,
+                        try_read_byte(raw, &mut offset)?,
+                    ),
+                    false => (0, false, 0),
+                };
+                Some(DialogFont {
+                    point_size,
+                    weight,
+                    italic,
+                    charset,
+                    typeface: try_read_utf16_nul(raw, &mut offset)?,
+                })
+            }
+        };
+
+        let mut controls = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            offset = align4(offset);
+            controls.push(read_control(raw, &mut offset, extended)?);
+        }
+
+        Ok(Self {
+            name,
+            language,
+            extended,
+            help_id,
+            ex_style,
+            style,
+            x,
+            y,
+            cx,
+            cy,
+            menu,
+            class,
+            title,
+            font,
+            controls,
+        })
+    }
+}
+
+fn read_rect(raw: &[u8], offset: &mut usize) -> Result<(i16, i16, i16, i16), ParsingError> {
+    Ok((
+        try_read_word(raw, offset)? as i16,
+        try_read_word(raw, offset)? as i16,
+        try_read_word(raw, offset)? as i16,
+        try_read_word(raw, offset)? as i16,
+    ))
+}
+
+fn read_control(
+    raw: &[u8],
+    offset: &mut usize,
+    extended: bool,
+) -> Result<DialogControl, ParsingError> {
+    let (help_id, ex_style, style) = match extended {
+        true => {
+            let help_id = try_read_dword(raw, offset)?;
+            let ex_style = try_read_dword(raw, offset)?;
+            (help_id, ex_style, try_read_dword(raw, offset)?)
+        }
+        false => {
+            let style = try_read_dword(raw, offset)?;
+            (0, try_read_dword(raw, offset)?, style)
+        }
+    };
+    let (x, y, cx, cy) = read_rect(raw, offset)?;
+    let id = match extended {
+        true => try_read_dword(raw, offset)?,
+        false => try_read_word(raw, offset)? as u32,
+    };
+    let class = NameOrOrdinal::new(raw, offset)?;
+    let title = NameOrOrdinal::new(raw, offset)?;
+    let extra = try_read_word(raw, offset)? as usize;
+    let creation_data = try_slice(raw, *offset, extra)?.to_vec();
+    *offset += extra;
+
+    Ok(DialogControl {
+        help_id,
+        ex_style,
+        style,
+        x,
+        y,
+        cx,
+        cy,
+        id,
+        class,
+        title,
+        creation_data,
+    })
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+// string literal in .rc syntax, where quotes are doubled
+pub(crate) fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\"\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\t' => quoted.push_str("\\t"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl fmt::Display for Dialog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = if self.extended { "DIALOGEX" } else { "DIALOG" };
+        writeln!(
+            f,
+            "{} {} {}, {}, {}, {}",
+            self.name, keyword, self.x, self.y, self.cx, self.cy
+        )?;
+        writeln!(f, "STYLE {:#010x}", self.style)?;
+        if self.ex_style != 0 {
+            writeln!(f, "EXSTYLE {:#010x}", self.ex_style)?;
+        }
+        if !self.title.is_empty() {
+            writeln!(f, "CAPTION {}", quote(&self.title))?;
+        }
+        if self.menu != NameOrOrdinal::None {
+            writeln!(f, "MENU {}", self.menu)?;
+        }
+        if let Some(class) = self.class.class_name() {
+            writeln!(f, "CLASS {}", quote(&class))?;
+        }
+        if let Some(font) = &self.font {
+            match self.extended {
+                true => writeln!(
+                    f,
+                    "FONT {}, {}, {}, {}, {:#x}",
+                    font.point_size,
+                    quote(&font.typeface),
+                    font.weight,
+                    font.italic as u8,
+                    font.charset
+                )?,
+                false => writeln!(f, "FONT {}, {}", font.point_size, quote(&font.typeface))?,
+            }
+        }
+        writeln!(f, "BEGIN")?;
+        for control in &self.controls {
+            // signed so IDC_STATIC comes out as -1, from a WORD in DLGITEMTEMPLATE
+            let id = match self.extended {
+                true => control.id as i32,
+                false => control.id as u16 as i16 as i32,
+            };
+            write!(
+                f,
+                "    CONTROL {}, {}, {}, {:#010x}, {}, {}, {}, {}",
+                control.title,
+                id,
+                quote(&control.class.class_name().unwrap_or_default()),
+                control.style,
+                control.x,
+                control.y,
+                control.cx,
+                control.cy
+            )?;
+            if control.ex_style != 0 || control.help_id != 0 {
+                write!(f, ", {:#010x}", control.ex_style)?;
+            }
+            if control.help_id != 0 {
+                write!(f, ", {}", control.help_id)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "END")
+    }
+}
@@ -0,0 +1,237 @@
+use super::ResourceId;
+use crate::prelude::*;
+
+/*
+RT_GROUP_ICON / RT_GROUP_CURSOR (GRPICONDIR):
++00 WORD    idReserved
++02 WORD    idType (1 = icon, 2 = cursor)
++04 WORD    idCount
++06         Entries[idCount], 14 bytes each
+
+GRPICONDIRENTRY:
++00 BYTE    bWidth
++01 BYTE    bHeight
++02 BYTE    bColorCount
++03 BYTE    bReserved
++04 WORD    wPlanes
++06 WORD    wBitCount
++08 DWORD   dwBytesInRes
++0C (12)    WORD    nId (name of the RT_ICON / RT_CURSOR resource holding the image)
+
+Cursor groups use the same size entry, but with the first four bytes as WORD wWidth and WORD
+wHeight (which counts both the XOR and AND masks, so is twice the real height). The RT_CURSOR
+images themselves start with a WORD x and WORD y hotspot.
+
+In a .ico/.cur file the entries are 16 bytes, with the nId replaced by a DWORD file offset of the
+image, and for cursors wPlanes/wBitCount replaced by the hotspot.
+ */
+const GROUP_ENTRY_SZ: usize = 14;
+const ICONDIR_SZ: usize = 6;
+const ICONDIRENTRY_SZ: usize = 16;
+const BITMAPFILEHEADER_SZ: usize = 14;
+const BITMAPCOREHEADER_SZ: u32 = 12;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconKind {
+    Icon,
+    Cursor,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupIconEntry {
+    pub width: u16,
+    pub height: u16,
+    pub color_count: u8,
+    pub reserved: u8,
+    pub planes: u16,
+    pub bit_count: u16,
+    pub bytes_in_res: u32,
+    pub id: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct IconGroup {
+    pub kind: IconKind,
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub entries: Vec<GroupIconEntry>,
+}
+
+impl IconGroup {
+    pub fn new(raw: &[u8], name: ResourceId, language: ResourceId) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let _reserved = try_read_word(raw, &mut offset)?;
+        let kind = match try_read_word(raw, &mut offset)? {
+            1 => IconKind::Icon,
+            2 => IconKind::Cursor,
+            other => {
+                return Err(ParsingError::Malformed {
+                    reason: format!("icon group has unknown type {}", other),
+                })
+            }
+        };
+        let count = try_read_word(raw, &mut offset)? as usize;
+        try_slice(raw, offset, count * GROUP_ENTRY_SZ)?;
+
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (width, height, color_count, reserved) = match kind {
+                IconKind::Icon => (
+                    read_byte(raw, &mut offset) as u16,
+                    read_byte(raw, &mut offset) as u16,
+                    read_byte(raw, &mut offset),
+                    read_byte(raw, &mut offset),
+                ),
+                IconKind::Cursor => (
+                    read_word(raw, &mut offset),
+                    read_word(raw, &mut offset) / 2,
+                    0,
+                    0,
+                ),
+            };
+            entries.push(GroupIconEntry {
+                width,
+                height,
+                color_count,
+                reserved,
+                planes: read_word(raw, &mut offset),
+                bit_count: read_word(raw, &mut offset),
+                bytes_in_res: read_dword(raw, &mut offset),
+                id: read_word(raw, &mut offset),
+            });
+        }
+
+        Ok(Self {
+            kind,
+            name,
+            language,
+            entries,
+        })
+    }
+
+    // builds a standalone .ico or .cur file. images holds the RT_ICON / RT_CURSOR data for each
+    // entry, in the same order as the entries.
+    pub fn to_file(&self, images: &[&[u8]]) -> Result<Vec<u8>, ParsingError> {
+        if images.len() != self.entries.len() {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "icon group has {} entries but {} images were given",
+                    self.entries.len(),
+                    images.len()
+                ),
+            });
+        }
+
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(
+            &match self.kind {
+                IconKind::Icon => 1u16,
+                IconKind::Cursor => 2,
+            }
+            .to_le_bytes(),
+        );
+        header.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+        let data_start = ICONDIR_SZ + ICONDIRENTRY_SZ * self.entries.len();
+
+        for (entry, image) in self.entries.iter().zip(images) {
+            // a file entry stores sizes as a byte, with 0 meaning 256
+            header.push(entry.width as u8);
+            header.push(entry.height as u8);
+            header.push(entry.color_count);
+            header.push(0);
+
+            let image = match self.kind {
+                IconKind::Icon => {
+                    header.extend_from_slice(&entry.planes.to_le_bytes());
+                    header.extend_from_slice(&entry.bit_count.to_le_bytes());
+                    *image
+                }
+                IconKind::Cursor => {
+                    let hotspot = try_slice(image, 0, DWORD_SZ)?;
+                    header.extend_from_slice(hotspot);
+                    &image[DWORD_SZ..]
+                }
+            };
+            header.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            header.extend_from_slice(&((data_start + body.len()) as u32).to_le_bytes());
+            // PNG compressed images are copied as is, same as BMP ones
+            body.extend_from_slice(image);
+        }
+
+        header.extend(body);
+        Ok(header)
+    }
+}
+
+pub fn is_png(image: &[u8]) -> bool {
+    image.starts_with(&PNG_SIGNATURE)
+}
+
+fn overflow(field: u32) -> ParsingError {
+    ParsingError::Malformed {
+        reason: format!(
+            "bitmap header field {:#x} puts the pixels out of range",
+            field
+        ),
+    }
+}
+
+// RT_BITMAP resources are a DIB without the BITMAPFILEHEADER, which needs the offset to the
+// pixels worked out from the info header and color table
+pub fn bitmap_file(dib: &[u8]) -> Result<Vec<u8>, ParsingError> {
+    let mut offset = 0;
+    let header_size = try_read_dword(dib, &mut offset)?;
+
+    let color_table = if header_size == BITMAPCOREHEADER_SZ {
+        // BITMAPCOREHEADER with RGBTRIPLE entries
+        let mut offset = 10;
+        let bit_count = try_read_word(dib, &mut offset)? as u32;
+        match bit_count {
+            1..=8 => 3 * (1 << bit_count),
+            _ => 0,
+        }
+    } else if header_size >= 40 {
+        let mut offset = 14;
+        let bit_count = try_read_word(dib, &mut offset)? as u32;
+        let compression = try_read_dword(dib, &mut offset)?;
+        let mut offset = 32;
+        let colors_used = try_read_dword(dib, &mut offset)?;
+        // only the original 40 byte header is followed by separate masks, later versions have
+        // them inside the header
+        let masks = match (header_size, compression) {
+            (40, BI_BITFIELDS) => 12,
+            (40, BI_ALPHABITFIELDS) => 16,
+            _ => 0,
+        };
+        let colors = match (colors_used, bit_count) {
+            (0, 1..=8) => 1 << bit_count,
+            (used, _) => used,
+        };
+        colors
+            .checked_mul(4)
+            .and_then(|table| table.checked_add(masks))
+            .ok_or_else(|| overflow(colors_used))?
+    } else {
+        return Err(ParsingError::Malformed {
+            reason: format!("bitmap has unknown header size {}", header_size),
+        });
+    };
+
+    let pixels = (BITMAPFILEHEADER_SZ as u32)
+        .checked_add(header_size)
+        .and_then(|size| size.checked_add(color_table))
+        .ok_or_else(|| overflow(header_size))?;
+    let size = (BITMAPFILEHEADER_SZ + dib.len()) as u32;
+    let mut file = Vec::with_capacity(size as usize);
+    file.extend_from_slice(b"BM");
+    file.extend_from_slice(&size.to_le_bytes());
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&pixels.to_le_bytes());
+    file.extend_from_slice(dib);
+    Ok(file)
+}
@@ -0,0 +1,422 @@
+use super::ResourceId;
+use crate::prelude::*;
+
+/*
+RT_MANIFEST resources are XML documents. The id says how the loader uses them:
+1   CREATEPROCESS_MANIFEST_RESOURCE_ID (executables)
+2   ISOLATIONAWARE_MANIFEST_RESOURCE_ID (DLLs)
+3   ISOLATIONAWARE_NOSTATICIMPORT_MANIFEST_RESOURCE_ID
+
+Only the handful of well known settings below are pulled out, so the parser is a small tolerant
+XML reader rather than a full implementation. Namespace prefixes are dropped from names.
+ */
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub id: ResourceId,
+    pub language: ResourceId,
+    pub text: String,
+}
+
+impl Manifest {
+    pub fn new(id: ResourceId, language: ResourceId, raw: &[u8]) -> Self {
+        Self {
+            id,
+            language,
+            text: decode_text(raw),
+        }
+    }
+
+    pub fn settings(&self) -> Result<ManifestSettings, ParsingError> {
+        ManifestSettings::new(&self.text)
+    }
+}
+
+// manifests are usually UTF-8, but UTF-16 (with or without a BOM) and legacy single byte
+// encodings show up too
+pub fn decode_text(raw: &[u8]) -> String {
+    let text = match raw {
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [a, 0, ..] if *a != 0 => utf16(raw, u16::from_le_bytes),
+        [0, b, ..] if *b != 0 => utf16(raw, u16::from_be_bytes),
+        _ => match std::str::from_utf8(raw) {
+            Ok(text) => text.to_string(),
+            // treat it as latin-1 rather than losing characters
+            Err(_) => raw.iter().map(|b| *b as char).collect(),
+        },
+    };
+    text.trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+fn utf16(raw: &[u8], convert: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = raw
+        .chunks_exact(WORD_SZ)
+        .map(|c| convert([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionLevel {
+    AsInvoker,
+    HighestAvailable,
+    RequireAdministrator,
+    Other(String),
+}
+
+impl ExecutionLevel {
+    fn new(level: &str) -> Self {
+        match level {
+            "asInvoker" => Self::AsInvoker,
+            "highestAvailable" => Self::HighestAvailable,
+            "requireAdministrator" => Self::RequireAdministrator,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportedOs {
+    pub id: String,
+}
+
+impl SupportedOs {
+    pub fn name(&self) -> Option<&'static str> {
+        match self.id.to_ascii_lowercase().as_str() {
+            "{e2011457-1546-43c5-a5fe-008deee3d3f0}" => Some("Windows Vista"),
+            "{35138b9a-5d96-4fbd-8e2d-a2440225f93a}" => Some("Windows 7"),
+            "{4a2f28e3-53b9-4441-ba9c-d69d4a4a6e38}" => Some("Windows 8"),
+            "{1f676c76-80e1-4239-95bb-83d0f6d0da78}" => Some("Windows 8.1"),
+            "{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}" => Some("Windows 10"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssemblyIdentity {
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub processor_architecture: Option<String>,
+    pub public_key_token: Option<String>,
+    pub language: Option<String>,
+}
+
+impl AssemblyIdentity {
+    fn new(element: &XmlElement) -> Self {
+        Self {
+            kind: element.attribute("type"),
+            name: element.attribute("name"),
+            version: element.attribute("version"),
+            processor_architecture: element.attribute("processorArchitecture"),
+            public_key_token: element.attribute("publicKeyToken"),
+            language: element.attribute("language"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManifestSettings {
+    pub identity: Option<AssemblyIdentity>,
+    pub execution_level: Option<ExecutionLevel>,
+    pub ui_access: Option<bool>,
+    // <dpiAware> takes true/false/per monitor, the newer <dpiAwareness> a comma separated list
+    pub dpi_aware: Option<String>,
+    pub dpi_awareness: Option<String>,
+    pub long_path_aware: Option<bool>,
+    pub supported_os: Vec<SupportedOs>,
+    pub dependencies: Vec<AssemblyIdentity>,
+}
+
+impl ManifestSettings {
+    pub fn new(text: &str) -> Result<Self, ParsingError> {
+        let root = XmlElement::parse(text)?;
+        // the identity directly under <assembly> describes the manifest itself, the ones nested
+        // in <dependency> are handled below
+        let mut settings = Self {
+            identity: root
+                .children
+                .iter()
+                .find(|child| child.name == "assemblyIdentity")
+                .map(AssemblyIdentity::new),
+            ..Default::default()
+        };
+
+        for element in root.descendants() {
+            match element.name.as_str() {
+                "requestedExecutionLevel" => {
+                    settings.execution_level =
+                        element.attribute("level").map(|l| ExecutionLevel::new(&l));
+                    settings.ui_access = element.attribute("uiAccess").map(|u| parse_bool(&u));
+                }
+                "dpiAware" => settings.dpi_aware = Some(element.text.trim().to_string()),
+                "dpiAwareness" => settings.dpi_awareness = Some(element.text.trim().to_string()),
+                "longPathAware" => settings.long_path_aware = Some(parse_bool(&element.text)),
+                "supportedOS" => {
+                    if let Some(id) = element.attribute("Id") {
+                        settings.supported_os.push(SupportedOs { id });
+                    }
+                }
+                "dependentAssembly" => {
+                    settings.dependencies.extend(
+                        element
+                            .children
+                            .iter()
+                            .filter(|child| child.name == "assemblyIdentity")
+                            .map(AssemblyIdentity::new),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+fn parse_bool(text: &str) -> bool {
+    text.trim().eq_ignore_ascii_case("true")
+}
+
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    // returns the document element
+    fn parse(text: &str) -> Result<Self, ParsingError> {
+        let mut reader = XmlReader { text, pos: 0 };
+        let mut stack: Vec<XmlElement> = vec![XmlElement::default()];
+
+        while let Some(token) = reader.next_token()? {
+            match token {
+                XmlToken::Start(element, self_closing) => {
+                    if self_closing {
+                        stack.last_mut().unwrap().children.push(element);
+                    } else {
+                        if stack.len() > MAX_DEPTH {
+                            return Err(ParsingError::Malformed {
+                                reason: "manifest is nested too deeply".to_string(),
+                            });
+                        }
+                        stack.push(element);
+                    }
+                }
+                XmlToken::End(name) => {
+                    // close everything up to the matching start tag, ignoring stray end tags
+                    if let Some(pos) = stack.iter().skip(1).rposition(|e| e.name == name) {
+                        while stack.len() > pos + 1 {
+                            let element = stack.pop().unwrap();
+                            stack.last_mut().unwrap().children.push(element);
+                        }
+                    }
+                }
+                XmlToken::Text(text) => stack.last_mut().unwrap().text.push_str(&text),
+            }
+        }
+        while stack.len() > 1 {
+            let element = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(element);
+        }
+
+        let mut document = stack.pop().unwrap();
+        match document.children.len() {
+            0 => Err(ParsingError::Malformed {
+                reason: "manifest has no document element".to_string(),
+            }),
+            _ => Ok(document.children.remove(0)),
+        }
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn descendants(&self) -> Vec<&XmlElement> {
+        let mut found = Vec::new();
+        let mut pending: Vec<&XmlElement> = self.children.iter().rev().collect();
+        while let Some(element) = pending.pop() {
+            found.push(element);
+            pending.extend(element.children.iter().rev());
+        }
+        found
+    }
+}
+
+enum XmlToken {
+    Start(XmlElement, bool),
+    End(String),
+    Text(String),
+}
+
+struct XmlReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_past(&mut self, terminator: &str) -> Result<&'a str, ParsingError> {
+        match self.rest().find(terminator) {
+            Some(end) => {
+                let skipped = &self.text[self.pos..self.pos + end];
+                self.pos += end + terminator.len();
+                Ok(skipped)
+            }
+            None => Err(ParsingError::Malformed {
+                reason: format!("manifest is missing a closing {}", terminator),
+            }),
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<XmlToken>, ParsingError> {
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Ok(None);
+            }
+
+            if !rest.starts_with('<') {
+                let end = rest.find('<').unwrap_or(rest.len());
+                let text = decode_entities(&rest[..end]);
+                self.pos += end;
+                return Ok(Some(XmlToken::Text(text)));
+            }
+
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let text = self.skip_past("]]>")?.to_string();
+                return Ok(Some(XmlToken::Text(text)));
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else if let Some(stripped) = rest.strip_prefix("</") {
+                let end = stripped.find('>').ok_or(ParsingError::Malformed {
+                    reason: "manifest has an unterminated end tag".to_string(),
+                })?;
+                let name = local_name(stripped[..end].trim());
+                self.pos += end + 3;
+                return Ok(Some(XmlToken::End(name)));
+            } else {
+                return self.start_tag().map(Some);
+            }
+        }
+    }
+
+    fn start_tag(&mut self) -> Result<XmlToken, ParsingError> {
+        let rest = &self.rest()[1..];
+        // find the end of the tag, skipping over '>' inside quoted attribute values
+        let mut quote = None;
+        let mut end = None;
+        for (i, c) in rest.char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => {
+                    end = Some(i);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let end = end.ok_or(ParsingError::Malformed {
+            reason: "manifest has an unterminated start tag".to_string(),
+        })?;
+        let tag = &rest[..end];
+        self.pos += end + 2;
+
+        let (tag, self_closing) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut element = XmlElement {
+            name: local_name(&tag[..name_end]),
+            ..Default::default()
+        };
+
+        let mut attrs = &tag[name_end..];
+        while let Some(eq) = attrs.find('=') {
+            let key = attrs[..eq].trim();
+            let value = attrs[eq + 1..].trim_start();
+            let Some(q) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                break;
+            };
+            let Some(close) = value[1..].find(q) else {
+                break;
+            };
+            element
+                .attributes
+                .push((local_name(key), decode_entities(&value[1..close + 1])));
+            attrs = &value[close + 2..];
+        }
+
+        Ok(XmlToken::Start(element, self_closing))
+    }
+}
+
+fn local_name(name: &str) -> String {
+    match name.rsplit_once(':') {
+        // keep xmlns:foo declarations distinguishable from plain attributes
+        Some(("xmlns", _)) => name.to_string(),
+        Some((_, local)) => local.to_string(),
+        None => name.to_string(),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semi];
+        let replacement = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        match replacement {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
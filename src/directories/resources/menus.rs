@@ -0,0 +1,328 @@
+use super::dialogs::quote;
+use super::ResourceId;
+use crate::prelude::*;
+use std::fmt;
+
+/*
+MENU (MENUITEMTEMPLATEHEADER):
++00 WORD    versionNumber (0)
++02 WORD    offset (to the first item, from the end of the header)
+
+MENUITEMTEMPLATE:
++00 WORD    mtOption (MF_POPUP, MF_END, ...)
++02 WORD    mtID (only when MF_POPUP isn't set)
+            mtString, null terminated UTF-16
+Popups are followed by their children, and the last item at each level has MF_END set.
+
+MENUEX (MENUEX_TEMPLATE_HEADER):
++00 WORD    wVersion (1)
++02 WORD    wOffset (to the first item, from the end of this field)
++04 DWORD   dwHelpId
+
+MENUEX_TEMPLATE_ITEM (DWORD aligned):
++00 DWORD   dwType
++04 DWORD   dwState
++08 DWORD   uId
++0C (12)    WORD    wFlags (0x01 = popup, 0x80 = last item)
++0E (14)    szText, then padding to a DWORD boundary
+            popups add a DWORD dwHelpId and are followed by their children
+
+ACCELTABLEENTRY (8 bytes):
++00 WORD    fFlags (FVIRTKEY, FNOINVERT, FSHIFT, FCONTROL, FALT, 0x80 = last entry)
++02 WORD    wAnsi (key)
++04 WORD    wId (command)
++06 WORD    padding
+ */
+const MF_POPUP: u16 = 0x10;
+const MF_END: u16 = 0x80;
+const MFR_POPUP: u16 = 0x01;
+const MFR_END: u16 = 0x80;
+const MFT_SEPARATOR: u32 = 0x800;
+const MAX_DEPTH: usize = 16;
+
+const FVIRTKEY: u16 = 0x01;
+const FNOINVERT: u16 = 0x02;
+const FSHIFT: u16 = 0x04;
+const FCONTROL: u16 = 0x08;
+const FALT: u16 = 0x10;
+const ACCEL_END: u16 = 0x80;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuItem {
+    // MF_* options for standard menus, MFT_* type for MENUEX
+    pub flags: u32,
+    // MFS_* state, MENUEX only
+    pub state: u32,
+    pub id: u32,
+    pub text: String,
+    pub help_id: Option<u32>,
+    pub children: Vec<MenuItem>,
+}
+
+impl MenuItem {
+    pub fn is_popup(&self) -> bool {
+        self.help_id.is_some() || !self.children.is_empty()
+    }
+
+    pub fn is_separator(&self) -> bool {
+        !self.is_popup()
+            && (self.flags & MFT_SEPARATOR != 0 || (self.id == 0 && self.text.is_empty()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Menu {
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub extended: bool,
+    pub help_id: u32,
+    pub items: Vec<MenuItem>,
+}
+
+impl Menu {
+    pub fn new(raw: &[u8], name: ResourceId, language: ResourceId) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let version = try_read_word(raw, &mut offset)?;
+        let header_offset = try_read_word(raw, &mut offset)? as usize;
+
+        let (help_id, items) = match version {
+            0 => {
+                offset += header_offset;
+                (0, read_items(raw, &mut offset, 0)?)
+            }
+            1 => {
+                let mut help_offset = offset;
+                let help_id = match header_offset >= DWORD_SZ {
+                    true => try_read_dword(raw, &mut help_offset)?,
+                    false => 0,
+                };
+                offset += header_offset;
+                (help_id, read_items_ex(raw, &mut offset, 0)?)
+            }
+            other => {
+                return Err(ParsingError::Malformed {
+                    reason: format!("menu has unknown version {}", other),
+                })
+            }
+        };
+
+        Ok(Self {
+            name,
+            language,
+            extended: version == 1,
+            help_id,
+            items,
+        })
+    }
+}
+
+fn too_deep(depth: usize) -> Result<(), ParsingError> {
+    match depth >= MAX_DEPTH {
+        true => Err(ParsingError::Malformed {
+            reason: "menu is nested too deeply".to_string(),
+        }),
+        false => Ok(()),
+    }
+}
+
+fn read_items(raw: &[u8], offset: &mut usize, depth: usize) -> Result<Vec<MenuItem>, ParsingError> {
+    too_deep(depth)?;
+    let mut items = Vec::new();
+    loop {
+        let option = try_read_word(raw, offset)?;
+        let id = match option & MF_POPUP {
+            0 => try_read_word(raw, offset)? as u32,
+            _ => 0,
+        };
+        let text = try_read_utf16_nul(raw, offset)?;
+        let children = match option & MF_POPUP {
+            0 => Vec::new(),
+            _ => read_items(raw, offset, depth + 1)?,
+        };
+        items.push(MenuItem {
+            flags: (option & !MF_END) as u32,
+            state: 0,
+            id,
+            text,
+            help_id: None,
+            children,
+        });
+        if option & MF_END != 0 {
+            return Ok(items);
+        }
+    }
+}
+
+fn read_items_ex(
+    raw: &[u8],
+    offset: &mut usize,
+    depth: usize,
+) -> Result<Vec<MenuItem>, ParsingError> {
+    too_deep(depth)?;
+    let mut items = Vec::new();
+    loop {
+        *offset = align4(*offset);
+        let kind = try_read_dword(raw, offset)?;
+        let state = try_read_dword(raw, offset)?;
+        let id = try_read_dword(raw, offset)?;
+        let flags = try_read_word(raw, offset)?;
+        let text = try_read_utf16_nul(raw, offset)?;
+
+        let (help_id, children) = match flags & MFR_POPUP {
+            0 => (None, Vec::new()),
+            _ => {
+                *offset = align4(*offset);
+                let help_id = try_read_dword(raw, offset)?;
+                (Some(help_id), read_items_ex(raw, offset, depth + 1)?)
+            }
+        };
+        items.push(MenuItem {
+            flags: kind,
+            state,
+            id,
+            text,
+            help_id,
+            children,
+        });
+        if flags & MFR_END != 0 {
+            return Ok(items);
+        }
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn write_items(f: &mut fmt::Formatter, items: &[MenuItem], indent: usize) -> fmt::Result {
+    let pad = "    ".repeat(indent);
+    writeln!(f, "{}BEGIN", pad)?;
+    for item in items {
+        if item.is_popup() {
+            writeln!(f, "{}    POPUP {}", pad, quote(&item.text))?;
+            write_items(f, &item.children, indent + 1)?;
+        } else if item.is_separator() {
+            writeln!(f, "{}    MENUITEM SEPARATOR", pad)?;
+        } else {
+            writeln!(f, "{}    MENUITEM {}, {}", pad, quote(&item.text), item.id)?;
+        }
+    }
+    writeln!(f, "{}END", pad)
+}
+
+impl fmt::Display for Menu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} {}",
+            self.name,
+            if self.extended { "MENUEX" } else { "MENU" }
+        )?;
+        write_items(f, &self.items, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accelerator {
+    pub flags: u16,
+    pub key: u16,
+    pub id: u16,
+}
+
+impl Accelerator {
+    pub fn is_virtkey(&self) -> bool {
+        self.flags & FVIRTKEY != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AcceleratorTable {
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub entries: Vec<Accelerator>,
+}
+
+impl AcceleratorTable {
+    pub fn new(raw: &[u8], name: ResourceId, language: ResourceId) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let mut entries = Vec::new();
+        while offset < raw.len() {
+            let flags = try_read_word(raw, &mut offset)?;
+            let key = try_read_word(raw, &mut offset)?;
+            let id = try_read_word(raw, &mut offset)?;
+            let _padding = try_read_word(raw, &mut offset)?;
+            entries.push(Accelerator {
+                flags: flags & !ACCEL_END,
+                key,
+                id,
+            });
+            if flags & ACCEL_END != 0 {
+                break;
+            }
+        }
+
+        Ok(Self {
+            name,
+            language,
+            entries,
+        })
+    }
+}
+
+fn virtual_key_name(key: u16) -> String {
+    match key {
+        0x30..=0x39 | 0x41..=0x5a => format!("\"{}\"", key as u8 as char),
+        0x70..=0x87 => format!("VK_F{}", key - 0x6f),
+        0x08 => "VK_BACK".to_string(),
+        0x09 => "VK_TAB".to_string(),
+        0x0d => "VK_RETURN".to_string(),
+        0x1b => "VK_ESCAPE".to_string(),
+        0x20 => "VK_SPACE".to_string(),
+        0x21 => "VK_PRIOR".to_string(),
+        0x22 => "VK_NEXT".to_string(),
+        0x23 => "VK_END".to_string(),
+        0x24 => "VK_HOME".to_string(),
+        0x25 => "VK_LEFT".to_string(),
+        0x26 => "VK_UP".to_string(),
+        0x27 => "VK_RIGHT".to_string(),
+        0x28 => "VK_DOWN".to_string(),
+        0x2d => "VK_INSERT".to_string(),
+        0x2e => "VK_DELETE".to_string(),
+        other => format!("{:#04x}", other),
+    }
+}
+
+impl fmt::Display for AcceleratorTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ACCELERATORS", self.name)?;
+        writeln!(f, "BEGIN")?;
+        for entry in &self.entries {
+            let key = match (entry.is_virtkey(), entry.key) {
+                (true, key) => virtual_key_name(key),
+                // control characters are written as ^X
+                (false, key @ 1..=0x1a) => format!("\"^{}\"", (key as u8 + b'@') as char),
+                (false, key) => match char::from_u32(key as u32) {
+                    Some(c) if !c.is_control() => quote(&c.to_string()),
+                    _ => key.to_string(),
+                },
+            };
+            write!(f, "    {}, {}", key, entry.id)?;
+            for (flag, name) in [
+                (FVIRTKEY, "VIRTKEY"),
+                (FNOINVERT, "NOINVERT"),
+                (FSHIFT, "SHIFT"),
+                (FCONTROL, "CONTROL"),
+                (FALT, "ALT"),
+            ] {
+                if entry.flags & flag != 0 {
+                    write!(f, ", {}", name)?;
+                }
+            }
+            if !entry.is_virtkey() {
+                write!(f, ", ASCII")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "END")
+    }
+}
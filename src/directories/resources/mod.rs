@@ -0,0 +1,406 @@
+pub mod dialogs;
+pub mod icons;
+pub mod manifest;
+pub mod menus;
+pub mod strings;
+pub mod typelib;
+pub mod version;
+
+use crate::prelude::*;
+use std::collections::HashSet;
+use std::fmt;
+
+/*
+Resource Directory Table:
++00 DWORD   Characteristics
++04 DWORD   TimeDateStamp
++08 WORD    MajorVersion
++0A (10)    WORD    MinorVersion
++0C (12)    WORD    NumberOfNameEntries
++0E (14)    WORD    NumberOfIdEntries
++10 (16)    Entries[NumberOfNameEntries + NumberOfIdEntries]
+
+Resource Directory Entry:
++00 DWORD   Name offset (high bit set) or Integer ID
++04 DWORD   Subdirectory offset (high bit set) or Data Entry offset
+
+Resource Data Entry:
++00 DWORD   Data RVA
++04 DWORD   Size
++08 DWORD   Codepage
++0C (12)    DWORD   Reserved
+
+All offsets other than the data RVA are relative to the start of the resource section. By
+convention the tree has three levels: type, name, and language.
+ */
+const HIGH_BIT: u32 = 0x8000_0000;
+
+// real trees are 3 levels deep, anything much deeper is malformed or hostile
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceType {
+    Cursor,
+    Bitmap,
+    Icon,
+    Menu,
+    Dialog,
+    String,
+    FontDir,
+    Font,
+    Accelerator,
+    RcData,
+    MessageTable,
+    GroupCursor,
+    GroupIcon,
+    Version,
+    DlgInclude,
+    PlugPlay,
+    Vxd,
+    AniCursor,
+    AniIcon,
+    Html,
+    Manifest,
+}
+
+impl ResourceType {
+    pub fn from_id(id: u32) -> Option<Self> {
+        match id {
+            1 => Some(Self::Cursor),
+            2 => Some(Self::Bitmap),
+            3 => Some(Self::Icon),
+            4 => Some(Self::Menu),
+            5 => Some(Self::Dialog),
+            6 => Some(Self::String),
+            7 => Some(Self::FontDir),
+            8 => Some(Self::Font),
+            9 => Some(Self::Accelerator),
+            10 => Some(Self::RcData),
+            11 => Some(Self::MessageTable),
+            12 => Some(Self::GroupCursor),
+            14 => Some(Self::GroupIcon),
+            16 => Some(Self::Version),
+            17 => Some(Self::DlgInclude),
+            19 => Some(Self::PlugPlay),
+            20 => Some(Self::Vxd),
+            21 => Some(Self::AniCursor),
+            22 => Some(Self::AniIcon),
+            23 => Some(Self::Html),
+            24 => Some(Self::Manifest),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Cursor => 1,
+            Self::Bitmap => 2,
+            Self::Icon => 3,
+            Self::Menu => 4,
+            Self::Dialog => 5,
+            Self::String => 6,
+            Self::FontDir => 7,
+            Self::Font => 8,
+            Self::Accelerator => 9,
+            Self::RcData => 10,
+            Self::MessageTable => 11,
+            Self::GroupCursor => 12,
+            Self::GroupIcon => 14,
+            Self::Version => 16,
+            Self::DlgInclude => 17,
+            Self::PlugPlay => 19,
+            Self::Vxd => 20,
+            Self::AniCursor => 21,
+            Self::AniIcon => 22,
+            Self::Html => 23,
+            Self::Manifest => 24,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cursor => "RT_CURSOR",
+            Self::Bitmap => "RT_BITMAP",
+            Self::Icon => "RT_ICON",
+            Self::Menu => "RT_MENU",
+            Self::Dialog => "RT_DIALOG",
+            Self::String => "RT_STRING",
+            Self::FontDir => "RT_FONTDIR",
+            Self::Font => "RT_FONT",
+            Self::Accelerator => "RT_ACCELERATOR",
+            Self::RcData => "RT_RCDATA",
+            Self::MessageTable => "RT_MESSAGETABLE",
+            Self::GroupCursor => "RT_GROUP_CURSOR",
+            Self::GroupIcon => "RT_GROUP_ICON",
+            Self::Version => "RT_VERSION",
+            Self::DlgInclude => "RT_DLGINCLUDE",
+            Self::PlugPlay => "RT_PLUGPLAY",
+            Self::Vxd => "RT_VXD",
+            Self::AniCursor => "RT_ANICURSOR",
+            Self::AniIcon => "RT_ANIICON",
+            Self::Html => "RT_HTML",
+            Self::Manifest => "RT_MANIFEST",
+        }
+    }
+}
+
+impl fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceId {
+    Id(u32),
+    Name(String),
+}
+
+impl ResourceId {
+    pub fn as_id(&self) -> Option<u32> {
+        match self {
+            Self::Id(id) => Some(*id),
+            Self::Name(_) => None,
+        }
+    }
+
+    // names are matched case insensitively, the same way FindResource does
+    pub fn matches(&self, other: &ResourceId) -> bool {
+        match (self, other) {
+            (Self::Id(a), Self::Id(b)) => a == b,
+            (Self::Name(a), Self::Name(b)) => a.eq_ignore_ascii_case(b),
+            _ => false,
+        }
+    }
+}
+
+impl From<ResourceType> for ResourceId {
+    fn from(kind: ResourceType) -> Self {
+        Self::Id(kind.id())
+    }
+}
+
+impl From<u32> for ResourceId {
+    fn from(id: u32) -> Self {
+        Self::Id(id)
+    }
+}
+
+impl From<&str> for ResourceId {
+    fn from(name: &str) -> Self {
+        Self::Name(name.to_string())
+    }
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "#{}", id),
+            Self::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceDataEntry {
+    pub data_rva: u32,
+    pub size: u32,
+    pub codepage: u32,
+    pub reserved: u32,
+}
+
+#[derive(Debug)]
+pub enum ResourceNode {
+    Directory(ResourceDirectory),
+    Data(ResourceDataEntry),
+}
+
+#[derive(Debug)]
+pub struct ResourceEntry {
+    pub id: ResourceId,
+    pub node: ResourceNode,
+}
+
+#[derive(Debug)]
+pub struct ResourceDirectory {
+    pub characteristics: u32,
+    pub timestamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub entries: Vec<ResourceEntry>,
+}
+
+impl ResourceDirectory {
+    // raw starts at the root of the resource section. visited holds the offsets of every
+    // directory parsed so far, so a tree pointing back at itself (or sharing subtrees to blow up
+    // its size) is rejected.
+    fn new(
+        raw: &[u8],
+        dir_offset: usize,
+        depth: usize,
+        visited: &mut HashSet<usize>,
+    ) -> Result<Self, ParsingError> {
+        if depth >= MAX_DEPTH {
+            return Err(ParsingError::Malformed {
+                reason: format!("resource tree is deeper than {} levels", MAX_DEPTH),
+            });
+        }
+        if !visited.insert(dir_offset) {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "resource directory at {:#x} is referenced more than once",
+                    dir_offset
+                ),
+            });
+        }
+
+        let mut offset = dir_offset;
+        let characteristics = try_read_dword(raw, &mut offset)?;
+        let timestamp = try_read_dword(raw, &mut offset)?;
+        let major_version = try_read_word(raw, &mut offset)?;
+        let minor_version = try_read_word(raw, &mut offset)?;
+        let num_named = try_read_word(raw, &mut offset)? as usize;
+        let num_ids = try_read_word(raw, &mut offset)? as usize;
+
+        // make sure the entry array is there before allocating for it
+        try_slice(raw, offset, (num_named + num_ids) * DWORDLONG_SZ)?;
+        let mut entries = Vec::with_capacity(num_named + num_ids);
+        for _ in 0..num_named + num_ids {
+            let name = try_read_dword(raw, &mut offset)?;
+            let target = try_read_dword(raw, &mut offset)?;
+
+            let id = match name & HIGH_BIT {
+                0 => ResourceId::Id(name),
+                _ => {
+                    let mut name_offset = (name & !HIGH_BIT) as usize;
+                    let len = try_read_word(raw, &mut name_offset)? as usize;
+                    ResourceId::Name(try_read_utf16(raw, &mut name_offset, len)?)
+                }
+            };
+
+            let node = match target & HIGH_BIT {
+                0 => {
+                    let mut data_offset = target as usize;
+                    ResourceNode::Data(ResourceDataEntry {
+                        data_rva: try_read_dword(raw, &mut data_offset)?,
+                        size: try_read_dword(raw, &mut data_offset)?,
+                        codepage: try_read_dword(raw, &mut data_offset)?,
+                        reserved: try_read_dword(raw, &mut data_offset)?,
+                    })
+                }
+                _ => ResourceNode::Directory(Self::new(
+                    raw,
+                    (target & !HIGH_BIT) as usize,
+                    depth + 1,
+                    visited,
+                )?),
+            };
+
+            entries.push(ResourceEntry { id, node });
+        }
+
+        Ok(Self {
+            characteristics,
+            timestamp,
+            major_version,
+            minor_version,
+            entries,
+        })
+    }
+}
+
+// a leaf of the tree along with the path that leads to it
+#[derive(Debug, Clone)]
+pub struct Resource {
+    pub kind: ResourceId,
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub data: ResourceDataEntry,
+}
+
+impl Resource {
+    pub fn resource_type(&self) -> Option<ResourceType> {
+        match self.kind {
+            ResourceId::Id(id) => ResourceType::from_id(id),
+            ResourceId::Name(_) => None,
+        }
+    }
+
+    pub fn language_id(&self) -> Option<u16> {
+        self.language.as_id().and_then(|id| u16::try_from(id).ok())
+    }
+}
+
+#[derive(Debug)]
+pub struct ResourceTree {
+    pub root: ResourceDirectory,
+}
+
+impl ResourceTree {
+    pub fn empty() -> Self {
+        Self {
+            root: ResourceDirectory {
+                characteristics: 0,
+                timestamp: 0,
+                major_version: 0,
+                minor_version: 0,
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut visited = HashSet::new();
+        let root = ResourceDirectory::new(raw, 0, 0, &mut visited)?;
+        Ok(Self { root })
+    }
+
+    // every type/name/language leaf. Data entries found at any other depth don't follow the
+    // convention and are left out.
+    pub fn resources(&self) -> Vec<Resource> {
+        let mut resources = Vec::new();
+        for kind in &self.root.entries {
+            let ResourceNode::Directory(names) = &kind.node else {
+                continue;
+            };
+            for name in &names.entries {
+                let ResourceNode::Directory(languages) = &name.node else {
+                    continue;
+                };
+                for language in &languages.entries {
+                    if let ResourceNode::Data(data) = &language.node {
+                        resources.push(Resource {
+                            kind: kind.id.clone(),
+                            name: name.id.clone(),
+                            language: language.id.clone(),
+                            data: data.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        resources
+    }
+
+    pub fn of_type(&self, kind: impl Into<ResourceId>) -> Vec<Resource> {
+        let kind = kind.into();
+        self.resources()
+            .into_iter()
+            .filter(|resource| resource.kind.matches(&kind))
+            .collect()
+    }
+
+    // e.g. find(ResourceType::Version, 1, None) for "RT_VERSION, id 1, any language"
+    pub fn find(
+        &self,
+        kind: impl Into<ResourceId>,
+        name: impl Into<ResourceId>,
+        language: Option<u16>,
+    ) -> Option<Resource> {
+        let name = name.into();
+        self.of_type(kind).into_iter().find(|resource| {
+            resource.name.matches(&name)
+                && language.is_none_or(|lang| resource.language_id() == Some(lang))
+        })
+    }
+}
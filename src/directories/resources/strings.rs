@@ -0,0 +1,152 @@
+use super::ResourceId;
+use crate::prelude::*;
+
+/*
+RT_STRING:
+Strings are stored in blocks of 16. The resource named N holds string ids (N - 1) * 16 through
+(N - 1) * 16 + 15, each as a WORD length (in UTF-16 code units) followed by the text, with no null
+terminator. Unused ids in a block have a length of 0.
+
+RT_MESSAGETABLE (MESSAGE_RESOURCE_DATA):
++00 DWORD   NumberOfBlocks
++04         Blocks[NumberOfBlocks]
+
+MESSAGE_RESOURCE_BLOCK:
++00 DWORD   LowId
++04 DWORD   HighId
++08 DWORD   OffsetToEntries (from the start of the resource)
+
+MESSAGE_RESOURCE_ENTRY (one per id from LowId to HighId):
++00 WORD    Length (of the whole entry)
++02 WORD    Flags (0 = ANSI, 1 = UTF-16, 2 = UTF-8)
++04         Text, null padded
+ */
+const STRINGS_PER_BLOCK: u32 = 16;
+const MAX_STRING_BLOCK: u32 = 0x1000;
+const MESSAGE_ENTRY_HEADER_SZ: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringEntry {
+    pub id: u32,
+    pub language: ResourceId,
+    pub text: String,
+}
+
+// block_id is the name of the RT_STRING resource the block came from
+pub fn decode_string_block(
+    raw: &[u8],
+    block_id: u32,
+    language: &ResourceId,
+) -> Result<Vec<StringEntry>, ParsingError> {
+    // string ids are WORDs, so the last block is 0x1000
+    if block_id == 0 || block_id > MAX_STRING_BLOCK {
+        return Err(ParsingError::Malformed {
+            reason: format!("string table block id {} isn't in 1..=0x1000", block_id),
+        });
+    }
+
+    let first_id = (block_id - 1) * STRINGS_PER_BLOCK;
+    let mut offset = 0;
+    let mut strings = Vec::new();
+    for index in 0..STRINGS_PER_BLOCK {
+        // blocks are occasionally truncated after the last used string
+        if offset >= raw.len() {
+            break;
+        }
+        let len = try_read_word(raw, &mut offset)? as usize;
+        if len == 0 {
+            continue;
+        }
+        strings.push(StringEntry {
+            id: first_id + index,
+            language: language.clone(),
+            text: try_read_utf16(raw, &mut offset, len)?,
+        });
+    }
+    Ok(strings)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageEncoding {
+    Ansi,
+    Unicode,
+    Utf8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageEntry {
+    pub id: u32,
+    pub language: ResourceId,
+    pub encoding: MessageEncoding,
+    pub text: String,
+}
+
+pub fn decode_message_table(
+    raw: &[u8],
+    language: &ResourceId,
+) -> Result<Vec<MessageEntry>, ParsingError> {
+    let mut offset = 0;
+    let num_blocks = try_read_dword(raw, &mut offset)? as usize;
+    try_slice(raw, offset, num_blocks * 3 * DWORD_SZ)?;
+
+    let mut messages = Vec::new();
+    for _ in 0..num_blocks {
+        let low_id = read_dword(raw, &mut offset);
+        let high_id = read_dword(raw, &mut offset);
+        let mut entry_offset = read_dword(raw, &mut offset) as usize;
+        if high_id < low_id {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "message table block has ids {:#x} to {:#x}",
+                    low_id, high_id
+                ),
+            });
+        }
+
+        // every entry is at least a header long, so a bogus id range runs out of data quickly
+        for id in low_id..=high_id {
+            let start = entry_offset;
+            let length = try_read_word(raw, &mut entry_offset)? as usize;
+            let flags = try_read_word(raw, &mut entry_offset)?;
+            if length < MESSAGE_ENTRY_HEADER_SZ {
+                return Err(ParsingError::Malformed {
+                    reason: format!("message {:#x} has length {}", id, length),
+                });
+            }
+            let text = try_slice(raw, entry_offset, length - MESSAGE_ENTRY_HEADER_SZ)?;
+            entry_offset = start + length;
+
+            let (encoding, text) = match flags {
+                0 => (
+                    MessageEncoding::Ansi,
+                    // no codepage is recorded, so the bytes are taken as latin-1
+                    text.iter().map(|b| *b as char).collect::<String>(),
+                ),
+                1 => {
+                    let units: Vec<u16> = text
+                        .chunks_exact(WORD_SZ)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect();
+                    (MessageEncoding::Unicode, String::from_utf16_lossy(&units))
+                }
+                2 => (
+                    MessageEncoding::Utf8,
+                    String::from_utf8_lossy(text).to_string(),
+                ),
+                other => {
+                    return Err(ParsingError::Malformed {
+                        reason: format!("message {:#x} has unknown flags {:#x}", id, other),
+                    })
+                }
+            };
+
+            messages.push(MessageEntry {
+                id,
+                language: language.clone(),
+                encoding,
+                text: text.trim_end_matches('\0').to_string(),
+            });
+        }
+    }
+    Ok(messages)
+}
@@ -0,0 +1,1365 @@
+use crate::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+MSFT type library header:
++00 DWORD   magic ("MSFT")
++04 DWORD   format version
++08 DWORD   offset of the library GUID in the GUID table
++0C (12)    DWORD   lcid
++10 (16)    DWORD   lcid2
++14 (20)    DWORD   varflags (low nibble is the SYSKIND, 0x100 = help DLL present)
++18 (24)    DWORD   version (major in the low word)
++1C (28)    DWORD   LIBFLAGS
++20 (32)    DWORD   number of typeinfos
++24 (36)    DWORD   help string (string table offset)
++28 (40)    DWORD   help string context
++2C (44)    DWORD   help context
++30 (48)    DWORD   number of names
++34 (52)    DWORD   number of name characters
++38 (56)    DWORD   library name (name table offset)
++3C (60)    DWORD   help file (string table offset)
++40 (64)    DWORD   custom data offset
++44 (68)    DWORD   reserved
++48 (72)    DWORD   reserved
++4C (76)    DWORD   HREFTYPE of IDispatch
++50 (80)    DWORD   number of imported typeinfos
++54 (84)    DWORD   help string DLL (only with varflags 0x100)
+            DWORD[number of typeinfos] typeinfo offsets
+            Segment directory, 15 entries of DWORD offset, DWORD length, DWORD, DWORD. In order the
+            segments are the typeinfo table, import info, import files, reference table, GUID hash,
+            GUID table, name hash, name table, string table, type descriptions, array
+            descriptions, custom data, custom data GUIDs and two unknown ones.
+
+Every offset in the file is relative to the start of the type library, and -1 means missing.
+
+Typeinfo (0x64 bytes):
++00 DWORD   TYPEKIND in the low nibble, alignment in bits 11-15
++04 DWORD   offset of the member data
++18 (24)    DWORD   number of functions (low word) and variables (high word)
++2C (44)    DWORD   GUID (GUID table offset)
++30 (48)    DWORD   TYPEFLAGS
++34 (52)    DWORD   name (name table offset)
++38 (56)    DWORD   version (major in the low word)
++3C (60)    DWORD   doc string (string table offset)
++4C (76)    WORD    number of implemented types
++4E (78)    WORD    vtable size
++50 (80)    DWORD   size of an instance
++54 (84)    DWORD   aliased type, base interface HREFTYPE, coclass reference table offset or module
+                    DLL name, depending on the kind
+
+Member data:
++00 DWORD   length of the records
++04         function records, then variable records, each starting with a DWORD whose low word
+            is the record length
+            DWORD[members] member ids
+            DWORD[members] names (name table offsets)
+            DWORD[members] record offsets
+
+Function record:
++04 DWORD   return type
++08 DWORD   FUNCFLAGS
++0C (12)    WORD    vtable offset
++10 (16)    DWORD   FUNCKIND (bits 0-2), INVOKEKIND (bits 3-6), CALLCONV (bits 8-11), 0x1000 = default
+                    values present, 0x2000 = entry point is an ordinal
++14 (20)    WORD    number of parameters
++16 (22)    WORD    number of optional parameters
++18 (24)    optional fields up to the parameters: help context, help string, entry point, ...
+            DWORD[parameters] default values, when flagged
+            parameters, 12 bytes each: DWORD type, DWORD name, DWORD PARAMFLAGS
+
+Variable record:
++04 DWORD   type
++08 DWORD   VARFLAGS
++0C (12)    WORD    VARKIND
++10 (16)    DWORD   value (constants) or offset in the instance
++14 (20)    optional fields: help context, help string, ...
+
+Types are either a negative number with the VARTYPE in the low 12 bits, or an offset into the
+type description table whose 8 byte entries are WORD vt, WORD, then for pointers and safe arrays
+the pointed to type (a VARTYPE when the last WORD is negative), for C arrays an offset into the
+array description table, and for user defined types a HREFTYPE split across both WORDs.
+
+HREFTYPEs with the low two bits clear are an offset into the typeinfo table. Otherwise they're an
+offset into the import info table, whose entries are DWORD flags (0x10000 = the target is a GUID
+table offset), DWORD import file offset and DWORD target. Import files are DWORD GUID, DWORD
+lcid, DWORD version, WORD length << 2 and the file name.
+
+Constant values with the high bit set hold the VARTYPE in bits 26-30 and the value in the low 26
+bits, anything else is an offset into the custom data table of a WORD VARTYPE and the value.
+ */
+const MSFT_MAGIC: u32 = 0x5446534d;
+const SLTG_MAGIC: u32 = 0x47544c53;
+const HEADER_SZ: usize = 0x54;
+const HELP_DLL_FLAG: u32 = 0x100;
+const SEGMENT_COUNT: usize = 15;
+const SEGMENT_ENTRY_SZ: usize = 16;
+const TYPEINFO_SZ: usize = 0x64;
+const PARAMETER_SZ: usize = 12;
+const FUNCTION_RECORD_MIN: usize = 0x18;
+const VARIABLE_RECORD_MIN: usize = 0x14;
+const HAS_DEFAULT_VALUES: u32 = 0x1000;
+const ENTRY_IS_ORDINAL: u32 = 0x2000;
+const IMPORT_IS_GUID: u32 = 0x10000;
+const MAX_TYPE_DEPTH: usize = 16;
+
+const SEG_TYPEINFO: usize = 0;
+const SEG_IMPORT_INFO: usize = 1;
+const SEG_IMPORT_FILES: usize = 2;
+const SEG_REFERENCES: usize = 3;
+const SEG_GUIDS: usize = 5;
+const SEG_NAMES: usize = 7;
+const SEG_STRINGS: usize = 8;
+const SEG_TYPE_DESCS: usize = 9;
+const SEG_ARRAY_DESCS: usize = 10;
+const SEG_CUSTOM_DATA: usize = 11;
+
+const VT_TYPEMASK: u32 = 0xfff;
+const VT_I2: u16 = 2;
+const VT_I4: u16 = 3;
+const VT_R4: u16 = 4;
+const VT_R8: u16 = 5;
+const VT_DATE: u16 = 7;
+const VT_BSTR: u16 = 8;
+const VT_ERROR: u16 = 10;
+const VT_BOOL: u16 = 11;
+const VT_I1: u16 = 16;
+const VT_UI1: u16 = 17;
+const VT_UI2: u16 = 18;
+const VT_UI4: u16 = 19;
+const VT_I8: u16 = 20;
+const VT_UI8: u16 = 21;
+const VT_INT: u16 = 22;
+const VT_UINT: u16 = 23;
+const VT_HRESULT: u16 = 25;
+const VT_PTR: u16 = 26;
+const VT_SAFEARRAY: u16 = 27;
+const VT_CARRAY: u16 = 28;
+const VT_USERDEFINED: u16 = 29;
+
+const FUNC_DISPATCH: u8 = 4;
+const PARAMFLAG_FIN: u32 = 0x01;
+const PARAMFLAG_FOUT: u32 = 0x02;
+const PARAMFLAG_FLCID: u32 = 0x04;
+const PARAMFLAG_FRETVAL: u32 = 0x08;
+const PARAMFLAG_FOPT: u32 = 0x10;
+const PARAMFLAG_FHASDEFAULT: u32 = 0x20;
+const IMPLTYPEFLAG_FDEFAULT: u32 = 0x01;
+const IMPLTYPEFLAG_FSOURCE: u32 = 0x02;
+const IMPLTYPEFLAG_FRESTRICTED: u32 = 0x04;
+
+const TYPE_ATTRIBUTES: [(u32, &str); 9] = [
+    (0x01, "appobject"),
+    (0x04, "licensed"),
+    (0x10, "hidden"),
+    (0x20, "control"),
+    (0x40, "dual"),
+    (0x80, "nonextensible"),
+    (0x100, "oleautomation"),
+    (0x200, "restricted"),
+    (0x400, "aggregatable"),
+];
+const FUNCTION_ATTRIBUTES: [(u16, &str); 5] = [
+    (0x01, "restricted"),
+    (0x02, "source"),
+    (0x04, "bindable"),
+    (0x40, "hidden"),
+    (0x100, "defaultbind"),
+];
+
+// interfaces nearly every library imports from stdole
+const WELL_KNOWN_INTERFACES: [(&str, &str); 4] = [
+    ("00000000-0000-0000-C000-000000000046", "IUnknown"),
+    ("00020400-0000-0000-C000-000000000046", "IDispatch"),
+    ("00020404-0000-0000-C000-000000000046", "IEnumVARIANT"),
+    ("7BF80981-BF32-101A-8BBA-00AA00300CAB", "IPictureDisp"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SysKind {
+    Win16,
+    Win32,
+    Mac,
+    Win64,
+    Unknown(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeKind {
+    Enum,
+    Record,
+    Module,
+    Interface,
+    Dispatch,
+    CoClass,
+    Alias,
+    Union,
+    Unknown(u32),
+}
+
+impl TypeKind {
+    pub fn new(raw: u32) -> Self {
+        match raw {
+            0 => Self::Enum,
+            1 => Self::Record,
+            2 => Self::Module,
+            3 => Self::Interface,
+            4 => Self::Dispatch,
+            5 => Self::CoClass,
+            6 => Self::Alias,
+            7 => Self::Union,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvokeKind {
+    Func,
+    PropertyGet,
+    PropertyPut,
+    PropertyPutRef,
+    Unknown(u32),
+}
+
+impl InvokeKind {
+    pub fn new(raw: u32) -> Self {
+        match raw {
+            1 => Self::Func,
+            2 => Self::PropertyGet,
+            4 => Self::PropertyPut,
+            8 => Self::PropertyPutRef,
+            other => Self::Unknown(other),
+        }
+    }
+
+    fn attribute(&self) -> Option<&'static str> {
+        match self {
+            Self::PropertyGet => Some("propget"),
+            Self::PropertyPut => Some("propput"),
+            Self::PropertyPutRef => Some("propputref"),
+            _ => None,
+        }
+    }
+}
+
+// a type defined in this library, or imported from another one
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeRef {
+    pub name: String,
+    pub guid: Option<Guid>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeDesc {
+    // a VT_* automation type
+    Base(u16),
+    Pointer(Box<TypeDesc>),
+    SafeArray(Box<TypeDesc>),
+    // bounds are (element count, lower bound) for each dimension
+    CArray {
+        element: Box<TypeDesc>,
+        bounds: Vec<(u32, i32)>,
+    },
+    UserDefined(TypeRef),
+}
+
+impl fmt::Display for TypeDesc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Base(vt) => write!(f, "{}", base_type_name(*vt)),
+            Self::Pointer(inner) => write!(f, "{}*", inner),
+            Self::SafeArray(inner) => write!(f, "SAFEARRAY({})", inner),
+            Self::CArray { element, bounds } => {
+                write!(f, "{}", element)?;
+                for (count, _) in bounds {
+                    write!(f, "[{}]", count)?;
+                }
+                Ok(())
+            }
+            Self::UserDefined(reference) => write!(f, "{}", reference.name),
+        }
+    }
+}
+
+fn base_type_name(vt: u16) -> String {
+    match vt {
+        0 => "EMPTY",
+        1 => "NULL",
+        2 => "short",
+        3 => "long",
+        4 => "single",
+        5 => "double",
+        6 => "CURRENCY",
+        7 => "DATE",
+        8 => "BSTR",
+        9 => "IDispatch*",
+        10 => "SCODE",
+        11 => "VARIANT_BOOL",
+        12 => "VARIANT",
+        13 => "IUnknown*",
+        14 => "DECIMAL",
+        16 => "char",
+        17 => "unsigned char",
+        18 => "unsigned short",
+        19 => "unsigned long",
+        20 => "int64",
+        21 => "uint64",
+        22 => "int",
+        23 => "unsigned int",
+        24 => "void",
+        25 => "HRESULT",
+        30 => "LPSTR",
+        31 => "LPWSTR",
+        36 => "RECORD",
+        37 => "INT_PTR",
+        38 => "UINT_PTR",
+        64 => "FILETIME",
+        other => return format!("VARTYPE({:#x})", other),
+    }
+    .to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Unsigned(u64),
+    Float(f64),
+    Bool(bool),
+    // None for a null BSTR
+    String(Option<String>),
+    // a VARTYPE whose value isn't decoded
+    Other(u16),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Unsigned(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{}", value),
+            Self::Bool(value) => write!(f, "{}", if *value { "-1" } else { "0" }),
+            Self::String(Some(text)) => write!(f, "{:?}", text),
+            Self::String(None) => write!(f, "NULL"),
+            Self::Other(vt) => write!(f, "<{}>", base_type_name(*vt)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: Option<String>,
+    pub param_type: TypeDesc,
+    pub flags: u32,
+    pub default: Option<Value>,
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut attributes = Vec::new();
+        for (flag, name) in [
+            (PARAMFLAG_FIN, "in"),
+            (PARAMFLAG_FOUT, "out"),
+            (PARAMFLAG_FLCID, "lcid"),
+            (PARAMFLAG_FRETVAL, "retval"),
+            (PARAMFLAG_FOPT, "optional"),
+        ] {
+            if self.flags & flag != 0 {
+                attributes.push(name.to_string());
+            }
+        }
+        if let Some(default) = &self.default {
+            attributes.push(format!("defaultvalue({})", default));
+        }
+        if !attributes.is_empty() {
+            write!(f, "[{}] ", attributes.join(", "))?;
+        }
+        write!(f, "{}", self.param_type)?;
+        if let Some(name) = &self.name {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub member_id: i32,
+    // FUNCKIND, FUNC_DISPATCH for dispinterface members
+    pub func_kind: u8,
+    pub invoke: InvokeKind,
+    pub call_conv: u8,
+    pub vtable_offset: u16,
+    pub flags: u16,
+    pub return_type: TypeDesc,
+    pub params: Vec<Parameter>,
+    pub optional_params: u16,
+    pub help_string: Option<String>,
+    // module functions name their DLL export, ordinals are written as #n
+    pub entry: Option<String>,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut attributes = vec![format!("id({:#x})", self.member_id)];
+        attributes.extend(self.invoke.attribute().map(str::to_string));
+        for (flag, name) in FUNCTION_ATTRIBUTES {
+            if self.flags & flag != 0 {
+                attributes.push(name.to_string());
+            }
+        }
+        if let Some(entry) = &self.entry {
+            attributes.push(format!("entry({:?})", entry));
+        }
+        write!(
+            f,
+            "[{}] {} {}(",
+            attributes.join(", "),
+            self.return_type,
+            self.name
+        )?;
+        for (index, param) in self.params.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VarKind {
+    // offset of the field inside the record
+    PerInstance(u32),
+    Static,
+    Const(Value),
+    Dispatch,
+    Unknown(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub member_id: i32,
+    pub var_type: TypeDesc,
+    pub flags: u16,
+    pub kind: VarKind,
+    pub help_string: Option<String>,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            VarKind::Dispatch => write!(
+                f,
+                "[id({:#x})] {} {}",
+                self.member_id, self.var_type, self.name
+            ),
+            VarKind::Const(value) => {
+                write!(f, "const {} {} = {}", self.var_type, self.name, value)
+            }
+            _ => write!(f, "{} {}", self.var_type, self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplementedType {
+    pub reference: TypeRef,
+    // IMPLTYPEFLAGS, only used by coclasses
+    pub flags: u32,
+}
+
+impl fmt::Display for ImplementedType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut attributes = Vec::new();
+        for (flag, name) in [
+            (IMPLTYPEFLAG_FDEFAULT, "default"),
+            (IMPLTYPEFLAG_FSOURCE, "source"),
+            (IMPLTYPEFLAG_FRESTRICTED, "restricted"),
+        ] {
+            if self.flags & flag != 0 {
+                attributes.push(name);
+            }
+        }
+        if !attributes.is_empty() {
+            write!(f, "[{}] ", attributes.join(", "))?;
+        }
+        write!(f, "interface {}", self.reference.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeInfo {
+    pub kind: TypeKind,
+    pub name: String,
+    pub guid: Option<Guid>,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub flags: u32,
+    pub help_string: Option<String>,
+    pub size: u32,
+    pub alignment: u16,
+    pub vtable_size: u16,
+    // base interface for interfaces and dispinterfaces, the interfaces of a coclass
+    pub implemented: Vec<ImplementedType>,
+    pub alias: Option<TypeDesc>,
+    pub dll_name: Option<String>,
+    pub functions: Vec<Function>,
+    pub variables: Vec<Variable>,
+}
+
+impl TypeInfo {
+    // the attributes and the line introducing the type, e.g. "[uuid(...), dual] interface IFoo :
+    // IDispatch"
+    pub fn declaration(&self) -> String {
+        let mut attributes = Vec::new();
+        if let Some(guid) = &self.guid {
+            attributes.push(format!("uuid({})", guid.to_string().to_lowercase()));
+        }
+        if self.major_version != 0 || self.minor_version != 0 {
+            attributes.push(format!(
+                "version({}.{})",
+                self.major_version, self.minor_version
+            ));
+        }
+        if let Some(help) = &self.help_string {
+            attributes.push(format!("helpstring({:?})", help));
+        }
+        if let Some(dll) = &self.dll_name {
+            attributes.push(format!("dllname({:?})", dll));
+        }
+        for (flag, name) in TYPE_ATTRIBUTES {
+            if self.flags & flag != 0 {
+                attributes.push(name.to_string());
+            }
+        }
+        // coclasses are creatable unless said otherwise
+        if self.kind == TypeKind::CoClass && self.flags & 0x02 == 0 {
+            attributes.push("noncreatable".to_string());
+        }
+
+        let base = self
+            .implemented
+            .first()
+            .map(|base| format!(" : {}", base.reference.name))
+            .unwrap_or_default();
+        let line = match self.kind {
+            TypeKind::Enum => format!("typedef enum {}", self.name),
+            TypeKind::Record => format!("typedef struct {}", self.name),
+            TypeKind::Union => format!("typedef union {}", self.name),
+            TypeKind::Module => format!("module {}", self.name),
+            TypeKind::Interface => format!("interface {}{}", self.name, base),
+            TypeKind::Dispatch => format!("dispinterface {}{}", self.name, base),
+            TypeKind::CoClass => format!("coclass {}", self.name),
+            TypeKind::Alias => format!(
+                "typedef {} {}",
+                self.alias
+                    .as_ref()
+                    .map(|alias| alias.to_string())
+                    .unwrap_or_default(),
+                self.name
+            ),
+            TypeKind::Unknown(kind) => format!("typekind({}) {}", kind, self.name),
+        };
+        match attributes.is_empty() {
+            true => line,
+            false => format!("[{}]\n{}", attributes.join(", "), line),
+        }
+    }
+
+    // everything a caller can bind to, keyed so that members can be matched up across versions
+    fn members(&self) -> Vec<(String, String)> {
+        let mut members = Vec::new();
+        for function in &self.functions {
+            let key = format!("{} {:?}", function.name, function.invoke);
+            let signature = match function.func_kind {
+                FUNC_DISPATCH => function.to_string(),
+                // the vtable slot matters as much as the signature for early bound callers
+                _ => format!("{} /* vtable {:#x} */", function, function.vtable_offset),
+            };
+            members.push((key, signature));
+        }
+        for variable in &self.variables {
+            let signature = match (&variable.kind, self.kind) {
+                (VarKind::Const(value), TypeKind::Enum) => {
+                    format!("{} = {}", variable.name, value)
+                }
+                _ => variable.to_string(),
+            };
+            members.push((variable.name.clone(), signature));
+        }
+        if self.kind == TypeKind::CoClass {
+            for implemented in &self.implemented {
+                members.push((implemented.reference.name.clone(), implemented.to_string()));
+            }
+        }
+        members
+    }
+}
+
+impl fmt::Display for TypeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.kind == TypeKind::Alias {
+            return writeln!(f, "{};", self.declaration());
+        }
+        writeln!(f, "{}", self.declaration())?;
+        writeln!(f, "{{")?;
+        match self.kind {
+            TypeKind::Enum => {
+                for (index, variable) in self.variables.iter().enumerate() {
+                    let separator = if index + 1 < self.variables.len() {
+                        ","
+                    } else {
+                        ""
+                    };
+                    match &variable.kind {
+                        VarKind::Const(value) => {
+                            writeln!(f, "    {} = {}{}", variable.name, value, separator)?
+                        }
+                        _ => writeln!(f, "    {}{}", variable.name, separator)?,
+                    }
+                }
+            }
+            TypeKind::CoClass => {
+                for implemented in &self.implemented {
+                    writeln!(f, "    {};", implemented)?;
+                }
+            }
+            TypeKind::Dispatch => {
+                writeln!(f, "properties:")?;
+                for variable in &self.variables {
+                    writeln!(f, "    {};", variable)?;
+                }
+                writeln!(f, "methods:")?;
+                for function in &self.functions {
+                    writeln!(f, "    {};", function)?;
+                }
+            }
+            _ => {
+                for variable in &self.variables {
+                    writeln!(f, "    {};", variable)?;
+                }
+                for function in &self.functions {
+                    writeln!(f, "    {};", function)?;
+                }
+            }
+        }
+        match self.kind {
+            TypeKind::Enum | TypeKind::Record | TypeKind::Union => writeln!(f, "}} {};", self.name),
+            _ => writeln!(f, "}};"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeLibChange {
+    TypeAdded {
+        name: String,
+    },
+    TypeRemoved {
+        name: String,
+    },
+    // kind, base interface or attributes changed
+    TypeChanged {
+        name: String,
+        old: String,
+        new: String,
+    },
+    MemberAdded {
+        type_name: String,
+        member: String,
+    },
+    MemberRemoved {
+        type_name: String,
+        member: String,
+    },
+    MemberChanged {
+        type_name: String,
+        old: String,
+        new: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TypeLib {
+    pub name: String,
+    pub guid: Option<Guid>,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub lcid: u32,
+    pub syskind: SysKind,
+    pub flags: u32,
+    pub help_string: Option<String>,
+    pub help_file: Option<String>,
+    pub types: Vec<TypeInfo>,
+}
+
+impl TypeLib {
+    // raw is the TYPELIB resource, or the contents of a .tlb file
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        match try_read_dword(raw, &mut offset)? {
+            MSFT_MAGIC => {}
+            SLTG_MAGIC => {
+                return Err(ParsingError::Malformed {
+                    reason: "SLTG type libraries aren't supported".to_string(),
+                })
+            }
+            _ => {
+                return Err(ParsingError::InvalidMagic {
+                    header: "MSFT".to_string(),
+                    offset: 0,
+                })
+            }
+        }
+        try_slice(raw, 0, HEADER_SZ)?;
+        let mut offset = 8;
+        let guid_offset = read_dword(raw, &mut offset);
+        let lcid = read_dword(raw, &mut offset);
+        let _lcid2 = read_dword(raw, &mut offset);
+        let var_flags = read_dword(raw, &mut offset);
+        let version = read_dword(raw, &mut offset);
+        let flags = read_dword(raw, &mut offset);
+        let type_count = read_dword(raw, &mut offset) as usize;
+        let help_string = read_dword(raw, &mut offset);
+        let mut offset = 0x38;
+        let name = read_dword(raw, &mut offset);
+        let help_file = read_dword(raw, &mut offset);
+
+        let mut offset = HEADER_SZ;
+        if var_flags & HELP_DLL_FLAG != 0 {
+            offset += DWORD_SZ;
+        }
+        // the typeinfo offsets are repeated here, the typeinfo table has them in order anyway
+        offset += type_count * DWORD_SZ;
+        try_slice(raw, offset, SEGMENT_COUNT * SEGMENT_ENTRY_SZ)?;
+        let mut segments: [&[u8]; SEGMENT_COUNT] = [&[]; SEGMENT_COUNT];
+        for segment in segments.iter_mut() {
+            let mut entry = offset;
+            let start = read_dword(raw, &mut entry);
+            let length = read_dword(raw, &mut entry);
+            if (start as i32) >= 0 {
+                *segment = try_slice(raw, start as usize, length as usize)?;
+            }
+            offset += SEGMENT_ENTRY_SZ;
+        }
+
+        let mut context = Context {
+            raw,
+            segments,
+            types: Vec::new(),
+        };
+        let table = try_slice(segments[SEG_TYPEINFO], 0, type_count * TYPEINFO_SZ)?;
+        let bases: Vec<TypeInfoBase> = table
+            .chunks_exact(TYPEINFO_SZ)
+            .map(TypeInfoBase::new)
+            .collect();
+        // every name is needed up front, since types refer to ones defined later on
+        for base in &bases {
+            context.types.push(TypeRef {
+                name: context.name(base.name)?.unwrap_or_default(),
+                guid: context.guid(base.guid)?,
+            });
+        }
+        let types = bases
+            .iter()
+            .zip(&context.types)
+            .map(|(base, reference)| context.type_info(base, reference))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            name: context.name(name)?.unwrap_or_default(),
+            guid: context.guid(guid_offset)?,
+            major_version: version as u16,
+            minor_version: (version >> 16) as u16,
+            lcid,
+            syskind: match var_flags & 0xf {
+                0 => SysKind::Win16,
+                1 => SysKind::Win32,
+                2 => SysKind::Mac,
+                3 => SysKind::Win64,
+                other => SysKind::Unknown(other),
+            },
+            flags,
+            help_string: context.string(help_string)?,
+            help_file: context.string(help_file)?,
+            types,
+        })
+    }
+
+    pub fn find(&self, name: &str) -> Option<&TypeInfo> {
+        self.types.iter().find(|info| info.name == name)
+    }
+
+    pub fn of_kind(&self, kind: TypeKind) -> impl Iterator<Item = &TypeInfo> {
+        self.types.iter().filter(move |info| info.kind == kind)
+    }
+
+    // what changed going from self to newer. Types are matched by GUID when they have one, and
+    // by name otherwise.
+    pub fn changes(&self, newer: &TypeLib) -> Vec<TypeLibChange> {
+        fn key(info: &TypeInfo) -> String {
+            match &info.guid {
+                Some(guid) => guid.to_string(),
+                None => format!("{:?} {}", info.kind, info.name),
+            }
+        }
+
+        let old: HashMap<String, &TypeInfo> = self.types.iter().map(|t| (key(t), t)).collect();
+        let new: HashMap<String, &TypeInfo> = newer.types.iter().map(|t| (key(t), t)).collect();
+        let mut changes = Vec::new();
+
+        for info in &self.types {
+            if !new.contains_key(&key(info)) {
+                changes.push(TypeLibChange::TypeRemoved {
+                    name: info.name.clone(),
+                });
+            }
+        }
+        for info in &newer.types {
+            let before = match old.get(&key(info)) {
+                Some(before) => before,
+                None => {
+                    changes.push(TypeLibChange::TypeAdded {
+                        name: info.name.clone(),
+                    });
+                    continue;
+                }
+            };
+            if before.declaration() != info.declaration() {
+                changes.push(TypeLibChange::TypeChanged {
+                    name: info.name.clone(),
+                    old: before.declaration(),
+                    new: info.declaration(),
+                });
+            }
+
+            let old_members: HashMap<String, String> = before.members().into_iter().collect();
+            let new_members = info.members();
+            for (member, signature) in before.members() {
+                if !new_members.iter().any(|(key, _)| *key == member) {
+                    changes.push(TypeLibChange::MemberRemoved {
+                        type_name: info.name.clone(),
+                        member: signature,
+                    });
+                }
+            }
+            for (member, signature) in new_members {
+                match old_members.get(&member) {
+                    None => changes.push(TypeLibChange::MemberAdded {
+                        type_name: info.name.clone(),
+                        member: signature,
+                    }),
+                    Some(old) if *old != signature => changes.push(TypeLibChange::MemberChanged {
+                        type_name: info.name.clone(),
+                        old: old.clone(),
+                        new: signature,
+                    }),
+                    Some(_) => {}
+                }
+            }
+        }
+        changes
+    }
+}
+
+impl fmt::Display for TypeLib {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut attributes = Vec::new();
+        if let Some(guid) = &self.guid {
+            attributes.push(format!("uuid({})", guid.to_string().to_lowercase()));
+        }
+        attributes.push(format!(
+            "version({}.{})",
+            self.major_version, self.minor_version
+        ));
+        if let Some(help) = &self.help_string {
+            attributes.push(format!("helpstring({:?})", help));
+        }
+        if let Some(file) = &self.help_file {
+            attributes.push(format!("helpfile({:?})", file));
+        }
+        writeln!(f, "[{}]", attributes.join(", "))?;
+        writeln!(f, "library {}", self.name)?;
+        writeln!(f, "{{")?;
+        for (index, info) in self.types.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            for line in info.to_string().lines() {
+                match line.is_empty() {
+                    true => writeln!(f)?,
+                    false => writeln!(f, "    {}", line)?,
+                }
+            }
+        }
+        writeln!(f, "}};")
+    }
+}
+
+// the fields of a typeinfo table entry that are understood
+struct TypeInfoBase {
+    kind: u32,
+    member_offset: u32,
+    function_count: u16,
+    variable_count: u16,
+    guid: u32,
+    flags: u32,
+    name: u32,
+    version: u32,
+    doc_string: u32,
+    implemented_count: u16,
+    vtable_size: u16,
+    size: u32,
+    data_type: u32,
+}
+
+impl TypeInfoBase {
+    fn new(raw: &[u8]) -> Self {
+        let dword = |at: usize| {
+            let mut offset = at;
+            read_dword(raw, &mut offset)
+        };
+        let word = |at: usize| {
+            let mut offset = at;
+            read_word(raw, &mut offset)
+        };
+        Self {
+            kind: dword(0x00),
+            member_offset: dword(0x04),
+            function_count: word(0x18),
+            variable_count: word(0x1a),
+            guid: dword(0x2c),
+            flags: dword(0x30),
+            name: dword(0x34),
+            version: dword(0x38),
+            doc_string: dword(0x3c),
+            implemented_count: word(0x4c),
+            vtable_size: word(0x4e),
+            size: dword(0x50),
+            data_type: dword(0x54),
+        }
+    }
+}
+
+fn latin1(raw: &[u8]) -> String {
+    raw.iter().map(|b| *b as char).collect()
+}
+
+fn dword_at(raw: &[u8], at: usize) -> Result<u32, ParsingError> {
+    let mut offset = at;
+    try_read_dword(raw, &mut offset)
+}
+
+fn missing(offset: u32) -> bool {
+    (offset as i32) < 0
+}
+
+struct Context<'a> {
+    raw: &'a [u8],
+    segments: [&'a [u8]; SEGMENT_COUNT],
+    // name and GUID of each typeinfo, by index
+    types: Vec<TypeRef>,
+}
+
+impl Context<'_> {
+    fn name(&self, offset: u32) -> Result<Option<String>, ParsingError> {
+        if missing(offset) {
+            return Ok(None);
+        }
+        // skipping the HREFTYPE and hash chain, only the low byte of the length is used
+        let names = self.segments[SEG_NAMES];
+        let len = (dword_at(names, offset as usize + 2 * DWORD_SZ)? & 0xff) as usize;
+        let text = try_slice(names, offset as usize + 3 * DWORD_SZ, len)?;
+        Ok(Some(latin1(text)))
+    }
+
+    fn string(&self, offset: u32) -> Result<Option<String>, ParsingError> {
+        if missing(offset) {
+            return Ok(None);
+        }
+        let strings = self.segments[SEG_STRINGS];
+        let mut offset = offset as usize;
+        let len = try_read_word(strings, &mut offset)? as usize;
+        Ok(Some(latin1(try_slice(strings, offset, len)?)))
+    }
+
+    fn guid(&self, offset: u32) -> Result<Option<Guid>, ParsingError> {
+        if missing(offset) {
+            return Ok(None);
+        }
+        let mut offset = offset as usize;
+        Ok(Some(Guid::new(self.segments[SEG_GUIDS], &mut offset)?))
+    }
+
+    fn type_desc(&self, code: u32, depth: usize) -> Result<TypeDesc, ParsingError> {
+        if missing(code) {
+            return Ok(TypeDesc::Base((code & VT_TYPEMASK) as u16));
+        }
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(ParsingError::Malformed {
+                reason: "type description is nested too deeply".to_string(),
+            });
+        }
+        let table = self.segments[SEG_TYPE_DESCS];
+        let mut offset = code as usize;
+        let vt = try_read_word(table, &mut offset)? & VT_TYPEMASK as u16;
+        let _flags = try_read_word(table, &mut offset)?;
+        let low = try_read_word(table, &mut offset)?;
+        let high = try_read_word(table, &mut offset)?;
+
+        match vt {
+            VT_PTR | VT_SAFEARRAY => {
+                let inner = match (high as i16) < 0 {
+                    true => TypeDesc::Base(low & VT_TYPEMASK as u16),
+                    false => self.type_desc(low as u32, depth + 1)?,
+                };
+                Ok(match vt {
+                    VT_PTR => TypeDesc::Pointer(Box::new(inner)),
+                    _ => TypeDesc::SafeArray(Box::new(inner)),
+                })
+            }
+            VT_CARRAY => self.array_desc(low as u32, depth + 1),
+            VT_USERDEFINED => Ok(TypeDesc::UserDefined(
+                self.reference((high as u32) << 16 | low as u32)?,
+            )),
+            other => Ok(TypeDesc::Base(other)),
+        }
+    }
+
+    fn array_desc(&self, offset: u32, depth: usize) -> Result<TypeDesc, ParsingError> {
+        let table = self.segments[SEG_ARRAY_DESCS];
+        let mut offset = offset as usize;
+        let element = try_read_word(table, &mut offset)?;
+        let is_base = (try_read_word(table, &mut offset)? as i16) < 0;
+        let dimensions = try_read_word(table, &mut offset)? as usize;
+        let _size = try_read_word(table, &mut offset)?;
+
+        let element = match is_base {
+            true => TypeDesc::Base(element & VT_TYPEMASK as u16),
+            false => self.type_desc(element as u32, depth)?,
+        };
+        let mut bounds = Vec::with_capacity(dimensions.min(64));
+        for _ in 0..dimensions {
+            let count = try_read_dword(table, &mut offset)?;
+            let lower = try_read_dword(table, &mut offset)? as i32;
+            bounds.push((count, lower));
+        }
+        Ok(TypeDesc::CArray {
+            element: Box::new(element),
+            bounds,
+        })
+    }
+
+    fn reference(&self, href: u32) -> Result<TypeRef, ParsingError> {
+        if href & 3 == 0 {
+            return self.types.get(href as usize / TYPEINFO_SZ).cloned().ok_or(
+                ParsingError::Malformed {
+                    reason: format!("type reference {:#x} is out of range", href),
+                },
+            );
+        }
+
+        let imports = self.segments[SEG_IMPORT_INFO];
+        let mut offset = (href & !3) as usize;
+        let flags = try_read_dword(imports, &mut offset)?;
+        let file = try_read_dword(imports, &mut offset)?;
+        let target = try_read_dword(imports, &mut offset)?;
+        let library = self.import_file(file)?;
+
+        if flags & IMPORT_IS_GUID == 0 {
+            // an index into the other library, which isn't available here
+            return Ok(TypeRef {
+                name: format!("{}#{}", library, target),
+                guid: None,
+            });
+        }
+        let guid = self.guid(target)?;
+        let name = match guid {
+            Some(guid) => {
+                let text = guid.to_string();
+                match WELL_KNOWN_INTERFACES
+                    .iter()
+                    .find(|(known, _)| *known == text)
+                {
+                    Some((_, name)) => name.to_string(),
+                    None => format!("{}:{}", library, text),
+                }
+            }
+            None => library,
+        };
+        Ok(TypeRef { name, guid })
+    }
+
+    fn import_file(&self, offset: u32) -> Result<String, ParsingError> {
+        let files = self.segments[SEG_IMPORT_FILES];
+        // skipping the GUID, lcid and version
+        let mut offset = offset as usize + 3 * DWORD_SZ;
+        let len = (try_read_word(files, &mut offset)? >> 2) as usize;
+        Ok(latin1(try_slice(files, offset, len)?))
+    }
+
+    fn value(&self, raw: u32) -> Result<Value, ParsingError> {
+        if missing(raw) {
+            let vt = ((raw & 0x7c000000) >> 26) as u16;
+            let value = (raw & 0x3ffffff) as i64;
+            return Ok(match vt {
+                VT_BOOL => Value::Bool(value != 0),
+                _ => Value::Integer(value),
+            });
+        }
+
+        let data = self.segments[SEG_CUSTOM_DATA];
+        let mut offset = raw as usize;
+        let vt = try_read_word(data, &mut offset)?;
+        Ok(match vt {
+            VT_I1 => Value::Integer(try_read_byte(data, &mut offset)? as i8 as i64),
+            VT_I2 => Value::Integer(try_read_word(data, &mut offset)? as i16 as i64),
+            VT_I4 | VT_INT | VT_ERROR | VT_HRESULT => {
+                Value::Integer(try_read_dword(data, &mut offset)? as i32 as i64)
+            }
+            VT_UI1 => Value::Unsigned(try_read_byte(data, &mut offset)? as u64),
+            VT_UI2 => Value::Unsigned(try_read_word(data, &mut offset)? as u64),
+            VT_UI4 | VT_UINT => Value::Unsigned(try_read_dword(data, &mut offset)? as u64),
+            VT_I8 => Value::Integer(try_read_dwordlong(data, &mut offset)? as i64),
+            VT_UI8 => Value::Unsigned(try_read_dwordlong(data, &mut offset)?),
+            VT_BOOL => Value::Bool(try_read_word(data, &mut offset)? != 0),
+            VT_R4 => Value::Float(f32::from_bits(try_read_dword(data, &mut offset)?) as f64),
+            VT_R8 | VT_DATE => Value::Float(f64::from_bits(try_read_dwordlong(data, &mut offset)?)),
+            VT_BSTR => match try_read_dword(data, &mut offset)? {
+                u32::MAX => Value::String(None),
+                len => Value::String(Some(latin1(try_slice(data, offset, len as usize)?))),
+            },
+            other => Value::Other(other),
+        })
+    }
+
+    fn type_info(
+        &self,
+        base: &TypeInfoBase,
+        reference: &TypeRef,
+    ) -> Result<TypeInfo, ParsingError> {
+        let kind = TypeKind::new(base.kind & 0xf);
+        let mut implemented = Vec::new();
+        let mut alias = None;
+        let mut dll_name = None;
+        match kind {
+            TypeKind::CoClass => {
+                let references = self.segments[SEG_REFERENCES];
+                let mut next = base.data_type;
+                for _ in 0..base.implemented_count {
+                    if missing(next) {
+                        break;
+                    }
+                    let mut offset = next as usize;
+                    let href = try_read_dword(references, &mut offset)?;
+                    let flags = try_read_dword(references, &mut offset)?;
+                    let _custom_data = try_read_dword(references, &mut offset)?;
+                    next = try_read_dword(references, &mut offset)?;
+                    implemented.push(ImplementedType {
+                        reference: self.reference(href)?,
+                        flags,
+                    });
+                }
+            }
+            TypeKind::Interface | TypeKind::Dispatch if !missing(base.data_type) => {
+                implemented.push(ImplementedType {
+                    reference: self.reference(base.data_type)?,
+                    flags: 0,
+                });
+            }
+            TypeKind::Alias => alias = Some(self.type_desc(base.data_type, 0)?),
+            TypeKind::Module => dll_name = self.string(base.data_type)?,
+            _ => {}
+        }
+        let (functions, variables) = self.members(base)?;
+
+        Ok(TypeInfo {
+            kind,
+            name: reference.name.clone(),
+            guid: reference.guid,
+            major_version: base.version as u16,
+            minor_version: (base.version >> 16) as u16,
+            flags: base.flags,
+            help_string: self.string(base.doc_string)?,
+            size: base.size,
+            alignment: ((base.kind >> 11) & 0x1f) as u16,
+            vtable_size: base.vtable_size,
+            implemented,
+            alias,
+            dll_name,
+            functions,
+            variables,
+        })
+    }
+
+    fn members(&self, base: &TypeInfoBase) -> Result<(Vec<Function>, Vec<Variable>), ParsingError> {
+        let function_count = base.function_count as usize;
+        let variable_count = base.variable_count as usize;
+        let total = function_count + variable_count;
+        let mut functions = Vec::with_capacity(function_count);
+        let mut variables = Vec::with_capacity(variable_count);
+        // without members the offset points past the end of the file
+        if total == 0 {
+            return Ok((functions, variables));
+        }
+
+        let mut offset = base.member_offset as usize;
+        let records_len = try_read_dword(self.raw, &mut offset)? as usize;
+        let records = try_slice(self.raw, offset, records_len)?;
+        let tail = try_slice(self.raw, offset + records_len, 2 * total * DWORD_SZ)?;
+        let member_id = |index: usize| dword_at(tail, index * DWORD_SZ).map(|id| id as i32);
+        let name = |index: usize| dword_at(tail, (total + index) * DWORD_SZ);
+
+        let mut at = 0;
+        for index in 0..total {
+            let len = (dword_at(records, at)? & 0xffff) as usize;
+            let record = try_slice(records, at, len)?;
+            at += len;
+
+            if index < function_count {
+                let function =
+                    self.function(record, member_id(index)?, name(index)?, functions.last())?;
+                functions.push(function);
+            } else {
+                variables.push(self.variable(record, member_id(index)?, name(index)?)?);
+            }
+        }
+        Ok((functions, variables))
+    }
+
+    fn function(
+        &self,
+        record: &[u8],
+        member_id: i32,
+        name: u32,
+        previous: Option<&Function>,
+    ) -> Result<Function, ParsingError> {
+        if record.len() < FUNCTION_RECORD_MIN {
+            return Err(ParsingError::Malformed {
+                reason: format!("function record is only {} bytes", record.len()),
+            });
+        }
+        let mut offset = DWORD_SZ;
+        let return_type = read_dword(record, &mut offset);
+        let flags = read_dword(record, &mut offset) as u16;
+        let vtable_offset = read_word(record, &mut offset) & !1;
+        let _descriptor_size = read_word(record, &mut offset);
+        let kind = read_dword(record, &mut offset);
+        let param_count = read_word(record, &mut offset) as usize;
+        let optional_params = read_word(record, &mut offset);
+
+        let defaults_len = match kind & HAS_DEFAULT_VALUES {
+            0 => 0,
+            _ => param_count * DWORD_SZ,
+        };
+        let params_start = record.len().checked_sub(param_count * PARAMETER_SZ);
+        let defaults_start = params_start.and_then(|start| start.checked_sub(defaults_len));
+        let (params_start, defaults_start) = match (params_start, defaults_start) {
+            (Some(params), Some(defaults)) if defaults >= FUNCTION_RECORD_MIN => (params, defaults),
+            _ => {
+                return Err(ParsingError::Malformed {
+                    reason: format!(
+                        "function record of {} bytes can't hold {} parameters",
+                        record.len(),
+                        param_count
+                    ),
+                })
+            }
+        };
+
+        // the optional fields run up to the default values
+        let help_string = match defaults_start > 0x1c {
+            true => self.string(dword_at(record, 0x1c)?)?,
+            false => None,
+        };
+        let entry = match defaults_start > 0x20 {
+            true => match dword_at(record, 0x20)? {
+                ordinal if kind & ENTRY_IS_ORDINAL != 0 => Some(format!("#{}", ordinal & 0xffff)),
+                entry => self.string(entry)?,
+            },
+            false => None,
+        };
+
+        let invoke = InvokeKind::new((kind >> 3) & 0xf);
+        let name = match self.name(name)? {
+            Some(name) => name,
+            // the second half of a property get/put pair can leave its name out
+            None => previous
+                .filter(|previous| previous.invoke != InvokeKind::Func)
+                .map(|previous| previous.name.clone())
+                .unwrap_or_default(),
+        };
+
+        let mut params = Vec::with_capacity(param_count);
+        for index in 0..param_count {
+            let mut offset = params_start + index * PARAMETER_SZ;
+            let param_type = read_dword(record, &mut offset);
+            let param_name = read_dword(record, &mut offset);
+            let flags = read_dword(record, &mut offset);
+            let default = match flags & PARAMFLAG_FHASDEFAULT != 0 && defaults_len != 0 {
+                true => Some(self.value(dword_at(record, defaults_start + index * DWORD_SZ)?)?),
+                false => None,
+            };
+            params.push(Parameter {
+                name: self.name(param_name)?,
+                param_type: self.type_desc(param_type, 0)?,
+                flags,
+                default,
+            });
+        }
+
+        Ok(Function {
+            name,
+            member_id,
+            func_kind: (kind & 0x7) as u8,
+            invoke,
+            call_conv: ((kind >> 8) & 0xf) as u8,
+            vtable_offset,
+            flags,
+            return_type: self.type_desc(return_type, 0)?,
+            params,
+            optional_params,
+            help_string,
+            entry,
+        })
+    }
+
+    fn variable(&self, record: &[u8], member_id: i32, name: u32) -> Result<Variable, ParsingError> {
+        if record.len() < VARIABLE_RECORD_MIN {
+            return Err(ParsingError::Malformed {
+                reason: format!("variable record is only {} bytes", record.len()),
+            });
+        }
+        let mut offset = DWORD_SZ;
+        let var_type = read_dword(record, &mut offset);
+        let flags = read_dword(record, &mut offset) as u16;
+        let kind = read_word(record, &mut offset);
+        let _descriptor_size = read_word(record, &mut offset);
+        let value = read_dword(record, &mut offset);
+
+        let help_string = match record.len() > 0x18 {
+            true => self.string(dword_at(record, 0x18)?)?,
+            false => None,
+        };
+
+        Ok(Variable {
+            name: self.name(name)?.unwrap_or_default(),
+            member_id,
+            var_type: self.type_desc(var_type, 0)?,
+            flags,
+            kind: match kind {
+                0 => VarKind::PerInstance(value),
+                1 => VarKind::Static,
+                2 => VarKind::Const(self.value(value)?),
+                3 => VarKind::Dispatch,
+                other => VarKind::Unknown(other),
+            },
+            help_string,
+        })
+    }
+}
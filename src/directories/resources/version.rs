@@ -0,0 +1,286 @@
+use crate::prelude::*;
+
+/*
+Every node of a version resource shares the same header:
++00 WORD    wLength (of the whole node, children included)
++02 WORD    wValueLength (in WORDs for text values, bytes otherwise)
++04 WORD    wType (1 = text, 0 = binary)
++06         szKey, null terminated UTF-16
+            padding to a 32 bit boundary
+            Value
+            padding to a 32 bit boundary
+            Children
+
+VS_VERSIONINFO (root, key "VS_VERSION_INFO", value VS_FIXEDFILEINFO)
+    StringFileInfo
+        StringTable (key is the language and codepage as 8 hex digits, e.g. "040904B0")
+            String (key is the name, value the text)
+    VarFileInfo
+        Var (key "Translation", value is an array of language/codepage WORD pairs)
+
+VS_FIXEDFILEINFO:
++00 DWORD   dwSignature (0xFEEF04BD)
++04 DWORD   dwStrucVersion
++08 DWORD   dwFileVersionMS
++0C (12)    DWORD   dwFileVersionLS
++10 (16)    DWORD   dwProductVersionMS
++14 (20)    DWORD   dwProductVersionLS
++18 (24)    DWORD   dwFileFlagsMask
++1C (28)    DWORD   dwFileFlags
++20 (32)    DWORD   dwFileOS
++24 (36)    DWORD   dwFileType
++28 (40)    DWORD   dwFileSubtype
++2C (44)    DWORD   dwFileDateMS
++30 (48)    DWORD   dwFileDateLS
+ */
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xfeef04bd;
+const FIXED_FILE_INFO_SZ: usize = 52;
+const BLOCK_HEADER_SZ: usize = 6;
+// the documented layout is 4 levels deep
+const MAX_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedFileInfo {
+    pub signature: u32,
+    pub struc_version: u32,
+    pub file_version_ms: u32,
+    pub file_version_ls: u32,
+    pub product_version_ms: u32,
+    pub product_version_ls: u32,
+    pub file_flags_mask: u32,
+    pub file_flags: u32,
+    pub file_os: u32,
+    pub file_type: u32,
+    pub file_subtype: u32,
+    pub file_date_ms: u32,
+    pub file_date_ls: u32,
+}
+
+impl FixedFileInfo {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        try_slice(raw, 0, FIXED_FILE_INFO_SZ)?;
+        let signature = read_dword(raw, &mut offset);
+        if signature != FIXED_FILE_INFO_SIGNATURE {
+            return Err(ParsingError::InvalidMagic {
+                header: "VS_FIXEDFILEINFO".to_string(),
+                offset: 0,
+            });
+        }
+
+        Ok(Self {
+            signature,
+            struc_version: read_dword(raw, &mut offset),
+            file_version_ms: read_dword(raw, &mut offset),
+            file_version_ls: read_dword(raw, &mut offset),
+            product_version_ms: read_dword(raw, &mut offset),
+            product_version_ls: read_dword(raw, &mut offset),
+            file_flags_mask: read_dword(raw, &mut offset),
+            file_flags: read_dword(raw, &mut offset),
+            file_os: read_dword(raw, &mut offset),
+            file_type: read_dword(raw, &mut offset),
+            file_subtype: read_dword(raw, &mut offset),
+            file_date_ms: read_dword(raw, &mut offset),
+            file_date_ls: read_dword(raw, &mut offset),
+        })
+    }
+
+    // major, minor, build, revision
+    pub fn file_version(&self) -> (u16, u16, u16, u16) {
+        split_version(self.file_version_ms, self.file_version_ls)
+    }
+
+    pub fn product_version(&self) -> (u16, u16, u16, u16) {
+        split_version(self.product_version_ms, self.product_version_ls)
+    }
+}
+
+fn split_version(ms: u32, ls: u32) -> (u16, u16, u16, u16) {
+    ((ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringTable {
+    pub key: String,
+    pub language: u16,
+    pub codepage: u16,
+    pub strings: Vec<(String, String)>,
+}
+
+impl StringTable {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Translation {
+    pub language: u16,
+    pub codepage: u16,
+}
+
+#[derive(Debug)]
+pub struct VersionInfo {
+    pub fixed: Option<FixedFileInfo>,
+    pub string_tables: Vec<StringTable>,
+    pub translations: Vec<Translation>,
+}
+
+impl VersionInfo {
+    pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        let root = VersionBlock::new(raw, &mut offset, 0)?;
+        if root.key != "VS_VERSION_INFO" {
+            return Err(ParsingError::InvalidMagic {
+                header: "VS_VERSIONINFO".to_string(),
+                offset: 0,
+            });
+        }
+
+        let fixed = match root.value.len() {
+            0 => None,
+            _ => Some(FixedFileInfo::new(root.value)?),
+        };
+
+        let mut string_tables = Vec::new();
+        let mut translations = Vec::new();
+        for child in &root.children {
+            match child.key.as_str() {
+                "StringFileInfo" => {
+                    for table in &child.children {
+                        string_tables.push(table.string_table());
+                    }
+                }
+                "VarFileInfo" => {
+                    for var in child.children.iter().filter(|v| v.key == "Translation") {
+                        translations.extend(var.value.chunks_exact(DWORD_SZ).map(|pair| {
+                            Translation {
+                                language: u16::from_le_bytes([pair[0], pair[1]]),
+                                codepage: u16::from_le_bytes([pair[2], pair[3]]),
+                            }
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            fixed,
+            string_tables,
+            translations,
+        })
+    }
+
+    // value from the first string table that has it, which is what most tools display
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.string_tables.iter().find_map(|table| table.get(name))
+    }
+}
+
+struct VersionBlock<'a> {
+    key: String,
+    value_type: u16,
+    value: &'a [u8],
+    children: Vec<VersionBlock<'a>>,
+}
+
+impl<'a> VersionBlock<'a> {
+    fn new(raw: &'a [u8], offset: &mut usize, depth: usize) -> Result<Self, ParsingError> {
+        if depth >= MAX_DEPTH {
+            return Err(ParsingError::Malformed {
+                reason: "version resource is nested too deeply".to_string(),
+            });
+        }
+
+        let start = *offset;
+        let length = try_read_word(raw, offset)? as usize;
+        let value_length = try_read_word(raw, offset)? as usize;
+        let value_type = try_read_word(raw, offset)?;
+        let end = start + length;
+        if length < BLOCK_HEADER_SZ || end > raw.len() {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "version resource node at {:#x} has length {}",
+                    start, length
+                ),
+            });
+        }
+        let key = try_read_utf16_nul(&raw[..end], offset)?;
+        *offset = align4(*offset).min(end);
+
+        // some compilers write the byte count for text values, so never read past the node
+        let value_size = match value_type {
+            1 => value_length * WORD_SZ,
+            _ => value_length,
+        };
+        let value_end = (*offset + value_size).min(end);
+        let value = &raw[*offset..value_end];
+        *offset = align4(value_end).min(end);
+
+        let mut children = Vec::new();
+        while *offset + BLOCK_HEADER_SZ <= end {
+            // zeroed padding after the last child
+            if raw[*offset] == 0 && raw[*offset + 1] == 0 {
+                break;
+            }
+            let child = Self::new(&raw[..end], offset, depth + 1)?;
+            children.push(child);
+            *offset = align4(*offset).min(end);
+        }
+        *offset = end;
+
+        Ok(Self {
+            key,
+            value_type,
+            value,
+            children,
+        })
+    }
+
+    fn text(&self) -> String {
+        let units: Vec<u16> = self
+            .value
+            .chunks_exact(WORD_SZ)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+
+    fn string_table(&self) -> StringTable {
+        let language = self
+            .key
+            .get(0..4)
+            .and_then(|l| u16::from_str_radix(l, 16).ok());
+        let codepage = self
+            .key
+            .get(4..8)
+            .and_then(|c| u16::from_str_radix(c, 16).ok());
+        let strings = self
+            .children
+            .iter()
+            .map(|string| {
+                let value = match string.value_type {
+                    1 => string.text(),
+                    _ => String::from_utf8_lossy(string.value).to_string(),
+                };
+                (string.key.clone(), value)
+            })
+            .collect();
+
+        StringTable {
+            key: self.key.clone(),
+            language: language.unwrap_or(0),
+            codepage: codepage.unwrap_or(0),
+            strings,
+        }
+    }
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
@@ -0,0 +1,93 @@
+use crate::prelude::*;
+
+/*
+IMAGE_TLS_DIRECTORY32 / IMAGE_TLS_DIRECTORY64 (pointer sized fields are VAs):
++00         StartAddressOfRawData (DWORD / ULONGLONG)
++04 / +08   EndAddressOfRawData
++08 / +10   AddressOfIndex
++0C / +18   AddressOfCallBacks (null terminated array of callback VAs)
++10 / +20   DWORD   SizeOfZeroFill
++14 / +24   DWORD   Characteristics (bits 20-23 are an IMAGE_SCN_ALIGN_* value)
+
+The raw data is the template each thread's TLS block is copied from, followed by SizeOfZeroFill
+zero bytes.
+ */
+// more callbacks than this and the array almost certainly isn't terminated
+const MAX_CALLBACKS: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsDirectory {
+    pub start_address_of_raw_data: u64,
+    pub end_address_of_raw_data: u64,
+    pub address_of_index: u64,
+    pub address_of_callbacks: u64,
+    pub size_of_zero_fill: u32,
+    pub characteristics: u32,
+}
+
+impl TlsDirectory {
+    pub fn new(raw: &[u8], magic: &PeFormat) -> Result<Self, ParsingError> {
+        let mut offset = 0;
+        Ok(Self {
+            start_address_of_raw_data: ArchDependentSized::try_new(raw, &mut offset, magic)?
+                .value(),
+            end_address_of_raw_data: ArchDependentSized::try_new(raw, &mut offset, magic)?.value(),
+            address_of_index: ArchDependentSized::try_new(raw, &mut offset, magic)?.value(),
+            address_of_callbacks: ArchDependentSized::try_new(raw, &mut offset, magic)?.value(),
+            size_of_zero_fill: try_read_dword(raw, &mut offset)?,
+            characteristics: try_read_dword(raw, &mut offset)?,
+        })
+    }
+
+    // size of the template data, without the zero fill
+    pub fn raw_data_size(&self) -> u64 {
+        self.end_address_of_raw_data
+            .saturating_sub(self.start_address_of_raw_data)
+    }
+
+    // alignment of the TLS block in bytes, when one is given
+    pub fn alignment(&self) -> Option<u32> {
+        match (self.characteristics >> 20) & 0xf {
+            0 | 0xf => None,
+            n => Some(1 << (n - 1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsCallback {
+    pub va: u64,
+    // None when the VA is below the image base or too far above it
+    pub rva: Option<u32>,
+    // name of the section the callback is in
+    pub section: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tls {
+    pub directory: TlsDirectory,
+    pub callbacks: Vec<TlsCallback>,
+}
+
+pub fn va_to_rva(va: u64, image_base: u64) -> Option<u32> {
+    va.checked_sub(image_base)
+        .and_then(|rva| u32::try_from(rva).ok())
+}
+
+// raw starts at the callback array
+pub fn callback_vas(raw: &[u8], magic: &PeFormat) -> Result<Vec<u64>, ParsingError> {
+    let mut offset = 0;
+    let mut vas = Vec::new();
+    loop {
+        let va = ArchDependentSized::try_new(raw, &mut offset, magic)?.value();
+        if va == 0 {
+            return Ok(vas);
+        }
+        if vas.len() == MAX_CALLBACKS {
+            return Err(ParsingError::Malformed {
+                reason: "TLS callback array isn't terminated".to_string(),
+            });
+        }
+        vas.push(va);
+    }
+}
@@ -1,6 +1,29 @@
 use crate::prelude::*;
 use chrono::DateTime;
 
+// machine types that change how the data directories are interpreted
+pub const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+pub const IMAGE_FILE_MACHINE_R4000: u16 = 0x166;
+pub const IMAGE_FILE_MACHINE_WCEMIPSV2: u16 = 0x169;
+pub const IMAGE_FILE_MACHINE_ARM: u16 = 0x1c0;
+pub const IMAGE_FILE_MACHINE_THUMB: u16 = 0x1c2;
+pub const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x1c4;
+pub const IMAGE_FILE_MACHINE_IA64: u16 = 0x200;
+pub const IMAGE_FILE_MACHINE_MIPS16: u16 = 0x266;
+pub const IMAGE_FILE_MACHINE_MIPSFPU: u16 = 0x366;
+pub const IMAGE_FILE_MACHINE_MIPSFPU16: u16 = 0x466;
+pub const IMAGE_FILE_MACHINE_RISCV32: u16 = 0x5032;
+pub const IMAGE_FILE_MACHINE_RISCV64: u16 = 0x5064;
+pub const IMAGE_FILE_MACHINE_RISCV128: u16 = 0x5128;
+pub const IMAGE_FILE_MACHINE_LOONGARCH32: u16 = 0x6232;
+pub const IMAGE_FILE_MACHINE_LOONGARCH64: u16 = 0x6264;
+pub const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+pub const IMAGE_FILE_MACHINE_ARM64EC: u16 = 0xa641;
+pub const IMAGE_FILE_MACHINE_ARM64X: u16 = 0xa64e;
+pub const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+pub const COFF_HEADER_SZ: usize = 24;
+
 /*
 PE Header:
 +00 DWORD Signature ($00004550)
@@ -24,11 +47,16 @@ pub struct CoffHeader {
 }
 
 impl CoffHeader {
+    #[allow(clippy::needless_borrow)]
     pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
+        // the reads below aren't bounds checked
+        try_slice(raw, 0, COFF_HEADER_SZ)?;
         let mut offset = 0;
 
         let signature =
-            read_utf8(&raw, &mut offset, DWORD_SZ).expect("Failed to read PE signature");
+            read_utf8(&raw, &mut offset, DWORD_SZ).map_err(|_| ParsingError::Malformed {
+                reason: "PE signature isn't UTF-8".to_string(),
+            })?;
 
         let machine = read_word(&raw, &mut offset);
 
@@ -31,12 +31,15 @@ pub struct DosHeader {
 
 impl DosHeader {
     pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
-        let e_magic = from_utf8(&raw[0..2])
-            .expect("UTF8 error parsing DOS magic")
+        let e_magic = from_utf8(try_slice(raw, 0, WORD_SZ)?)
+            .map_err(|_| ParsingError::InvalidMagic {
+                header: "DOS header".to_string(),
+                offset: 0,
+            })?
             .to_string();
 
         let offset = 0x3c;
-        let lfa_bytes = &raw[offset..offset + 4];
+        let lfa_bytes = try_slice(raw, offset, DWORD_SZ)?;
         let bytes = match lfa_bytes.try_into() {
             Ok(arr) => u32::from_le_bytes(arr),
             Err(_) => {
@@ -66,6 +66,16 @@ use crate::prelude::*;
 +F0  (240)	DWORD	0
 +F4  (244)	DWORD	0
  */
+// up to and including the 15 data directories parsed below
+const OPTIONAL_HEADER_PE32_SZ: usize = 0xd8;
+const OPTIONAL_HEADER_PE32P_SZ: usize = 0xe8;
+// from the start of the optional header, ImageBase and the data directories move with the format
+pub const IMAGE_BASE_PE32_OFFSET: usize = 0x1c;
+pub const IMAGE_BASE_PE32P_OFFSET: usize = 0x18;
+pub const CHECKSUM_OFFSET: usize = 0x40;
+pub const DATA_DIRECTORIES_PE32_OFFSET: usize = 0x60;
+pub const DATA_DIRECTORIES_PE32P_OFFSET: usize = 0x70;
+
 #[derive(Debug)]
 pub struct OptionalHeader {
     // standard fields
@@ -118,9 +128,10 @@ pub struct OptionalHeader {
 }
 
 impl OptionalHeader {
+    #[allow(clippy::needless_borrow)]
     pub fn new(raw: &[u8]) -> Result<Self, ParsingError> {
         let mut offset: usize = 0; // optional header starts
-        let magic: PeFormat = match read_word(&raw, &mut offset) {
+        let magic: PeFormat = match try_read_word(raw, &mut offset)? {
             0x10b => PeFormat::PE32,
             0x20b => PeFormat::PE32P,
             _ => {
@@ -129,6 +140,13 @@ impl OptionalHeader {
                 })
             }
         };
+        // the reads below aren't bounds checked, so the fields and all the data directories have
+        // to be there
+        let size = match magic {
+            PeFormat::PE32 => OPTIONAL_HEADER_PE32_SZ,
+            PeFormat::PE32P => OPTIONAL_HEADER_PE32P_SZ,
+        };
+        try_slice(raw, 0, size)?;
         let major_linked_version = read_byte(&raw, &mut offset);
         let minor_linked_version = read_byte(&raw, &mut offset);
         let size_of_code = read_dword(&raw, &mut offset);
@@ -1,21 +1,40 @@
 use crate::prelude::*;
 
+pub const SECTION_HEADER_SZ: usize = 40;
+
 #[derive(Debug)]
 pub struct SectionTable {
     pub section_headers: Vec<SectionHeader>,
 }
 
 impl SectionTable {
-    pub fn new(raw: &[u8], num_sections: usize) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(raw: &[u8], num_sections: usize) -> Result<Self, ParsingError> {
+        // SectionHeader::new doesn't check bounds
+        try_slice(raw, 0, num_sections * SECTION_HEADER_SZ)?;
         let mut offset: usize = 0; // optional header starts
         let mut section_headers = Vec::with_capacity(num_sections);
-        for _ in 0..section_headers.len() {
+        for _ in 0..num_sections {
             let section_header = SectionHeader::new(raw, &mut offset);
             section_headers.push(section_header);
         }
 
         Ok(Self { section_headers })
     }
+
+    pub fn section_for_rva(&self, rva: u32) -> Option<&SectionHeader> {
+        self.section_headers.iter().find(|s| s.contains_rva(rva))
+    }
+
+    pub fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        let section = self.section_for_rva(rva)?;
+        let delta = rva - section.virtual_address;
+        // the tail of a section past its raw data is zero filled by the loader and has no
+        // backing bytes in the file
+        if delta >= section.size_raw_data {
+            return None;
+        }
+        Some(section.pointer_raw_data as usize + delta as usize)
+    }
 }
 
 #[derive(Debug)]
@@ -34,8 +53,11 @@ pub struct SectionHeader {
 
 impl SectionHeader {
     pub fn new(raw: &[u8], offset: &mut usize) -> Self {
-        let name =
-            read_utf8(raw, offset, DWORDLONG_SZ).expect("failed to parse section header name");
+        // NUL padded unless the name takes up all 8 bytes
+        let name = String::from_utf8_lossy(&raw[*offset..*offset + DWORDLONG_SZ])
+            .trim_end_matches('\0')
+            .to_string();
+        *offset += DWORDLONG_SZ;
         let virtual_size = read_dword(raw, offset);
         let virtual_address = read_dword(raw, offset);
         let size_raw_data = read_dword(raw, offset);
@@ -59,4 +81,17 @@ impl SectionHeader {
             characteristics,
         }
     }
+
+    // sections with a virtual size of 0 are mapped using their raw size instead
+    pub fn virtual_extent(&self) -> u32 {
+        match self.virtual_size {
+            0 => self.size_raw_data,
+            size => size,
+        }
+    }
+
+    pub fn contains_rva(&self, rva: u32) -> bool {
+        rva >= self.virtual_address
+            && (rva as u64) < self.virtual_address as u64 + self.virtual_extent() as u64
+    }
 }
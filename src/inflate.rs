@@ -0,0 +1,274 @@
+use crate::prelude::*;
+
+/*
+Raw DEFLATE (RFC 1951) decompression.
+
+Each block starts with a 3 bit header, read least significant bit first:
+bit  0      BFINAL (last block)
+bits 1-2    BTYPE (0 = stored, 1 = fixed Huffman codes, 2 = dynamic Huffman codes, 3 = error)
+
+Stored blocks skip to the next byte boundary and hold a WORD LEN, a WORD NLEN (its complement) and
+LEN literal bytes. Huffman blocks encode literals and <length, distance> back references until the
+end of block symbol 256. Dynamic blocks first describe their code lengths with a third code.
+ */
+const MAX_BITS: usize = 15;
+const MAX_LITERALS: usize = 288;
+const MAX_DISTANCES: usize = 30;
+const END_OF_BLOCK: u16 = 256;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// order the code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn corrupt(reason: &str) -> ParsingError {
+    ParsingError::Malformed {
+        reason: format!("deflate stream {}", reason),
+    }
+}
+
+struct BitReader<'a> {
+    raw: &'a [u8],
+    offset: usize,
+    bit: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, ParsingError> {
+        while self.bit_count < count {
+            let byte = *self
+                .raw
+                .get(self.offset)
+                .ok_or_else(|| corrupt("is truncated"))?;
+            self.offset += 1;
+            self.bit |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit & ((1u32 << count) - 1);
+        self.bit >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    // stored blocks start on a byte boundary
+    fn align(&mut self) {
+        self.bit = 0;
+        self.bit_count = 0;
+    }
+}
+
+// canonical Huffman code, as the number of codes of each length and the symbols sorted by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, ParsingError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+
+        // over subscribed codes can't be decoded, incomplete ones are allowed
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(corrupt("has an over subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ParsingError> {
+        // codes are packed most significant bit first, so they're read a bit at a time
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupt("has an invalid Huffman code"))
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), ParsingError> {
+    let mut lengths = [0u8; MAX_LITERALS];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; MAX_DISTANCES])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ParsingError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > MAX_DISTANCES {
+        return Err(corrupt("has too many Huffman codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_count) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => {
+                lengths[index] = symbol as u8;
+                index += 1;
+                continue;
+            }
+            16 => match index {
+                0 => return Err(corrupt("repeats a code length before the first")),
+                _ => (lengths[index - 1], 3 + reader.bits(2)? as usize),
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > lengths.len() {
+            return Err(corrupt("repeats code lengths past the end"));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(corrupt("has no end of block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+    limit: usize,
+) -> Result<(), ParsingError> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            END_OF_BLOCK => return Ok(()),
+            _ => {
+                let index = (symbol - 257) as usize;
+                if index >= LENGTH_BASE.len() {
+                    return Err(corrupt("has an invalid length symbol"));
+                }
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(corrupt("has an invalid distance symbol"));
+                }
+                let distance = DISTANCE_BASE[index] as usize
+                    + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(corrupt("refers back past the start of the output"));
+                }
+                // the copy can overlap what it's producing, so it goes a byte at a time
+                let start = out.len() - distance;
+                for i in 0..length {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+        if out.len() > limit {
+            return Err(corrupt("inflates past its expected size"));
+        }
+    }
+}
+
+// decompresses a raw DEFLATE stream, failing once the output would grow past limit bytes
+pub fn inflate(raw: &[u8], limit: usize) -> Result<Vec<u8>, ParsingError> {
+    let mut reader = BitReader {
+        raw,
+        offset: 0,
+        bit: 0,
+        bit_count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let mut offset = reader.offset;
+                let len = try_read_word(raw, &mut offset)?;
+                let nlen = try_read_word(raw, &mut offset)?;
+                if len != !nlen {
+                    return Err(corrupt("has a stored block with a bad length"));
+                }
+                out.extend_from_slice(try_slice(raw, offset, len as usize)?);
+                reader.offset = offset + len as usize;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_block(&mut reader, &mut out, &literals, &distances, limit)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literals, &distances, limit)?;
+            }
+            _ => return Err(corrupt("has a block of reserved type 3")),
+        }
+        if out.len() > limit {
+            return Err(corrupt("inflates past its expected size"));
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
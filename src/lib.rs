@@ -1,5 +1,9 @@
+pub mod crypto;
+pub mod der;
+pub mod directories;
 pub mod error;
 pub mod headers;
+pub mod inflate;
 pub mod pe;
 pub mod utils;
 
@@ -1,10 +1,54 @@
+use super::crypto::rsa::RsaPrivateKey;
+use super::crypto::{DigestAlgorithm, Hasher};
+use super::directories::certificates::page_hashes::{PageHash, PageHashReport, PAGE_HASH_SIZE};
+use super::directories::certificates::pkcs7::AuthenticodeSignature;
+use super::directories::certificates::sign::{authenticode_signed_data, win_certificate_entry};
+use super::directories::certificates::verify::{verify_signature, Verdict};
+use super::directories::certificates::x509::Certificate;
+use super::directories::certificates::{
+    certificate_anomalies, win_certificates, CertificateAnomaly, WinCertificate,
+};
+use super::directories::chpe::{code_map, entry_points, redirections, Arm64EcMetadata, Chpe};
+use super::directories::debug::codeview::{image_symbol_key, CodeView};
+use super::directories::debug::portable_pdb::PdbChecksum;
+use super::directories::debug::{debug_directories, DebugData, DebugDirectory, DebugEntry};
+use super::directories::dynamic_relocations::DynamicRelocationTable;
+use super::directories::exceptions::{arm, arm64, x64, ExceptionTable};
+use super::directories::guard::{guard_table, GuardEntry, GuardFlags, GuardTables};
+use super::directories::load_config::{safe_seh_handlers, LoadConfig};
+use super::directories::relocations::{
+    BaseRelocation, RebasedImage, RelocationIssue, RelocationProblem,
+};
+use super::directories::resources::dialogs::Dialog;
+use super::directories::resources::icons::{bitmap_file, IconGroup, IconKind};
+use super::directories::resources::manifest::Manifest;
+use super::directories::resources::menus::{AcceleratorTable, Menu};
+use super::directories::resources::strings::{
+    decode_message_table, decode_string_block, MessageEntry, StringEntry,
+};
+use super::directories::resources::typelib::TypeLib;
+use super::directories::resources::version::VersionInfo;
+use super::directories::resources::{Resource, ResourceDataEntry, ResourceTree, ResourceType};
+use super::directories::tls::{callback_vas, va_to_rva, Tls, TlsCallback, TlsDirectory};
+use super::headers::coff::{
+    COFF_HEADER_SZ, IMAGE_FILE_MACHINE_AMD64, IMAGE_FILE_MACHINE_ARM, IMAGE_FILE_MACHINE_ARM64,
+    IMAGE_FILE_MACHINE_ARM64EC, IMAGE_FILE_MACHINE_ARM64X, IMAGE_FILE_MACHINE_ARMNT,
+    IMAGE_FILE_MACHINE_THUMB,
+};
+use super::headers::optional::{
+    CHECKSUM_OFFSET, DATA_DIRECTORIES_PE32P_OFFSET, DATA_DIRECTORIES_PE32_OFFSET,
+    IMAGE_BASE_PE32P_OFFSET, IMAGE_BASE_PE32_OFFSET,
+};
 use super::prelude::*;
-use std::{fmt, path::Path};
+use chrono::{DateTime, Utc};
+use std::{fmt, ops::Range, path::Path};
+
+// largest image materialize() will lay out
+const MAX_IMAGE_SIZE: u64 = 0x4000_0000;
 
-#[derive(Debug)]
 pub struct Pe {
-    //raw: Vec<u8>,
-    path: &'static Path,
+    raw: Vec<u8>,
+    path: Option<&'static Path>,
     pub dos_header: DosHeader,
     pub coff_header: CoffHeader,
     pub optional_header: OptionalHeader,
@@ -16,37 +60,1039 @@ impl Pe {
         let path = Path::new(path_str);
         let raw = std::fs::read(path).expect("failed to read file");
 
-        let dos_header = DosHeader::new(&raw).expect("failed to parse DOS header");
+        let mut pe = Self::from_bytes(raw)?;
+        pe.path = Some(path);
+        Ok(pe)
+    }
+
+    pub fn from_bytes(raw: Vec<u8>) -> Result<Self, ParsingError> {
+        // everything from offset on, which each header parser checks is long enough
+        let from = |offset: usize| {
+            raw.get(offset..)
+                .ok_or(ParsingError::PointerAccessError { byte: offset })
+        };
+
+        let dos_header = DosHeader::new(&raw)?;
 
         let coff_header_offset = dos_header.e_lfanew as usize;
-        let coff_header =
-            CoffHeader::new(&raw[coff_header_offset..]).expect("failed to parse coff header");
+        let coff_header = CoffHeader::new(from(coff_header_offset)?)?;
 
         // optional header starts 24 bytes after coff_header
-        let optional_header_offset = coff_header_offset + 24;
-        let optional_header = OptionalHeader::new(&raw[optional_header_offset..])
-            .expect("failed to parse optional header");
+        let optional_header_offset = coff_header_offset + COFF_HEADER_SZ;
+        let optional_header = OptionalHeader::new(from(optional_header_offset)?)?;
 
         // will eventually need to account for scenario where there's no optional header (non image
         // files)
         let section_table_offset =
             optional_header_offset + coff_header.size_optional_header as usize;
         let num_sections = coff_header.num_sections as usize;
-        let section_table = SectionTable::new(&raw[section_table_offset..], num_sections)
-            .expect("failed to parse section table");
+        let section_table = SectionTable::new(from(section_table_offset)?, num_sections)?;
 
         Ok(Self {
-            //raw,
-            path,
+            raw,
+            path: None,
             dos_header,
             coff_header,
             optional_header,
             section_table,
         })
     }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        // anything below the first section is still inside the headers, which are mapped 1:1
+        if rva < self.optional_header.size_of_headers
+            && self.section_table.section_for_rva(rva).is_none()
+        {
+            return Some(rva as usize);
+        }
+        self.section_table.rva_to_offset(rva)
+    }
+
+    // bytes backing [rva, rva + len) in the file. The whole range has to come from the same
+    // section since neighbouring sections aren't necessarily adjacent on disk.
+    pub fn read_at_rva(&self, rva: u32, len: usize) -> Result<&[u8], ParsingError> {
+        let start = self
+            .rva_to_offset(rva)
+            .ok_or(ParsingError::PointerAccessError { byte: rva as usize })?;
+        if len > 0 {
+            let last = rva
+                .checked_add(len as u32 - 1)
+                .ok_or(ParsingError::PointerAccessError { byte: rva as usize })?;
+            match self.rva_to_offset(last) {
+                Some(end) if end == start + len - 1 => {}
+                _ => {
+                    return Err(ParsingError::PointerAccessError {
+                        byte: last as usize,
+                    })
+                }
+            }
+        }
+        try_slice(&self.raw, start, len)
+    }
+
+    // everything from rva up to the end of the file data of whatever contains it, for
+    // structures whose extent isn't known up front
+    pub fn read_from_rva(&self, rva: u32) -> Result<&[u8], ParsingError> {
+        let start = self
+            .rva_to_offset(rva)
+            .ok_or(ParsingError::PointerAccessError { byte: rva as usize })?;
+        let end = match self.section_table.section_for_rva(rva) {
+            Some(section) => {
+                section.pointer_raw_data as usize
+                    + section.size_raw_data.min(section.virtual_extent()) as usize
+            }
+            None => self.optional_header.size_of_headers as usize,
+        };
+        let end = end.min(self.raw.len());
+        try_slice(&self.raw, start, end.saturating_sub(start))
+    }
+
+    pub fn base_relocations(&self) -> Result<BaseRelocationTable, ParsingError> {
+        let directory = &self
+            .optional_header
+            .data_directories
+            .offset_relocation_table;
+        if directory.virtual_addr == 0 || directory.size == 0 {
+            return Ok(BaseRelocationTable { blocks: Vec::new() });
+        }
+        let raw = self.read_at_rva(directory.virtual_addr, directory.size as usize)?;
+        BaseRelocationTable::new(raw, self.coff_header.machine)
+    }
+
+    pub fn resources(&self) -> Result<ResourceTree, ParsingError> {
+        let directory = &self.optional_header.data_directories.resource_table;
+        if directory.virtual_addr == 0 || directory.size == 0 {
+            return Ok(ResourceTree::empty());
+        }
+        // offsets inside the tree are relative to its root and aren't always within the size
+        // the directory claims, so hand over the rest of the section
+        ResourceTree::new(self.read_from_rva(directory.virtual_addr)?)
+    }
+
+    pub fn resource_data(&self, entry: &ResourceDataEntry) -> Result<&[u8], ParsingError> {
+        self.read_at_rva(entry.data_rva, entry.size as usize)
+    }
+
+    // RT_VERSION with id 1 in whichever language comes first
+    pub fn version_info(&self) -> Result<Option<VersionInfo>, ParsingError> {
+        match self.resources()?.find(ResourceType::Version, 1, None) {
+            Some(resource) => Ok(Some(VersionInfo::new(self.resource_data(&resource.data)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn manifests(&self) -> Result<Vec<Manifest>, ParsingError> {
+        let mut manifests = Vec::new();
+        for resource in self.resources()?.of_type(ResourceType::Manifest) {
+            let raw = self.resource_data(&resource.data)?;
+            manifests.push(Manifest::new(resource.name, resource.language, raw));
+        }
+        Ok(manifests)
+    }
+
+    // both RT_GROUP_ICON and RT_GROUP_CURSOR groups
+    pub fn icon_groups(&self) -> Result<Vec<IconGroup>, ParsingError> {
+        let tree = self.resources()?;
+        let mut groups = Vec::new();
+        for kind in [ResourceType::GroupIcon, ResourceType::GroupCursor] {
+            for resource in tree.of_type(kind) {
+                let raw = self.resource_data(&resource.data)?;
+                groups.push(IconGroup::new(raw, resource.name, resource.language)?);
+            }
+        }
+        Ok(groups)
+    }
+
+    // reassembles a group into the contents of a .ico or .cur file
+    pub fn icon_file(&self, group: &IconGroup) -> Result<Vec<u8>, ParsingError> {
+        let tree = self.resources()?;
+        let kind = match group.kind {
+            IconKind::Icon => ResourceType::Icon,
+            IconKind::Cursor => ResourceType::Cursor,
+        };
+        let language = group.language.as_id().and_then(|id| u16::try_from(id).ok());
+
+        let mut images = Vec::with_capacity(group.entries.len());
+        for entry in &group.entries {
+            // the image in the group's own language if there is one
+            let resource = tree
+                .find(kind, entry.id as u32, language)
+                .or_else(|| tree.find(kind, entry.id as u32, None))
+                .ok_or_else(|| ParsingError::Malformed {
+                    reason: format!(
+                        "{} {} referenced by group {} is missing",
+                        kind, entry.id, group.name
+                    ),
+                })?;
+            images.push(self.resource_data(&resource.data)?);
+        }
+        group.to_file(&images)
+    }
+
+    // RT_BITMAP resource as the contents of a .bmp file
+    pub fn bitmap_file(&self, resource: &Resource) -> Result<Vec<u8>, ParsingError> {
+        bitmap_file(self.resource_data(&resource.data)?)
+    }
+
+    // every RT_STRING string in every language, ordered by block then id
+    pub fn string_table(&self) -> Result<Vec<StringEntry>, ParsingError> {
+        let mut strings = Vec::new();
+        for resource in self.resources()?.of_type(ResourceType::String) {
+            let block_id = resource
+                .name
+                .as_id()
+                .ok_or_else(|| ParsingError::Malformed {
+                    reason: format!("string table block {} is not numbered", resource.name),
+                })?;
+            let raw = self.resource_data(&resource.data)?;
+            strings.extend(decode_string_block(raw, block_id, &resource.language)?);
+        }
+        Ok(strings)
+    }
+
+    pub fn message_table(&self) -> Result<Vec<MessageEntry>, ParsingError> {
+        let mut messages = Vec::new();
+        for resource in self.resources()?.of_type(ResourceType::MessageTable) {
+            let raw = self.resource_data(&resource.data)?;
+            messages.extend(decode_message_table(raw, &resource.language)?);
+        }
+        Ok(messages)
+    }
+
+    pub fn dialogs(&self) -> Result<Vec<Dialog>, ParsingError> {
+        let mut dialogs = Vec::new();
+        for resource in self.resources()?.of_type(ResourceType::Dialog) {
+            let raw = self.resource_data(&resource.data)?;
+            dialogs.push(Dialog::new(raw, resource.name, resource.language)?);
+        }
+        Ok(dialogs)
+    }
+
+    pub fn menus(&self) -> Result<Vec<Menu>, ParsingError> {
+        let mut menus = Vec::new();
+        for resource in self.resources()?.of_type(ResourceType::Menu) {
+            let raw = self.resource_data(&resource.data)?;
+            menus.push(Menu::new(raw, resource.name, resource.language)?);
+        }
+        Ok(menus)
+    }
+
+    pub fn accelerators(&self) -> Result<Vec<AcceleratorTable>, ParsingError> {
+        let mut tables = Vec::new();
+        for resource in self.resources()?.of_type(ResourceType::Accelerator) {
+            let raw = self.resource_data(&resource.data)?;
+            tables.push(AcceleratorTable::new(
+                raw,
+                resource.name,
+                resource.language,
+            )?);
+        }
+        Ok(tables)
+    }
+
+    // COM servers embed their type library as a "TYPELIB" resource, usually named 1
+    pub fn type_libraries(&self) -> Result<Vec<TypeLib>, ParsingError> {
+        let mut libraries = Vec::new();
+        for resource in self.resources()?.of_type("TYPELIB") {
+            libraries.push(TypeLib::new(self.resource_data(&resource.data)?)?);
+        }
+        Ok(libraries)
+    }
+
+    pub fn exceptions(&self) -> Result<Option<ExceptionTable>, ParsingError> {
+        let directory = &self.optional_header.data_directories.exception_table;
+        if directory.virtual_addr == 0 || directory.size == 0 {
+            return Ok(None);
+        }
+        let raw = self.read_at_rva(directory.virtual_addr, directory.size as usize)?;
+        match self.coff_header.machine {
+            IMAGE_FILE_MACHINE_AMD64 => Ok(Some(ExceptionTable::X64(x64::function_table(
+                raw,
+                |rva| self.read_from_rva(rva),
+            )?))),
+            IMAGE_FILE_MACHINE_ARM64 | IMAGE_FILE_MACHINE_ARM64EC | IMAGE_FILE_MACHINE_ARM64X => {
+                Ok(Some(ExceptionTable::Arm64(arm64::function_table(
+                    raw,
+                    |rva| self.read_from_rva(rva),
+                )?)))
+            }
+            IMAGE_FILE_MACHINE_ARM | IMAGE_FILE_MACHINE_THUMB | IMAGE_FILE_MACHINE_ARMNT => Ok(
+                Some(ExceptionTable::Arm(arm::function_table(raw, |rva| {
+                    self.read_from_rva(rva)
+                })?)),
+            ),
+            machine => Err(ParsingError::Malformed {
+                reason: format!(
+                    "exception directory of machine {:#x} isn't supported",
+                    machine
+                ),
+            }),
+        }
+    }
+
+    pub fn debug_directory(&self) -> Result<Vec<DebugEntry>, ParsingError> {
+        let directory = &self.optional_header.data_directories.debug_table;
+        if directory.virtual_addr == 0 || directory.size == 0 {
+            return Ok(Vec::new());
+        }
+        let raw = self.read_at_rva(directory.virtual_addr, directory.size as usize)?;
+        let mut entries = Vec::new();
+        // each entry on its own, one stripped or broken entry doesn't take the others with it
+        for directory in debug_directories(raw)? {
+            let data = match self.debug_data(&directory) {
+                Ok(data) => DebugData::new(directory.kind, data),
+                Err(_) => DebugData::Raw(self.partial_debug_data(&directory).to_vec()),
+            };
+            entries.push(DebugEntry { directory, data });
+        }
+        Ok(entries)
+    }
+
+    // the data is normally mapped, but entries left out of the image only have a file offset
+    pub fn debug_data(&self, directory: &DebugDirectory) -> Result<&[u8], ParsingError> {
+        let size = directory.size_of_data as usize;
+        match directory.address_of_raw_data {
+            0 => try_slice(&self.raw, directory.pointer_to_raw_data as usize, size),
+            rva => self.read_at_rva(rva, size),
+        }
+    }
+
+    // as much of an entry's data as the file has, for entries debug_data can't read whole
+    fn partial_debug_data(&self, directory: &DebugDirectory) -> &[u8] {
+        let available = match directory.address_of_raw_data {
+            0 => self.raw.get(directory.pointer_to_raw_data as usize..),
+            rva => self.read_from_rva(rva).ok(),
+        };
+        available.map_or(&[], |data| {
+            &data[..data.len().min(directory.size_of_data as usize)]
+        })
+    }
+
+    pub fn codeview(&self) -> Result<Option<CodeView>, ParsingError> {
+        Ok(self
+            .debug_directory()?
+            .into_iter()
+            .find_map(|entry| match entry.data {
+                DebugData::CodeView(codeview) => Some(codeview),
+                _ => None,
+            }))
+    }
+
+    // the decompressed portable PDB from an EmbeddedPortablePdb entry
+    pub fn embedded_pdb(&self) -> Result<Option<Vec<u8>>, ParsingError> {
+        for entry in self.debug_directory()? {
+            if let DebugData::EmbeddedPortablePdb(embedded) = entry.data {
+                return Ok(Some(embedded.decompress()?));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn pdb_checksums(&self) -> Result<Vec<PdbChecksum>, ParsingError> {
+        Ok(self
+            .debug_directory()?
+            .into_iter()
+            .filter_map(|entry| match entry.data {
+                DebugData::PdbChecksum(checksum) => Some(checksum),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub fn image_base(&self) -> u64 {
+        self.optional_header.image_offset.value()
+    }
+
+    pub fn va_to_rva(&self, va: u64) -> Option<u32> {
+        va_to_rva(va, self.image_base())
+    }
+
+    pub fn tls(&self) -> Result<Option<Tls>, ParsingError> {
+        let directory = &self.optional_header.data_directories.tls_table;
+        if directory.virtual_addr == 0 {
+            return Ok(None);
+        }
+        let magic = &self.optional_header.magic;
+        // the directory's Size is frequently wrong, so it's read from the start of the structure
+        let directory = TlsDirectory::new(self.read_from_rva(directory.virtual_addr)?, magic)?;
+
+        let callbacks = match directory.address_of_callbacks {
+            0 => Vec::new(),
+            va => {
+                let rva = self.va_to_rva(va).ok_or(ParsingError::Malformed {
+                    reason: format!("TLS callback array VA {:#x} is outside the image", va),
+                })?;
+                callback_vas(self.read_from_rva(rva)?, magic)?
+                    .into_iter()
+                    .map(|va| {
+                        let rva = self.va_to_rva(va);
+                        let section = rva
+                            .and_then(|rva| self.section_table.section_for_rva(rva))
+                            .map(|section| section.name.clone());
+                        TlsCallback { va, rva, section }
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(Some(Tls {
+            directory,
+            callbacks,
+        }))
+    }
+
+    pub fn load_config(&self) -> Result<Option<LoadConfig>, ParsingError> {
+        let directory = &self.optional_header.data_directories.load_config_table;
+        if directory.virtual_addr == 0 {
+            return Ok(None);
+        }
+        // the structure's own Size is what counts, not the directory's
+        let raw = self.read_from_rva(directory.virtual_addr)?;
+        Ok(Some(LoadConfig::new(raw, &self.optional_header.magic)?))
+    }
+
+    // RVAs of the registered exception handlers, for x86 images built with /SAFESEH
+    pub fn safe_seh_handlers(&self) -> Result<Vec<u32>, ParsingError> {
+        let config = match self.load_config()? {
+            Some(config) if config.se_handler_table != 0 => config,
+            _ => return Ok(Vec::new()),
+        };
+        let rva = self
+            .va_to_rva(config.se_handler_table)
+            .ok_or(ParsingError::Malformed {
+                reason: format!(
+                    "SafeSEH handler table VA {:#x} is outside the image",
+                    config.se_handler_table
+                ),
+            })?;
+        safe_seh_handlers(self.read_from_rva(rva)?, config.se_handler_count)
+    }
+
+    pub fn guard_tables(&self) -> Result<Option<GuardTables>, ParsingError> {
+        let config = match self.load_config()? {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        let flags = GuardFlags(config.guard_flags);
+        let table = |va: u64, count: u64| -> Result<Vec<GuardEntry>, ParsingError> {
+            if va == 0 || count == 0 {
+                return Ok(Vec::new());
+            }
+            let rva = self.va_to_rva(va).ok_or(ParsingError::Malformed {
+                reason: format!("guard table VA {:#x} is outside the image", va),
+            })?;
+            guard_table(self.read_from_rva(rva)?, count, flags.stride())
+        };
+
+        Ok(Some(GuardTables {
+            flags,
+            functions: table(
+                config.guard_cf_function_table,
+                config.guard_cf_function_count,
+            )?,
+            address_taken_iat_entries: table(
+                config.guard_address_taken_iat_entry_table,
+                config.guard_address_taken_iat_entry_count,
+            )?,
+            long_jump_targets: table(
+                config.guard_long_jump_target_table,
+                config.guard_long_jump_target_count,
+            )?,
+            eh_continuation_targets: table(
+                config.guard_eh_continuation_table,
+                config.guard_eh_continuation_count,
+            )?,
+        }))
+    }
+
+    pub fn dynamic_relocations(&self) -> Result<Option<DynamicRelocationTable>, ParsingError> {
+        let config = match self.load_config()? {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        // newer linkers give a section and offset, older ones a VA
+        let rva = match (
+            config.dynamic_value_reloc_table_section,
+            config.dynamic_value_reloc_table,
+        ) {
+            (0, 0) => return Ok(None),
+            (0, va) => self.va_to_rva(va).ok_or(ParsingError::Malformed {
+                reason: format!("dynamic relocation table VA {:#x} is outside the image", va),
+            })?,
+            (section, _) => {
+                let header = self
+                    .section_table
+                    .section_headers
+                    .get(section as usize - 1)
+                    .ok_or(ParsingError::Malformed {
+                        reason: format!(
+                            "dynamic relocation table is in missing section {}",
+                            section
+                        ),
+                    })?;
+                header
+                    .virtual_address
+                    .wrapping_add(config.dynamic_value_reloc_table_offset)
+            }
+        };
+        let raw = self.read_from_rva(rva)?;
+        Ok(Some(DynamicRelocationTable::new(
+            raw,
+            &self.optional_header.magic,
+            self.coff_header.machine,
+        )?))
+    }
+
+    // ARM64EC metadata, present in ARM64EC and ARM64X images
+    pub fn chpe(&self) -> Result<Option<Chpe>, ParsingError> {
+        if !matches!(
+            self.coff_header.machine,
+            IMAGE_FILE_MACHINE_AMD64
+                | IMAGE_FILE_MACHINE_ARM64
+                | IMAGE_FILE_MACHINE_ARM64EC
+                | IMAGE_FILE_MACHINE_ARM64X
+        ) {
+            return Ok(None);
+        }
+        let va = match self.load_config()? {
+            Some(config) if config.chpe_metadata_pointer != 0 => config.chpe_metadata_pointer,
+            _ => return Ok(None),
+        };
+        let rva = self.va_to_rva(va).ok_or(ParsingError::Malformed {
+            reason: format!("CHPE metadata VA {:#x} is outside the image", va),
+        })?;
+        let metadata = Arm64EcMetadata::new(self.read_from_rva(rva)?)?;
+
+        let read = |rva: u32, count: u32| -> Result<&[u8], ParsingError> {
+            match (rva, count) {
+                (0, _) | (_, 0) => Ok(&[]),
+                _ => self.read_from_rva(rva),
+            }
+        };
+        let code_ranges = code_map(
+            read(metadata.code_map, metadata.code_map_count)?,
+            metadata.code_map_count,
+        )?;
+        let entry_points = entry_points(
+            read(
+                metadata.code_ranges_to_entry_points,
+                metadata.code_ranges_to_entry_points_count,
+            )?,
+            metadata.code_ranges_to_entry_points_count,
+        )?;
+        let redirections = redirections(
+            read(
+                metadata.redirection_metadata,
+                metadata.redirection_metadata_count,
+            )?,
+            metadata.redirection_metadata_count,
+        )?;
+
+        // one slot for each of the regular IAT's
+        let iat = &self.optional_header.data_directories.import_address_table;
+        let slots = iat.size as usize / DWORDLONG_SZ;
+        let auxiliary = read(metadata.auxiliary_iat, slots as u32)?;
+        let mut offset = 0;
+        let mut auxiliary_iat = Vec::with_capacity(slots);
+        while auxiliary_iat.len() < slots && !auxiliary.is_empty() {
+            auxiliary_iat.push(try_read_dwordlong(auxiliary, &mut offset)?);
+        }
+
+        Ok(Some(Chpe {
+            metadata,
+            code_ranges,
+            entry_points,
+            redirections,
+            auxiliary_iat,
+        }))
+    }
+
+    // the other half of an ARM64X image, with the ARM64X fixups from the dynamic relocation table
+    // applied to the headers and whatever else they target
+    pub fn arm64x_view(&self) -> Result<Option<Pe>, ParsingError> {
+        let table = match self.dynamic_relocations()? {
+            Some(table) => table,
+            None => return Ok(None),
+        };
+        let mut fixups = table.arm64x_fixups().peekable();
+        if fixups.peek().is_none() {
+            return Ok(None);
+        }
+
+        let mut raw = self.raw.clone();
+        for fixup in fixups {
+            let offset = self
+                .rva_to_offset(fixup.rva)
+                .ok_or(ParsingError::PointerAccessError {
+                    byte: fixup.rva as usize,
+                })?;
+            fixup.apply(&mut raw, offset)?;
+        }
+        Ok(Some(Pe::from_bytes(raw)?))
+    }
+
+    // the security directory's VirtualAddress is a file offset, the table isn't mapped
+    pub fn certificates(&self) -> Result<Vec<WinCertificate>, ParsingError> {
+        let directory = &self.optional_header.data_directories.certificate_table;
+        if directory.virtual_addr == 0 || directory.size == 0 {
+            return Ok(Vec::new());
+        }
+        let offset = directory.virtual_addr as usize;
+        win_certificates(
+            try_slice(&self.raw, offset, directory.size as usize)?,
+            offset,
+        )
+    }
+
+    // Authenticode signatures from the PKCS_SIGNED_DATA entries, nested ones stay with the
+    // signature they're attached to
+    pub fn signatures(&self) -> Result<Vec<AuthenticodeSignature>, ParsingError> {
+        self.certificates()?
+            .iter()
+            .filter_map(|certificate| certificate.signature())
+            .collect()
+    }
+
+    // verdicts for the Authenticode signatures, checked against roots as of now
+    pub fn verify_signatures(&self, roots: &[Certificate]) -> Result<Vec<Verdict>, ParsingError> {
+        self.verify_signatures_at(roots, Utc::now())
+    }
+
+    // verdicts for the Authenticode signatures, with certificate validity checked at time for
+    // signatures without a good timestamp
+    pub fn verify_signatures_at(
+        &self,
+        roots: &[Certificate],
+        time: DateTime<Utc>,
+    ) -> Result<Vec<Verdict>, ParsingError> {
+        let image_digest = |algorithm| self.authenticode_digest(algorithm);
+        self.signatures()?
+            .iter()
+            .map(|signature| verify_signature(signature, &image_digest, roots, time))
+            .collect()
+    }
+
+    // raw TimeDateStamp from the COFF header
+    pub fn timestamp(&self) -> Result<u32, ParsingError> {
+        let mut offset = self.dos_header.e_lfanew as usize + 8;
+        try_read_dword(&self.raw, &mut offset)
+    }
+
+    // key the binary itself is stored under on a symbol server
+    pub fn symbol_key(&self) -> Result<String, ParsingError> {
+        Ok(image_symbol_key(
+            self.timestamp()?,
+            self.optional_header.size_of_image,
+        ))
+    }
+
+    // file offset of the optional header, right after the PE signature and COFF header
+    pub fn optional_header_offset(&self) -> usize {
+        self.dos_header.e_lfanew as usize + COFF_HEADER_SZ
+    }
+
+    // file offset of the optional header CheckSum
+    fn checksum_offset(&self) -> usize {
+        self.optional_header_offset() + CHECKSUM_OFFSET
+    }
+
+    // file offset of the security data directory entry
+    fn security_directory_offset(&self) -> usize {
+        let directories = match self.optional_header.magic {
+            PeFormat::PE32 => self.optional_header_offset() + DATA_DIRECTORIES_PE32_OFFSET,
+            PeFormat::PE32P => self.optional_header_offset() + DATA_DIRECTORIES_PE32P_OFFSET,
+        };
+        directories + 4 * DWORDLONG_SZ
+    }
+
+    // the headers without the CheckSum field and the security directory entry, as hashed
+    fn authenticode_header_ranges(&self) -> Result<Vec<Range<usize>>, ParsingError> {
+        let checksum = self.checksum_offset();
+        let security = self.security_directory_offset();
+        let headers = (self.optional_header.size_of_headers as usize).min(self.raw.len());
+        if security + DWORDLONG_SZ > headers {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "SizeOfHeaders {:#x} doesn't cover the data directories",
+                    headers
+                ),
+            });
+        }
+        Ok(vec![
+            0..checksum,
+            checksum + DWORD_SZ..security,
+            security + DWORDLONG_SZ..headers,
+        ])
+    }
+
+    // sections with raw data by PointerToRawData, the order Authenticode hashes them in
+    fn authenticode_sections(&self) -> Result<Vec<&SectionHeader>, ParsingError> {
+        let mut sections: Vec<&SectionHeader> = self
+            .section_table
+            .section_headers
+            .iter()
+            .filter(|section| section.size_raw_data != 0)
+            .collect();
+        sections.sort_by_key(|section| section.pointer_raw_data);
+        for section in &sections {
+            if section.pointer_raw_data as usize + section.size_raw_data as usize > self.raw.len() {
+                return Err(ParsingError::Malformed {
+                    reason: format!("section {} runs past the end of the file", section.name),
+                });
+            }
+        }
+        Ok(sections)
+    }
+
+    // file ranges the Authenticode digest covers, in the order they're hashed: the headers without
+    // the CheckSum field and the security directory entry, each section's raw data by
+    // PointerToRawData, then whatever follows the sections apart from the certificate table
+    pub fn authenticode_ranges(&self) -> Result<Vec<Range<usize>>, ParsingError> {
+        let file_size = self.raw.len();
+        let mut ranges = self.authenticode_header_ranges()?;
+        let mut hashed = ranges.last().map_or(0, |headers| headers.end);
+        for section in self.authenticode_sections()? {
+            let start = section.pointer_raw_data as usize;
+            let end = start + section.size_raw_data as usize;
+            ranges.push(start..end);
+            hashed = hashed.max(end);
+        }
+
+        let table = &self.optional_header.data_directories.certificate_table;
+        let table = match table.size {
+            0 => file_size..file_size,
+            size => {
+                let start = table.virtual_addr as usize;
+                start..(start + size as usize).min(file_size)
+            }
+        };
+        // a table overlapping the headers or sections can't be left out of the digest
+        if table.start < hashed && !table.is_empty() {
+            return Err(ParsingError::Malformed {
+                reason: format!("certificate table at {:#x} overlaps the image", table.start),
+            });
+        }
+        ranges.push(hashed..table.start.max(hashed));
+        ranges.push(table.end.max(hashed)..file_size);
+        ranges.retain(|range| range.start < range.end);
+        Ok(ranges)
+    }
+
+    pub fn authenticode_digest(&self, algorithm: DigestAlgorithm) -> Result<Vec<u8>, ParsingError> {
+        let mut hasher = algorithm.hasher();
+        for range in self.authenticode_ranges()? {
+            hasher.update(&self.raw[range]);
+        }
+        Ok(hasher.finalize())
+    }
+
+    // the page hash table as it'd be signed: the headers (hashed like the Authenticode digest)
+    // padded with a page less SizeOfHeaders of zeros, every page of section raw data zero padded
+    // to a full page, then an entry at the end of the last section with an all zero hash
+    pub fn page_hashes(&self, algorithm: DigestAlgorithm) -> Result<Vec<PageHash>, ParsingError> {
+        let pad = |hasher: &mut Hasher, len: usize| {
+            hasher.update(&vec![0; len.next_multiple_of(PAGE_HASH_SIZE) - len])
+        };
+        let mut hasher = algorithm.hasher();
+        let mut headers = 0;
+        for range in self.authenticode_header_ranges()? {
+            headers = range.end;
+            hasher.update(&self.raw[range]);
+        }
+        // a page less SizeOfHeaders, so the 12 skipped bytes leave it short of a full page, as
+        // signtool and osslsigncode hash it
+        hasher.update(&vec![0; PAGE_HASH_SIZE.saturating_sub(headers)]);
+        let mut pages = vec![PageHash {
+            offset: 0,
+            hash: hasher.finalize(),
+        }];
+
+        let mut end = 0;
+        for section in self.authenticode_sections()? {
+            let start = section.pointer_raw_data as usize;
+            end = start + section.size_raw_data as usize;
+            for page in (start..end).step_by(PAGE_HASH_SIZE) {
+                let data = &self.raw[page..(page + PAGE_HASH_SIZE).min(end)];
+                let mut hasher = algorithm.hasher();
+                hasher.update(data);
+                pad(&mut hasher, data.len());
+                pages.push(PageHash {
+                    offset: page as u32,
+                    hash: hasher.finalize(),
+                });
+            }
+        }
+        pages.push(PageHash {
+            offset: end as u32,
+            hash: vec![0; algorithm.size()],
+        });
+        Ok(pages)
+    }
+
+    // signs of data smuggled into or after the certificate table, which the signature doesn't cover
+    pub fn certificate_anomalies(&self) -> Vec<CertificateAnomaly> {
+        let directory = &self.optional_header.data_directories.certificate_table;
+        if directory.virtual_addr == 0 || directory.size == 0 {
+            return Vec::new();
+        }
+        let start = directory.virtual_addr as usize;
+        // taken from the headers as they are, so a truncated section or a bad SizeOfHeaders still
+        // gets looked at rather than refused, anything claiming to run past EOF stops there
+        let image_end = self
+            .section_table
+            .section_headers
+            .iter()
+            .map(|section| section.pointer_raw_data as usize + section.size_raw_data as usize)
+            .chain(std::iter::once(
+                self.optional_header.size_of_headers as usize,
+            ))
+            .max()
+            .unwrap_or(0)
+            .min(self.raw.len());
+        certificate_anomalies(&self.raw, start..start + directory.size as usize, image_end)
+    }
+
+    // CheckSum as it should be for the file
+    pub fn compute_checksum(&self) -> u32 {
+        image_checksum(&self.raw, self.checksum_offset())
+    }
+
+    // the file Authenticode signed by key, with chain (signer certificate first) in the signature;
+    // an existing signature is replaced, the file padded to 8 bytes before the certificate table and
+    // the CheckSum updated
+    pub fn sign(
+        &self,
+        key: &RsaPrivateKey,
+        chain: &[Certificate],
+        algorithm: DigestAlgorithm,
+    ) -> Result<Vec<u8>, ParsingError> {
+        let mut raw = self.raw.clone();
+        let table = &self.optional_header.data_directories.certificate_table;
+        if table.virtual_addr != 0 && table.size != 0 {
+            let start = table.virtual_addr as usize;
+            if start + table.size as usize != raw.len() {
+                return Err(ParsingError::Malformed {
+                    reason: format!(
+                        "certificate table at {:#x} isn't at the end of the file",
+                        start
+                    ),
+                });
+            }
+            raw.truncate(start);
+        }
+        raw.resize(raw.len().next_multiple_of(DWORDLONG_SZ), 0);
+        let security = self.security_directory_offset();
+        raw[security..security + DWORDLONG_SZ].fill(0);
+
+        let digest = Pe::from_bytes(raw.clone())?.authenticode_digest(algorithm)?;
+        let entry =
+            win_certificate_entry(&authenticode_signed_data(algorithm, &digest, key, chain)?);
+        let offset = raw.len() as u32;
+        raw[security..security + DWORD_SZ].copy_from_slice(&offset.to_le_bytes());
+        raw[security + DWORD_SZ..security + DWORDLONG_SZ]
+            .copy_from_slice(&(entry.len() as u32).to_le_bytes());
+        raw.extend_from_slice(&entry);
+
+        let checksum = self.checksum_offset();
+        let sum = image_checksum(&raw, checksum);
+        raw[checksum..checksum + DWORD_SZ].copy_from_slice(&sum.to_le_bytes());
+        Ok(raw)
+    }
+
+    // the signatures' page hashes checked against the file, for the signatures that have them
+    pub fn verify_page_hashes(&self) -> Result<Vec<PageHashReport>, ParsingError> {
+        let mut reports = Vec::new();
+        for signature in self.signatures()? {
+            for signature in signature.all() {
+                let signed = match signature.page_hashes()? {
+                    Some(signed) => signed,
+                    None => continue,
+                };
+                let actual = self.page_hashes(signed.algorithm)?;
+                reports.push(PageHashReport::new(
+                    &signed,
+                    &actual,
+                    &self.section_table.section_headers,
+                ));
+            }
+        }
+        Ok(reports)
+    }
+
+    // the image laid out the way the loader maps it: headers at 0, each section at its RVA, and
+    // everything not backed by the file zero filled
+    pub fn materialize(&self) -> Result<Vec<u8>, ParsingError> {
+        // SizeOfImage comes straight from the header, so don't allocate more than the headers and
+        // sections actually span
+        let alignment = self.optional_header.section_alignment.max(1) as u64;
+        let extent = self
+            .section_table
+            .section_headers
+            .iter()
+            .map(|section| section.virtual_address as u64 + section.virtual_extent() as u64)
+            .fold(self.optional_header.size_of_headers as u64, u64::max)
+            .next_multiple_of(alignment);
+        let size_of_image = self.optional_header.size_of_image as u64;
+        if size_of_image > extent || size_of_image > MAX_IMAGE_SIZE {
+            return Err(ParsingError::Malformed {
+                reason: format!(
+                    "SizeOfImage {:#x} is larger than the sections span ({:#x}) or {:#x}",
+                    size_of_image, extent, MAX_IMAGE_SIZE
+                ),
+            });
+        }
+
+        let mut image = vec![0u8; size_of_image as usize];
+        let headers = (self.optional_header.size_of_headers as usize)
+            .min(self.raw.len())
+            .min(image.len());
+        image[..headers].copy_from_slice(&self.raw[..headers]);
+
+        for section in &self.section_table.section_headers {
+            let len = section.size_raw_data.min(section.virtual_extent()) as usize;
+            let src = match self.raw.get(section.pointer_raw_data as usize..) {
+                Some(src) => &src[..len.min(src.len())],
+                None => continue,
+            };
+            let dst = match image.get_mut(section.virtual_address as usize..) {
+                Some(dst) => dst,
+                None => continue,
+            };
+            let len = src.len().min(dst.len());
+            dst[..len].copy_from_slice(&src[..len]);
+        }
+        Ok(image)
+    }
+
+    // materialized image with every base relocation applied for a load at new_base
+    pub fn rebase_image(&self, new_base: u64) -> Result<RebasedImage, ParsingError> {
+        let mut bytes = self.materialize()?;
+        let issues = self.apply_relocations(&mut bytes, new_base, |rva, _, _| Ok(rva as usize))?;
+        Ok(RebasedImage {
+            image_base: new_base,
+            bytes,
+            issues,
+        })
+    }
+
+    // copy of the file with every base relocation applied, so that it matches what a load at
+    // new_base would have looked like while still being parseable as a PE
+    pub fn rebase_file(&self, new_base: u64) -> Result<RebasedImage, ParsingError> {
+        let mut bytes = self.raw.clone();
+        let issues = self.apply_relocations(&mut bytes, new_base, |rva, size, problem| {
+            // without a single section backing the whole fixup there's no contiguous run of
+            // file bytes to patch
+            if let Some(problem) = problem {
+                return Err(problem);
+            }
+            let start = self.rva_to_offset(rva);
+            let end = self.rva_to_offset(rva + size.max(1) as u32 - 1);
+            match (start, end) {
+                (Some(start), Some(end)) if end + 1 - start == size.max(1) => Ok(start),
+                _ => Err(RelocationProblem::NotInFile),
+            }
+        })?;
+        Ok(RebasedImage {
+            image_base: new_base,
+            bytes,
+            issues,
+        })
+    }
+
+    fn relocation_problem(&self, relocation: &BaseRelocation) -> Option<RelocationProblem> {
+        let sections = &self.section_table;
+        let size = relocation.kind.fixup_size().max(1) as u32;
+        let first = sections.section_for_rva(relocation.target_rva);
+        let last = relocation
+            .target_rva
+            .checked_add(size - 1)
+            .and_then(|rva| sections.section_for_rva(rva));
+        match (first, last) {
+            (None, _) => Some(RelocationProblem::OutsideSections),
+            (Some(first), Some(last)) if std::ptr::eq(first, last) => None,
+            _ => Some(RelocationProblem::StraddlesSections),
+        }
+    }
+
+    // locate maps a fixup (target rva, size, problem found with it) to an offset in bytes, or
+    // rejects it
+    fn apply_relocations(
+        &self,
+        bytes: &mut [u8],
+        new_base: u64,
+        locate: impl Fn(u32, usize, Option<RelocationProblem>) -> Result<usize, RelocationProblem>,
+    ) -> Result<Vec<RelocationIssue>, ParsingError> {
+        let image_base_offset = self.optional_header_offset()
+            + match self.optional_header.magic {
+                PeFormat::PE32 => IMAGE_BASE_PE32_OFFSET,
+                PeFormat::PE32P => IMAGE_BASE_PE32P_OFFSET,
+            };
+        let image_base = match self.optional_header.magic {
+            PeFormat::PE32 => u32::try_from(new_base)
+                .map_err(|_| ParsingError::Malformed {
+                    reason: format!("image base {:#x} does not fit in a PE32 header", new_base),
+                })?
+                .to_le_bytes()
+                .to_vec(),
+            PeFormat::PE32P => new_base.to_le_bytes().to_vec(),
+        };
+
+        let table = self.base_relocations()?;
+        let delta = new_base.wrapping_sub(self.optional_header.image_offset.value());
+        let mut issues = Vec::new();
+        for relocation in table.fixups() {
+            let problem = self.relocation_problem(relocation);
+            if let Some(problem) = problem {
+                issues.push(RelocationIssue {
+                    target_rva: relocation.target_rva,
+                    kind: relocation.kind,
+                    problem,
+                });
+            }
+
+            let size = relocation.kind.fixup_size();
+            let applied = locate(relocation.target_rva, size, problem)
+                .and_then(|at| relocation.apply(bytes, at, delta));
+            match applied {
+                Err(failure) if Some(failure) != problem => issues.push(RelocationIssue {
+                    target_rva: relocation.target_rva,
+                    kind: relocation.kind,
+                    problem: failure,
+                }),
+                _ => {}
+            }
+        }
+
+        let header = bytes
+            .get_mut(image_base_offset..image_base_offset + image_base.len())
+            .ok_or(ParsingError::PointerAccessError {
+                byte: image_base_offset,
+            })?;
+        header.copy_from_slice(&image_base);
+        Ok(issues)
+    }
+}
+
+// raw bytes are needed to follow the data directories, but dumping them makes the debug output
+// useless, so only their length is shown
+impl fmt::Debug for Pe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pe")
+            .field("raw", &format_args!("[{} bytes]", self.raw.len()))
+            .field("path", &self.path)
+            .field("dos_header", &self.dos_header)
+            .field("coff_header", &self.coff_header)
+            .field("optional_header", &self.optional_header)
+            .field("section_table", &self.section_table)
+            .finish()
+    }
 }
 
-// now that I'm not storing the raw bytes on the Pe struct and can use debug outbut, I don't need to finishing doing all this manually.
+// don't need to finishing doing all this manually now that the debug output is usable.
 // will keep for now though. This output is much more pleasant to read.
 impl fmt::Display for Pe {
     // This trait requires `fmt` with this exact signature.
@@ -54,7 +1100,7 @@ impl fmt::Display for Pe {
         writeln!(
             f,
             "\nPE File: {:?}\n==========================================",
-            self.path
+            self.path.unwrap_or(Path::new("<memory>"))
         )?;
         write!(f, "{}", self.dos_header)?;
         write!(f, "{}", self.coff_header)
@@ -1,6 +1,7 @@
 pub use super::utils::{
-    read_byte, read_dword, read_dwordlong, read_utf8, read_word, ArchDependentSized, PeFormat,
-    DWORDLONG_SZ, DWORD_SZ, WORD_SZ,
+    image_checksum, read_byte, read_dword, read_utf8, read_word, try_read_byte, try_read_dword,
+    try_read_dwordlong, try_read_utf16, try_read_utf16_nul, try_read_word, try_slice,
+    ArchDependentSized, Guid, PeFormat, DWORDLONG_SZ, DWORD_SZ, WORD_SZ,
 };
 
 pub use super::error::ParsingError;
@@ -9,3 +10,5 @@ pub use super::headers::coff::CoffHeader;
 pub use super::headers::dos::DosHeader;
 pub use super::headers::optional::OptionalHeader;
 pub use super::headers::sections::{SectionHeader, SectionTable};
+
+pub use super::directories::relocations::BaseRelocationTable;
@@ -1,4 +1,5 @@
 use super::prelude::*;
+use std::fmt;
 use std::str::{from_utf8, Utf8Error};
 
 /*
@@ -35,6 +36,22 @@ impl ArchDependentSized {
             }
         }
     }
+
+    // bounds checked, for fields read out of data directories
+    pub fn try_new(raw: &[u8], offset: &mut usize, magic: &PeFormat) -> Result<Self, ParsingError> {
+        match magic {
+            PeFormat::PE32 => Ok(Self::PE32(try_read_dword(raw, offset)?)),
+            PeFormat::PE32P => Ok(Self::PE32P(try_read_dwordlong(raw, offset)?)),
+        }
+    }
+
+    // widened value regardless of which format the field came from
+    pub fn value(&self) -> u64 {
+        match self {
+            Self::PE32(dword) => *dword as u64,
+            Self::PE32P(dwordlong) => *dwordlong,
+        }
+    }
 }
 
 impl PeFormat {
@@ -49,6 +66,47 @@ impl PeFormat {
     }
 }
 
+// GUID as laid out in memory, with the first three groups little endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    pub fn new(raw: &[u8], offset: &mut usize) -> Result<Self, ParsingError> {
+        let data1 = try_read_dword(raw, offset)?;
+        let data2 = try_read_word(raw, offset)?;
+        let data3 = try_read_word(raw, offset)?;
+        let mut data4 = [0; 8];
+        data4.copy_from_slice(try_slice(raw, *offset, 8)?);
+        *offset += 8;
+
+        Ok(Self {
+            data1,
+            data2,
+            data3,
+            data4,
+        })
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            self.data1, self.data2, self.data3, self.data4[0], self.data4[1]
+        )?;
+        for byte in &self.data4[2..] {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
 // TODO: These read functions should be returning Result / Option instead of just u8
 
 pub fn read_byte(raw: &[u8], offset: &mut usize) -> u8 {
@@ -100,3 +158,75 @@ pub fn read_utf8(raw: &[u8], offset: &mut usize, len: usize) -> Result<String, U
     *offset += len;
     Ok(str)
 }
+
+/*
+ * Bounds checked variants of the readers above. Anything that follows pointers found inside the
+ * file (data directories, resource trees, etc) should use these so a malformed binary produces a
+ * ParsingError instead of a panic.
+ */
+pub fn try_slice(raw: &[u8], offset: usize, len: usize) -> Result<&[u8], ParsingError> {
+    match offset.checked_add(len) {
+        Some(end) if end <= raw.len() => Ok(&raw[offset..end]),
+        _ => Err(ParsingError::PointerAccessError {
+            byte: offset.saturating_add(len),
+        }),
+    }
+}
+
+pub fn try_read_byte(raw: &[u8], offset: &mut usize) -> Result<u8, ParsingError> {
+    try_slice(raw, *offset, 1)?;
+    Ok(read_byte(raw, offset))
+}
+
+pub fn try_read_word(raw: &[u8], offset: &mut usize) -> Result<u16, ParsingError> {
+    try_slice(raw, *offset, WORD_SZ)?;
+    Ok(read_word(raw, offset))
+}
+
+pub fn try_read_dword(raw: &[u8], offset: &mut usize) -> Result<u32, ParsingError> {
+    try_slice(raw, *offset, DWORD_SZ)?;
+    Ok(read_dword(raw, offset))
+}
+
+pub fn try_read_dwordlong(raw: &[u8], offset: &mut usize) -> Result<u64, ParsingError> {
+    try_slice(raw, *offset, DWORDLONG_SZ)?;
+    Ok(read_dwordlong(raw, offset))
+}
+
+// len is in UTF-16 code units, not bytes
+pub fn try_read_utf16(raw: &[u8], offset: &mut usize, len: usize) -> Result<String, ParsingError> {
+    let bytes = try_slice(raw, *offset, len * WORD_SZ)?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(WORD_SZ)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    *offset += len * WORD_SZ;
+    Ok(String::from_utf16_lossy(&units))
+}
+
+// reads up to and including the terminating null, which isn't part of the returned string
+pub fn try_read_utf16_nul(raw: &[u8], offset: &mut usize) -> Result<String, ParsingError> {
+    let mut units = Vec::new();
+    loop {
+        match try_read_word(raw, offset)? {
+            0 => break,
+            unit => units.push(unit),
+        }
+    }
+    Ok(String::from_utf16_lossy(&units))
+}
+
+// the optional header CheckSum: the file summed as 16 bit words with end around carry, the field
+// itself counted as zero, plus the file size
+pub fn image_checksum(raw: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum = 0u32;
+    for (i, word) in raw.chunks(WORD_SZ).enumerate() {
+        let at = i * WORD_SZ;
+        if at >= checksum_offset && at < checksum_offset + DWORD_SZ {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u32;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum.wrapping_add(raw.len() as u32)
+}
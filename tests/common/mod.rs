@@ -0,0 +1,1102 @@
+// Builds small synthetic PE images so directory parsing can be tested without checking in a
+// binary for every feature.
+#![allow(dead_code)]
+
+use pepper::crypto::bignum::BigUint;
+use pepper::crypto::sha2::sha256;
+
+pub const FILE_ALIGNMENT: u32 = 0x200;
+pub const SECTION_ALIGNMENT: u32 = 0x1000;
+
+pub const DIR_EXPORT: usize = 0;
+pub const DIR_IMPORT: usize = 1;
+pub const DIR_RESOURCE: usize = 2;
+pub const DIR_EXCEPTION: usize = 3;
+pub const DIR_SECURITY: usize = 4;
+pub const DIR_BASERELOC: usize = 5;
+pub const DIR_DEBUG: usize = 6;
+pub const DIR_TLS: usize = 9;
+pub const DIR_LOAD_CONFIG: usize = 10;
+pub const DIR_CLR: usize = 14;
+
+pub struct TestSection {
+    pub name: &'static str,
+    pub rva: u32,
+    pub data: Vec<u8>,
+    pub virtual_size: u32,
+}
+
+pub struct TestPe {
+    pub pe32: bool,
+    pub machine: u16,
+    pub image_base: u64,
+    pub entry_point: u32,
+    pub sections: Vec<TestSection>,
+    pub directories: [(u32, u32); 16],
+    pub trailing: Vec<u8>,
+}
+
+impl TestPe {
+    pub fn new(machine: u16) -> Self {
+        Self {
+            pe32: false,
+            machine,
+            image_base: 0x1_4000_0000,
+            entry_point: 0x1000,
+            sections: Vec::new(),
+            directories: [(0, 0); 16],
+            trailing: Vec::new(),
+        }
+    }
+
+    pub fn pe32(mut self) -> Self {
+        self.pe32 = true;
+        self.image_base = 0x40_0000;
+        self
+    }
+
+    pub fn image_base(mut self, image_base: u64) -> Self {
+        self.image_base = image_base;
+        self
+    }
+
+    pub fn section(mut self, name: &'static str, rva: u32, data: Vec<u8>) -> Self {
+        let virtual_size = data.len() as u32;
+        self.sections.push(TestSection {
+            name,
+            rva,
+            data,
+            virtual_size,
+        });
+        self
+    }
+
+    pub fn directory(mut self, index: usize, rva: u32, size: u32) -> Self {
+        self.directories[index] = (rva, size);
+        self
+    }
+
+    pub fn trailing(mut self, data: Vec<u8>) -> Self {
+        self.trailing = data;
+        self
+    }
+
+    pub fn optional_header_offset(&self) -> usize {
+        0x40 + 24
+    }
+
+    pub fn headers_size(&self) -> u32 {
+        let optional_size = if self.pe32 { 0xe0 } else { 0xf0 };
+        let end = self.optional_header_offset() + optional_size + 40 * self.sections.len();
+        align(end as u32, FILE_ALIGNMENT)
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut raw = vec![0u8; 0x40];
+        raw[0..2].copy_from_slice(b"MZ");
+        raw[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+
+        // COFF header
+        raw.extend_from_slice(b"PE\0\0");
+        push16(&mut raw, self.machine);
+        push16(&mut raw, self.sections.len() as u16);
+        push32(&mut raw, 0x6000_0000);
+        push32(&mut raw, 0);
+        push32(&mut raw, 0);
+        push16(&mut raw, if self.pe32 { 0xe0 } else { 0xf0 });
+        push16(&mut raw, if self.pe32 { 0x0102 } else { 0x0022 });
+
+        let size_of_image = self
+            .sections
+            .iter()
+            .map(|s| align(s.rva + s.virtual_size.max(1), SECTION_ALIGNMENT))
+            .max()
+            .unwrap_or(SECTION_ALIGNMENT);
+
+        // optional header
+        push16(&mut raw, if self.pe32 { 0x10b } else { 0x20b });
+        raw.push(14);
+        raw.push(0);
+        push32(&mut raw, 0);
+        push32(&mut raw, 0);
+        push32(&mut raw, 0);
+        push32(&mut raw, self.entry_point);
+        push32(&mut raw, 0x1000);
+        if self.pe32 {
+            push32(&mut raw, 0);
+            push32(&mut raw, self.image_base as u32);
+        } else {
+            push64(&mut raw, self.image_base);
+        }
+        push32(&mut raw, SECTION_ALIGNMENT);
+        push32(&mut raw, FILE_ALIGNMENT);
+        for version in [6u16, 0, 0, 0, 6, 0] {
+            push16(&mut raw, version);
+        }
+        push32(&mut raw, 0);
+        push32(&mut raw, size_of_image);
+        push32(&mut raw, self.headers_size());
+        push32(&mut raw, 0);
+        push16(&mut raw, 3);
+        push16(&mut raw, 0x8160);
+        for size in [0x100000u64, 0x1000, 0x100000, 0x1000] {
+            if self.pe32 {
+                push32(&mut raw, size as u32);
+            } else {
+                push64(&mut raw, size);
+            }
+        }
+        push32(&mut raw, 0);
+        push32(&mut raw, 16);
+        for (rva, size) in self.directories {
+            push32(&mut raw, rva);
+            push32(&mut raw, size);
+        }
+
+        // section headers
+        let mut pointer = self.headers_size();
+        let mut pointers = Vec::new();
+        for section in &self.sections {
+            let mut name = [0u8; 8];
+            name[..section.name.len()].copy_from_slice(section.name.as_bytes());
+            raw.extend_from_slice(&name);
+            let size_raw = align(section.data.len() as u32, FILE_ALIGNMENT);
+            push32(&mut raw, section.virtual_size);
+            push32(&mut raw, section.rva);
+            push32(&mut raw, size_raw);
+            push32(&mut raw, if size_raw == 0 { 0 } else { pointer });
+            push32(&mut raw, 0);
+            push32(&mut raw, 0);
+            push16(&mut raw, 0);
+            push16(&mut raw, 0);
+            push32(&mut raw, 0x4000_0040);
+            pointers.push(pointer);
+            pointer += size_raw;
+        }
+        raw.resize(self.headers_size() as usize, 0);
+
+        for (section, pointer) in self.sections.iter().zip(pointers) {
+            if section.data.is_empty() {
+                continue;
+            }
+            raw.resize(pointer as usize, 0);
+            raw.extend_from_slice(&section.data);
+            raw.resize(align(raw.len() as u32, FILE_ALIGNMENT) as usize, 0);
+        }
+
+        raw.extend_from_slice(&self.trailing);
+        raw
+    }
+}
+
+pub fn align(value: u32, alignment: u32) -> u32 {
+    value.div_ceil(alignment) * alignment
+}
+
+pub fn push16(raw: &mut Vec<u8>, value: u16) {
+    raw.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn push32(raw: &mut Vec<u8>, value: u32) {
+    raw.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn push64(raw: &mut Vec<u8>, value: u64) {
+    raw.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn utf16(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+#[derive(Clone)]
+pub enum TestId {
+    Id(u32),
+    Name(&'static str),
+}
+
+pub struct TestResource {
+    pub kind: TestId,
+    pub name: TestId,
+    pub language: u32,
+    pub data: Vec<u8>,
+}
+
+pub fn resource(kind: TestId, name: TestId, language: u32, data: Vec<u8>) -> TestResource {
+    TestResource {
+        kind,
+        name,
+        language,
+        data,
+    }
+}
+
+fn same_id(a: &TestId, b: &TestId) -> bool {
+    match (a, b) {
+        (TestId::Id(a), TestId::Id(b)) => a == b,
+        (TestId::Name(a), TestId::Name(b)) => a == b,
+        _ => false,
+    }
+}
+
+// named entries have to come before integer ids in each directory
+fn id_order(id: &TestId) -> (u8, u32, &'static str) {
+    match id {
+        TestId::Name(name) => (0, 0, name),
+        TestId::Id(id) => (1, *id, ""),
+    }
+}
+
+// type -> name -> [(language, resource index)]
+type TestTree = Vec<(TestId, Vec<(TestId, Vec<(u32, usize)>)>)>;
+
+// lays out a three level .rsrc section that will be mapped at rva
+pub fn resource_section(rva: u32, resources: &[TestResource]) -> Vec<u8> {
+    let mut tree: TestTree = Vec::new();
+    for (index, res) in resources.iter().enumerate() {
+        let kind = match tree.iter().position(|(k, _)| same_id(k, &res.kind)) {
+            Some(i) => i,
+            None => {
+                tree.push((res.kind.clone(), Vec::new()));
+                tree.len() - 1
+            }
+        };
+        let names = &mut tree[kind].1;
+        let name = match names.iter().position(|(n, _)| same_id(n, &res.name)) {
+            Some(i) => i,
+            None => {
+                names.push((res.name.clone(), Vec::new()));
+                names.len() - 1
+            }
+        };
+        names[name].1.push((res.language, index));
+    }
+    tree.sort_by(|a, b| id_order(&a.0).cmp(&id_order(&b.0)));
+    for (_, names) in tree.iter_mut() {
+        names.sort_by(|a, b| id_order(&a.0).cmp(&id_order(&b.0)));
+    }
+
+    let dir_size = |entries: usize| 16 + 8 * entries as u32;
+    let root_size = dir_size(tree.len());
+    let types_size: u32 = tree.iter().map(|(_, names)| dir_size(names.len())).sum();
+    let names_size: u32 = tree
+        .iter()
+        .flat_map(|(_, names)| names.iter().map(|(_, langs)| dir_size(langs.len())))
+        .sum();
+    let data_entries_start = root_size + types_size + names_size;
+    let strings_start = data_entries_start + 16 * resources.len() as u32;
+
+    let mut strings = Vec::new();
+    let mut string_offsets = Vec::new();
+    let mut add_string = |name: &str| {
+        let offset = strings_start + strings.len() as u32;
+        push16(&mut strings, name.encode_utf16().count() as u16);
+        strings.extend(utf16(name));
+        offset
+    };
+    for (kind, names) in &tree {
+        if let TestId::Name(name) = kind {
+            string_offsets.push(add_string(name));
+        }
+        for (name, _) in names {
+            if let TestId::Name(name) = name {
+                string_offsets.push(add_string(name));
+            }
+        }
+    }
+    let blobs_start = align(strings_start + strings.len() as u32, 8);
+
+    let mut blob_offsets = vec![0u32; resources.len()];
+    let mut blobs = Vec::new();
+    for (index, res) in resources.iter().enumerate() {
+        blob_offsets[index] = blobs_start + blobs.len() as u32;
+        blobs.extend_from_slice(&res.data);
+        blobs.resize(align(blobs.len() as u32, 8) as usize, 0);
+    }
+
+    let mut out = Vec::new();
+    let mut strings_used = string_offsets.into_iter();
+    let mut id_field = |id: &TestId| match id {
+        TestId::Id(id) => *id,
+        TestId::Name(_) => 0x8000_0000 | strings_used.next().unwrap(),
+    };
+    let dir_header = |out: &mut Vec<u8>, ids: Vec<&TestId>| {
+        push32(out, 0);
+        push32(out, 0);
+        push16(out, 4);
+        push16(out, 0);
+        let named = ids
+            .iter()
+            .filter(|id| matches!(id, TestId::Name(_)))
+            .count();
+        push16(out, named as u16);
+        push16(out, (ids.len() - named) as u16);
+    };
+
+    // ids have to be resolved in the same order the strings were added
+    let mut type_ids = Vec::new();
+    let mut name_ids = Vec::new();
+    for (kind, names) in &tree {
+        type_ids.push(id_field(kind));
+        name_ids.push(
+            names
+                .iter()
+                .map(|(name, _)| id_field(name))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    // root
+    dir_header(&mut out, tree.iter().map(|(k, _)| k).collect());
+    let mut next_dir = root_size;
+    for (i, (_, names)) in tree.iter().enumerate() {
+        push32(&mut out, type_ids[i]);
+        push32(&mut out, 0x8000_0000 | next_dir);
+        next_dir += dir_size(names.len());
+    }
+    // type level
+    for (i, (_, names)) in tree.iter().enumerate() {
+        dir_header(&mut out, names.iter().map(|(n, _)| n).collect());
+        for (j, (_, langs)) in names.iter().enumerate() {
+            push32(&mut out, name_ids[i][j]);
+            push32(&mut out, 0x8000_0000 | next_dir);
+            next_dir += dir_size(langs.len());
+        }
+    }
+    // name level
+    let mut data_order = Vec::new();
+    for (_, names) in &tree {
+        for (_, langs) in names {
+            let ids: Vec<TestId> = langs.iter().map(|(l, _)| TestId::Id(*l)).collect();
+            dir_header(&mut out, ids.iter().collect());
+            for (language, index) in langs {
+                push32(&mut out, *language);
+                push32(&mut out, data_entries_start + 16 * data_order.len() as u32);
+                data_order.push(*index);
+            }
+        }
+    }
+    for index in data_order {
+        push32(&mut out, rva + blob_offsets[index]);
+        push32(&mut out, resources[index].data.len() as u32);
+        push32(&mut out, 0);
+        push32(&mut out, 0);
+    }
+    out.extend(strings);
+    out.resize(blobs_start as usize, 0);
+    out.extend(blobs);
+    out
+}
+
+// PE with only a resource section, mapped at 0x1000
+pub fn pe_with_resources(resources: &[TestResource]) -> Vec<u8> {
+    let rsrc = resource_section(0x1000, resources);
+    let size = rsrc.len() as u32;
+    TestPe::new(0x8664)
+        .section(".rsrc", 0x1000, rsrc)
+        .directory(DIR_RESOURCE, 0x1000, size)
+        .build()
+}
+
+// one node of a VS_VERSIONINFO tree. Text values are given as UTF-16 bytes including the null.
+pub fn version_node(key: &str, text: bool, value: Vec<u8>, children: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut node = vec![0u8; 6];
+    node.extend(utf16(key));
+    node.extend([0, 0]);
+    node.resize(align(node.len() as u32, 4) as usize, 0);
+    let value_length = if text { value.len() / 2 } else { value.len() };
+    node.extend(&value);
+    for child in children {
+        node.resize(align(node.len() as u32, 4) as usize, 0);
+        node.extend(child);
+    }
+    let length = node.len() as u16;
+    node[0..2].copy_from_slice(&length.to_le_bytes());
+    node[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+    node[4..6].copy_from_slice(&(text as u16).to_le_bytes());
+    node
+}
+
+pub fn version_string(key: &str, value: &str) -> Vec<u8> {
+    let mut text = utf16(value);
+    text.extend([0, 0]);
+    version_node(key, true, text, vec![])
+}
+
+// segments of an MSFT type library, filled in by the tests
+pub const TLB_IMPORT_INFO: usize = 1;
+pub const TLB_IMPORT_FILES: usize = 2;
+pub const TLB_REFERENCES: usize = 3;
+pub const TLB_GUIDS: usize = 5;
+pub const TLB_NAMES: usize = 7;
+pub const TLB_STRINGS: usize = 8;
+pub const TLB_TYPE_DESCS: usize = 9;
+pub const TLB_CUSTOM_DATA: usize = 11;
+
+pub struct TestTypeInfo {
+    pub kind: u32,
+    pub name: u32,
+    pub guid: u32,
+    pub flags: u32,
+    pub data_type: u32,
+    pub implemented: u16,
+    pub functions: Vec<Vec<u8>>,
+    pub variables: Vec<Vec<u8>>,
+    // member ids and name offsets, functions first
+    pub ids: Vec<u32>,
+    pub names: Vec<u32>,
+}
+
+impl TestTypeInfo {
+    pub fn new(kind: u32, name: u32, guid: u32) -> Self {
+        Self {
+            kind,
+            name,
+            guid,
+            flags: 0,
+            data_type: u32::MAX,
+            implemented: 0,
+            functions: Vec::new(),
+            variables: Vec::new(),
+            ids: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TestTypeLib {
+    pub segments: [Vec<u8>; 15],
+    pub infos: Vec<TestTypeInfo>,
+}
+
+impl TestTypeLib {
+    pub fn name(&mut self, text: &str) -> u32 {
+        let names = &mut self.segments[TLB_NAMES];
+        let offset = names.len() as u32;
+        push32(names, u32::MAX);
+        push32(names, u32::MAX);
+        push32(names, text.len() as u32);
+        names.extend(text.bytes());
+        names.resize(align(names.len() as u32, 4) as usize, 0);
+        offset
+    }
+
+    pub fn string(&mut self, text: &str) -> u32 {
+        let strings = &mut self.segments[TLB_STRINGS];
+        let offset = strings.len() as u32;
+        push16(strings, text.len() as u16);
+        strings.extend(text.bytes());
+        strings.resize(align(strings.len() as u32, 4) as usize, 0);
+        offset
+    }
+
+    // entries are the GUID followed by a HREFTYPE and hash chain
+    pub fn guid(&mut self, guid: [u8; 16]) -> u32 {
+        let guids = &mut self.segments[TLB_GUIDS];
+        let offset = guids.len() as u32;
+        guids.extend(guid);
+        push32(guids, u32::MAX);
+        push32(guids, u32::MAX);
+        offset
+    }
+
+    // lays out the header, segment directory, segments and then the member data of each type
+    pub fn build(&self, name: u32, guid: u32, help_string: u32) -> Vec<u8> {
+        let count = self.infos.len();
+        let segments_start = 0x54 + count * 4 + 15 * 16;
+        let mut table = Vec::new();
+        let mut segment_offsets = Vec::new();
+        let mut offset = segments_start + count * 0x64;
+        segment_offsets.push((segments_start, count * 0x64));
+        for segment in &self.segments[1..] {
+            segment_offsets.push((offset, segment.len()));
+            offset += segment.len();
+        }
+
+        let mut members = Vec::new();
+        for info in &self.infos {
+            let member_offset = offset + members.len();
+            let records: Vec<u8> = info
+                .functions
+                .iter()
+                .chain(&info.variables)
+                .flatten()
+                .copied()
+                .collect();
+            if !info.ids.is_empty() {
+                push32(&mut members, records.len() as u32);
+                members.extend(&records);
+                for value in info.ids.iter().chain(&info.names) {
+                    push32(&mut members, *value);
+                }
+                // record offsets, unused by the parser
+                for _ in &info.ids {
+                    push32(&mut members, 0);
+                }
+            }
+
+            let mut entry = vec![0u8; 0x64];
+            let mut put = |at: usize, value: u32| {
+                entry[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            };
+            put(0x00, info.kind);
+            put(0x04, member_offset as u32);
+            put(
+                0x18,
+                info.functions.len() as u32 | (info.variables.len() as u32) << 16,
+            );
+            put(0x2c, info.guid);
+            put(0x30, info.flags);
+            put(0x34, info.name);
+            put(0x38, 1);
+            put(0x3c, u32::MAX);
+            put(0x4c, info.implemented as u32);
+            put(0x54, info.data_type);
+            table.extend(entry);
+        }
+
+        let mut raw = Vec::new();
+        raw.extend(b"MSFT");
+        push32(&mut raw, 0x00010002);
+        push32(&mut raw, guid);
+        push32(&mut raw, 0x409);
+        push32(&mut raw, 0);
+        // win32, no help DLL
+        push32(&mut raw, 1);
+        // version 1.2
+        push32(&mut raw, 0x00020001);
+        push32(&mut raw, 0);
+        push32(&mut raw, count as u32);
+        push32(&mut raw, help_string);
+        push32(&mut raw, 0);
+        push32(&mut raw, 0);
+        push32(&mut raw, 0);
+        push32(&mut raw, 0);
+        push32(&mut raw, name);
+        push32(&mut raw, u32::MAX);
+        push32(&mut raw, u32::MAX);
+        push32(&mut raw, 0x20);
+        push32(&mut raw, 0x80);
+        push32(&mut raw, u32::MAX);
+        push32(&mut raw, 0);
+        for index in 0..count {
+            push32(&mut raw, (index * 0x64) as u32);
+        }
+        for (index, (offset, length)) in segment_offsets.iter().enumerate() {
+            let present = index == 0 || *length > 0;
+            push32(&mut raw, if present { *offset as u32 } else { u32::MAX });
+            push32(&mut raw, *length as u32);
+            push32(&mut raw, u32::MAX);
+            push32(&mut raw, 0x0f);
+        }
+        raw.extend(table);
+        for segment in &self.segments[1..] {
+            raw.extend(segment);
+        }
+        raw.extend(members);
+        raw
+    }
+}
+
+// params are (type, name, PARAMFLAGS), defaults are given per parameter when any are present
+pub fn tlb_function(
+    return_type: u32,
+    kind: u32,
+    vtable_offset: u16,
+    params: &[(u32, u32, u32)],
+    defaults: Option<&[u32]>,
+) -> Vec<u8> {
+    let mut record = vec![0u8; 4];
+    push32(&mut record, return_type);
+    push32(&mut record, 0);
+    push16(&mut record, vtable_offset);
+    push16(&mut record, 0);
+    push32(
+        &mut record,
+        kind | if defaults.is_some() { 0x1000 } else { 0 },
+    );
+    push16(&mut record, params.len() as u16);
+    push16(&mut record, 0);
+    // help context and help string
+    push32(&mut record, 0);
+    push32(&mut record, u32::MAX);
+    for value in defaults.unwrap_or_default() {
+        push32(&mut record, *value);
+    }
+    for (param_type, name, flags) in params {
+        push32(&mut record, *param_type);
+        push32(&mut record, *name);
+        push32(&mut record, *flags);
+    }
+    let length = record.len() as u32;
+    record[0..4].copy_from_slice(&length.to_le_bytes());
+    record
+}
+
+pub fn tlb_variable(var_type: u32, kind: u16, value: u32) -> Vec<u8> {
+    let mut record = Vec::new();
+    push32(&mut record, 0x14);
+    push32(&mut record, var_type);
+    push32(&mut record, 0);
+    push16(&mut record, kind);
+    push16(&mut record, 0);
+    push32(&mut record, value);
+    record
+}
+
+// DEFLATE stream made only of stored blocks
+pub fn deflate_stored(data: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::new();
+    let mut chunks = data.chunks(0xffff).peekable();
+    if chunks.peek().is_none() {
+        return vec![0x01, 0x00, 0x00, 0xff, 0xff];
+    }
+    while let Some(chunk) = chunks.next() {
+        raw.push(chunks.peek().is_none() as u8);
+        push16(&mut raw, chunk.len() as u16);
+        push16(&mut raw, !(chunk.len() as u16));
+        raw.extend_from_slice(chunk);
+    }
+    raw
+}
+
+// portable PDB metadata with a #Pdb stream holding the given id, and an empty #~ stream
+pub fn portable_pdb(id: &[u8; 20]) -> Vec<u8> {
+    let mut pdb = Vec::new();
+    push32(&mut pdb, 0x424a_5342);
+    push16(&mut pdb, 1);
+    push16(&mut pdb, 1);
+    push32(&mut pdb, 0);
+    push32(&mut pdb, 12);
+    pdb.extend_from_slice(b"PDB v1.0\0\0\0\0");
+    push16(&mut pdb, 0);
+    push16(&mut pdb, 2);
+    // headers take 0x20 bytes, so the streams start at 0x40
+    push32(&mut pdb, 0x40);
+    push32(&mut pdb, 0x20);
+    pdb.extend_from_slice(b"#Pdb\0\0\0\0");
+    push32(&mut pdb, 0x60);
+    push32(&mut pdb, 0x10);
+    pdb.extend_from_slice(b"#~\0\0");
+    pdb.resize(0x40, 0);
+    pdb.extend_from_slice(id);
+    pdb.resize(0x70, 0);
+    pdb
+}
+
+// overwrite little endian values in place, for filling out fixed layout structures
+pub fn put32(raw: &mut [u8], offset: usize, value: u32) {
+    raw[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub fn put64(raw: &mut [u8], offset: usize, value: u64) {
+    raw[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+// DER value with the given tag around content
+pub fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut raw = vec![tag];
+    let length = content.len();
+    if length < 0x80 {
+        raw.push(length as u8);
+    } else {
+        let bytes: Vec<u8> = length
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        raw.push(0x80 | bytes.len() as u8);
+        raw.extend_from_slice(&bytes);
+    }
+    raw.extend_from_slice(content);
+    raw
+}
+
+// constructed DER value holding parts in order
+pub fn der_seq(tag: u8, parts: &[Vec<u8>]) -> Vec<u8> {
+    der(tag, &parts.concat())
+}
+
+pub fn der_oid(oid: &str) -> Vec<u8> {
+    let arcs: Vec<u64> = oid.split('.').map(|arc| arc.parse().unwrap()).collect();
+    let mut content = Vec::new();
+    for arc in std::iter::once(arcs[0] * 40 + arcs[1]).chain(arcs[2..].iter().copied()) {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest != 0 {
+            bytes.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        bytes.reverse();
+        content.extend_from_slice(&bytes);
+    }
+    der(0x06, &content)
+}
+
+pub fn der_algorithm(oid: &str) -> Vec<u8> {
+    der_seq(0x30, &[der_oid(oid), der(0x05, &[])])
+}
+
+// Name with just a common name
+pub fn der_name(common_name: &str) -> Vec<u8> {
+    der_seq(
+        0x30,
+        &[der_seq(
+            0x31,
+            &[der_seq(
+                0x30,
+                &[der_oid("2.5.4.3"), der(0x0c, common_name.as_bytes())],
+            )],
+        )],
+    )
+}
+
+pub fn der_attribute(oid: &str, values: &[Vec<u8>]) -> Vec<u8> {
+    der_seq(0x30, &[der_oid(oid), der_seq(0x31, values)])
+}
+
+// X.509 v3 certificate, the signature and key are placeholders
+pub fn der_certificate(subject: &str, issuer: &str, serial: u8) -> Vec<u8> {
+    let tbs = der_seq(
+        0x30,
+        &[
+            der_seq(0xa0, &[der(0x02, &[2])]),
+            der(0x02, &[serial]),
+            der_algorithm("1.2.840.113549.1.1.11"),
+            der_name(issuer),
+            der_seq(
+                0x30,
+                &[der(0x17, b"200101000000Z"), der(0x18, b"20391231235959Z")],
+            ),
+            der_name(subject),
+            der_seq(
+                0x30,
+                &[
+                    der_algorithm("1.2.840.113549.1.1.1"),
+                    der(0x03, &[0, 0x30, 0x00]),
+                ],
+            ),
+        ],
+    );
+    der_seq(
+        0x30,
+        &[
+            tbs,
+            der_algorithm("1.2.840.113549.1.1.11"),
+            der(0x03, &[0, 1, 2, 3]),
+        ],
+    )
+}
+
+// SignerInfo identified by issuer and serial
+pub fn der_signer(
+    issuer: &str,
+    serial: u8,
+    digest_oid: &str,
+    authenticated: &[Vec<u8>],
+    unauthenticated: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut parts = vec![
+        der(0x02, &[1]),
+        der_seq(0x30, &[der_name(issuer), der(0x02, &[serial])]),
+        der_algorithm(digest_oid),
+    ];
+    if !authenticated.is_empty() {
+        parts.push(der_seq(0xa0, authenticated));
+    }
+    parts.push(der_algorithm("1.2.840.113549.1.1.1"));
+    parts.push(der(0x04, &[0x55; 16]));
+    if !unauthenticated.is_empty() {
+        parts.push(der_seq(0xa1, unauthenticated));
+    }
+    der_seq(0x30, &parts)
+}
+
+// ContentInfo around a SignedData with the given content
+pub fn der_signed_data(
+    digest_oid: &str,
+    content_type: &str,
+    content: Vec<u8>,
+    certificates: &[Vec<u8>],
+    signer: Vec<u8>,
+) -> Vec<u8> {
+    let mut parts = vec![
+        der(0x02, &[1]),
+        der_seq(0x31, &[der_algorithm(digest_oid)]),
+        der_seq(0x30, &[der_oid(content_type), der_seq(0xa0, &[content])]),
+    ];
+    if !certificates.is_empty() {
+        parts.push(der_seq(0xa0, certificates));
+    }
+    parts.push(der_seq(0x31, &[signer]));
+    der_seq(
+        0x30,
+        &[
+            der_oid("1.2.840.113549.1.7.2"),
+            der_seq(0xa0, &[der_seq(0x30, &parts)]),
+        ],
+    )
+}
+
+// SpcIndirectDataContent for a PE image with the given digest
+pub fn spc_indirect_data(digest_oid: &str, digest: &[u8]) -> Vec<u8> {
+    spc_indirect_data_for(der_seq(0x30, &[der(0x03, &[0])]), digest_oid, digest)
+}
+
+// SpcIndirectDataContent with image_data as the SpcPeImageData
+pub fn spc_indirect_data_for(image_data: Vec<u8>, digest_oid: &str, digest: &[u8]) -> Vec<u8> {
+    der_seq(
+        0x30,
+        &[
+            der_seq(0x30, &[der_oid("1.3.6.1.4.1.311.2.1.15"), image_data]),
+            der_seq(0x30, &[der_algorithm(digest_oid), der(0x04, digest)]),
+        ],
+    )
+}
+
+// SpcPeImageData whose SpcLink is the page hash table serialized under the given OID
+pub fn spc_pe_image_data_with_page_hashes(oid: &str, table: &[u8]) -> Vec<u8> {
+    let serialized = der_seq(
+        0x31,
+        &[der_seq(
+            0x30,
+            &[der_oid(oid), der_seq(0x31, &[der(0x04, table)])],
+        )],
+    );
+    let class_id = [
+        0xa6, 0xb5, 0x86, 0xd5, 0xb4, 0xa1, 0x24, 0x66, 0xae, 0x05, 0xa2, 0x17, 0xda, 0x8e, 0x60,
+        0xd6,
+    ];
+    der_seq(
+        0x30,
+        &[
+            der(0x03, &[0]),
+            der_seq(
+                0xa0,
+                &[der_seq(
+                    0xa1,
+                    &[der(0x04, &class_id), der(0x04, &serialized)],
+                )],
+            ),
+        ],
+    )
+}
+
+// WIN_CERTIFICATE entry padded to 8 bytes with pad
+pub fn win_certificate(revision: u16, kind: u16, data: &[u8], pad: u8) -> Vec<u8> {
+    let mut entry = Vec::new();
+    push32(&mut entry, 8 + data.len() as u32);
+    push16(&mut entry, revision);
+    push16(&mut entry, kind);
+    entry.extend_from_slice(data);
+    entry.resize(align(entry.len() as u32, 8) as usize, pad);
+    entry
+}
+
+// an AMD64 PE with the certificate table appended to the file
+pub fn signed_pe(table: &[u8]) -> Vec<u8> {
+    let pe = TestPe::new(0x8664).section(".text", 0x1000, vec![0xc3; 0x10]);
+    append_certificate_table(&pe, pe.build(), table)
+}
+
+// raw, built from pe, with the certificate table appended and the security directory pointing at it
+pub fn append_certificate_table(pe: &TestPe, mut raw: Vec<u8>, table: &[u8]) -> Vec<u8> {
+    let directory = pe.optional_header_offset() + 0x70 + 8 * DIR_SECURITY;
+    let offset = raw.len() as u32;
+    put32(&mut raw, directory, offset);
+    put32(&mut raw, directory + 4, table.len() as u32);
+    raw.extend_from_slice(table);
+    raw
+}
+
+// 1024 bit RSA keys for signing test certificates and signatures, (n, d) in hex with e = 65537
+pub type TestKey = (&'static str, &'static str);
+
+pub const TEST_ROOT_KEY: TestKey = (
+    concat!(
+        "c4f28746ab6a645576ef8ccfd111bbd4dc1b734cb99dec1a56aaf20fa00556df322dd7bd664af03d60c67f9e",
+        "108a0d1b540bb03a22075f3d53cc75728adb1de35ab4f4e7a8fad8e7b4c58676b24268baba873d857fd42313",
+        "1e1c8d4d2fe4dd54c6fab93a8130a211bba297fd2408bbd0fe6ba9ca0e22081f56e2b89a24c1b4bf"
+    ),
+    concat!(
+        "12d799359ba4bc20a916560473de1c784aee9d6353c6d615d700d7d3c2dd7fe82ea6d02320ac3ebbc08c7d1d",
+        "243bf0f1126363e2cbeaa4a3fb666e3e1d26c57f15c6c0862edbcc951ca92061f4358bafac33eef726da2dca",
+        "89f43bedc00611feff61e9b14c153ea31aa9f6d26a6cb2b0119c59f290615a97b614676d36f24661"
+    ),
+);
+
+pub const TEST_LEAF_KEY: TestKey = (
+    concat!(
+        "e4c3daff7f2911dabe66f119ecd8646da271e654f23bce4b5013a498d63a9af3f1e5055d45794b584e5f9035",
+        "bb964351be9b766db0ecf838933511c93d2b79e1321b2f036c14d45be227698c6f58639af8c2bcf071e9f3e0",
+        "17ae14837e3cf1778465abc7c087fc1a3d2c35a3031931069db61f9390b1ecd81e5da0c4c896dcf9"
+    ),
+    concat!(
+        "6e3e90a8764075914416d9f41376c91728a4d94a709e2fb18dba99a4ea00be31f78dd602f7fb173b4c31b38e",
+        "a1832d98145259454d7135c215f70a463fcb56e52d7109c9ff9ee8d8c461a791746d7dcfafc578302db648f7",
+        "13d09b277853fccbea99ad4026ab7bf7be73738743beb39d8021c09033726a5967b32341c535f781"
+    ),
+);
+
+// TEST_LEAF_KEY as a DER PKCS#8 PrivateKeyInfo
+pub const TEST_LEAF_KEY_PKCS8: &str = concat!(
+    "30820276020100300d06092a864886f70d0101010500048202603082025c02010002818100e4c3daff7f2911",
+    "dabe66f119ecd8646da271e654f23bce4b5013a498d63a9af3f1e5055d45794b584e5f9035bb964351be9b76",
+    "6db0ecf838933511c93d2b79e1321b2f036c14d45be227698c6f58639af8c2bcf071e9f3e017ae14837e3cf1",
+    "778465abc7c087fc1a3d2c35a3031931069db61f9390b1ecd81e5da0c4c896dcf902030100010281806e3e90",
+    "a8764075914416d9f41376c91728a4d94a709e2fb18dba99a4ea00be31f78dd602f7fb173b4c31b38ea1832d",
+    "98145259454d7135c215f70a463fcb56e52d7109c9ff9ee8d8c461a791746d7dcfafc578302db648f713d09b",
+    "277853fccbea99ad4026ab7bf7be73738743beb39d8021c09033726a5967b32341c535f781024100f777a15a",
+    "4a21493fd9d8fad08433b0da1705745d460f46e8f6b7a59aad05cf4a7e13236b5df7d3e5504470f7e857223a",
+    "adc87770a06b681bac374c6f51091ba9024100eca7246ccea68b54babd7f0aac3e7f1c6e1c823ba9ece4403a",
+    "2217241f48f914c4fa66c19ffb7ea4aeb16da3fc4e337f4cf24bb13349ac598a67cc865b3a08d102401f7819",
+    "0eb04a97540551f0633fcc8afad0dea2b391cb338b40848e0ca3600fc23b5217b8f994206012db013aa5531f",
+    "4932fc412413625534dfab908ebf515731024002bda0cf5dc951fdc637e19bc708e641f7087f7d9f672df18c",
+    "7ace97cd80f3d988ba79ac2e65243f56029a09772794b319ba08a461baadb34fc609f9585f20b1024100d08a",
+    "138e8caf6d333352835496b8d51c21cc768d81dbbe9642082156aa252a9327207ab7fe5657349398f80a7901",
+    "37d0a6eebd67ca1973421b111914fb9bad62",
+);
+
+// DER INTEGER for a big endian unsigned value
+pub fn der_unsigned(bytes: &[u8]) -> Vec<u8> {
+    let mut content = vec![0];
+    content.extend(bytes.iter().skip_while(|b| **b == 0));
+    if content.len() == 1 || content[1] & 0x80 == 0 {
+        content.remove(0);
+    }
+    der(0x02, &content)
+}
+
+// RSAPublicKey for key
+pub fn rsa_public_key(key: TestKey) -> Vec<u8> {
+    let n = BigUint::from_hex(key.0).unwrap();
+    der_seq(
+        0x30,
+        &[der_unsigned(&n.to_bytes_be(0)), der_unsigned(&[1, 0, 1])],
+    )
+}
+
+// PKCS#1 v1.5 signature over the SHA-256 digest of data
+pub fn rsa_sign(key: TestKey, data: &[u8]) -> Vec<u8> {
+    let n = BigUint::from_hex(key.0).unwrap();
+    let d = BigUint::from_hex(key.1).unwrap();
+    let size = n.bits().div_ceil(8);
+    let digest_info = der_seq(
+        0x30,
+        &[
+            der_algorithm("2.16.840.1.101.3.4.2.1"),
+            der(0x04, &sha256(data)),
+        ],
+    );
+    let mut encoded = vec![0x00, 0x01];
+    encoded.resize(size - digest_info.len() - 1, 0xff);
+    encoded.push(0x00);
+    encoded.extend_from_slice(&digest_info);
+    BigUint::from_bytes_be(&encoded)
+        .pow_mod(&d, &n)
+        .to_bytes_be(size)
+}
+
+// X.509 v3 certificate for key, signed with sha256WithRSAEncryption by issuer_key
+pub fn signed_certificate(
+    subject: &str,
+    issuer: &str,
+    serial: u8,
+    key: TestKey,
+    issuer_key: TestKey,
+    validity: (&[u8], &[u8]),
+) -> Vec<u8> {
+    signed_certificate_with(subject, issuer, serial, key, issuer_key, validity, &[])
+}
+
+// same, with the given DER Extensions
+pub fn signed_certificate_with(
+    subject: &str,
+    issuer: &str,
+    serial: u8,
+    key: TestKey,
+    issuer_key: TestKey,
+    validity: (&[u8], &[u8]),
+    extensions: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut public_key = vec![0];
+    public_key.extend(rsa_public_key(key));
+    let mut fields = vec![
+        der_seq(0xa0, &[der(0x02, &[2])]),
+        der(0x02, &[serial]),
+        der_algorithm("1.2.840.113549.1.1.11"),
+        der_name(issuer),
+        der_seq(0x30, &[der(0x17, validity.0), der(0x17, validity.1)]),
+        der_name(subject),
+        der_seq(
+            0x30,
+            &[
+                der_algorithm("1.2.840.113549.1.1.1"),
+                der(0x03, &public_key),
+            ],
+        ),
+    ];
+    if !extensions.is_empty() {
+        fields.push(der_seq(0xa3, &[der_seq(0x30, extensions)]));
+    }
+    let tbs = der_seq(0x30, &fields);
+    let mut signature = vec![0];
+    signature.extend(rsa_sign(issuer_key, &tbs));
+    der_seq(
+        0x30,
+        &[
+            tbs,
+            der_algorithm("1.2.840.113549.1.1.11"),
+            der(0x03, &signature),
+        ],
+    )
+}
+
+pub fn der_extension(oid: &str, critical: bool, value: Vec<u8>) -> Vec<u8> {
+    let mut parts = vec![der_oid(oid)];
+    if critical {
+        parts.push(der(0x01, &[0xff]));
+    }
+    parts.push(der(0x04, &value));
+    der_seq(0x30, &parts)
+}
+
+// basicConstraints cA, with an optional pathLenConstraint
+pub fn der_ca(path_length: Option<u8>) -> Vec<u8> {
+    let mut value = vec![der(0x01, &[0xff])];
+    value.extend(path_length.map(|length| der(0x02, &[length])));
+    der_extension("2.5.29.19", true, der_seq(0x30, &value))
+}
+
+// keyUsage with the first byte of the bits, unused bits left out
+pub fn der_key_usage(bits: u8) -> Vec<u8> {
+    der_extension("2.5.29.15", true, der(0x03, &[0, bits]))
+}
+
+pub fn der_extended_key_usage(purposes: &[&str]) -> Vec<u8> {
+    let purposes: Vec<Vec<u8>> = purposes.iter().map(|oid| der_oid(oid)).collect();
+    der_extension("2.5.29.37", false, der_seq(0x30, &purposes))
+}
+
+// SignerInfo with a SHA-256 RSA signature by key over the authenticated attributes
+pub fn signed_signer(
+    issuer: &str,
+    serial: u8,
+    key: TestKey,
+    authenticated: &[Vec<u8>],
+    unauthenticated: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut parts = vec![
+        der(0x02, &[1]),
+        der_seq(0x30, &[der_name(issuer), der(0x02, &[serial])]),
+        der_algorithm("2.16.840.1.101.3.4.2.1"),
+        der_seq(0xa0, authenticated),
+        der_algorithm("1.2.840.113549.1.1.1"),
+        der(0x04, &rsa_sign(key, &der_seq(0x31, authenticated))),
+    ];
+    if !unauthenticated.is_empty() {
+        parts.push(der_seq(0xa1, unauthenticated));
+    }
+    der_seq(0x30, &parts)
+}
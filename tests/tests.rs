@@ -1,10 +1,72 @@
-use pepper;
+mod common;
 
 #[cfg(test)]
 mod tests {
+    use chrono::{TimeZone, Utc};
+    use pepper::crypto::bignum::BigUint;
+    use pepper::crypto::ecdsa::{Curve, EcPublicKey};
+    use pepper::crypto::md5::md5;
+    use pepper::crypto::rsa::{RsaPrivateKey, RsaPublicKey};
+    use pepper::crypto::sha1::{sha1, Sha1};
+    use pepper::crypto::sha2::{sha256, sha384, sha512, Sha256};
+    use pepper::crypto::{to_hex, DigestAlgorithm};
+    use pepper::der::{encode, encode_oid, encode_set, encode_unsigned, Tlv};
+    use pepper::directories::certificates::page_hashes::PageHash;
+    use pepper::directories::certificates::pkcs7::{SignerId, Timestamp};
+    use pepper::directories::certificates::verify::VerifyProblem;
+    use pepper::directories::certificates::x509::Certificate;
+    use pepper::directories::certificates::{CertificateAnomaly, CertificateType};
+    use pepper::directories::chpe::CodeKind;
+    use pepper::directories::debug::codeview::CodeView;
+    use pepper::directories::debug::fpo::FrameType;
+    use pepper::directories::debug::portable_pdb::pdb_id;
+    use pepper::directories::debug::{DebugData, DebugType};
+    use pepper::directories::dynamic_relocations::{
+        Arm64XFixupKind, DynamicRelocationData, ImportControlTransfer, IndirectControlTransfer,
+    };
+    use pepper::directories::exceptions::x64::{scope_table, UnwindOp};
+    use pepper::directories::exceptions::ExceptionTable;
+    use pepper::directories::exceptions::{arm, arm64};
+    use pepper::directories::guard::IMAGE_GUARD_CF_INSTRUMENTED;
+    use pepper::directories::relocations::{RelocationProblem, RelocationType};
+    use pepper::directories::resources::icons::{bitmap_file, is_png, IconKind};
+    use pepper::directories::resources::manifest::{decode_text, ExecutionLevel};
+    use pepper::directories::resources::strings::MessageEncoding;
+    use pepper::directories::resources::typelib::{TypeKind, TypeLibChange, Value};
+    use pepper::directories::resources::{ResourceId, ResourceTree, ResourceType};
+    use pepper::headers::coff::*;
+    use pepper::inflate::inflate;
     use pepper::utils::{ArchDependentSized, PeFormat};
 
-    use super::pepper::pe::Pe;
+    use super::common::*;
+    use pepper::pe::Pe;
+
+    fn reloc_block(page_rva: u32, entries: &[u16]) -> Vec<u8> {
+        let mut block = Vec::new();
+        push32(&mut block, page_rva);
+        push32(&mut block, 8 + 2 * entries.len() as u32);
+        for entry in entries {
+            push16(&mut block, *entry);
+        }
+        block
+    }
+
+    // an AMD64 PE whose load config points at a DVRT by VA
+    fn dvrt_pe(version: u32, relocations: &[u8]) -> Pe {
+        let base = 0x1_4000_0000u64;
+        let mut rdata = vec![0u8; 0x148];
+        put32(&mut rdata, 0, 0x148);
+        put64(&mut rdata, 0xc0, base + 0x2200);
+        rdata.resize(0x200, 0);
+        push32(&mut rdata, version);
+        push32(&mut rdata, relocations.len() as u32);
+        rdata.extend_from_slice(relocations);
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".rdata", 0x2000, rdata)
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x148)
+            .build();
+        Pe::from_bytes(raw).unwrap()
+    }
 
     #[test]
     fn test_dos_header() {
@@ -75,4 +137,3488 @@ mod tests {
             assert_eq!(*correct, parsed.name);
         }
     }
+
+    // every header in NumberOfSections gets parsed, and names shorter than 8 bytes lose their
+    // NUL padding so they compare equal to the plain name
+    #[test]
+    fn test_section_table() {
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0xc3; 0x10])
+            .section(".rdata", 0x2000, vec![1; 0x10])
+            .section(".textbss", 0x3000, Vec::new())
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let names: Vec<&str> = pe
+            .section_table
+            .section_headers
+            .iter()
+            .map(|section| section.name.as_str())
+            .collect();
+        assert_eq!(names, [".text", ".rdata", ".textbss"]);
+        assert_eq!(pe.section_table.section_headers[1].virtual_address, 0x2000);
+        assert_eq!(pe.section_table.section_headers[1].pointer_raw_data, 0x400);
+    }
+
+    // untrusted input has to come back as an error, not a panic
+    #[test]
+    fn test_malformed_headers() {
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0xc3; 0x10])
+            .section(".data", 0x2000, vec![1; 0x10])
+            .build();
+        let section_table_end = 0x58 + 0xf0 + 2 * 40;
+        for len in 0..section_table_end {
+            assert!(Pe::from_bytes(raw[..len].to_vec()).is_err(), "{:#x}", len);
+        }
+        assert!(Pe::from_bytes(raw[..section_table_end].to_vec()).is_ok());
+
+        let mut bad = raw.clone();
+        put32(&mut bad, 0x3c, 0xffff_fff0);
+        assert!(Pe::from_bytes(bad).is_err());
+        let mut bad = raw.clone();
+        bad[0] = 0xff;
+        assert!(Pe::from_bytes(bad).is_err());
+        let mut bad = raw.clone();
+        bad[0x40] = 0xff;
+        assert!(Pe::from_bytes(bad).is_err());
+        let mut bad = raw.clone();
+        bad[0x46..0x48].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert!(Pe::from_bytes(bad).is_err());
+        // names that aren't UTF-8 are still parsed
+        let mut bad = raw.clone();
+        bad[0x148] = 0xff;
+        let pe = Pe::from_bytes(bad).unwrap();
+        assert_eq!(pe.section_table.section_headers[0].name, "\u{fffd}text");
+
+        // SizeOfImage past what the sections span isn't laid out
+        let pe = Pe::from_bytes(raw.clone()).unwrap();
+        assert_eq!(pe.materialize().unwrap().len(), 0x3000);
+        let mut forged = raw.clone();
+        put32(&mut forged, 0x58 + 0x38, 0xffff_f000);
+        let pe = Pe::from_bytes(forged).unwrap();
+        assert!(pe.materialize().is_err());
+        assert!(pe.rebase_image(0x1_8000_0000).is_err());
+    }
+
+    #[test]
+    fn test_base_relocations() {
+        let mut reloc = reloc_block(0x1000, &[0xa010, 0xa018, 0x3020, 0x0000]);
+        reloc.extend(reloc_block(0x2000, &[0x4004, 0x1234, 0xa008, 0x0000]));
+        let size = reloc.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0; 0x100])
+            .section(".data", 0x2000, vec![0; 0x100])
+            .section(".reloc", 0x3000, reloc)
+            .directory(DIR_BASERELOC, 0x3000, size)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let table = pe.base_relocations().unwrap();
+        assert_eq!(table.blocks.len(), 2);
+        assert_eq!(table.blocks[0].page_rva, 0x1000);
+        assert_eq!(table.blocks[0].entries.len(), 4);
+        assert_eq!(table.blocks[0].entries[3].kind, RelocationType::Absolute);
+
+        // HIGHADJ swallows the entry after it
+        assert_eq!(table.blocks[1].entries.len(), 3);
+        assert_eq!(table.blocks[1].entries[0].kind, RelocationType::HighAdj);
+        assert_eq!(table.blocks[1].entries[0].param, Some(0x1234));
+
+        let fixups: Vec<(RelocationType, u32)> =
+            table.fixups().map(|r| (r.kind, r.target_rva)).collect();
+        assert_eq!(
+            fixups,
+            vec![
+                (RelocationType::Dir64, 0x1010),
+                (RelocationType::Dir64, 0x1018),
+                (RelocationType::HighLow, 0x1020),
+                (RelocationType::HighAdj, 0x2004),
+                (RelocationType::Dir64, 0x2008),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_base_relocation_machine_specific_types() {
+        let reloc = reloc_block(0x1000, &[0x5000, 0x7008, 0x8010, 0x9018]);
+        let size = reloc.len() as u32;
+        let build = |machine| {
+            let raw = TestPe::new(machine)
+                .pe32()
+                .section(".text", 0x1000, vec![0; 0x100])
+                .section(".reloc", 0x2000, reloc.clone())
+                .directory(DIR_BASERELOC, 0x2000, size)
+                .build();
+            let pe = Pe::from_bytes(raw).unwrap();
+            let table = pe.base_relocations().unwrap();
+            table.fixups().map(|r| r.kind).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            build(IMAGE_FILE_MACHINE_ARMNT),
+            vec![
+                RelocationType::ArmMov32,
+                RelocationType::ThumbMov32,
+                RelocationType::Unknown(8),
+                RelocationType::Unknown(9),
+            ]
+        );
+        assert_eq!(
+            build(IMAGE_FILE_MACHINE_RISCV64),
+            vec![
+                RelocationType::RiscvHigh20,
+                RelocationType::RiscvLow12I,
+                RelocationType::RiscvLow12S,
+                RelocationType::Unknown(9),
+            ]
+        );
+        assert_eq!(
+            build(IMAGE_FILE_MACHINE_LOONGARCH64)[2],
+            RelocationType::LoongArch64MarkLa
+        );
+        assert_eq!(
+            build(IMAGE_FILE_MACHINE_R4000)[3],
+            RelocationType::MipsJmpAddr16
+        );
+    }
+
+    #[test]
+    fn test_base_relocation_block_validation() {
+        // block size claims fewer bytes than its own header
+        let mut reloc = Vec::new();
+        push32(&mut reloc, 0x1000);
+        push32(&mut reloc, 4);
+        let size = reloc.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".reloc", 0x1000, reloc)
+            .directory(DIR_BASERELOC, 0x1000, size)
+            .build();
+        assert!(Pe::from_bytes(raw).unwrap().base_relocations().is_err());
+
+        // block is not padded to a 32 bit boundary
+        let reloc = reloc_block(0x1000, &[0xa010]);
+        let size = reloc.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".reloc", 0x1000, reloc)
+            .directory(DIR_BASERELOC, 0x1000, size)
+            .build();
+        assert!(Pe::from_bytes(raw).unwrap().base_relocations().is_err());
+
+        // directory size rounded up with zeroes is tolerated
+        let mut reloc = reloc_block(0x1000, &[0xa010, 0x0000]);
+        reloc.extend([0; 8]);
+        let size = reloc.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".reloc", 0x1000, reloc)
+            .directory(DIR_BASERELOC, 0x1000, size)
+            .build();
+        let table = Pe::from_bytes(raw).unwrap().base_relocations().unwrap();
+        assert_eq!(table.blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_rebase() {
+        let mut text = vec![0u8; 0x100];
+        text[0x10..0x18].copy_from_slice(&0x1_4000_2000u64.to_le_bytes());
+        // 8 byte fixup that starts 4 bytes before the end of .data
+        let reloc = reloc_block(0x1000, &[0xa010, 0xa0fc, 0xa000, 0x0000]);
+        let mut reloc = reloc;
+        reloc.extend(reloc_block(0x3000, &[0xa000, 0x0000]));
+        let size = reloc.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, text)
+            .section(".data", 0x2000, vec![0; 0x100])
+            .section(".reloc", 0x4000, reloc)
+            .directory(DIR_BASERELOC, 0x4000, size)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let rebased = pe.rebase_file(0x7ff6_0000_0000).unwrap();
+        let text_offset = pe.rva_to_offset(0x1010).unwrap();
+        assert_eq!(
+            rebased.bytes[text_offset..text_offset + 8],
+            0x7ff6_0000_2000u64.to_le_bytes()
+        );
+        let problems: Vec<(u32, RelocationProblem)> = rebased
+            .issues
+            .iter()
+            .map(|issue| (issue.target_rva, issue.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (0x10fc, RelocationProblem::StraddlesSections),
+                (0x3000, RelocationProblem::OutsideSections),
+            ]
+        );
+
+        let reparsed = Pe::from_bytes(rebased.bytes).unwrap();
+        assert_eq!(
+            reparsed.optional_header.image_offset,
+            ArchDependentSized::PE32P(0x7ff6_0000_0000)
+        );
+
+        // the mapped image has the same fixup at its rva
+        let image = pe.rebase_image(0x7ff6_0000_0000).unwrap();
+        assert_eq!(image.bytes.len(), 0x5000);
+        assert_eq!(
+            image.bytes[0x1010..0x1018],
+            0x7ff6_0000_2000u64.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_rebase_arm_mov32() {
+        // movw r0, #0x1234 ; movt r0, #0x0040
+        let mut text = Vec::new();
+        for halfword in [0xf241u16, 0x2034, 0xf2c0, 0x0040] {
+            push16(&mut text, halfword);
+        }
+        text.resize(0x100, 0);
+        let reloc = reloc_block(0x1000, &[0x7000, 0x0000]);
+        let size = reloc.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_ARMNT)
+            .pe32()
+            .section(".text", 0x1000, text)
+            .section(".reloc", 0x2000, reloc)
+            .directory(DIR_BASERELOC, 0x2000, size)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let image = pe.rebase_image(0x1000_0000).unwrap();
+        assert!(image.issues.is_empty());
+        let halfwords: Vec<u16> = image.bytes[0x1000..0x1008]
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        // movw r0, #0x1234 ; movt r0, #0x1000
+        assert_eq!(halfwords, vec![0xf241, 0x2034, 0xf2c1, 0x0000]);
+    }
+
+    #[test]
+    fn test_resource_tree() {
+        let raw = pe_with_resources(&[
+            resource(TestId::Id(16), TestId::Id(1), 1033, b"version".to_vec()),
+            resource(TestId::Id(24), TestId::Id(1), 1033, b"manifest".to_vec()),
+            resource(
+                TestId::Name("TYPELIB"),
+                TestId::Id(1),
+                0,
+                b"typelib".to_vec(),
+            ),
+            resource(TestId::Id(3), TestId::Name("APPICON"), 1031, b"de".to_vec()),
+            resource(TestId::Id(3), TestId::Name("APPICON"), 1033, b"en".to_vec()),
+        ]);
+        let pe = Pe::from_bytes(raw).unwrap();
+        let tree = pe.resources().unwrap();
+        assert_eq!(tree.resources().len(), 5);
+
+        let version = tree.find(ResourceType::Version, 1, None).unwrap();
+        assert_eq!(version.resource_type(), Some(ResourceType::Version));
+        assert_eq!(version.language_id(), Some(1033));
+        assert_eq!(pe.resource_data(&version.data).unwrap(), b"version");
+
+        let typelib = tree.find("typelib", 1, None).unwrap();
+        assert_eq!(typelib.kind, ResourceId::Name("TYPELIB".to_string()));
+        assert_eq!(pe.resource_data(&typelib.data).unwrap(), b"typelib");
+
+        let icon = tree
+            .find(ResourceType::Icon, "AppIcon", Some(1033))
+            .unwrap();
+        assert_eq!(pe.resource_data(&icon.data).unwrap(), b"en");
+        assert_eq!(tree.of_type(ResourceType::Icon).len(), 2);
+        assert!(tree
+            .find(ResourceType::Icon, "AppIcon", Some(1036))
+            .is_none());
+    }
+
+    #[test]
+    fn test_resource_tree_cycle() {
+        // root with a single entry pointing back at the root
+        let mut rsrc = Vec::new();
+        push32(&mut rsrc, 0);
+        push32(&mut rsrc, 0);
+        push32(&mut rsrc, 0);
+        push16(&mut rsrc, 0);
+        push16(&mut rsrc, 1);
+        push32(&mut rsrc, 16);
+        push32(&mut rsrc, 0x8000_0000);
+        assert!(ResourceTree::new(&rsrc).is_err());
+
+        // a chain of directories each pointing to the next one
+        let mut rsrc = Vec::new();
+        for level in 0..16u32 {
+            push32(&mut rsrc, 0);
+            push32(&mut rsrc, 0);
+            push32(&mut rsrc, 0);
+            push16(&mut rsrc, 0);
+            push16(&mut rsrc, 1);
+            push32(&mut rsrc, 1);
+            push32(&mut rsrc, 0x8000_0000 | ((level + 1) * 24));
+        }
+        assert!(ResourceTree::new(&rsrc).is_err());
+    }
+
+    #[test]
+    fn test_version_info() {
+        let mut fixed = Vec::new();
+        for dword in [
+            0xfeef04bd,
+            0x10000,
+            0x0001_0002,
+            0x0003_0004,
+            0x0005_0006,
+            0x0007_0008,
+            0x3f,
+            0,
+            0x40004,
+            1,
+            0,
+            0,
+            0,
+        ] {
+            push32(&mut fixed, dword);
+        }
+        let mut translation = Vec::new();
+        push16(&mut translation, 0x409);
+        push16(&mut translation, 0x4b0);
+
+        let version = version_node(
+            "VS_VERSION_INFO",
+            false,
+            fixed,
+            vec![
+                version_node(
+                    "StringFileInfo",
+                    true,
+                    vec![],
+                    vec![version_node(
+                        "040904b0",
+                        true,
+                        vec![],
+                        vec![
+                            version_string("CompanyName", "Pepper Corp"),
+                            version_string("FileDescription", "Test binary"),
+                            version_string("OriginalFilename", "test.exe"),
+                        ],
+                    )],
+                ),
+                version_node(
+                    "VarFileInfo",
+                    true,
+                    vec![],
+                    vec![version_node("Translation", false, translation, vec![])],
+                ),
+            ],
+        );
+        let raw = pe_with_resources(&[resource(TestId::Id(16), TestId::Id(1), 1033, version)]);
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let info = pe.version_info().unwrap().unwrap();
+        let fixed = info.fixed.as_ref().unwrap();
+        assert_eq!(fixed.file_version(), (1, 2, 3, 4));
+        assert_eq!(fixed.product_version(), (5, 6, 7, 8));
+
+        assert_eq!(info.string_tables.len(), 1);
+        assert_eq!(info.string_tables[0].language, 0x409);
+        assert_eq!(info.string_tables[0].codepage, 0x4b0);
+        assert_eq!(info.get("CompanyName"), Some("Pepper Corp"));
+        assert_eq!(info.get("FileDescription"), Some("Test binary"));
+        assert_eq!(info.get("OriginalFilename"), Some("test.exe"));
+        assert_eq!(info.get("ProductName"), None);
+
+        assert_eq!(info.translations.len(), 1);
+        assert_eq!(info.translations[0].language, 0x409);
+        assert_eq!(info.translations[0].codepage, 0x4b0);
+    }
+
+    const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">
+  <assemblyIdentity type="win32" name="Pepper.Test" version="1.2.3.4" processorArchitecture="amd64"/>
+  <!-- <requestedExecutionLevel level="asInvoker"/> -->
+  <trustInfo xmlns="urn:schemas-microsoft-com:asm.v3">
+    <security>
+      <requestedPrivileges>
+        <requestedExecutionLevel level="requireAdministrator" uiAccess="false"/>
+      </requestedPrivileges>
+    </security>
+  </trustInfo>
+  <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">
+    <application>
+      <supportedOS Id="{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}"/>
+      <supportedOS Id="{35138b9a-5d96-4fbd-8e2d-a2440225f93a}"/>
+    </application>
+  </compatibility>
+  <asmv3:application xmlns:asmv3="urn:schemas-microsoft-com:asm.v3">
+    <asmv3:windowsSettings>
+      <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true/pm</dpiAware>
+      <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">PerMonitorV2, PerMonitor</dpiAwareness>
+      <longPathAware xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">true</longPathAware>
+    </asmv3:windowsSettings>
+  </asmv3:application>
+  <dependency>
+    <dependentAssembly>
+      <assemblyIdentity type="win32" name="Microsoft.Windows.Common-Controls" version="6.0.0.0" processorArchitecture="*" publicKeyToken="6595b64144ccf1df" language="*"/>
+    </dependentAssembly>
+  </dependency>
+</assembly>
+"#;
+
+    #[test]
+    fn test_manifest() {
+        let mut utf8 = vec![0xef, 0xbb, 0xbf];
+        utf8.extend(MANIFEST.as_bytes());
+        let raw = pe_with_resources(&[resource(TestId::Id(24), TestId::Id(1), 1033, utf8)]);
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let manifests = pe.manifests().unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].id, ResourceId::Id(1));
+        assert!(manifests[0].text.starts_with("<?xml"));
+
+        let settings = manifests[0].settings().unwrap();
+        let identity = settings.identity.unwrap();
+        assert_eq!(identity.name.as_deref(), Some("Pepper.Test"));
+        assert_eq!(identity.version.as_deref(), Some("1.2.3.4"));
+        assert_eq!(
+            settings.execution_level,
+            Some(ExecutionLevel::RequireAdministrator)
+        );
+        assert_eq!(settings.ui_access, Some(false));
+        assert_eq!(settings.dpi_aware.as_deref(), Some("true/pm"));
+        assert_eq!(
+            settings.dpi_awareness.as_deref(),
+            Some("PerMonitorV2, PerMonitor")
+        );
+        assert_eq!(settings.long_path_aware, Some(true));
+        let os: Vec<Option<&str>> = settings.supported_os.iter().map(|os| os.name()).collect();
+        assert_eq!(os, vec![Some("Windows 10"), Some("Windows 7")]);
+        assert_eq!(settings.dependencies.len(), 1);
+        assert_eq!(
+            settings.dependencies[0].name.as_deref(),
+            Some("Microsoft.Windows.Common-Controls")
+        );
+        assert_eq!(
+            settings.dependencies[0].public_key_token.as_deref(),
+            Some("6595b64144ccf1df")
+        );
+    }
+
+    #[test]
+    fn test_manifest_utf16() {
+        let mut utf16le = vec![0xff, 0xfe];
+        utf16le.extend(utf16("<assembly><trustInfo/></assembly>"));
+        assert_eq!(decode_text(&utf16le), "<assembly><trustInfo/></assembly>");
+
+        // no BOM, null padded
+        let mut utf16le = utf16("<a>&lt;b&gt;</a>");
+        utf16le.extend([0, 0, 0, 0]);
+        assert_eq!(decode_text(&utf16le), "<a>&lt;b&gt;</a>");
+    }
+
+    fn group_entry(raw: &mut Vec<u8>, dims: [u8; 4], planes: u16, bits: u16, size: u32, id: u16) {
+        raw.extend(dims);
+        push16(raw, planes);
+        push16(raw, bits);
+        push32(raw, size);
+        push16(raw, id);
+    }
+
+    #[test]
+    fn test_icon_extraction() {
+        let bmp_icon = vec![0x28, 0, 0, 0, 1, 2, 3, 4];
+        let mut png_icon = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        png_icon.extend([0xaa; 8]);
+
+        let mut group = Vec::new();
+        push16(&mut group, 0);
+        push16(&mut group, 1);
+        push16(&mut group, 2);
+        group_entry(&mut group, [16, 16, 0, 0], 1, 32, 8, 1);
+        group_entry(&mut group, [0, 0, 0, 0], 1, 32, 16, 2);
+
+        let raw = pe_with_resources(&[
+            resource(TestId::Id(3), TestId::Id(1), 1033, bmp_icon.clone()),
+            resource(TestId::Id(3), TestId::Id(2), 1033, png_icon.clone()),
+            resource(TestId::Id(14), TestId::Name("MAINICON"), 1033, group),
+        ]);
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let groups = pe.icon_groups().unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, IconKind::Icon);
+        assert_eq!(groups[0].entries[1].width, 0);
+
+        let ico = pe.icon_file(&groups[0]).unwrap();
+        assert_eq!(ico[0..6], [0, 0, 1, 0, 2, 0]);
+        // first entry: 16x16, 1 plane, 32bpp, 8 bytes at offset 6 + 2 * 16
+        assert_eq!(ico[6..10], [16, 16, 0, 0]);
+        assert_eq!(ico[14..18], 8u32.to_le_bytes());
+        assert_eq!(ico[18..22], 38u32.to_le_bytes());
+        assert_eq!(ico[38..46], bmp_icon[..]);
+        // the PNG image comes through untouched
+        assert_eq!(ico[34..38], 46u32.to_le_bytes());
+        assert!(is_png(&ico[46..]));
+        assert_eq!(ico[46..], png_icon[..]);
+    }
+
+    #[test]
+    fn test_cursor_extraction() {
+        let mut cursor = Vec::new();
+        push16(&mut cursor, 5);
+        push16(&mut cursor, 7);
+        cursor.extend([0x28, 0, 0, 0, 9, 9]);
+
+        let mut group = Vec::new();
+        push16(&mut group, 0);
+        push16(&mut group, 2);
+        push16(&mut group, 1);
+        // 32 wide, 64 high counting both masks
+        group_entry(&mut group, [32, 0, 64, 0], 1, 1, 10, 1);
+
+        let raw = pe_with_resources(&[
+            resource(TestId::Id(1), TestId::Id(1), 1033, cursor),
+            resource(TestId::Id(12), TestId::Id(100), 1033, group),
+        ]);
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let groups = pe.icon_groups().unwrap();
+        assert_eq!(groups[0].kind, IconKind::Cursor);
+        assert_eq!(groups[0].entries[0].height, 32);
+
+        let cur = pe.icon_file(&groups[0]).unwrap();
+        assert_eq!(cur[0..6], [0, 0, 2, 0, 1, 0]);
+        assert_eq!(cur[6..10], [32, 32, 0, 0]);
+        // hotspot in place of planes and bit count
+        assert_eq!(cur[10..14], [5, 0, 7, 0]);
+        assert_eq!(cur[14..18], 6u32.to_le_bytes());
+        assert_eq!(cur[22..], [0x28, 0, 0, 0, 9, 9]);
+    }
+
+    #[test]
+    fn test_bitmap_extraction() {
+        // 8bpp BITMAPINFOHEADER with an implied 256 entry color table
+        let mut dib = Vec::new();
+        push32(&mut dib, 40);
+        push32(&mut dib, 1);
+        push32(&mut dib, 1);
+        push16(&mut dib, 1);
+        push16(&mut dib, 8);
+        for _ in 0..6 {
+            push32(&mut dib, 0);
+        }
+        dib.extend(vec![0; 1024 + 4]);
+
+        let raw = pe_with_resources(&[resource(TestId::Id(2), TestId::Id(1), 1033, dib.clone())]);
+        let pe = Pe::from_bytes(raw).unwrap();
+        let tree = pe.resources().unwrap();
+        let bitmap = tree.find(ResourceType::Bitmap, 1, None).unwrap();
+
+        let bmp = pe.bitmap_file(&bitmap).unwrap();
+        assert_eq!(bmp[0..2], *b"BM");
+        assert_eq!(bmp[2..6], ((14 + dib.len()) as u32).to_le_bytes());
+        assert_eq!(bmp[10..14], (14u32 + 40 + 1024).to_le_bytes());
+        assert_eq!(bmp[14..], dib[..]);
+
+        // biClrUsed and biSize are taken as is, so they can't be allowed to wrap
+        let mut colors = dib.clone();
+        put32(&mut colors, 32, 0x4000_0000);
+        assert!(bitmap_file(&colors).is_err());
+        let mut header = dib.clone();
+        put32(&mut header, 0, 0xffff_fff8);
+        assert!(bitmap_file(&header).is_err());
+    }
+
+    #[test]
+    fn test_string_table() {
+        // block 2 holds ids 16 to 31
+        let mut block = Vec::new();
+        for index in 0..16 {
+            let text = match index {
+                0 => "Open",
+                3 => "Sauvegarder",
+                _ => "",
+            };
+            push16(&mut block, text.encode_utf16().count() as u16);
+            block.extend(utf16(text));
+        }
+        let mut french = Vec::new();
+        push16(&mut french, 6);
+        french.extend(utf16("Ouvrir"));
+
+        let raw = pe_with_resources(&[
+            resource(TestId::Id(6), TestId::Id(2), 1033, block),
+            resource(TestId::Id(6), TestId::Id(2), 1036, french),
+        ]);
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let strings: Vec<(u32, Option<u32>, String)> = pe
+            .string_table()
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.id, s.language.as_id(), s.text))
+            .collect();
+        assert_eq!(
+            strings,
+            vec![
+                (16, Some(1033), "Open".to_string()),
+                (19, Some(1033), "Sauvegarder".to_string()),
+                (16, Some(1036), "Ouvrir".to_string()),
+            ]
+        );
+
+        // the last block holds ids up to 0xffff, anything past it is rejected
+        let mut last = Vec::new();
+        push16(&mut last, 0);
+        push16(&mut last, 4);
+        last.extend(utf16("Last"));
+        for block_id in [0x1000, 0x1001, 0x7fff_ffff] {
+            let raw = pe_with_resources(&[resource(
+                TestId::Id(6),
+                TestId::Id(block_id),
+                1033,
+                last.clone(),
+            )]);
+            let strings = Pe::from_bytes(raw).unwrap().string_table();
+            match block_id {
+                0x1000 => assert_eq!(strings.unwrap()[0].id, 0xfff1),
+                _ => assert!(strings.is_err()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_table() {
+        let mut table = Vec::new();
+        push32(&mut table, 2);
+        // block 1 has ids 1 and 2, block 2 only 0x100
+        let ansi = b"Started\r\n\0".to_vec();
+        let mut unicode = utf16("Stopped\r\n");
+        unicode.extend([0, 0]);
+        let entries_start = 4 + 2 * 12;
+        let second_block = entries_start + 4 + ansi.len() + 4 + unicode.len();
+        for (low, high, offset) in [(1u32, 2u32, entries_start), (0x100, 0x100, second_block)] {
+            push32(&mut table, low);
+            push32(&mut table, high);
+            push32(&mut table, offset as u32);
+        }
+        let utf8 = b"\xc3\xa9t\0".to_vec();
+        for (flags, text) in [(0u16, &ansi), (1, &unicode), (2, &utf8)] {
+            push16(&mut table, 4 + text.len() as u16);
+            push16(&mut table, flags);
+            table.extend(text.iter());
+        }
+
+        let raw = pe_with_resources(&[resource(TestId::Id(11), TestId::Id(1), 1033, table)]);
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let messages: Vec<(u32, MessageEncoding, String)> = pe
+            .message_table()
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.id, m.encoding, m.text))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, MessageEncoding::Ansi, "Started\r\n".to_string()),
+                (2, MessageEncoding::Unicode, "Stopped\r\n".to_string()),
+                (0x100, MessageEncoding::Utf8, "\u{e9}t".to_string()),
+            ]
+        );
+    }
+
+    fn utf16_nul(text: &str) -> Vec<u8> {
+        let mut raw = utf16(text);
+        raw.extend([0, 0]);
+        raw
+    }
+
+    fn pad4(raw: &mut Vec<u8>) {
+        raw.resize(align(raw.len() as u32, 4) as usize, 0);
+    }
+
+    #[test]
+    fn test_dialogs() {
+        let mut extended = Vec::new();
+        push16(&mut extended, 1);
+        push16(&mut extended, 0xffff);
+        push32(&mut extended, 0);
+        push32(&mut extended, 0);
+        push32(&mut extended, 0x80c800c0);
+        push16(&mut extended, 1);
+        for value in [0u16, 0, 200, 100] {
+            push16(&mut extended, value);
+        }
+        // no menu or class
+        push32(&mut extended, 0);
+        extended.extend(utf16_nul("About"));
+        push16(&mut extended, 8);
+        push16(&mut extended, 400);
+        extended.extend([0, 1]);
+        extended.extend(utf16_nul("MS Shell Dlg"));
+        pad4(&mut extended);
+        push32(&mut extended, 0);
+        push32(&mut extended, 0);
+        push32(&mut extended, 0x50010000);
+        for value in [10u16, 10, 50, 14] {
+            push16(&mut extended, value);
+        }
+        push32(&mut extended, 1);
+        push16(&mut extended, 0xffff);
+        push16(&mut extended, 0x80);
+        extended.extend(utf16_nul("OK"));
+        push16(&mut extended, 0);
+
+        let mut standard = Vec::new();
+        push32(&mut standard, 0x80c80000);
+        push32(&mut standard, 0);
+        push16(&mut standard, 1);
+        for value in [5u16, 5, 80, 40] {
+            push16(&mut standard, value);
+        }
+        push16(&mut standard, 0xffff);
+        push16(&mut standard, 7);
+        push16(&mut standard, 0);
+        standard.extend(utf16_nul("Go \"Now\""));
+        pad4(&mut standard);
+        push32(&mut standard, 0x50000003);
+        push32(&mut standard, 0x20);
+        for value in [1u16, 2, 16, 16] {
+            push16(&mut standard, value);
+        }
+        push16(&mut standard, 2);
+        push16(&mut standard, 0xffff);
+        push16(&mut standard, 0x82);
+        push16(&mut standard, 0xffff);
+        push16(&mut standard, 3);
+        push16(&mut standard, 2);
+        standard.extend([0xaa, 0xbb]);
+        // the same with IDC_STATIC, a WORD -1
+        let mut static_id = standard.clone();
+        static_id[60..62].copy_from_slice(&[0xff, 0xff]);
+
+        let raw = pe_with_resources(&[
+            resource(TestId::Id(5), TestId::Id(100), 1033, extended),
+            resource(TestId::Id(5), TestId::Name("GO"), 1033, standard),
+            resource(TestId::Id(5), TestId::Id(101), 1033, static_id),
+        ]);
+        let pe = Pe::from_bytes(raw).unwrap();
+        let dialogs = pe.dialogs().unwrap();
+        assert_eq!(dialogs.len(), 3);
+
+        let about = dialogs.iter().find(|d| d.extended).unwrap();
+        let font = about.font.as_ref().unwrap();
+        assert_eq!((font.point_size, font.weight, font.charset), (8, 400, 1));
+        assert_eq!(
+            about.controls[0].class.class_name(),
+            Some("BUTTON".to_string())
+        );
+        assert_eq!(
+            about.to_string(),
+            "#100 DIALOGEX 0, 0, 200, 100\n\
+             STYLE 0x80c800c0\n\
+             CAPTION \"About\"\n\
+             FONT 8, \"MS Shell Dlg\", 400, 0, 0x1\n\
+             BEGIN\n    \
+             CONTROL \"OK\", 1, \"BUTTON\", 0x50010000, 10, 10, 50, 14\n\
+             END\n"
+        );
+
+        let go = dialogs
+            .iter()
+            .find(|d| d.name == ResourceId::Name("GO".to_string()))
+            .unwrap();
+        assert!(go.font.is_none());
+        assert_eq!(go.controls[0].creation_data, vec![0xaa, 0xbb]);
+        assert_eq!(
+            go.to_string(),
+            "GO DIALOG 5, 5, 80, 40\n\
+             STYLE 0x80c80000\n\
+             CAPTION \"Go \"\"Now\"\"\"\n\
+             MENU 7\n\
+             BEGIN\n    \
+             CONTROL 3, 2, \"STATIC\", 0x50000003, 1, 2, 16, 16, 0x00000020\n\
+             END\n"
+        );
+        let static_id = dialogs
+            .iter()
+            .find(|d| d.name == ResourceId::Id(101))
+            .unwrap();
+        assert_eq!(static_id.controls[0].id, 0xffff);
+        assert!(static_id
+            .to_string()
+            .contains("CONTROL 3, -1, \"STATIC\", 0x50000003"));
+    }
+
+    #[test]
+    fn test_menus() {
+        let mut standard = Vec::new();
+        push32(&mut standard, 0);
+        push16(&mut standard, 0x10);
+        standard.extend(utf16_nul("&File"));
+        for (flags, id, text) in [(0u16, 100u16, "&Open"), (0, 0, ""), (0x80, 101, "E&xit")] {
+            push16(&mut standard, flags);
+            push16(&mut standard, id);
+            standard.extend(utf16_nul(text));
+        }
+        push16(&mut standard, 0x80);
+        push16(&mut standard, 200);
+        standard.extend(utf16_nul("&Help"));
+
+        let mut extended = Vec::new();
+        push16(&mut extended, 1);
+        push16(&mut extended, 4);
+        push32(&mut extended, 7);
+        let items: [(u32, u32, u16, &str, Option<u32>); 3] = [
+            (0, 0, 0x81, "&Edit", Some(9)),
+            (0, 300, 0, "Cu&t", None),
+            (0x800, 0, 0x80, "", None),
+        ];
+        for (kind, id, flags, text, help_id) in items {
+            pad4(&mut extended);
+            push32(&mut extended, kind);
+            push32(&mut extended, 0);
+            push32(&mut extended, id);
+            push16(&mut extended, flags);
+            extended.extend(utf16_nul(text));
+            if let Some(help_id) = help_id {
+                pad4(&mut extended);
+                push32(&mut extended, help_id);
+            }
+        }
+
+        let raw = pe_with_resources(&[
+            resource(TestId::Id(4), TestId::Id(1), 1033, standard),
+            resource(TestId::Id(4), TestId::Id(2), 1033, extended),
+        ]);
+        let pe = Pe::from_bytes(raw).unwrap();
+        let menus = pe.menus().unwrap();
+        assert_eq!(
+            menus[0].to_string(),
+            "#1 MENU\n\
+             BEGIN\n    \
+             POPUP \"&File\"\n    \
+             BEGIN\n        \
+             MENUITEM \"&Open\", 100\n        \
+             MENUITEM SEPARATOR\n        \
+             MENUITEM \"E&xit\", 101\n    \
+             END\n    \
+             MENUITEM \"&Help\", 200\n\
+             END\n"
+        );
+
+        let edit = &menus[1];
+        assert!(edit.extended);
+        assert_eq!(edit.help_id, 7);
+        assert_eq!(edit.items.len(), 1);
+        assert_eq!(edit.items[0].help_id, Some(9));
+        assert_eq!(edit.items[0].children.len(), 2);
+        assert!(edit.items[0].children[1].is_separator());
+        assert_eq!(
+            edit.to_string(),
+            "#2 MENUEX\n\
+             BEGIN\n    \
+             POPUP \"&Edit\"\n    \
+             BEGIN\n        \
+             MENUITEM \"Cu&t\", 300\n        \
+             MENUITEM SEPARATOR\n    \
+             END\n\
+             END\n"
+        );
+
+        // a popup that never ends runs out of data instead of looping
+        let mut unterminated = Vec::new();
+        push32(&mut unterminated, 0);
+        for _ in 0..4 {
+            push16(&mut unterminated, 0x10);
+            unterminated.extend(utf16_nul("x"));
+        }
+        let raw = pe_with_resources(&[resource(TestId::Id(4), TestId::Id(1), 0, unterminated)]);
+        assert!(Pe::from_bytes(raw).unwrap().menus().is_err());
+    }
+
+    #[test]
+    fn test_accelerators() {
+        let mut table = Vec::new();
+        for (flags, key, id) in [
+            (0x09u16, b'O' as u16, 100u16),
+            (0x01, 0x70, 101),
+            (0x80, 0x01, 102),
+            (0, 0, 103),
+        ] {
+            push16(&mut table, flags);
+            push16(&mut table, key);
+            push16(&mut table, id);
+            push16(&mut table, 0);
+        }
+
+        let raw = pe_with_resources(&[resource(TestId::Id(9), TestId::Id(1), 1033, table)]);
+        let pe = Pe::from_bytes(raw).unwrap();
+        let tables = pe.accelerators().unwrap();
+        assert_eq!(tables.len(), 1);
+        // entries after the one marked last are ignored
+        assert_eq!(tables[0].entries.len(), 3);
+        assert_eq!(
+            tables[0].to_string(),
+            "#1 ACCELERATORS\n\
+             BEGIN\n    \
+             \"O\", 100, VIRTKEY, CONTROL\n    \
+             VK_F1, 101, VIRTKEY\n    \
+             \"^A\", 102, ASCII\n\
+             END\n"
+        );
+    }
+
+    // DemoLib with an IWidget interface, a Color enum, a Widget coclass and a Shade alias.
+    // The revised version drops the Name setter and Shade, and adds Resize.
+    fn demo_typelib(revised: bool) -> Vec<u8> {
+        const LONG: u32 = 0x80000003;
+        const BSTR: u32 = 0x80000008;
+        const HRESULT: u32 = 0x80000019;
+        let mut tlb = TestTypeLib::default();
+        let lib_name = tlb.name("DemoLib");
+        let lib_guid = tlb.guid([0x10; 16]);
+        let help = tlb.string("Demo library");
+
+        // IDispatch, imported from stdole2.tlb by GUID
+        let dispatch = tlb.guid([0, 4, 2, 0, 0, 0, 0, 0, 0xc0, 0, 0, 0, 0, 0, 0, 0x46]);
+        let files = &mut tlb.segments[TLB_IMPORT_FILES];
+        push32(files, 0);
+        push32(files, 0);
+        push32(files, 0x20000);
+        push16(files, (11 << 2) | 1);
+        files.extend(b"stdole2.tlb\0");
+        let imports = &mut tlb.segments[TLB_IMPORT_INFO];
+        push32(imports, 0x10004);
+        push32(imports, 0);
+        push32(imports, dispatch);
+
+        // BSTR* at 0, Color (typeinfo 1) at 8
+        let descs = &mut tlb.segments[TLB_TYPE_DESCS];
+        for value in [26u16, 0, 8, 0xffff, 29, 0, 0x64, 0] {
+            push16(descs, value);
+        }
+        // -1 as a VT_I4
+        let data = &mut tlb.segments[TLB_CUSTOM_DATA];
+        push16(data, 3);
+        push32(data, u32::MAX);
+        // [default] IWidget
+        let references = &mut tlb.segments[TLB_REFERENCES];
+        for value in [0, 1, u32::MAX, u32::MAX] {
+            push32(references, value);
+        }
+
+        let (name, widget_guid) = (tlb.name("IWidget"), tlb.guid([0x11; 16]));
+        let mut widget = TestTypeInfo::new(3, name, widget_guid);
+        widget.flags = 0x100;
+        widget.data_type = 1;
+        widget.implemented = 1;
+        let (value, color, times) = (tlb.name("value"), tlb.name("color"), tlb.name("times"));
+        let (property, paint, resize) = (tlb.name("Name"), tlb.name("Paint"), tlb.name("Resize"));
+        // pure virtual stdcall functions
+        let kind = |invoke: u32| 1 | invoke << 3 | 4 << 8;
+        widget.functions.push(tlb_function(
+            HRESULT,
+            kind(2),
+            0x1c,
+            &[(0, value, 0x0a)],
+            None,
+        ));
+        widget.ids.push(0x60020000);
+        widget.names.push(property);
+        if !revised {
+            widget.functions.push(tlb_function(
+                HRESULT,
+                kind(4),
+                0x20,
+                &[(BSTR, value, 1)],
+                None,
+            ));
+            widget.ids.push(0x60020000);
+            // the setter shares the getter's name
+            widget.names.push(u32::MAX);
+        }
+        widget.functions.push(tlb_function(
+            HRESULT,
+            kind(1),
+            0x24,
+            &[(8, color, 1), (LONG, times, 0x31)],
+            Some(&[u32::MAX, 0x8c000003]),
+        ));
+        widget.ids.push(0x60020002);
+        widget.names.push(paint);
+        if revised {
+            widget.functions.push(tlb_function(
+                HRESULT,
+                kind(1),
+                0x28,
+                &[(LONG, times, 1)],
+                None,
+            ));
+            widget.ids.push(0x60020003);
+            widget.names.push(resize);
+        }
+
+        let mut enumeration = TestTypeInfo::new(0, tlb.name("Color"), u32::MAX);
+        for (id, text, value) in [
+            (1, "Red", 0x8c000000),
+            (2, "Green", 0x8c000001),
+            (3, "Blue", 0),
+        ] {
+            enumeration.variables.push(tlb_variable(LONG, 2, value));
+            enumeration.ids.push(0x40000000 + id);
+            enumeration.names.push(tlb.name(text));
+        }
+
+        let (name, guid) = (tlb.name("Widget"), tlb.guid([0x12; 16]));
+        let mut coclass = TestTypeInfo::new(5, name, guid);
+        coclass.flags = 0x02;
+        coclass.data_type = 0;
+        coclass.implemented = 1;
+
+        let mut alias = TestTypeInfo::new(6, tlb.name("Shade"), u32::MAX);
+        alias.data_type = 8;
+
+        tlb.infos = vec![widget, enumeration, coclass];
+        if !revised {
+            tlb.infos.push(alias);
+        }
+        tlb.build(lib_name, lib_guid, help)
+    }
+
+    #[test]
+    fn test_type_library() {
+        let raw = pe_with_resources(&[resource(
+            TestId::Name("TYPELIB"),
+            TestId::Id(1),
+            0,
+            demo_typelib(false),
+        )]);
+        let pe = Pe::from_bytes(raw).unwrap();
+        let libraries = pe.type_libraries().unwrap();
+        assert_eq!(libraries.len(), 1);
+        let library = &libraries[0];
+        assert_eq!(library.name, "DemoLib");
+        assert_eq!((library.major_version, library.minor_version), (1, 2));
+        assert_eq!(
+            library.guid.unwrap().to_string(),
+            "10101010-1010-1010-1010-101010101010"
+        );
+        assert_eq!(library.help_string.as_deref(), Some("Demo library"));
+        assert_eq!(library.types.len(), 4);
+
+        let widget = library.find("IWidget").unwrap();
+        assert_eq!(widget.kind, TypeKind::Interface);
+        assert_eq!(widget.implemented[0].reference.name, "IDispatch");
+        assert_eq!(widget.functions[1].name, "Name");
+        assert_eq!(
+            widget.functions[2].params[1].default,
+            Some(Value::Integer(3))
+        );
+        assert_eq!(
+            widget.to_string(),
+            "[uuid(11111111-1111-1111-1111-111111111111), version(1.0), oleautomation]\n\
+             interface IWidget : IDispatch\n\
+             {\n    \
+             [id(0x60020000), propget] HRESULT Name([out, retval] BSTR* value);\n    \
+             [id(0x60020000), propput] HRESULT Name([in] BSTR value);\n    \
+             [id(0x60020002)] HRESULT Paint([in] Color color, \
+             [in, optional, defaultvalue(3)] long times);\n\
+             };\n"
+        );
+        assert_eq!(
+            library.find("Color").unwrap().to_string(),
+            "[version(1.0)]\n\
+             typedef enum Color\n\
+             {\n    \
+             Red = 0,\n    \
+             Green = 1,\n    \
+             Blue = -1\n\
+             } Color;\n"
+        );
+        assert_eq!(
+            library.find("Widget").unwrap().to_string(),
+            "[uuid(12121212-1212-1212-1212-121212121212), version(1.0)]\n\
+             coclass Widget\n\
+             {\n    \
+             [default] interface IWidget;\n\
+             };\n"
+        );
+        assert_eq!(
+            library.find("Shade").unwrap().to_string(),
+            "[version(1.0)]\ntypedef Color Shade;\n"
+        );
+        let rendered = library.to_string();
+        assert!(rendered.starts_with(
+            "[uuid(10101010-1010-1010-1010-101010101010), version(1.2), \
+             helpstring(\"Demo library\")]\nlibrary DemoLib\n{\n"
+        ));
+        assert!(rendered.contains("\n    coclass Widget\n"));
+
+        let revised =
+            pepper::directories::resources::typelib::TypeLib::new(&demo_typelib(true)).unwrap();
+        assert_eq!(
+            library.changes(&revised),
+            vec![
+                TypeLibChange::TypeRemoved {
+                    name: "Shade".to_string()
+                },
+                TypeLibChange::MemberRemoved {
+                    type_name: "IWidget".to_string(),
+                    member: "[id(0x60020000), propput] HRESULT Name([in] BSTR value) \
+                             /* vtable 0x20 */"
+                        .to_string(),
+                },
+                TypeLibChange::MemberAdded {
+                    type_name: "IWidget".to_string(),
+                    member: "[id(0x60020003)] HRESULT Resize([in] long times) /* vtable 0x28 */"
+                        .to_string(),
+                },
+            ]
+        );
+        assert!(revised.changes(&revised).is_empty());
+
+        // cutting into the member names of the last type is an error rather than a panic
+        let mut broken = demo_typelib(false);
+        broken.truncate(broken.len() - 13);
+        assert!(pepper::directories::resources::typelib::TypeLib::new(&broken).is_err());
+    }
+
+    fn unwind_info(flags: u8, prolog: u8, frame: u8, codes: &[u16], tail: &[u32]) -> Vec<u8> {
+        let mut info = vec![flags << 3 | 1, prolog, codes.len() as u8, frame];
+        for code in codes {
+            push16(&mut info, *code);
+        }
+        pad4(&mut info);
+        for value in tail {
+            push32(&mut info, *value);
+        }
+        info
+    }
+
+    #[test]
+    fn test_x64_exceptions() {
+        let mut xdata = vec![0u8; 0x300];
+        // push rbp; sub rsp, 0x123456; lea rbp, [rsp + 0x20]; mov [rsp + 0x40], rbx, with a
+        // __C_specific_handler scope table
+        let codes = [0x3410, 0x0008, 0x030c, 0x1108, 0x3456, 0x0012, 0x5001];
+        let first = unwind_info(
+            1,
+            0x10,
+            0x25,
+            &codes,
+            &[0x1500, 1, 0x1010, 0x1020, 1, 0x1030],
+        );
+        xdata[..first.len()].copy_from_slice(&first);
+        // sub rsp, 0x28 inside an interrupt frame with an error code
+        let second = unwind_info(0, 4, 0, &[0x4204, 0x1a00], &[]);
+        xdata[0x100..0x100 + second.len()].copy_from_slice(&second);
+        // push r12, chained to the first function
+        let third = unwind_info(4, 2, 0, &[0xc002], &[0x1000, 0x1100, 0x2000]);
+        xdata[0x200..0x200 + third.len()].copy_from_slice(&third);
+
+        let mut pdata = Vec::new();
+        for (begin, end, unwind) in [
+            (0x1000, 0x1100, 0x2000),
+            (0x1100, 0x1180, 0x2100),
+            (0x1180, 0x11a0, 0x2200),
+            // shares the second function's unwind info
+            (0x11a0, 0x11b0, 0x300d),
+        ] {
+            push32(&mut pdata, begin);
+            push32(&mut pdata, end);
+            push32(&mut pdata, unwind);
+        }
+        let size = pdata.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".xdata", 0x2000, xdata.clone())
+            .section(".pdata", 0x3000, pdata.clone())
+            .directory(DIR_EXCEPTION, 0x3000, size)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let functions = match pe.exceptions().unwrap() {
+            Some(ExceptionTable::X64(functions)) => functions,
+            other => panic!("unexpected exception table {:?}", other),
+        };
+        assert_eq!(functions.len(), 4);
+
+        let first = &functions[0];
+        let ops: Vec<&UnwindOp> = first.unwind[0].codes.iter().map(|c| &c.op).collect();
+        assert_eq!(
+            ops,
+            vec![
+                &UnwindOp::SaveNonVolatile {
+                    register: 3,
+                    offset: 0x40
+                },
+                &UnwindOp::SetFramePointer,
+                &UnwindOp::AllocLarge { size: 0x123456 },
+                &UnwindOp::PushNonVolatile { register: 5 },
+            ]
+        );
+        assert_eq!(first.unwind[0].prolog_size, 0x10);
+        assert_eq!(first.frame_register(), Some((5, 0x20)));
+        assert_eq!(first.stack_size(), 8 + 0x123456);
+        let handler = first.handler().unwrap();
+        assert_eq!((handler.rva, handler.data_rva), (0x1500, 0x2018));
+        let scopes = scope_table(pe.read_from_rva(handler.data_rva).unwrap()).unwrap();
+        assert_eq!(scopes.len(), 1);
+        assert_eq!(
+            (scopes[0].begin, scopes[0].end, scopes[0].target),
+            (0x1010, 0x1020, 0x1030)
+        );
+
+        assert_eq!(functions[1].stack_size(), 0x28 + 48);
+        assert_eq!(functions[2].unwind.len(), 2);
+        assert_eq!(functions[2].stack_size(), 8 + 8 + 0x123456);
+        assert_eq!(functions[2].handler(), None);
+        assert_eq!(functions[3].unwind, functions[1].unwind);
+
+        // an unknown unwind version is an error
+        xdata[0x100] = 3;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".xdata", 0x2000, xdata)
+            .section(".pdata", 0x3000, pdata)
+            .directory(DIR_EXCEPTION, 0x3000, size)
+            .build();
+        assert!(Pe::from_bytes(raw).unwrap().exceptions().is_err());
+    }
+
+    #[test]
+    fn test_arm64_exceptions() {
+        let mut xdata = Vec::new();
+        // X set, one epilog scope and two code words
+        push32(&mut xdata, 0x20 | 1 << 20 | 1 << 22 | 2 << 27);
+        push32(&mut xdata, 0x1c | 5 << 22);
+        // stp x19, x20, [sp, #-32]!; stp fp, lr, [sp, #-16]!; sub sp, sp, #256; end, then an
+        // epilog of add sp, sp, #256; end
+        xdata.extend_from_slice(&[0x24, 0x81, 0xc0, 0x10, 0xe4, 0x10, 0xe4, 0xe3]);
+        push32(&mut xdata, 0x1500);
+        xdata.resize(0x100, 0);
+        // E set with the extension word, one code word
+        push32(&mut xdata, 4 | 1 << 21);
+        push32(&mut xdata, 1 << 16);
+        xdata.extend_from_slice(&[0x02, 0xe4, 0xe3, 0xe3]);
+
+        let mut pdata = Vec::new();
+        // packed: 0x40 bytes, x19-x20 saved, frame chain, 0x40 byte frame
+        for (begin, unwind) in [
+            (0x1000, 1 | 0x10 << 2 | 2 << 16 | 3 << 21 | 4 << 23),
+            (0x1040, 0x2000),
+            (0x10c0, 0x2100),
+        ] {
+            push32(&mut pdata, begin);
+            push32(&mut pdata, unwind);
+        }
+        let build = |pdata: Vec<u8>| {
+            let size = pdata.len() as u32;
+            let raw = TestPe::new(IMAGE_FILE_MACHINE_ARM64)
+                .section(".xdata", 0x2000, xdata.clone())
+                .section(".pdata", 0x3000, pdata)
+                .directory(DIR_EXCEPTION, 0x3000, size)
+                .build();
+            Pe::from_bytes(raw).unwrap()
+        };
+        let functions = match build(pdata.clone()).exceptions().unwrap() {
+            Some(ExceptionTable::Arm64(functions)) => functions,
+            other => panic!("unexpected exception table {:?}", other),
+        };
+        assert_eq!(functions.len(), 3);
+
+        let packed = match &functions[0].unwind {
+            arm64::Unwind::Packed(packed) => packed,
+            other => panic!("unexpected unwind data {:?}", other),
+        };
+        assert_eq!((packed.reg_i, packed.cr, packed.home_params), (2, 3, false));
+        assert_eq!(functions[0].stack_size(), 0x40);
+        assert!(functions[0].contains(0x103c));
+        assert!(!functions[0].contains(0x1040));
+
+        let data = match &functions[1].unwind {
+            arm64::Unwind::Unpacked(data) => data,
+            other => panic!("unexpected unwind data {:?}", other),
+        };
+        assert_eq!(data.function_length, 0x80);
+        let ops: Vec<&arm64::UnwindOp> = data.prolog().iter().map(|c| &c.op).collect();
+        assert_eq!(
+            ops,
+            vec![
+                &arm64::UnwindOp::SaveR19R20 { offset: 32 },
+                &arm64::UnwindOp::SaveFpLr {
+                    offset: 16,
+                    pre_index: true
+                },
+                &arm64::UnwindOp::AllocStack { size: 256 },
+                &arm64::UnwindOp::End,
+            ]
+        );
+        assert_eq!(data.epilogs.len(), 1);
+        assert_eq!(data.epilogs[0].start_offset, 0x70);
+        let ops: Vec<&arm64::UnwindOp> = data
+            .epilog(&data.epilogs[0])
+            .iter()
+            .map(|c| &c.op)
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                &arm64::UnwindOp::AllocStack { size: 256 },
+                &arm64::UnwindOp::End
+            ]
+        );
+        assert_eq!(functions[1].stack_size(), 32 + 16 + 256);
+        let handler = functions[1].handler().unwrap();
+        assert_eq!((handler.rva, handler.data_rva), (0x1500, 0x2014));
+
+        let data = match &functions[2].unwind {
+            arm64::Unwind::Unpacked(data) => data,
+            other => panic!("unexpected unwind data {:?}", other),
+        };
+        assert_eq!(data.single_epilog_index, Some(0));
+        assert!(data.epilogs.is_empty());
+        assert_eq!(functions[2].stack_size(), 32);
+        assert_eq!(functions[2].handler(), None);
+
+        // flag 3 is reserved
+        pdata[4] |= 3;
+        assert!(build(pdata).exceptions().is_err());
+    }
+
+    #[test]
+    fn test_arm_exceptions() {
+        let mut xdata = Vec::new();
+        // E set, one code word: add sp, sp, #16; vpop {d8-d9}; pop {r4, r5, lr}; end
+        push32(&mut xdata, 0x20 | 1 << 21 | 1 << 28);
+        xdata.extend_from_slice(&[0x04, 0xe1, 0xd5, 0xff]);
+
+        let mut pdata = Vec::new();
+        // packed: 0x20 bytes, r4-r7 and lr saved, 16 bytes of locals
+        for (begin, unwind) in [
+            (0x1001, 1 | 0x10 << 2 | 3 << 16 | 1 << 20 | 4 << 22),
+            (0x1021, 0x2000),
+        ] {
+            push32(&mut pdata, begin);
+            push32(&mut pdata, unwind);
+        }
+        let size = pdata.len() as u32;
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_ARMNT)
+            .pe32()
+            .section(".xdata", 0x2000, xdata)
+            .section(".pdata", 0x3000, pdata)
+            .directory(DIR_EXCEPTION, 0x3000, size)
+            .build();
+        let functions = match Pe::from_bytes(raw).unwrap().exceptions().unwrap() {
+            Some(ExceptionTable::Arm(functions)) => functions,
+            other => panic!("unexpected exception table {:?}", other),
+        };
+        assert_eq!(functions.len(), 2);
+
+        assert_eq!(functions[0].function.start(), 0x1000);
+        let packed = match &functions[0].unwind {
+            arm::Unwind::Packed(packed) => packed,
+            other => panic!("unexpected unwind data {:?}", other),
+        };
+        assert_eq!(
+            (packed.reg, packed.link_register, packed.r),
+            (3, true, false)
+        );
+        assert_eq!(functions[0].stack_size(), 16);
+        assert!(functions[0].contains(0x101f));
+        assert!(!functions[0].contains(0x1020));
+
+        let data = match &functions[1].unwind {
+            arm::Unwind::Unpacked(data) => data,
+            other => panic!("unexpected unwind data {:?}", other),
+        };
+        assert_eq!(data.function_length, 0x40);
+        assert_eq!(data.single_epilog_index, Some(0));
+        let ops: Vec<&arm::UnwindOp> = data.prolog().iter().map(|c| &c.op).collect();
+        assert_eq!(
+            ops,
+            vec![
+                &arm::UnwindOp::AllocStack {
+                    size: 16,
+                    wide: false
+                },
+                &arm::UnwindOp::PopFloatRegisters { first: 8, last: 9 },
+                &arm::UnwindOp::PopRegisters {
+                    mask: 0x4030,
+                    wide: false
+                },
+                &arm::UnwindOp::End { nop: None },
+            ]
+        );
+        assert_eq!(functions[1].stack_size(), 16 + 16 + 12);
+    }
+
+    fn debug_directory(kind: u32, size: u32, rva: u32, pointer: u32) -> Vec<u8> {
+        let mut directory = Vec::new();
+        push32(&mut directory, 0);
+        push32(&mut directory, 0x6500_0000);
+        push32(&mut directory, 0);
+        push32(&mut directory, kind);
+        push32(&mut directory, size);
+        push32(&mut directory, rva);
+        push32(&mut directory, pointer);
+        directory
+    }
+
+    #[test]
+    fn test_codeview() {
+        let mut rsds = b"RSDS".to_vec();
+        push32(&mut rsds, 0x1234_5678);
+        push16(&mut rsds, 0x9abc);
+        push16(&mut rsds, 0xdef0);
+        rsds.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        push32(&mut rsds, 0x2a);
+        rsds.extend_from_slice(b"C:\\build\\obj\\app.pdb\0");
+        let mut nb10 = b"NB10".to_vec();
+        push32(&mut nb10, 0);
+        push32(&mut nb10, 0x3c5a_0f01);
+        push32(&mut nb10, 3);
+        nb10.extend_from_slice(b"old.pdb\0");
+
+        let build = |pointer: u32| {
+            let mut rdata = Vec::new();
+            rdata.extend(debug_directory(2, rsds.len() as u32, 0x2100, 0));
+            rdata.extend(debug_directory(2, nb10.len() as u32, 0x2200, 0));
+            // not mapped, only reachable through the file offset
+            rdata.extend(debug_directory(11, 4, 0, pointer));
+            rdata.resize(0x100, 0);
+            rdata.extend_from_slice(&rsds);
+            rdata.resize(0x200, 0);
+            rdata.extend_from_slice(&nb10);
+            rdata.resize(0x300, 0);
+            rdata.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+            let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+                .section(".rdata", 0x2000, rdata)
+                .directory(DIR_DEBUG, 0x2000, 3 * 28)
+                .build();
+            Pe::from_bytes(raw).unwrap()
+        };
+        let pointer = build(0).rva_to_offset(0x2300).unwrap() as u32;
+        let pe = build(pointer);
+
+        let entries = pe.debug_directory().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].directory.kind, DebugType::CodeView);
+        assert_eq!(entries[0].directory.timestamp, 0x6500_0000);
+        assert_eq!(entries[2].directory.kind, DebugType::Clsid);
+        assert_eq!(
+            entries[2].data,
+            DebugData::Raw(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+
+        let codeview = pe.codeview().unwrap().unwrap();
+        match &codeview {
+            CodeView::Pdb70 { guid, age, path } => {
+                assert_eq!(guid.to_string(), "12345678-9ABC-DEF0-0102-030405060708");
+                assert_eq!(*age, 0x2a);
+                assert_eq!(path, "C:\\build\\obj\\app.pdb");
+            }
+            other => panic!("unexpected codeview record {:?}", other),
+        }
+        assert_eq!(codeview.pdb_name(), Some("app.pdb"));
+        assert_eq!(
+            codeview.symbol_key().unwrap(),
+            "123456789ABCDEF001020304050607082A"
+        );
+        assert_eq!(
+            codeview.symbol_path().unwrap(),
+            "app.pdb/123456789ABCDEF001020304050607082A/app.pdb"
+        );
+
+        let nb10 = match &entries[1].data {
+            DebugData::CodeView(codeview) => codeview,
+            other => panic!("unexpected debug data {:?}", other),
+        };
+        assert_eq!(nb10.symbol_key().unwrap(), "3C5A0F013");
+        assert_eq!(nb10.pdb_name(), Some("old.pdb"));
+
+        // entries that are out of the file, cut short or not what their type says come back raw
+        // and don't hide the good one after them
+        let mut rdata = Vec::new();
+        rdata.extend(debug_directory(2, 0x40, 0, 0xffff_0000));
+        rdata.extend(debug_directory(2, 0x1000, 0x2100, 0));
+        rdata.extend(debug_directory(2, 4, 0x20f0, 0));
+        rdata.extend(debug_directory(2, rsds.len() as u32, 0x2100, 0));
+        rdata.resize(0xf0, 0);
+        rdata.extend_from_slice(b"RSDS");
+        rdata.resize(0x100, 0);
+        rdata.extend_from_slice(&rsds);
+        let broken = Pe::from_bytes(
+            TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+                .section(".rdata", 0x2000, rdata)
+                .directory(DIR_DEBUG, 0x2000, 4 * 28)
+                .build(),
+        )
+        .unwrap();
+        let entries = broken.debug_directory().unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].data, DebugData::Raw(Vec::new()));
+        match &entries[1].data {
+            DebugData::Raw(data) => assert!(data.starts_with(&rsds)),
+            other => panic!("unexpected debug data {:?}", other),
+        }
+        assert_eq!(entries[2].data, DebugData::Raw(b"RSDS".to_vec()));
+        assert_eq!(broken.codeview().unwrap().unwrap(), codeview);
+
+        assert_eq!(pe.timestamp().unwrap(), 0x6000_0000);
+        assert_eq!(
+            pe.symbol_key().unwrap(),
+            format!("60000000{:X}", pe.optional_header.size_of_image)
+        );
+    }
+
+    fn debug_pe(machine: u16, entries: &[(u32, Vec<u8>)]) -> Pe {
+        let mut rdata = Vec::new();
+        for (i, (kind, data)) in entries.iter().enumerate() {
+            let rva = 0x2100 + 0x100 * i as u32;
+            rdata.extend(debug_directory(*kind, data.len() as u32, rva, 0));
+        }
+        for (i, (_, data)) in entries.iter().enumerate() {
+            rdata.resize(0x100 + 0x100 * i, 0);
+            rdata.extend_from_slice(data);
+        }
+        let size = 28 * entries.len() as u32;
+        let raw = TestPe::new(machine)
+            .section(".rdata", 0x2000, rdata)
+            .directory(DIR_DEBUG, 0x2000, size)
+            .build();
+        Pe::from_bytes(raw).unwrap()
+    }
+
+    #[test]
+    fn test_debug_entries() {
+        let mut pogo = b"LTCG".iter().rev().copied().collect::<Vec<u8>>();
+        for (rva, size, name) in [(0x1000, 0x20, ".text$mn"), (0x2000, 0x8, ".rdata")] {
+            push32(&mut pogo, rva);
+            push32(&mut pogo, size);
+            pogo.extend_from_slice(name.as_bytes());
+            pogo.push(0);
+            pad4(&mut pogo);
+        }
+        pogo.extend_from_slice(&[0; 12]);
+        let mut vc_feature = Vec::new();
+        for count in [0, 12, 10, 3, 1] {
+            push32(&mut vc_feature, count);
+        }
+        let mut repro = Vec::new();
+        push32(&mut repro, 4);
+        repro.extend_from_slice(&[1, 2, 3, 4]);
+        let mut misc = Vec::new();
+        push32(&mut misc, 1);
+        push32(&mut misc, 24);
+        misc.extend_from_slice(&[0; 4]);
+        misc.extend_from_slice(b"app.exe\0\0\0\0\0");
+
+        let pe = debug_pe(
+            IMAGE_FILE_MACHINE_AMD64,
+            &[
+                (13, pogo),
+                (12, vc_feature),
+                (16, repro),
+                (16, Vec::new()),
+                (20, vec![0x41, 0, 0, 0]),
+                (4, misc),
+            ],
+        );
+        let entries = pe.debug_directory().unwrap();
+        assert_eq!(entries.len(), 6);
+
+        let pogo = match &entries[0].data {
+            DebugData::Pogo(pogo) => pogo,
+            other => panic!("unexpected debug data {:?}", other),
+        };
+        assert_eq!(pogo.kind(), "LTCG");
+        assert_eq!(pogo.entries.len(), 2);
+        assert_eq!(pogo.entries[0].name, ".text$mn");
+        assert_eq!((pogo.entries[1].rva, pogo.entries[1].size), (0x2000, 8));
+        assert_eq!(pogo.entries[1].name, ".rdata");
+
+        match &entries[1].data {
+            DebugData::VcFeature(features) => {
+                assert_eq!((features.c_cpp, features.gs, features.sdl), (12, 10, 3))
+            }
+            other => panic!("unexpected debug data {:?}", other),
+        }
+        assert_eq!(entries[2].data, DebugData::Repro(vec![1, 2, 3, 4]));
+        assert_eq!(entries[3].data, DebugData::Repro(Vec::new()));
+        match &entries[4].data {
+            DebugData::ExDllCharacteristics(flags) => {
+                assert!(flags.cet_compatible());
+                assert!(!flags.cet_strict());
+                assert!(flags.forward_cfi_compatible());
+            }
+            other => panic!("unexpected debug data {:?}", other),
+        }
+        match &entries[5].data {
+            DebugData::Misc(misc) => {
+                assert_eq!((misc.data_type, misc.name.as_str()), (1, "app.exe"))
+            }
+            other => panic!("unexpected debug data {:?}", other),
+        }
+
+        // x86 FPO records
+        let mut fpo = Vec::new();
+        push32(&mut fpo, 0x1000);
+        push32(&mut fpo, 0x40);
+        push32(&mut fpo, 2);
+        push16(&mut fpo, 3);
+        push16(&mut fpo, 0x06 | 2 << 8 | 1 << 12 | 3 << 14);
+        let pe = debug_pe(IMAGE_FILE_MACHINE_I386, &[(3, fpo)]);
+        let records = match pe.debug_directory().unwrap().remove(0).data {
+            DebugData::Fpo(records) => records,
+            other => panic!("unexpected debug data {:?}", other),
+        };
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!((record.locals_size, record.params_size), (8, 12));
+        assert_eq!((record.prolog_size, record.saved_registers), (6, 2));
+        assert!(record.uses_bp && !record.has_seh);
+        assert_eq!(record.frame, FrameType::NonFpo);
+        assert!(record.contains(0x103f));
+
+        // a truncated POGO or VC_FEATURE blob is kept raw without hiding the entries around it
+        let pe = debug_pe(
+            IMAGE_FILE_MACHINE_AMD64,
+            &[
+                (13, b"GTCL\0\x10\0\0\x20\0\0\0.text".to_vec()),
+                (12, vec![0; 8]),
+                (16, vec![0, 0, 0, 0]),
+            ],
+        );
+        let entries = pe.debug_directory().unwrap();
+        assert!(matches!(&entries[0].data, DebugData::Raw(data) if data.len() == 17));
+        assert_eq!(entries[1].data, DebugData::Raw(vec![0; 8]));
+        assert_eq!(entries[2].data, DebugData::Repro(Vec::new()));
+        assert_eq!(
+            DebugData::new(DebugType::ExDllCharacteristics, &[0x41]),
+            DebugData::Raw(vec![0x41])
+        );
+
+        // a truncated FPO array is kept as it is
+        let pe = debug_pe(IMAGE_FILE_MACHINE_I386, &[(3, vec![0; 10])]);
+        assert_eq!(
+            pe.debug_directory().unwrap()[0].data,
+            DebugData::Raw(vec![0; 10])
+        );
+    }
+
+    fn sample_text() -> Vec<u8> {
+        (0..40)
+            .flat_map(|i| format!("line {}: pepper parses portable executables\n", i).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_sha2() {
+        assert_eq!(
+            to_hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            to_hex(&sha384(b"")),
+            "38b060a751ac96384cd9327eb1b1e36a21fdb71114be07434c0cc7bf63f6e1da274edebfe76f65fbd51ad2f14898b95b"
+        );
+        assert_eq!(
+            to_hex(&sha512(b"abc")),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+
+        let text = sample_text();
+        assert_eq!(
+            to_hex(&sha512(&text)),
+            "e883f1fc599db626a40402dddb5d5e8717825a93532ad160a0755754ab40e295b8e05994eafdcb7c3995ea4d6d59736865bd2013d1eb8b4da84cf5244e7d0395"
+        );
+        // fed in pieces that don't line up with the blocks
+        let mut hasher = Sha256::new();
+        for chunk in text.chunks(37) {
+            hasher.update(chunk);
+        }
+        assert_eq!(
+            to_hex(&hasher.finalize()),
+            "141d8289b8d91b1cb5acd779745b8d5e69eb2f71da6df014b9819781edb18e96"
+        );
+    }
+
+    #[test]
+    fn test_inflate() {
+        let text = sample_text();
+        // zlib output with dynamic Huffman codes
+        let dynamic = [
+            0x95, 0xd4, 0xbb, 0x0d, 0x02, 0x41, 0x0c, 0x45, 0xd1, 0x9c, 0x2a, 0x5c, 0x02, 0xf6,
+            0xe3, 0xdf, 0xcd, 0x82, 0x1c, 0x20, 0x8d, 0x76, 0x47, 0x33, 0x20, 0x51, 0x3e, 0x82,
+            0x0a, 0x7c, 0x33, 0x07, 0x37, 0x3b, 0x7a, 0x6e, 0xcf, 0x35, 0x6d, 0x7f, 0xb3, 0x9e,
+            0xbd, 0xe7, 0xb0, 0xbe, 0x8c, 0x99, 0xd3, 0xfa, 0x36, 0x5e, 0xcb, 0xbd, 0xa5, 0xe5,
+            0x27, 0x1f, 0xef, 0xff, 0x39, 0x77, 0xed, 0x97, 0x7a, 0x3d, 0x8d, 0x7a, 0xaa, 0x7a,
+            0x7a, 0xa8, 0xa7, 0xc7, 0x7a, 0x7a, 0xaa, 0xa7, 0xe7, 0x7a, 0x7a, 0xa9, 0xa7, 0x57,
+            0x40, 0x40, 0xb8, 0x80, 0x97, 0x03, 0x30, 0x07, 0x62, 0x0e, 0xc8, 0x1c, 0x98, 0x39,
+            0x40, 0x73, 0xa0, 0xe6, 0x80, 0xcd, 0x81, 0x5b, 0x00, 0xb7, 0x20, 0x3b, 0x03, 0x6e,
+            0x01, 0xdc, 0x02, 0xb8, 0x05, 0x70, 0x0b, 0xe0, 0x16, 0xc0, 0x2d, 0x80, 0x5b, 0x00,
+            0x37, 0x01, 0x37, 0x01, 0x37, 0x91, 0x07, 0x09, 0xdc, 0x04, 0xdc, 0x04, 0xdc, 0x04,
+            0xdc, 0x04, 0xdc, 0x04, 0xdc, 0x54, 0x73, 0xfb, 0x02,
+        ];
+        assert_eq!(inflate(&dynamic, text.len()).unwrap(), text);
+        // a smaller limit is an error rather than a truncated result
+        assert!(inflate(&dynamic, 100).is_err());
+        assert!(inflate(&dynamic[..100], text.len()).is_err());
+
+        // fixed Huffman codes with overlapping back references
+        let fixed = [0x4b, 0x4c, 0x84, 0x81, 0xa4, 0x64, 0x38, 0x02, 0x00];
+        assert_eq!(inflate(&fixed, 64).unwrap(), b"aaaaaaaaaabcabcabcabc");
+
+        let stored = deflate_stored(&text);
+        assert_eq!(inflate(&stored, text.len()).unwrap(), text);
+        // reserved block type
+        assert!(inflate(&[0x07], 16).is_err());
+    }
+
+    #[test]
+    fn test_embedded_pdb() {
+        let mut id = [0u8; 20];
+        id[..16].copy_from_slice(&[
+            0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde, 1, 2, 3, 4, 5, 6, 7, 8,
+        ]);
+        id[16..].copy_from_slice(&0x6500_0000u32.to_le_bytes());
+        let pdb = portable_pdb(&id);
+
+        let mut embedded = b"MPDB".to_vec();
+        push32(&mut embedded, pdb.len() as u32);
+        embedded.extend(deflate_stored(&pdb));
+        let mut zeroed = pdb.clone();
+        zeroed[0x40..0x54].fill(0);
+        let mut checksum = b"SHA256\0".to_vec();
+        checksum.extend_from_slice(&sha256(&zeroed));
+
+        let pe = debug_pe(
+            IMAGE_FILE_MACHINE_AMD64,
+            &[(17, embedded.clone()), (19, checksum)],
+        );
+        let extracted = pe.embedded_pdb().unwrap().unwrap();
+        assert_eq!(extracted, pdb);
+        let (guid, stamp) = pdb_id(&extracted).unwrap();
+        assert_eq!(guid.to_string(), "12345678-9ABC-DEF0-0102-030405060708");
+        assert_eq!(stamp, 0x6500_0000);
+
+        let checksums = pe.pdb_checksums().unwrap();
+        assert_eq!(checksums.len(), 1);
+        assert_eq!(checksums[0].algorithm, "SHA256");
+        assert!(checksums[0].verify(&extracted).unwrap());
+        let mut tampered = extracted.clone();
+        tampered[0x6f] ^= 1;
+        assert!(!checksums[0].verify(&tampered).unwrap());
+        // the id itself isn't covered
+        let mut other_id = extracted;
+        other_id[0x40] ^= 1;
+        assert!(checksums[0].verify(&other_id).unwrap());
+
+        // a size no DEFLATE stream that short could inflate to isn't even tried
+        let mut oversized = embedded.clone();
+        put32(&mut oversized, 4, 0xffff_ffff);
+        let pe = debug_pe(IMAGE_FILE_MACHINE_AMD64, &[(17, oversized)]);
+        let error = pe.embedded_pdb().unwrap_err().to_string();
+        assert!(error.contains("claims 0xffffffff bytes"), "{}", error);
+
+        // a size that doesn't match what inflates is an error
+        embedded[4] += 1;
+        let pe = debug_pe(IMAGE_FILE_MACHINE_AMD64, &[(17, embedded)]);
+        assert!(pe.embedded_pdb().is_err());
+        assert!(debug_pe(IMAGE_FILE_MACHINE_AMD64, &[])
+            .embedded_pdb()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_tls() {
+        let base = 0x1_4000_0000u64;
+        let mut tls = Vec::new();
+        push64(&mut tls, base + 0x3100);
+        push64(&mut tls, base + 0x3140);
+        push64(&mut tls, base + 0x3200);
+        push64(&mut tls, base + 0x3300);
+        push32(&mut tls, 0x20);
+        // IMAGE_SCN_ALIGN_16BYTES
+        push32(&mut tls, 0x0050_0000);
+        tls.resize(0x300, 0);
+        // two callbacks in .text, and one pointing outside the image
+        push64(&mut tls, base + 0x1010);
+        push64(&mut tls, base + 0x1080);
+        push64(&mut tls, 0x1000);
+        push64(&mut tls, 0);
+
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0xc3; 0x100])
+            .section(".tls", 0x3000, tls)
+            .directory(DIR_TLS, 0x3000, 0x28)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let tls = pe.tls().unwrap().unwrap();
+        assert_eq!(tls.directory.raw_data_size(), 0x40);
+        assert_eq!(tls.directory.address_of_index, base + 0x3200);
+        assert_eq!(tls.directory.size_of_zero_fill, 0x20);
+        assert_eq!(tls.directory.alignment(), Some(16));
+
+        assert_eq!(tls.callbacks.len(), 3);
+        assert_eq!(tls.callbacks[0].rva, Some(0x1010));
+        assert_eq!(tls.callbacks[0].section.as_deref(), Some(".text"));
+        assert_eq!(tls.callbacks[1].rva, Some(0x1080));
+        assert_eq!(tls.callbacks[2].va, 0x1000);
+        assert_eq!(
+            (tls.callbacks[2].rva, tls.callbacks[2].section.clone()),
+            (None, None)
+        );
+
+        // PE32 without callbacks
+        let mut tls = Vec::new();
+        for value in [0x40_2000, 0x40_2004, 0x40_2010, 0, 0, 0] {
+            push32(&mut tls, value);
+        }
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_I386)
+            .pe32()
+            .section(".rdata", 0x2000, tls)
+            .directory(DIR_TLS, 0x2000, 0x18)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let tls = pe.tls().unwrap().unwrap();
+        assert_eq!(tls.directory.raw_data_size(), 4);
+        assert_eq!(tls.directory.alignment(), None);
+        assert!(tls.callbacks.is_empty());
+
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_I386).pe32().build();
+        assert!(Pe::from_bytes(raw).unwrap().tls().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_load_config() {
+        // the layout up to SEHandlerCount that Windows XP era linkers produced
+        let mut config = vec![0u8; 0x48];
+        put32(&mut config, 0, 0x48);
+        put32(&mut config, 0x2c, 0x4);
+        put32(&mut config, 0x3c, 0x40_3000);
+        put32(&mut config, 0x40, 0x40_2100);
+        put32(&mut config, 0x44, 3);
+        config.resize(0x100, 0);
+        for handler in [0x1010, 0x1040, 0x1080] {
+            push32(&mut config, handler);
+        }
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_I386)
+            .pe32()
+            .section(".rdata", 0x2000, config)
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x40)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let config = pe.load_config().unwrap().unwrap();
+        assert_eq!(config.size, 0x48);
+        assert_eq!(config.process_heap_flags, 4);
+        assert_eq!(config.security_cookie, 0x40_3000);
+        assert_eq!(config.se_handler_count, 3);
+        assert!(config.covers(0x44, 4));
+        assert!(!config.covers(0x58, 4));
+        assert_eq!(config.guard_flags, 0);
+        assert!(config.trailing.is_empty());
+        assert_eq!(
+            pe.safe_seh_handlers().unwrap(),
+            vec![0x1010, 0x1040, 0x1080]
+        );
+
+        // a 64 bit structure from a newer Windows than this parser knows about
+        let base = 0x1_4000_0000u64;
+        let mut config = vec![0u8; 0x150];
+        put32(&mut config, 0, 0x150);
+        put64(&mut config, 0x40, 0xff);
+        put32(&mut config, 0x48, 0x40000);
+        config[0x4c] = 0x2;
+        put64(&mut config, 0x58, base + 0x3000);
+        put32(&mut config, 0x90, 0x1050_0500);
+        put64(&mut config, 0xc8, base + 0x4000);
+        put32(&mut config, 0xe0, 0x80);
+        config[0xe4] = 5;
+        put64(&mut config, 0x100, base + 0x5000);
+        put64(&mut config, 0x140, base + 0x6000);
+        put64(&mut config, 0x148, 0x1122_3344_5566_7788);
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".rdata", 0x2000, config)
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x140)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let config = pe.load_config().unwrap().unwrap();
+        assert_eq!(config.process_affinity_mask, 0xff);
+        assert_eq!(config.process_heap_flags, 0x40000);
+        assert_eq!(config.csd_version, 2);
+        assert_eq!(config.security_cookie, base + 0x3000);
+        assert_eq!(config.guard_flags, 0x1050_0500);
+        assert_eq!(config.chpe_metadata_pointer, base + 0x4000);
+        assert_eq!(config.dynamic_value_reloc_table_offset, 0x80);
+        assert_eq!(config.dynamic_value_reloc_table_section, 5);
+        assert_eq!(config.volatile_metadata_pointer, base + 0x5000);
+        assert_eq!(config.uma_function_pointers, base + 0x6000);
+        assert_eq!(config.trailing, 0x1122_3344_5566_7788u64.to_le_bytes());
+        assert!(pe.safe_seh_handlers().unwrap().is_empty());
+
+        // Size running past the section is an error
+        let mut config = vec![0u8; 0x40];
+        put32(&mut config, 0, 0x400);
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".rdata", 0x2000, config)
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x40)
+            .build();
+        assert!(Pe::from_bytes(raw).unwrap().load_config().is_err());
+    }
+
+    #[test]
+    fn test_guard_tables() {
+        let base = 0x1_4000_0000u64;
+        let mut rdata = vec![0u8; 0x148];
+        put32(&mut rdata, 0, 0x148);
+        // CF_INSTRUMENTED, CF_FUNCTION_TABLE_PRESENT, CF_LONGJUMP_TABLE_PRESENT and
+        // EH_CONTINUATION_TABLE_PRESENT with one metadata byte per entry
+        let flags = 0x1000_0000 | 0x100 | 0x400 | 0x1_0000 | 0x40_0000;
+        put64(&mut rdata, 0x80, base + 0x2200);
+        put64(&mut rdata, 0x88, 4);
+        put32(&mut rdata, 0x90, flags);
+        put64(&mut rdata, 0xa0, base + 0x2300);
+        put64(&mut rdata, 0xa8, 1);
+        put64(&mut rdata, 0xb0, base + 0x2400);
+        put64(&mut rdata, 0xb8, 1);
+        put64(&mut rdata, 0x108, base + 0x2500);
+        put64(&mut rdata, 0x110, 2);
+        rdata.resize(0x200, 0);
+        // plain, SuppressedCall, ExportSuppressed and XFG entries
+        for (rva, metadata) in [(0x1000, 0), (0x1010, 1), (0x1020, 2), (0x1030, 8)] {
+            push32(&mut rdata, rva);
+            rdata.push(metadata);
+        }
+        rdata.resize(0x300, 0);
+        push32(&mut rdata, 0x3008);
+        rdata.push(0);
+        rdata.resize(0x400, 0);
+        push32(&mut rdata, 0x1100);
+        rdata.push(0);
+        rdata.resize(0x500, 0);
+        for rva in [0x1200, 0x1210] {
+            push32(&mut rdata, rva);
+            rdata.push(0);
+        }
+
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".rdata", 0x2000, rdata.clone())
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x148)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        let guard = pe.guard_tables().unwrap().unwrap();
+        assert!(guard.flags.cf_instrumented());
+        assert!(guard.flags.has(IMAGE_GUARD_CF_INSTRUMENTED));
+        assert_eq!(guard.flags.stride(), 1);
+        assert_eq!(
+            guard.flags.names(),
+            vec![
+                "CF_INSTRUMENTED",
+                "CF_FUNCTION_TABLE_PRESENT",
+                "CF_LONGJUMP_TABLE_PRESENT",
+                "EH_CONTINUATION_TABLE_PRESENT"
+            ]
+        );
+
+        let rvas: Vec<u32> = guard.functions.iter().map(|entry| entry.rva).collect();
+        assert_eq!(rvas, vec![0x1000, 0x1010, 0x1020, 0x1030]);
+        assert!(guard.functions[1].is_suppressed());
+        assert!(guard.functions[2].is_export_suppressed());
+        assert!(guard.functions[3].is_xfg());
+        assert!(guard.is_valid_target(0x1000));
+        assert!(!guard.is_valid_target(0x1010));
+        assert!(!guard.is_valid_target(0x1004));
+        assert_eq!(guard.address_taken_iat_entries[0].rva, 0x3008);
+        assert_eq!(guard.long_jump_targets[0].rva, 0x1100);
+        assert_eq!(guard.eh_continuation_targets.len(), 2);
+
+        // a count running past the section is an error
+        put64(&mut rdata, 0x88, 0x10000);
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".rdata", 0x2000, rdata)
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x148)
+            .build();
+        assert!(Pe::from_bytes(raw).unwrap().guard_tables().is_err());
+    }
+
+    #[test]
+    fn test_arm64x() {
+        let base = 0x1_4000_0000u64;
+        let mut rdata = vec![0u8; 0x148];
+        put32(&mut rdata, 0, 0x148);
+        put64(&mut rdata, 0xc8, base + 0x2200);
+        // DVRT by section and offset, .rdata is section 1
+        put32(&mut rdata, 0xe0, 0x300);
+        rdata[0xe4..0xe6].copy_from_slice(&1u16.to_le_bytes());
+        rdata.resize(0x200, 0);
+
+        // metadata with two code ranges, one entry point range and one redirection
+        let mut metadata = vec![0u8; 0x50];
+        put32(&mut metadata, 0x00, 1);
+        put32(&mut metadata, 0x04, 0x2280);
+        put32(&mut metadata, 0x08, 2);
+        put32(&mut metadata, 0x0c, 0x2290);
+        put32(&mut metadata, 0x10, 0x22a0);
+        put32(&mut metadata, 0x2c, 0x2400);
+        put32(&mut metadata, 0x30, 1);
+        put32(&mut metadata, 0x34, 1);
+        rdata.extend_from_slice(&metadata);
+        rdata.resize(0x280, 0);
+        for value in [0x1001, 0x100, 0x1402, 0x80] {
+            push32(&mut rdata, value);
+        }
+        rdata.resize(0x290, 0);
+        for value in [0x1000, 0x1100, 0x1040] {
+            push32(&mut rdata, value);
+        }
+        rdata.resize(0x2a0, 0);
+        push32(&mut rdata, 0x1400);
+        push32(&mut rdata, 0x1020);
+
+        // ARM64X fixups to the headers: the machine, the entry point and the exception directory
+        // for the x64 view, and the debug directory cleared
+        let mut block = Vec::new();
+        push32(&mut block, 0);
+        push32(&mut block, 32);
+        for word in [
+            0x5044, 0x8664, 0x9068, 0x1800, 0x0000, 0x90e0, 0x5000, 0x0000,
+        ] {
+            push16(&mut block, word);
+        }
+        for word in [0x20e4, 4, 0xc0f8, 0] {
+            push16(&mut block, word);
+        }
+        rdata.resize(0x300, 0);
+        push32(&mut rdata, 1);
+        push32(&mut rdata, 12 + block.len() as u32);
+        push64(&mut rdata, 6);
+        push32(&mut rdata, block.len() as u32);
+        rdata.extend_from_slice(&block);
+
+        rdata.resize(0x400, 0);
+        push64(&mut rdata, base + 0x1400);
+        push64(&mut rdata, base + 0x1410);
+
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_ARM64X)
+            .section(".rdata", 0x2000, rdata)
+            .directory(DIR_EXCEPTION, 0x4000, 0x20)
+            .directory(DIR_DEBUG, 0x2800, 0x1c)
+            .directory(DIR_LOAD_CONFIG, 0x2000, 0x148)
+            .directory(12, 0x3000, 16)
+            .build();
+        let pe = Pe::from_bytes(raw).unwrap();
+
+        let chpe = pe.chpe().unwrap().unwrap();
+        assert_eq!(chpe.metadata.version, 1);
+        assert_eq!(chpe.code_ranges.len(), 2);
+        assert_eq!(chpe.code_kind(0x1080), Some(CodeKind::Arm64Ec));
+        assert_eq!(chpe.code_kind(0x1420), Some(CodeKind::X64));
+        assert_eq!(chpe.code_kind(0x1500), None);
+        let x64: Vec<u32> = chpe.x64_ranges().map(|range| range.start).collect();
+        assert_eq!(x64, vec![0x1400]);
+        assert_eq!(chpe.entry_points[0].entry_point, 0x1040);
+        assert_eq!(chpe.redirect(0x1400), 0x1020);
+        assert_eq!(chpe.redirect(0x1404), 0x1404);
+        assert_eq!(chpe.auxiliary_iat, vec![base + 0x1400, base + 0x1410]);
+
+        let table = pe.dynamic_relocations().unwrap().unwrap();
+        let fixups: Vec<_> = table.arm64x_fixups().collect();
+        assert_eq!(fixups.len(), 5);
+        assert_eq!(
+            fixups[0].kind,
+            Arm64XFixupKind::Value {
+                size: 2,
+                value: 0x8664
+            }
+        );
+        assert_eq!(fixups[3].kind, Arm64XFixupKind::Delta { delta: 16 });
+        assert_eq!(fixups[4].rva, 0xf8);
+        assert_eq!(fixups[4].kind, Arm64XFixupKind::ZeroFill { size: 8 });
+
+        let view = pe.arm64x_view().unwrap().unwrap();
+        assert_eq!(view.coff_header.machine, IMAGE_FILE_MACHINE_AMD64);
+        assert_eq!(view.optional_header.address_of_entry_point, 0x1800);
+        let directories = &view.optional_header.data_directories;
+        assert_eq!(directories.exception_table.virtual_addr, 0x5000);
+        assert_eq!(directories.exception_table.size, 0x30);
+        assert_eq!(directories.debug_table.virtual_addr, 0);
+        assert_eq!(directories.debug_table.size, 0);
+        // the native view is untouched
+        assert_eq!(pe.coff_header.machine, IMAGE_FILE_MACHINE_ARM64X);
+
+        // plain ARM64 images have neither
+        let raw = TestPe::new(IMAGE_FILE_MACHINE_ARM64).build();
+        let pe = Pe::from_bytes(raw).unwrap();
+        assert!(pe.chpe().unwrap().is_none());
+        assert!(pe.arm64x_view().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dynamic_relocations() {
+        let mut relocations = Vec::new();
+        // import control transfers, an indirect call through IAT slot 3 and a jump through slot 5
+        push64(&mut relocations, 3);
+        push32(&mut relocations, 16);
+        push32(&mut relocations, 0x1000);
+        push32(&mut relocations, 16);
+        push32(&mut relocations, (3 << 13) | 0x1000 | 0x010);
+        push32(&mut relocations, (5 << 13) | 0x020);
+        // indirect control transfers, with the block padded by a zero WORD
+        push64(&mut relocations, 4);
+        push32(&mut relocations, 16);
+        push32(&mut relocations, 0x1000);
+        push32(&mut relocations, 16);
+        for entry in [0x5040, 0x2050, 0x1060, 0] {
+            push16(&mut relocations, entry);
+        }
+        // a switch table branch through r9
+        push64(&mut relocations, 5);
+        push32(&mut relocations, 12);
+        push32(&mut relocations, 0x3000);
+        push32(&mut relocations, 12);
+        for entry in [0x9100, 0] {
+            push16(&mut relocations, entry);
+        }
+        // a function override patching two call sites and one DIR64, and its BDD
+        let mut function = Vec::new();
+        push32(&mut function, 0x4000);
+        push32(&mut function, 0);
+        push32(&mut function, 8);
+        push32(&mut function, 12);
+        push32(&mut function, 0x1100);
+        push32(&mut function, 0x1200);
+        function.extend_from_slice(&reloc_block(0x5000, &[0xa008, 0]));
+        let mut overrides = Vec::new();
+        push32(&mut overrides, function.len() as u32);
+        overrides.extend_from_slice(&function);
+        push32(&mut overrides, 1);
+        push32(&mut overrides, 8);
+        push16(&mut overrides, 1);
+        push16(&mut overrides, 2);
+        push32(&mut overrides, 0x1234);
+        push64(&mut relocations, 7);
+        push32(&mut relocations, overrides.len() as u32);
+        relocations.extend_from_slice(&overrides);
+        // a symbol that isn't known
+        push64(&mut relocations, 0x20);
+        push32(&mut relocations, 4);
+        push32(&mut relocations, 0xdead_beef);
+
+        let table = dvrt_pe(1, &relocations)
+            .dynamic_relocations()
+            .unwrap()
+            .unwrap();
+        assert_eq!(table.version, 1);
+        let names: Vec<&str> = table.relocations.iter().map(|r| r.name()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "GUARD_IMPORT_CONTROL_TRANSFER",
+                "GUARD_INDIR_CONTROL_TRANSFER",
+                "GUARD_SWITCHTABLE_BRANCH",
+                "FUNCTION_OVERRIDE",
+                "UNKNOWN"
+            ]
+        );
+        assert_eq!(
+            table.relocations[0].data,
+            DynamicRelocationData::ImportControlTransfer(vec![
+                ImportControlTransfer {
+                    rva: 0x1010,
+                    indirect_call: true,
+                    iat_index: 3
+                },
+                ImportControlTransfer {
+                    rva: 0x1020,
+                    indirect_call: false,
+                    iat_index: 5
+                },
+            ])
+        );
+        match &table.relocations[1].data {
+            DynamicRelocationData::IndirectControlTransfer(entries) => {
+                assert_eq!(entries.len(), 3);
+                assert_eq!(
+                    entries[0],
+                    IndirectControlTransfer {
+                        rva: 0x1040,
+                        indirect_call: true,
+                        rex_w_prefix: false,
+                        cfg_check: true
+                    }
+                );
+                assert!(entries[1].rex_w_prefix);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match &table.relocations[2].data {
+            DynamicRelocationData::SwitchTableBranch(entries) => {
+                assert_eq!(entries.len(), 1);
+                assert_eq!((entries[0].rva, entries[0].register), (0x3100, 9));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match &table.relocations[3].data {
+            DynamicRelocationData::FunctionOverride(overrides) => {
+                let function = &overrides.overrides[0];
+                assert_eq!(function.original_rva, 0x4000);
+                assert_eq!(function.rvas, vec![0x1100, 0x1200]);
+                assert_eq!(function.relocations.len(), 1);
+                assert_eq!(function.relocations[0].kind, RelocationType::Dir64);
+                let bdd = overrides.bdd(function).unwrap();
+                assert_eq!(bdd.version, 1);
+                assert_eq!(
+                    (bdd.nodes[0].left, bdd.nodes[0].right, bdd.nodes[0].value),
+                    (1, 2, 0x1234)
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            table.relocations[4].data,
+            DynamicRelocationData::Raw(vec![0xef, 0xbe, 0xad, 0xde])
+        );
+        assert_eq!(
+            table.targets(),
+            vec![0x1010, 0x1020, 0x1040, 0x1050, 0x1060, 0x1100, 0x1200, 0x3100, 0x5008]
+        );
+
+        // version 2 headers carry a group, flags and a symbol specific part
+        let mut relocations = Vec::new();
+        push32(&mut relocations, 28);
+        push32(&mut relocations, 12);
+        push64(&mut relocations, 3);
+        push32(&mut relocations, 2);
+        push32(&mut relocations, 1);
+        push32(&mut relocations, 0xaabb_ccdd);
+        push32(&mut relocations, 0x1000);
+        push32(&mut relocations, 12);
+        push32(&mut relocations, (7 << 13) | 0x030);
+        let table = dvrt_pe(2, &relocations)
+            .dynamic_relocations()
+            .unwrap()
+            .unwrap();
+        let relocation = &table.relocations[0];
+        assert_eq!((relocation.symbol_group, relocation.flags), (2, 1));
+        assert_eq!(relocation.header, vec![0xdd, 0xcc, 0xbb, 0xaa]);
+        assert_eq!(table.targets(), vec![0x1030]);
+
+        assert!(dvrt_pe(3, &[]).dynamic_relocations().is_err());
+        // a header size smaller than the fixed part
+        let mut relocations = Vec::new();
+        push32(&mut relocations, 4);
+        push32(&mut relocations, 0);
+        push64(&mut relocations, 3);
+        push32(&mut relocations, 0);
+        push32(&mut relocations, 0);
+        assert!(dvrt_pe(2, &relocations).dynamic_relocations().is_err());
+    }
+
+    #[test]
+    fn test_certificates() {
+        const SHA256: &str = "2.16.840.1.101.3.4.2.1";
+        const SHA1: &str = "1.3.14.3.2.26";
+
+        let leaf = der_certificate("Pepper Test Signer", "Pepper Test CA", 1);
+        let ca = der_certificate("Pepper Test CA", "Pepper Test CA", 2);
+        // "pepper" as a BMPString program name and a URL
+        let opus = der_seq(
+            0x30,
+            &[
+                der_seq(
+                    0xa0,
+                    &[der(
+                        0x80,
+                        &[0, b'p', 0, b'e', 0, b'p', 0, b'p', 0, b'e', 0, b'r'],
+                    )],
+                ),
+                der_seq(0xa1, &[der(0x80, b"https://example.com")]),
+            ],
+        );
+        let authenticated = [
+            der_attribute("1.2.840.113549.1.9.3", &[der_oid("1.3.6.1.4.1.311.2.1.4")]),
+            der_attribute("1.2.840.113549.1.9.4", &[der(0x04, &[0x11; 32])]),
+            der_attribute("1.3.6.1.4.1.311.2.1.12", &[opus]),
+        ];
+
+        let countersignature = der_signer(
+            "Pepper Test CA",
+            3,
+            SHA1,
+            &[der_attribute(
+                "1.2.840.113549.1.9.5",
+                &[der(0x17, b"230102030405Z")],
+            )],
+            &[],
+        );
+        let tst_info = der_seq(
+            0x30,
+            &[
+                der(0x02, &[1]),
+                der_oid("1.2.3.4"),
+                der_seq(0x30, &[der_algorithm(SHA256), der(0x04, &[0x22; 32])]),
+                der(0x02, &[0x42]),
+                der(0x18, b"20240506070809Z"),
+            ],
+        );
+        let rfc3161 = der_signed_data(
+            SHA256,
+            "1.2.840.113549.1.9.16.1.4",
+            der(0x04, &tst_info),
+            &[der_certificate("Pepper Test TSA", "Pepper Test CA", 4)],
+            der_signer("Pepper Test CA", 4, SHA256, &[], &[]),
+        );
+        let nested = der_signed_data(
+            SHA1,
+            "1.3.6.1.4.1.311.2.1.4",
+            spc_indirect_data(SHA1, &[0xbb; 20]),
+            std::slice::from_ref(&leaf),
+            der_signer("Pepper Test CA", 1, SHA1, &[], &[]),
+        );
+        let unauthenticated = [
+            der_attribute("1.2.840.113549.1.9.6", &[countersignature]),
+            der_attribute("1.3.6.1.4.1.311.3.3.1", &[rfc3161]),
+            der_attribute("1.3.6.1.4.1.311.2.4.1", &[nested]),
+        ];
+        let signature = der_signed_data(
+            SHA256,
+            "1.3.6.1.4.1.311.2.1.4",
+            spc_indirect_data(SHA256, &[0xaa; 32]),
+            &[leaf, ca],
+            der_signer(
+                "Pepper Test CA",
+                1,
+                SHA256,
+                &authenticated,
+                &unauthenticated,
+            ),
+        );
+
+        let mut table = win_certificate(0x0200, 2, &signature, 0);
+        table.extend(win_certificate(0x0100, 1, &[1, 2, 3], 0xcc));
+        let pe = Pe::from_bytes(signed_pe(&table)).unwrap();
+
+        let certificates = pe.certificates().unwrap();
+        assert_eq!(certificates.len(), 2);
+        assert_eq!(certificates[0].revision, 0x0200);
+        assert_eq!(
+            certificates[0].certificate_type,
+            CertificateType::PkcsSignedData
+        );
+        assert_eq!(certificates[0].data, signature);
+        assert_eq!(
+            certificates[1].offset,
+            certificates[0].offset + align(8 + signature.len() as u32, 8) as usize
+        );
+        assert_eq!(certificates[1].certificate_type, CertificateType::X509);
+        assert_eq!(certificates[1].padding, vec![0xcc; 5]);
+        assert!(certificates[1].signature().is_none());
+
+        let signatures = pe.signatures().unwrap();
+        assert_eq!(signatures.len(), 1);
+        let signature = &signatures[0];
+        assert_eq!(signature.data_type, "1.3.6.1.4.1.311.2.1.15");
+        assert_eq!(signature.digest_algorithm, SHA256);
+        assert_eq!(signature.digest, vec![0xaa; 32]);
+
+        let certs = signature.certificates();
+        assert_eq!(certs.len(), 2);
+        assert_eq!(certs[0].version, 3);
+        assert_eq!(certs[0].subject.to_string(), "CN=Pepper Test Signer");
+        assert_eq!(certs[0].issuer.common_name(), Some("Pepper Test CA"));
+        assert_eq!(certs[0].serial_hex(), "01");
+        assert_eq!(certs[0].not_before.to_string(), "2020-01-01 00:00:00 UTC");
+        assert_eq!(certs[0].not_after.to_string(), "2039-12-31 23:59:59 UTC");
+        assert!(!certs[0].is_self_issued());
+        assert!(certs[1].is_self_issued());
+
+        let signer = signature.signer();
+        match &signer.id {
+            SignerId::IssuerAndSerial { issuer, serial } => {
+                assert_eq!(issuer.common_name(), Some("Pepper Test CA"));
+                assert_eq!(serial, &vec![1]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(signature.signing_certificate(), Some(&certs[0]));
+        assert_eq!(signer.message_digest(), Some(vec![0x11; 32]));
+        assert_eq!(
+            signer.content_type().as_deref(),
+            Some("1.3.6.1.4.1.311.2.1.4")
+        );
+        assert_eq!(
+            signer.opus_info(),
+            (
+                Some("pepper".to_string()),
+                Some("https://example.com".to_string())
+            )
+        );
+        assert_eq!(
+            signer.authenticated_attributes_der.as_ref().unwrap()[0],
+            0x31
+        );
+
+        assert_eq!(signature.timestamps.len(), 2);
+        match &signature.timestamps[0] {
+            Timestamp::Countersignature(signer) => assert_eq!(signer.digest_algorithm, SHA1),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            signature.timestamps[0].time().unwrap().to_string(),
+            "2023-01-02 03:04:05 UTC"
+        );
+        match &signature.timestamps[1] {
+            Timestamp::Rfc3161 { signed_data, info } => {
+                assert_eq!(info.policy, "1.2.3.4");
+                assert_eq!(info.hashed_message, vec![0x22; 32]);
+                assert_eq!(info.serial, vec![0x42]);
+                assert_eq!(
+                    signed_data.certificates[0].subject.common_name(),
+                    Some("Pepper Test TSA")
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            signature.timestamps[1].time().unwrap().to_string(),
+            "2024-05-06 07:08:09 UTC"
+        );
+
+        assert_eq!(signature.nested.len(), 1);
+        assert_eq!(signature.nested[0].digest_algorithm, SHA1);
+        assert_eq!(signature.nested[0].digest, vec![0xbb; 20]);
+        assert_eq!(signature.all().len(), 2);
+
+        // unauthenticated attributes that don't decode are kept aside rather than failing it
+        let garbage = der(0x04, b"not a timestamp");
+        let not_tst_info = der_signed_data(
+            SHA256,
+            "1.2.840.113549.1.7.1",
+            der(0x04, &tst_info),
+            &[],
+            der_signer("Pepper Test CA", 4, SHA256, &[], &[]),
+        );
+        let unauthenticated = [
+            der_attribute("1.2.840.113549.1.9.6", std::slice::from_ref(&garbage)),
+            der_attribute("1.3.6.1.4.1.311.3.3.1", &[not_tst_info]),
+            der_attribute("1.3.6.1.4.1.311.2.4.1", std::slice::from_ref(&garbage)),
+        ];
+        let signature = der_signed_data(
+            SHA256,
+            "1.3.6.1.4.1.311.2.1.4",
+            spc_indirect_data(SHA256, &[0xaa; 32]),
+            &[],
+            der_signer(
+                "Pepper Test CA",
+                1,
+                SHA256,
+                &authenticated,
+                &unauthenticated,
+            ),
+        );
+        let table = win_certificate(0x0200, 2, &signature, 0);
+        let signatures = Pe::from_bytes(signed_pe(&table))
+            .unwrap()
+            .signatures()
+            .unwrap();
+        match signatures[0].timestamps.as_slice() {
+            [Timestamp::Undecodable(countersignature), Timestamp::Undecodable(rfc3161)] => {
+                assert_eq!(countersignature.oid, "1.2.840.113549.1.9.6");
+                assert_eq!(countersignature.value, garbage);
+                assert!(rfc3161.reason.contains("isn't TSTInfo"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(signatures[0].timestamps[0].time().is_none());
+        assert!(signatures[0].nested.is_empty());
+        assert_eq!(signatures[0].bad_nested.len(), 1);
+        assert_eq!(signatures[0].bad_nested[0].value, garbage);
+
+        // dwLength smaller than the header
+        let mut table = win_certificate(0x0200, 2, &[], 0);
+        put32(&mut table, 0, 4);
+        assert!(Pe::from_bytes(signed_pe(&table))
+            .unwrap()
+            .certificates()
+            .is_err());
+        // a PKCS entry that isn't Authenticode
+        let table = win_certificate(0x0200, 2, &der(0x30, &[]), 0);
+        assert!(Pe::from_bytes(signed_pe(&table))
+            .unwrap()
+            .signatures()
+            .is_err());
+    }
+
+    #[test]
+    fn test_md5_sha1() {
+        assert_eq!(to_hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(to_hex(&md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            to_hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            to_hex(&sha1(b"")),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+
+        let text = sample_text();
+        assert_eq!(to_hex(&md5(&text)), "1191995d4d06b97002d577d1c447df8b");
+        let mut hasher = Sha1::new();
+        for chunk in text.chunks(37) {
+            hasher.update(chunk);
+        }
+        assert_eq!(
+            to_hex(&hasher.finalize()),
+            "42ec1fd90cd2c03b4f4295cbfc357eab5c836601"
+        );
+        assert_eq!(
+            DigestAlgorithm::from_oid("1.3.14.3.2.26"),
+            Some(DigestAlgorithm::Sha1)
+        );
+        assert_eq!(DigestAlgorithm::Sha384.digest(b"").len(), 48);
+    }
+
+    #[test]
+    fn test_authenticode_digest() {
+        let pe = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0xc3; 0x300])
+            .section(".data", 0x2000, vec![0x11; 0x200]);
+        let mut raw = pe.build();
+        // list .data before .text in the section table, the digest still follows the file order
+        let headers = pe.optional_header_offset() + 0xf0;
+        let text: Vec<u8> = raw[headers..headers + 40].to_vec();
+        raw.copy_within(headers + 40..headers + 80, headers);
+        raw[headers + 40..headers + 80].copy_from_slice(&text);
+        let sections_end = raw.len();
+        raw.extend_from_slice(b"overlay before the table");
+        let table_offset = raw.len();
+        let table = win_certificate(0x0200, 2, &[0x30, 0x00], 0);
+        raw.extend_from_slice(&table);
+        raw.extend_from_slice(b"after the table");
+
+        let checksum = pe.optional_header_offset() + 64;
+        let security = pe.optional_header_offset() + 0x70 + 8 * DIR_SECURITY;
+        put32(&mut raw, checksum, 0x1234_5678);
+        put32(&mut raw, security, table_offset as u32);
+        put32(&mut raw, security + 4, table.len() as u32);
+
+        let parsed = Pe::from_bytes(raw.clone()).unwrap();
+        assert_eq!(
+            parsed.authenticode_ranges().unwrap(),
+            vec![
+                0..checksum,
+                checksum + 4..security,
+                security + 8..0x200,
+                0x200..0x600,
+                0x600..0x800,
+                sections_end..table_offset,
+                table_offset + table.len()..raw.len(),
+            ]
+        );
+
+        let mut hashed = Vec::new();
+        hashed.extend_from_slice(&raw[..checksum]);
+        hashed.extend_from_slice(&raw[checksum + 4..security]);
+        hashed.extend_from_slice(&raw[security + 8..table_offset]);
+        hashed.extend_from_slice(&raw[table_offset + table.len()..]);
+        for algorithm in [
+            DigestAlgorithm::Md5,
+            DigestAlgorithm::Sha1,
+            DigestAlgorithm::Sha256,
+            DigestAlgorithm::Sha384,
+            DigestAlgorithm::Sha512,
+        ] {
+            assert_eq!(
+                parsed.authenticode_digest(algorithm).unwrap(),
+                algorithm.digest(&hashed)
+            );
+        }
+        let digest = parsed.authenticode_digest(DigestAlgorithm::Sha256).unwrap();
+
+        // the checksum and the table's contents aren't covered
+        let mut changed = raw.clone();
+        put32(&mut changed, checksum, 0);
+        changed[table_offset + 9] = 0xff;
+        let changed = Pe::from_bytes(changed).unwrap();
+        assert_eq!(
+            changed
+                .authenticode_digest(DigestAlgorithm::Sha256)
+                .unwrap(),
+            digest
+        );
+        // section data and the overlay are
+        for offset in [0x210, sections_end + 1] {
+            let mut changed = raw.clone();
+            changed[offset] ^= 1;
+            let changed = Pe::from_bytes(changed).unwrap();
+            assert_ne!(
+                changed
+                    .authenticode_digest(DigestAlgorithm::Sha256)
+                    .unwrap(),
+                digest
+            );
+        }
+
+        // a table pointing into a section
+        let mut overlapping = raw.clone();
+        put32(&mut overlapping, security, 0x300);
+        let overlapping = Pe::from_bytes(overlapping).unwrap();
+        assert!(overlapping.authenticode_ranges().is_err());
+    }
+
+    #[test]
+    fn test_ecdsa() {
+        let hex = |value: &str| BigUint::from_hex(value).unwrap().to_bytes_be(0);
+        let vectors = [
+            (
+                Curve::P256,
+                DigestAlgorithm::Sha256,
+                concat!(
+                    "044bbbefdf1fe1929441716999f3600a5f0e1ae240b2cb0e1d35cf9036d8ec188985b2c3e7762ee5",
+                    "5a60ec22a1db19358c306b636d8741148243e101f29a35868c"
+                ),
+                concat!(
+                    "3045022100d26765e7cf2f321dcd0ab9fb21994fe5e022c29ff14462f48754bb39fc88e47e022024",
+                    "028b121808ca5276a33d59f6924c5495328390447b73337258755368841fdc"
+                ),
+            ),
+            // the SHA-512 digest is cut down to 256 bits
+            (
+                Curve::P256,
+                DigestAlgorithm::Sha512,
+                concat!(
+                    "0421ed6c5239de3a906095651fd327a43b30a8c641a4d9ba6df580400aa0dca85f32cdc7345652b2",
+                    "f9f8825414e9ca3f8c931f267cd8c1a486e600556786e900da"
+                ),
+                concat!(
+                    "3046022100fe049aaa6a7cfb9f4323c305f0254b3e87dc051015a16d9f1513fd61def924570221",
+                    "008dc998f82401c0fb3409e8fe57e2986314ffe9acbb7c5deb521c9fd8012805d8"
+                ),
+            ),
+            (
+                Curve::P384,
+                DigestAlgorithm::Sha384,
+                concat!(
+                    "047ccb3023b1051c012bdabf7da136a220d95fbf03125ba63fcef1c8d4b212930e033d64ecb3e0f9",
+                    "216e9cb47735bc05b0d20c8ecf9a09bcb5e8de4fbd4c2ff9c0daf716ddb4a5b0c05ee64a7bf32fdd",
+                    "77069dcaca8978c7308ce467821d807e81"
+                ),
+                concat!(
+                    "30640230501512f99e882caf2ed33e692223f7ab529f32e68382b82d3a3c83b3d3c7090a92d38176",
+                    "ba338c1a0d35d22b74e8ee1b0230643628151633a363ba64dcaa0431a53eca3e71297d0917ebc114",
+                    "2827e20aa57e6f9d989bd969e6c3d5f999c18725766d"
+                ),
+            ),
+        ];
+        for (curve, algorithm, point, signature) in vectors {
+            let key = EcPublicKey::new(curve, &hex(point)).unwrap();
+            let signature = hex(signature);
+            assert!(key.verify(&algorithm.digest(b"sample"), &signature));
+            assert!(!key.verify(&algorithm.digest(b"sampl"), &signature));
+            let mut tampered = signature.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(!key.verify(&algorithm.digest(b"sample"), &tampered));
+        }
+
+        // points off the curve and compressed points are refused
+        let mut point = hex(vectors[0].2);
+        *point.last_mut().unwrap() ^= 1;
+        assert!(EcPublicKey::new(Curve::P256, &point).is_err());
+        point[0] = 0x02;
+        assert!(EcPublicKey::new(Curve::P256, &point[..33]).is_err());
+
+        let key = RsaPublicKey::new(&rsa_public_key(TEST_LEAF_KEY)).unwrap();
+        assert_eq!(key.size(), 128);
+        let digest = sha256(b"sample");
+        let signature = rsa_sign(TEST_LEAF_KEY, b"sample");
+        assert!(key.verify(DigestAlgorithm::Sha256, &digest, &signature));
+        assert!(!key.verify(DigestAlgorithm::Sha1, &digest, &signature));
+        assert!(!key.verify(
+            DigestAlgorithm::Sha256,
+            &digest,
+            &rsa_sign(TEST_ROOT_KEY, b"sample")
+        ));
+
+        // the encoded message has to be byte for byte what a signer produces, with or without the
+        // NULL parameters
+        let sign_digest_info = |digest_info: Vec<u8>| {
+            let n = BigUint::from_hex(TEST_LEAF_KEY.0).unwrap();
+            let d = BigUint::from_hex(TEST_LEAF_KEY.1).unwrap();
+            let mut encoded = vec![0x00, 0x01];
+            encoded.resize(128 - digest_info.len() - 1, 0xff);
+            encoded.push(0x00);
+            encoded.extend_from_slice(&digest_info);
+            BigUint::from_bytes_be(&encoded)
+                .pow_mod(&d, &n)
+                .to_bytes_be(128)
+        };
+        let sha256_oid = der_oid("2.16.840.1.101.3.4.2.1");
+        let no_null = der_seq(
+            0x30,
+            &[
+                der_seq(0x30, std::slice::from_ref(&sha256_oid)),
+                der(0x04, &digest),
+            ],
+        );
+        assert!(key.verify(DigestAlgorithm::Sha256, &digest, &sign_digest_info(no_null)));
+        let garbage_parameters = der_seq(
+            0x30,
+            &[
+                der_seq(0x30, &[sha256_oid.clone(), der(0x04, &[0xaa; 8])]),
+                der(0x04, &digest),
+            ],
+        );
+        assert!(!key.verify(
+            DigestAlgorithm::Sha256,
+            &digest,
+            &sign_digest_info(garbage_parameters)
+        ));
+        let mut long_length = der_algorithm("2.16.840.1.101.3.4.2.1");
+        long_length.extend([0x04, 0x81, 0x20]);
+        long_length.extend_from_slice(&digest);
+        let long_length = der_seq(0x30, &[long_length]);
+        assert!(!key.verify(
+            DigestAlgorithm::Sha256,
+            &digest,
+            &sign_digest_info(long_length)
+        ));
+
+        // tiny moduli are refused before doing any arithmetic
+        let tiny = RsaPublicKey::new(&der_seq(0x30, &[der_unsigned(&[0x0d]), der_unsigned(&[3])]))
+            .unwrap();
+        assert!(!tiny.verify(DigestAlgorithm::Sha256, &digest, &[0x01]));
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        const SHA256: &str = "2.16.840.1.101.3.4.2.1";
+        const ROOT: &str = "Pepper Test Root";
+        const SIGNER: &str = "Pepper Test Signer";
+
+        let root = signed_certificate(
+            ROOT,
+            ROOT,
+            1,
+            TEST_ROOT_KEY,
+            TEST_ROOT_KEY,
+            (b"200101000000Z", b"391231235959Z"),
+        );
+        // expired, so only a timestamp keeps the signature valid
+        let leaf = signed_certificate(
+            SIGNER,
+            ROOT,
+            2,
+            TEST_LEAF_KEY,
+            TEST_ROOT_KEY,
+            (b"210101000000Z", b"211231235959Z"),
+        );
+        let roots = [Certificate::new(&Tlv::parse(&root).unwrap()).unwrap()];
+        let signed_at = Utc.with_ymd_and_hms(2021, 6, 1, 12, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+
+        let image = signed_pe(&[]);
+        let digest = Pe::from_bytes(image)
+            .unwrap()
+            .authenticode_digest(DigestAlgorithm::Sha256)
+            .unwrap();
+        let content = spc_indirect_data(SHA256, &digest);
+        let authenticated = [
+            der_attribute("1.2.840.113549.1.9.3", &[der_oid("1.3.6.1.4.1.311.2.1.4")]),
+            der_attribute(
+                "1.2.840.113549.1.9.4",
+                &[der(0x04, &sha256(Tlv::parse(&content).unwrap().content))],
+            ),
+        ];
+        let encrypted_digest = rsa_sign(TEST_LEAF_KEY, &der_seq(0x31, &authenticated));
+        // countersignature by the root over countersigned
+        let countersign = |countersigned: &[u8]| {
+            der_attribute(
+                "1.2.840.113549.1.9.6",
+                &[signed_signer(
+                    ROOT,
+                    1,
+                    TEST_ROOT_KEY,
+                    &[
+                        der_attribute("1.2.840.113549.1.9.3", &[der_oid("1.2.840.113549.1.7.1")]),
+                        der_attribute("1.2.840.113549.1.9.5", &[der(0x17, b"210601120000Z")]),
+                        der_attribute("1.2.840.113549.1.9.4", &[der(0x04, &sha256(countersigned))]),
+                    ],
+                    &[],
+                )],
+            )
+        };
+        let countersignature = countersign(&encrypted_digest);
+        let signature = |certificates: &[Vec<u8>], key, unauthenticated: &[Vec<u8>]| {
+            let signer = signed_signer(ROOT, 2, key, &authenticated, unauthenticated);
+            let signed_data = der_signed_data(
+                SHA256,
+                "1.3.6.1.4.1.311.2.1.4",
+                content.clone(),
+                certificates,
+                signer,
+            );
+            Pe::from_bytes(signed_pe(&win_certificate(0x0200, 2, &signed_data, 0))).unwrap()
+        };
+        let problems = |pe: &Pe, roots: &[Certificate], time| {
+            let verdicts = pe.verify_signatures_at(roots, time).unwrap();
+            assert_eq!(verdicts.len(), 1);
+            verdicts[0].problems.clone()
+        };
+
+        // timestamped, the leaf is checked at the signing time
+        let pe = signature(
+            &[leaf.clone(), root.clone()],
+            TEST_LEAF_KEY,
+            &[countersignature],
+        );
+        let verdict = &pe.verify_signatures_at(&roots, now).unwrap()[0];
+        assert!(verdict.is_valid(), "{:?}", verdict.problems);
+        assert_eq!(verdict.digest_algorithm, Some(DigestAlgorithm::Sha256));
+        assert_eq!(verdict.image_digest, digest);
+        assert_eq!(
+            verdict.signer.as_ref().unwrap().subject.to_string(),
+            "CN=Pepper Test Signer"
+        );
+        let chain: Vec<String> = verdict
+            .chain
+            .iter()
+            .map(|cert| cert.subject.to_string())
+            .collect();
+        assert_eq!(chain, ["CN=Pepper Test Signer", "CN=Pepper Test Root"]);
+        assert_eq!(verdict.timestamp, Some(signed_at));
+        assert_eq!(verdict.checked_at, signed_at);
+        assert!(verdict.warnings.is_empty());
+        assert!(verdict.nested.is_empty());
+
+        // without the timestamp the leaf has expired by now, but not back then
+        let untimestamped = signature(&[leaf.clone(), root.clone()], TEST_LEAF_KEY, &[]);
+        assert_eq!(
+            problems(&untimestamped, &roots, now),
+            [VerifyProblem::CertificateNotValid {
+                subject: "CN=Pepper Test Signer".to_string()
+            }]
+        );
+        assert!(problems(&untimestamped, &roots, signed_at).is_empty());
+
+        // the root isn't trusted, or isn't there at all
+        assert_eq!(
+            problems(&untimestamped, &[], signed_at),
+            [VerifyProblem::UntrustedRoot]
+        );
+        let leaf_only = signature(std::slice::from_ref(&leaf), TEST_LEAF_KEY, &[]);
+        assert_eq!(
+            problems(&leaf_only, &[], signed_at),
+            [VerifyProblem::IncompleteChain]
+        );
+        assert!(problems(&leaf_only, &roots, signed_at).is_empty());
+
+        // signed with the wrong key, and a leaf the root didn't sign
+        let wrong_key = signature(std::slice::from_ref(&leaf), TEST_ROOT_KEY, &[]);
+        assert_eq!(
+            problems(&wrong_key, &roots, signed_at),
+            [VerifyProblem::BadSignature]
+        );
+        let self_signed = signed_certificate(
+            SIGNER,
+            ROOT,
+            2,
+            TEST_LEAF_KEY,
+            TEST_LEAF_KEY,
+            (b"210101000000Z", b"211231235959Z"),
+        );
+        assert_eq!(
+            problems(
+                &signature(&[self_signed], TEST_LEAF_KEY, &[]),
+                &roots,
+                signed_at
+            ),
+            [VerifyProblem::BadCertificateSignature {
+                subject: "CN=Pepper Test Signer".to_string()
+            }]
+        );
+
+        // a patched image no longer matches
+        let mut raw = signed_pe(&win_certificate(
+            0x0200,
+            2,
+            &der_signed_data(
+                SHA256,
+                "1.3.6.1.4.1.311.2.1.4",
+                content.clone(),
+                std::slice::from_ref(&leaf),
+                signed_signer(ROOT, 2, TEST_LEAF_KEY, &authenticated, &[]),
+            ),
+            0,
+        ));
+        raw[0x200] ^= 1;
+        let patched = Pe::from_bytes(raw).unwrap();
+        assert_eq!(
+            problems(&patched, &roots, signed_at),
+            [VerifyProblem::ImageDigestMismatch]
+        );
+
+        // a timestamp over some other signature is only a problem when the signature needed it,
+        // otherwise it's a warning
+        let forged = signature(
+            &[leaf.clone(), root.clone()],
+            TEST_LEAF_KEY,
+            &[countersign(b"some other signature")],
+        );
+        let expired = problems(&forged, &roots, now);
+        assert_eq!(expired.len(), 2);
+        assert!(matches!(
+            expired[0],
+            VerifyProblem::CertificateNotValid { .. }
+        ));
+        assert!(matches!(expired[1], VerifyProblem::BadTimestamp(_)));
+        let verdict = &forged.verify_signatures_at(&roots, signed_at).unwrap()[0];
+        assert!(verdict.is_valid(), "{:?}", verdict.problems);
+        assert_eq!(verdict.timestamp, None);
+        assert!(matches!(
+            verdict.warnings.as_slice(),
+            [VerifyProblem::BadTimestamp(_)]
+        ));
+
+        // an RFC 3161 attribute or nested signature that isn't even DER still gets a verdict
+        let garbage = signature(
+            &[leaf.clone(), root.clone()],
+            TEST_LEAF_KEY,
+            &[
+                der_attribute("1.3.6.1.4.1.311.3.3.1", &[der(0x04, b"garbage")]),
+                der_attribute("1.3.6.1.4.1.311.2.4.1", &[der(0x30, &[0xff; 3])]),
+            ],
+        );
+        let verdict = &garbage.verify_signatures_at(&roots, signed_at).unwrap()[0];
+        assert!(verdict.is_valid(), "{:?}", verdict.problems);
+        assert!(matches!(
+            verdict.warnings.as_slice(),
+            [
+                VerifyProblem::BadTimestamp(_),
+                VerifyProblem::BadNestedSignature(_)
+            ]
+        ));
+        let expired = problems(&garbage, &roots, now);
+        assert!(matches!(
+            expired.as_slice(),
+            [
+                VerifyProblem::CertificateNotValid { .. },
+                VerifyProblem::BadTimestamp(_)
+            ]
+        ));
+
+        // timestamps have to come from a certificate allowed to make them
+        let tsa = signed_certificate_with(
+            "Pepper Test TSA",
+            ROOT,
+            3,
+            TEST_ROOT_KEY,
+            TEST_ROOT_KEY,
+            (b"200101000000Z", b"391231235959Z"),
+            &[der_extended_key_usage(&["1.3.6.1.5.5.7.3.3"])],
+        );
+        let countersignature = der_attribute(
+            "1.2.840.113549.1.9.6",
+            &[signed_signer(
+                ROOT,
+                3,
+                TEST_ROOT_KEY,
+                &[
+                    der_attribute("1.2.840.113549.1.9.3", &[der_oid("1.2.840.113549.1.7.1")]),
+                    der_attribute("1.2.840.113549.1.9.5", &[der(0x17, b"210601120000Z")]),
+                    der_attribute(
+                        "1.2.840.113549.1.9.4",
+                        &[der(0x04, &sha256(&encrypted_digest))],
+                    ),
+                ],
+                &[],
+            )],
+        );
+        let pe = signature(
+            &[leaf.clone(), root.clone(), tsa],
+            TEST_LEAF_KEY,
+            &[countersignature],
+        );
+        let verdict = &pe.verify_signatures_at(&roots, now).unwrap()[0];
+        assert_eq!(verdict.timestamp, None);
+        assert!(verdict.problems.contains(&VerifyProblem::BadTimestamp(
+            "CN=Pepper Test TSA isn't allowed for timeStamping".to_string()
+        )));
+    }
+
+    // a certificate under a supplied root only issues signers if it's a CA allowed to, and the
+    // signer has to be allowed to sign code
+    #[test]
+    fn test_verify_chain_constraints() {
+        const ROOT: &str = "Pepper Test Root";
+        const ISSUER: &str = "Pepper Test Issuer";
+        const SIGNER: &str = "Pepper Test Signer";
+        const CODE_SIGNING: &str = "1.3.6.1.5.5.7.3.3";
+        const SERVER_AUTH: &str = "1.3.6.1.5.5.7.3.1";
+        let validity: (&[u8], &[u8]) = (b"200101000000Z", b"391231235959Z");
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        let parse = |raw: &[u8]| Certificate::new(&Tlv::parse(raw).unwrap()).unwrap();
+        let key = RsaPrivateKey::from_pkcs8(
+            &BigUint::from_hex(TEST_LEAF_KEY_PKCS8)
+                .unwrap()
+                .to_bytes_be(0),
+        )
+        .unwrap();
+        let pe = Pe::from_bytes(signed_pe(&[])).unwrap();
+
+        // root -> issuer -> signer, the issuer and signer sharing a key
+        let problems = |root: &[Vec<u8>], issuer: &[Vec<u8>], signer: &[Vec<u8>]| {
+            let root = parse(&signed_certificate_with(
+                ROOT,
+                ROOT,
+                1,
+                TEST_ROOT_KEY,
+                TEST_ROOT_KEY,
+                validity,
+                root,
+            ));
+            let issuer = signed_certificate_with(
+                ISSUER,
+                ROOT,
+                2,
+                TEST_LEAF_KEY,
+                TEST_ROOT_KEY,
+                validity,
+                issuer,
+            );
+            let signer = signed_certificate_with(
+                SIGNER,
+                ISSUER,
+                3,
+                TEST_LEAF_KEY,
+                TEST_LEAF_KEY,
+                validity,
+                signer,
+            );
+            let chain = [parse(&signer), parse(&issuer)];
+            let signed = pe.sign(&key, &chain, DigestAlgorithm::Sha256).unwrap();
+            let verdicts = Pe::from_bytes(signed)
+                .unwrap()
+                .verify_signatures_at(std::slice::from_ref(&root), now)
+                .unwrap();
+            verdicts[0].problems.clone()
+        };
+        let code_signing = der_extended_key_usage(&[CODE_SIGNING]);
+
+        assert!(problems(&[], &[der_ca(None)], std::slice::from_ref(&code_signing)).is_empty());
+        assert!(problems(
+            &[der_ca(Some(1))],
+            &[der_ca(Some(0)), der_key_usage(0x06)],
+            &[der_key_usage(0x80)]
+        )
+        .is_empty());
+
+        // a TLS server certificate from the same root issuing a "signer"
+        assert_eq!(
+            problems(
+                &[],
+                &[der_extended_key_usage(&[SERVER_AUTH])],
+                std::slice::from_ref(&code_signing)
+            ),
+            [VerifyProblem::NotCa {
+                subject: "CN=Pepper Test Issuer".to_string()
+            }]
+        );
+        let not_ca = der_extension("2.5.29.19", true, der_seq(0x30, &[]));
+        assert_eq!(
+            problems(&[], &[not_ca], &[]),
+            [VerifyProblem::NotCa {
+                subject: "CN=Pepper Test Issuer".to_string()
+            }]
+        );
+        // a CA whose key isn't for signing certificates, or one too far from its root
+        assert_eq!(
+            problems(&[], &[der_ca(None), der_key_usage(0x80)], &[]),
+            [VerifyProblem::UsageNotAllowed {
+                subject: "CN=Pepper Test Issuer".to_string(),
+                usage: "keyCertSign".to_string()
+            }]
+        );
+        assert_eq!(
+            problems(&[der_ca(Some(0))], &[der_ca(None)], &[]),
+            [VerifyProblem::PathTooLong {
+                subject: "CN=Pepper Test Root".to_string()
+            }]
+        );
+
+        // signers for something other than code
+        assert_eq!(
+            problems(
+                &[],
+                &[der_ca(None)],
+                &[der_extended_key_usage(&[SERVER_AUTH])]
+            ),
+            [VerifyProblem::UsageNotAllowed {
+                subject: "CN=Pepper Test Signer".to_string(),
+                usage: "codeSigning".to_string()
+            }]
+        );
+        assert_eq!(
+            problems(&[], &[der_ca(None)], &[der_key_usage(0x04)]),
+            [VerifyProblem::UsageNotAllowed {
+                subject: "CN=Pepper Test Signer".to_string(),
+                usage: "digitalSignature".to_string()
+            }]
+        );
+
+        // critical extensions nobody here understands
+        let unknown = der_extension("1.2.3.4", true, der(0x05, &[]));
+        assert_eq!(
+            problems(&[], &[der_ca(None)], std::slice::from_ref(&unknown)),
+            [VerifyProblem::UnknownCriticalExtension {
+                subject: "CN=Pepper Test Signer".to_string(),
+                oid: "1.2.3.4".to_string()
+            }]
+        );
+        let ignorable = der_extension("1.2.3.4", false, der(0x05, &[]));
+        assert!(problems(&[unknown], &[der_ca(None)], &[ignorable]).is_empty());
+    }
+
+    #[test]
+    fn test_page_hashes() {
+        const SHA256: &str = "2.16.840.1.101.3.4.2.1";
+
+        let pe = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0xc3; 0x1100])
+            .section(".data", 0x3000, vec![0x11; 0x200]);
+        let raw = pe.build();
+        let parsed = Pe::from_bytes(raw.clone()).unwrap();
+
+        // headers hashed like the image digest, then each 4K of section data, zero padded
+        let pages = parsed.page_hashes(DigestAlgorithm::Sha256).unwrap();
+        let offsets: Vec<u32> = pages.iter().map(|page| page.offset).collect();
+        assert_eq!(offsets, [0, 0x200, 0x1200, 0x1400, 0x1600]);
+        let checksum = pe.optional_header_offset() + 64;
+        let security = pe.optional_header_offset() + 0x70 + 8 * DIR_SECURITY;
+        let mut headers = Vec::new();
+        headers.extend_from_slice(&raw[..checksum]);
+        headers.extend_from_slice(&raw[checksum + 4..security]);
+        headers.extend_from_slice(&raw[security + 8..0x200]);
+        // a page less SizeOfHeaders of padding, not up to a full page
+        headers.resize(headers.len() + 0x1000 - 0x200, 0);
+        assert_eq!(headers.len(), 0x1000 - 12);
+        assert_eq!(pages[0].hash, sha256(&headers));
+        assert_eq!(pages[1].hash, sha256(&raw[0x200..0x1200]));
+        let mut last = raw[0x1200..0x1400].to_vec();
+        last.resize(0x1000, 0);
+        assert_eq!(pages[2].hash, sha256(&last));
+        assert_eq!(pages[4].hash, vec![0; 32]);
+
+        // known answers, hashed outside this crate following osslsigncode's pe_page_hash_calc
+        let known = [
+            "52b6d2212483ee5c1fe1349ce7fc5e180ceed1d4dd718bf95073c2decee9f729",
+            "ea391c76e44008904552280ae510eac0f37a53df7728b12cfa80d0f10b8ddb90",
+            "74ae446a81c268d9dddfa11201f2e74d39d6f8da0c258a5109dfceda457c8b2d",
+            "06385410b681795c404bca12950ebbfa7fe475c1579a031a54c329a92666d4fd",
+        ];
+        for (page, known) in pages.iter().zip(known) {
+            assert_eq!(to_hex(&page.hash), known);
+        }
+
+        let table = |pages: &[PageHash]| -> Vec<u8> {
+            let mut table = Vec::new();
+            for page in pages {
+                push32(&mut table, page.offset);
+                table.extend_from_slice(&page.hash);
+            }
+            table
+        };
+        let sign = |raw: &[u8], image_data: Vec<u8>| {
+            let signed_data = der_signed_data(
+                SHA256,
+                "1.3.6.1.4.1.311.2.1.4",
+                spc_indirect_data_for(image_data, SHA256, &[0x11; 32]),
+                &[],
+                der_signer("Pepper Test CA", 1, SHA256, &[], &[]),
+            );
+            let table = win_certificate(0x0200, 2, &signed_data, 0);
+            Pe::from_bytes(append_certificate_table(&pe, raw.to_vec(), &table)).unwrap()
+        };
+        let image_data =
+            spc_pe_image_data_with_page_hashes("1.3.6.1.4.1.311.2.3.2", &table(&pages));
+
+        let signed = sign(&raw, image_data.clone());
+        let hashes = signed.signatures().unwrap()[0]
+            .page_hashes()
+            .unwrap()
+            .unwrap();
+        assert_eq!(hashes.algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(hashes.pages, pages);
+        let reports = signed.verify_page_hashes().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].is_valid());
+        assert_eq!(reports[0].pages, 5);
+
+        // a patched DOS stub and second page of .text
+        let mut patched = raw.clone();
+        patched[0x50] ^= 1;
+        patched[0x1300] ^= 1;
+        let report = &sign(&patched, image_data).verify_page_hashes().unwrap()[0];
+        let mismatches: Vec<(u32, Option<&str>)> = report
+            .mismatches
+            .iter()
+            .map(|mismatch| (mismatch.offset, mismatch.section.as_deref()))
+            .collect();
+        assert_eq!(mismatches, [(0, None), (0x1200, Some(".text"))]);
+        assert_eq!(report.mismatches[1].signed.as_ref(), Some(&pages[2].hash));
+        assert_ne!(report.mismatches[1].actual, report.mismatches[1].signed);
+
+        // SHA-1 tables, and signatures without page hashes
+        let sha1_pages = parsed.page_hashes(DigestAlgorithm::Sha1).unwrap();
+        assert_eq!(sha1_pages[1].hash, sha1(&raw[0x200..0x1200]).to_vec());
+        let signed = sign(
+            &raw,
+            spc_pe_image_data_with_page_hashes("1.3.6.1.4.1.311.2.3.1", &table(&sha1_pages)),
+        );
+        let reports = signed.verify_page_hashes().unwrap();
+        assert_eq!(reports[0].algorithm, DigestAlgorithm::Sha1);
+        assert!(reports[0].is_valid());
+        let unhashed = sign(&raw, der_seq(0x30, &[der(0x03, &[0])]));
+        assert_eq!(
+            unhashed.signatures().unwrap()[0].page_hashes().unwrap(),
+            None
+        );
+        assert!(unhashed.verify_page_hashes().unwrap().is_empty());
+
+        // a table that isn't whole entries
+        let mut truncated = table(&pages);
+        truncated.pop();
+        let signed = sign(
+            &raw,
+            spc_pe_image_data_with_page_hashes("1.3.6.1.4.1.311.2.3.2", &truncated),
+        );
+        assert!(signed.verify_page_hashes().is_err());
+    }
+
+    #[test]
+    fn test_certificate_anomalies() {
+        let signature = der_signed_data(
+            "2.16.840.1.101.3.4.2.1",
+            "1.3.6.1.4.1.311.2.1.4",
+            spc_indirect_data("2.16.840.1.101.3.4.2.1", &[0x11; 32]),
+            &[],
+            der_signer("Pepper Test CA", 1, "2.16.840.1.101.3.4.2.1", &[], &[]),
+        );
+        let anomalies = |raw: Vec<u8>| Pe::from_bytes(raw).unwrap().certificate_anomalies();
+        let security = TestPe::new(IMAGE_FILE_MACHINE_AMD64).optional_header_offset()
+            + 0x70
+            + 8 * DIR_SECURITY;
+
+        // unsigned images, and a clean table at the end of the file
+        assert!(anomalies(TestPe::new(IMAGE_FILE_MACHINE_AMD64).build()).is_empty());
+        let clean = signed_pe(&win_certificate(0x0200, 2, &signature, 0));
+        let table_offset = clean.len() - signature.len().next_multiple_of(8) - 8;
+        assert!(anomalies(clean.clone()).is_empty());
+
+        // zero alignment inside dwLength is fine, anything more is smuggled in
+        let mut aligned = signature.clone();
+        aligned.resize(signature.len().next_multiple_of(8), 0);
+        assert!(anomalies(signed_pe(&win_certificate(0x0200, 2, &aligned, 0))).is_empty());
+        let mut smuggled = signature.clone();
+        smuggled.extend_from_slice(b"payload after the PKCS#7");
+        assert_eq!(
+            anomalies(signed_pe(&win_certificate(0x0200, 2, &smuggled, 0))),
+            [CertificateAnomaly::DataInsideSignature {
+                offset: table_offset + 8 + signature.len(),
+                size: 24
+            }]
+        );
+
+        // bCertificate that isn't DER at all
+        let garbage = [0xcc; 24];
+        assert_eq!(
+            anomalies(signed_pe(&win_certificate(0x0200, 2, &garbage, 0))),
+            [CertificateAnomaly::UndecodableSignature {
+                offset: table_offset + 8,
+                size: garbage.len()
+            }]
+        );
+        let mut cut = signature.clone();
+        cut.truncate(signature.len() - 8);
+        assert_eq!(
+            anomalies(signed_pe(&win_certificate(0x0200, 2, &cut, 0))),
+            [CertificateAnomaly::UndecodableSignature {
+                offset: table_offset + 8,
+                size: cut.len()
+            }]
+        );
+
+        // padding that isn't zero
+        let mut odd = signature.clone();
+        odd.resize(signature.len().next_multiple_of(8) + 3, 0);
+        let padded = signed_pe(&win_certificate(0x0200, 2, &odd, 0xcc));
+        assert_eq!(
+            anomalies(padded),
+            [CertificateAnomaly::NonZeroPadding {
+                offset: table_offset + 8 + odd.len(),
+                size: 5
+            }]
+        );
+
+        // unsigned data after the table
+        let mut appended = clean.clone();
+        appended.extend_from_slice(b"appended");
+        assert_eq!(
+            anomalies(appended),
+            [CertificateAnomaly::DataAfterTable {
+                offset: clean.len(),
+                size: 8
+            }]
+        );
+
+        // the directory and dwLength disagreeing
+        let table_size = (clean.len() - table_offset) as u32;
+        let mut longer = clean.clone();
+        longer.extend_from_slice(&[0; 4]);
+        put32(&mut longer, security + 4, table_size + 4);
+        assert_eq!(
+            anomalies(longer),
+            [CertificateAnomaly::SizeMismatch {
+                directory: table_size + 4,
+                entries: table_size as usize
+            }]
+        );
+        let mut overlong = clean.clone();
+        put32(&mut overlong, table_offset, table_size + 8);
+        assert_eq!(
+            anomalies(overlong),
+            [CertificateAnomaly::BadLength {
+                offset: table_offset,
+                length: table_size + 8
+            }]
+        );
+
+        // a table moved into the section data, off alignment
+        let mut moved = clean.clone();
+        put32(&mut moved, security, 0x204);
+        let found = anomalies(moved);
+        assert!(found.contains(&CertificateAnomaly::MisalignedTable { offset: 0x204 }));
+        assert!(found.contains(&CertificateAnomaly::InsideImage { offset: 0x204 }));
+        // section data running past EOF or headers that don't cover the directories still get a
+        // result, the table counts as inside the image when the section claims to cover it
+        let text = security - 8 * DIR_SECURITY + 8 * 16;
+        let mut overrun = clean.clone();
+        put32(&mut overrun, text + 0x10, 0x10_0000);
+        assert_eq!(
+            anomalies(overrun),
+            [CertificateAnomaly::InsideImage {
+                offset: table_offset
+            }]
+        );
+        let mut headers = clean.clone();
+        put32(
+            &mut headers,
+            security - 8 * DIR_SECURITY - 0x70 + 0x3c,
+            0x10,
+        );
+        assert!(anomalies(headers).is_empty());
+
+        let mut truncated = clean.clone();
+        truncated.truncate(clean.len() - 8);
+        assert_eq!(
+            anomalies(truncated),
+            [
+                CertificateAnomaly::PastEndOfFile { end: clean.len() },
+                CertificateAnomaly::BadLength {
+                    offset: table_offset,
+                    length: 8 + signature.len() as u32
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        const ROOT: &str = "Pepper Test Root";
+        const SIGNER: &str = "Pepper Test Signer";
+        let validity: (&[u8], &[u8]) = (b"200101000000Z", b"391231235959Z");
+        let root = signed_certificate(ROOT, ROOT, 1, TEST_ROOT_KEY, TEST_ROOT_KEY, validity);
+        let leaf = signed_certificate(SIGNER, ROOT, 2, TEST_LEAF_KEY, TEST_ROOT_KEY, validity);
+        let parse = |raw: &[u8]| Certificate::new(&Tlv::parse(raw).unwrap()).unwrap();
+        let chain = [parse(&leaf), parse(&root)];
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+
+        // the encoder
+        assert_eq!(encode_unsigned(&[0, 0, 0x7f]), [0x02, 0x01, 0x7f]);
+        assert_eq!(encode_unsigned(&[0x80]), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(encode_unsigned(&[]), [0x02, 0x01, 0x00]);
+        assert_eq!(&encode(0x04, &[0; 0x100])[..4], [0x04, 0x82, 0x01, 0x00]);
+        let oid = encode_oid("1.3.6.1.4.1.311.2.1.4").unwrap();
+        assert_eq!(oid, der_oid("1.3.6.1.4.1.311.2.1.4"));
+        assert_eq!(
+            Tlv::parse(&oid).unwrap().oid().unwrap(),
+            "1.3.6.1.4.1.311.2.1.4"
+        );
+        assert!(encode_oid("1.x.3").is_err());
+        assert_eq!(
+            encode_set(&[vec![0x05, 0x00], vec![0x02, 0x01, 0x01]]),
+            [0x31, 0x05, 0x02, 0x01, 0x01, 0x05, 0x00]
+        );
+
+        // PKCS#8 keys, signing through the primes matches m^d
+        let pkcs8 = BigUint::from_hex(TEST_LEAF_KEY_PKCS8)
+            .unwrap()
+            .to_bytes_be(0);
+        let key = RsaPrivateKey::from_pkcs8(&pkcs8).unwrap();
+        assert_eq!(
+            key.public_key(),
+            RsaPublicKey::new(&rsa_public_key(TEST_LEAF_KEY)).unwrap()
+        );
+        assert_eq!(
+            key.sign(DigestAlgorithm::Sha256, &sha256(b"sample"))
+                .unwrap(),
+            rsa_sign(TEST_LEAF_KEY, b"sample")
+        );
+        assert!(RsaPrivateKey::from_pkcs8(&pkcs8[..pkcs8.len() - 1]).is_err());
+
+        // CheckSum of an unaligned file
+        let unsigned = TestPe::new(IMAGE_FILE_MACHINE_AMD64)
+            .section(".text", 0x1000, vec![0xc3; 0x123])
+            .trailing(b"abc".to_vec())
+            .build();
+        let pe = Pe::from_bytes(unsigned.clone()).unwrap();
+        assert_eq!(pe.compute_checksum(), 0x4ae7);
+
+        for algorithm in [DigestAlgorithm::Sha256, DigestAlgorithm::Sha1] {
+            let signed = pe.sign(&key, &chain, algorithm).unwrap();
+            // only the CheckSum and the directory change before the padding and the table
+            assert_eq!(&signed[0x200..unsigned.len()], &unsigned[0x200..]);
+            assert_eq!(&signed[unsigned.len()..0x408], [0; 5]);
+            let signed = Pe::from_bytes(signed).unwrap();
+            let table = &signed.optional_header.data_directories.certificate_table;
+            assert_eq!(table.virtual_addr, 0x408);
+            assert_eq!(table.size as usize, signed.raw().len() - 0x408);
+            assert_eq!(signed.optional_header.checksum, signed.compute_checksum());
+            assert!(signed.certificate_anomalies().is_empty());
+
+            let verdicts = signed.verify_signatures_at(&chain[1..], now).unwrap();
+            assert_eq!(verdicts.len(), 1);
+            assert!(verdicts[0].is_valid(), "{:?}", verdicts[0].problems);
+            assert_eq!(verdicts[0].digest_algorithm, Some(algorithm));
+            assert_eq!(verdicts[0].chain, chain);
+            let signature = &signed.signatures().unwrap()[0];
+            assert_eq!(signature.signer().opus_info(), (None, None));
+
+            // signing again replaces the signature
+            let resigned = Pe::from_bytes(signed.sign(&key, &chain, algorithm).unwrap()).unwrap();
+            assert_eq!(resigned.raw(), signed.raw());
+        }
+
+        // the key has to be the signer certificate's, and an existing table has to be last
+        assert!(pe.sign(&key, &chain[1..], DigestAlgorithm::Sha256).is_err());
+        assert!(pe.sign(&key, &[], DigestAlgorithm::Sha256).is_err());
+        let mut appended = pe.sign(&key, &chain, DigestAlgorithm::Sha256).unwrap();
+        appended.extend_from_slice(b"appended");
+        let appended = Pe::from_bytes(appended).unwrap();
+        assert!(appended
+            .sign(&key, &chain, DigestAlgorithm::Sha256)
+            .is_err());
+    }
 }